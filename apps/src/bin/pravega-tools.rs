@@ -11,13 +11,20 @@
 // A CLI that provides tools to manage Pravega streams.
 
 use clap::Clap;
+use std::convert::TryFrom;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Seek, SeekFrom, Write};
 use std::time::{Duration, SystemTime};
 
 use pravega_client::client_factory::ClientFactory;
 use pravega_client_config::ClientConfigBuilder;
-use pravega_client_shared::{Scope, Stream, ScopedStream};
-use pravega_video::index::{IndexSearcher, SearchMethod, get_index_stream_name};
-use pravega_video::utils::parse_controller_uri;
+use pravega_client_shared::{Scope, Stream, StreamConfiguration, ScopedStream, Scaling, ScaleType};
+use pravega_video::archive::{Archiver, MediaStreamReaders, create_archive_target_from_uri};
+use pravega_video::index::{IndexBuilder, IndexRecord, IndexRecordWriter, IndexSearcher, SearchFilter, SearchMethod,
+    get_index_stream_name, rebuild_index};
+use pravega_video::marker::get_marker_stream_name;
+use pravega_video::storage::{PravegaStorage, StorageBackend};
+use pravega_video::utils::{CurrentHead, parse_controller_uri};
+use pravega_video::verify::{VerifyReport, verify_streams};
 use pravega_video::timestamp::PravegaTimestamp;
 
 /// Tools to manage Pravega streams.
@@ -33,6 +40,9 @@ struct Opts {
 #[derive(Clap)]
 enum SubCommand {
     TruncateStream(TruncateStream),
+    RebuildIndex(RebuildIndex),
+    Verify(Verify),
+    ListMarkers(ListMarkers),
 }
 
 /// Truncate a stream written by the pravegasink GStreamer plugin.
@@ -47,6 +57,76 @@ struct TruncateStream {
     /// All data older than this many days will be deleted. Decimals are allowed.
     #[clap(long)]
     age_days: f64,
+    /// Archive the data before deleting it. Use file:///path for a local directory or s3://bucket/prefix for an
+    /// S3-compatible object store (configured with AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY, AWS_REGION, and AWS_ENDPOINT_URL).
+    /// S3 requires the s3 feature.
+    #[clap(long)]
+    archive_uri: Option<String>,
+}
+
+/// Rebuild the index of a stream written by the pravegasink GStreamer plugin by reading the data stream.
+/// The existing index is replaced unless --index-stream is specified.
+/// The new index records are appended to the index stream, and the existing records are truncated only after
+/// the new records have been written.
+/// This should not be used while pravegasink is writing to the stream.
+#[derive(Clap)]
+struct RebuildIndex {
+    /// Pravega scope
+    #[clap(long)]
+    scope: String,
+    /// Pravega stream
+    #[clap(long)]
+    stream: String,
+    /// Write the index to this stream instead of the index stream of the data stream.
+    #[clap(long)]
+    index_stream: Option<String>,
+    /// A key frame that does not have the include-in-index flag will not be indexed if an index record was created
+    /// less than this many seconds before it. Events with the include-in-index flag are always indexed.
+    /// This should match the index-min-sec property of pravegasink.
+    #[clap(long, default_value = "0.5")]
+    index_min_sec: f64,
+    /// A delta frame will be indexed if no index record was created for this many seconds.
+    /// This should match the index-max-sec property of pravegasink.
+    #[clap(long, default_value = "10.0")]
+    index_max_sec: f64,
+    /// Print the index records that would be written without writing them.
+    #[clap(long)]
+    dry_run: bool,
+}
+
+/// Verify that a stream written by the pravegasink GStreamer plugin is consistent with its index.
+/// The exit code will be 1 if any violations are found.
+#[derive(Clap)]
+struct Verify {
+    /// Pravega scope
+    #[clap(long)]
+    scope: String,
+    /// Pravega stream
+    #[clap(long)]
+    stream: String,
+    /// Print the report as JSON.
+    #[clap(long)]
+    json: bool,
+}
+
+/// List the markers of a stream written by the pravegasink GStreamer plugin, in order of timestamp.
+#[derive(Clap)]
+struct ListMarkers {
+    /// Pravega scope
+    #[clap(long)]
+    scope: String,
+    /// Pravega stream
+    #[clap(long)]
+    stream: String,
+    /// Only list markers on or after this time, in RFC 3339 format. For example: 2021-12-28T23:41:45.691Z
+    #[clap(long)]
+    begin_utc: Option<String>,
+    /// Only list markers before this time, in RFC 3339 format.
+    #[clap(long)]
+    end_utc: Option<String>,
+    /// Print each marker as a line of JSON.
+    #[clap(long)]
+    json: bool,
 }
 
 fn main() {
@@ -54,18 +134,76 @@ fn main() {
     let opts: Opts = Opts::parse();
     match opts.subcmd {
         SubCommand::TruncateStream(c) => {
-            truncate_stream(opts.controller, c.scope, c.stream, c.age_days);
+            if let Err(error) = truncate_stream(opts.controller, c.scope, c.stream, c.age_days, c.archive_uri) {
+                eprintln!("Failed to truncate stream: {}", error);
+                std::process::exit(1);
+            }
+        }
+        SubCommand::RebuildIndex(c) => {
+            if let Err(error) = rebuild_index_stream(opts.controller, c.scope, c.stream, c.index_stream, c.index_min_sec,
+                    c.index_max_sec, c.dry_run) {
+                eprintln!("Failed to rebuild index: {}", error);
+                std::process::exit(1);
+            }
+        }
+        SubCommand::Verify(c) => {
+            let ok = verify(opts.controller, c.scope, c.stream, c.json);
+            if !ok {
+                std::process::exit(1);
+            }
+        }
+        SubCommand::ListMarkers(c) => {
+            list_markers(opts.controller, c.scope, c.stream, c.begin_utc, c.end_utc, c.json);
         }
     }
 }
 
-fn truncate_stream(controller: String, scope_name: String, stream_name: String, age_days: f64) {
+fn truncate_stream(controller: String, scope_name: String, stream_name: String, age_days: f64,
+                   archive_uri: Option<String>) -> Result<(), Error> {
     let age_seconds = age_days * 24.0 * 60.0 * 60.0;
     let age = Duration::from_secs_f64(age_seconds);
     let truncate_at_time = SystemTime::now() - age;
-    let truncate_at_timestamp: PravegaTimestamp = truncate_at_time.into();
+    // This fails if the truncation time is during a leap second, where the Unix time is ambiguous.
+    let truncate_at_timestamp = PravegaTimestamp::try_from(truncate_at_time).map_err(|error| {
+        Error::new(ErrorKind::InvalidInput, format!("Unable to convert truncation time to a timestamp: {}", error))
+    })?;
     println!("Truncating stream {}/{} at {}", scope_name, stream_name, truncate_at_timestamp);
     let index_stream_name = get_index_stream_name(&stream_name);
+    let controller_uri = parse_controller_uri(controller).map_err(|error| {
+        Error::new(ErrorKind::InvalidInput, format!("Invalid controller: {}", error))
+    })?;
+    let client_config = ClientConfigBuilder::default()
+        .controller_uri(controller_uri)
+        .build()
+        .expect("creating config");
+    let storage = PravegaStorage::new(ClientFactory::new(client_config));
+    let mut writer = storage.create_writer(&scope_name, &stream_name)?;
+    let mut index_writer = storage.create_writer(&scope_name, &index_stream_name)?;
+    let index_reader = storage.create_reader(&scope_name, &index_stream_name)?;
+    let mut index_searcher = IndexSearcher::new(index_reader);
+    let index_record = index_searcher.search_timestamp_and_return_index_offset(
+        truncate_at_timestamp, SearchMethod::Before, SearchFilter::Any)?;
+    println!("Truncating prior to {:?}", index_record);
+    if let Some(archive_uri) = archive_uri {
+        // Data is only truncated after the archive has been confirmed.
+        let archiver = Archiver::new(create_archive_target_from_uri(&archive_uri)?);
+        let mut readers = MediaStreamReaders::new(&storage, &scope_name, &stream_name)?;
+        let stats = archiver.archive(&mut readers, &scope_name, &stream_name, index_record.0.offset, index_record.1).map_err(|error| {
+            Error::new(error.kind(), format!("Failed to archive; nothing was truncated: {}", error))
+        })?;
+        println!("Archived to {}: {:?}", archive_uri, stats);
+    }
+    index_writer.truncate_data_before(index_record.1)?;
+    println!("Index truncated at offset {}", index_record.1);
+    writer.truncate_data_before(index_record.0.offset)?;
+    println!("Data truncated at offset {}", index_record.0.offset);
+    Ok(())
+}
+
+fn rebuild_index_stream(controller: String, scope_name: String, stream_name: String, index_stream_name: Option<String>,
+                        index_min_sec: f64, index_max_sec: f64, dry_run: bool) -> Result<(), Error> {
+    let index_stream_name = index_stream_name.unwrap_or_else(|| get_index_stream_name(&stream_name));
+    println!("Rebuilding index of stream {}/{} into {}/{}", scope_name, stream_name, scope_name, index_stream_name);
     let scope = Scope::from(scope_name);
     let stream = Stream::from(stream_name);
     let index_stream = Stream::from(index_stream_name);
@@ -80,19 +218,178 @@ fn truncate_stream(controller: String, scope_name: String, stream_name: String,
         scope: scope.clone(),
         stream: stream.clone(),
     };
-    let writer = client_factory.create_byte_writer(scoped_stream);
+    let mut reader = client_factory.create_byte_reader(scoped_stream);
+    let data_head_offset = reader.current_head()?;
+    let data_tail_offset = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(data_head_offset))?;
+    println!("Reading data stream from offset {} to {}", data_head_offset, data_tail_offset);
+    let mut reader = BufReader::new(reader);
+    let mut index_builder = IndexBuilder::new((index_min_sec * 1e9) as u64, (index_max_sec * 1e9) as u64);
+
+    // Read the entire data stream before changing the index stream so that a failure leaves the existing index intact.
+    let mut records = Vec::new();
+    let events_read = rebuild_index(&mut reader, data_head_offset, data_tail_offset, &mut index_builder, |record| {
+        records.push(*record);
+        Ok(())
+    })?;
+
+    if dry_run {
+        for record in records.iter() {
+            println!("{:?}", record);
+        }
+        println!("Dry run: read {} events, index would have {} records", events_read, index_builder.records_created());
+        return Ok(());
+    }
+
+    // Create the index stream if it does not exist.
+    let controller_client = client_factory.controller_client();
+    let index_stream_config = StreamConfiguration {
+        scoped_stream: ScopedStream {
+            scope: scope.clone(),
+            stream: index_stream.clone(),
+        },
+        scaling: Scaling {
+            scale_type: ScaleType::FixedNumSegments,
+            min_num_segments: 1,
+            ..Default::default()
+        },
+        retention: Default::default(),
+        tags: None,
+    };
+    runtime.block_on(controller_client.create_stream(&index_stream_config)).map_err(|error| {
+        Error::new(ErrorKind::Other, format!("Failed to create index stream: {:?}", error))
+    })?;
     let index_scoped_stream = ScopedStream {
         scope: scope.clone(),
         stream: index_stream.clone(),
     };
-    let index_writer = client_factory.create_byte_writer(index_scoped_stream.clone());
-    let index_reader = client_factory.create_byte_reader(index_scoped_stream.clone());
-    let mut index_searcher = IndexSearcher::new(index_reader);
-    let index_record = index_searcher.search_timestamp_and_return_index_offset(
-        truncate_at_timestamp, SearchMethod::Before).unwrap();
-    println!("Truncating prior to {:?}", index_record);
-    runtime.block_on(index_writer.truncate_data_before(index_record.1 as i64)).unwrap();
-    println!("Index truncated at offset {}", index_record.1);
-    runtime.block_on(writer.truncate_data_before(index_record.0.offset as i64)).unwrap();
-    println!("Data truncated at offset {}", index_record.0.offset);
+
+    // The new records are appended after any existing index records.
+    // IndexSearcher requires that records begin at a multiple of the record size.
+    let mut index_reader = client_factory.create_byte_reader(index_scoped_stream.clone());
+    let index_tail_offset = index_reader.seek(SeekFrom::End(0))?;
+    if index_tail_offset % IndexRecord::RECORD_SIZE as u64 != 0 {
+        return Err(Error::new(ErrorKind::InvalidData, format!(
+            "Index stream has a partial record at offset {}; use --index-stream to write to a new stream", index_tail_offset)));
+    }
+    let mut index_writer = client_factory.create_byte_writer(index_scoped_stream);
+    index_writer.seek_to_tail();
+
+    let mut index_writer = BufWriter::new(index_writer);
+    let mut index_record_writer = IndexRecordWriter::new();
+    for record in records.iter() {
+        index_record_writer.write(record, &mut index_writer)?;
+    }
+    index_writer.flush()?;
+    println!("Read {} events, wrote {} index records", events_read, index_builder.records_created());
+
+    // Remove the existing index records only after the new records have been written.
+    if index_tail_offset > 0 {
+        let index_writer = index_writer.get_mut();
+        runtime.block_on(index_writer.truncate_data_before(index_tail_offset as i64)).map_err(|error| {
+            Error::new(ErrorKind::Other, format!("Failed to truncate index stream: {:?}", error))
+        })?;
+        println!("Existing index truncated at offset {}", index_tail_offset);
+    }
+    Ok(())
+}
+
+fn verify(controller: String, scope_name: String, stream_name: String, json: bool) -> bool {
+    let index_stream_name = get_index_stream_name(&stream_name);
+    let scope = Scope::from(scope_name.clone());
+    let stream = Stream::from(stream_name.clone());
+    let index_stream = Stream::from(index_stream_name);
+    let controller_uri = parse_controller_uri(controller).unwrap();
+    let client_config = ClientConfigBuilder::default()
+        .controller_uri(controller_uri)
+        .build()
+        .expect("creating config");
+    let client_factory = ClientFactory::new(client_config);
+    let reader = client_factory.create_byte_reader(ScopedStream {
+        scope: scope.clone(),
+        stream,
+    });
+    let index_reader = client_factory.create_byte_reader(ScopedStream {
+        scope,
+        stream: index_stream,
+    });
+    let report = verify_streams(reader, index_reader).unwrap();
+    if json {
+        println!("{}", verify_report_to_json(&scope_name, &stream_name, &report));
+    } else {
+        println!("Verified stream {}/{}", scope_name, stream_name);
+        println!("Data stream offsets {} to {}, {} events, timestamps {} to {}",
+            report.data_head_offset, report.data_tail_offset, report.event_count, report.first_timestamp, report.last_timestamp);
+        println!("Index stream offsets {} to {}, {} records",
+            report.index_head_offset, report.index_tail_offset, report.index_record_count);
+        for violation in report.violations.iter() {
+            println!("{}: data_offset={:?}, index_offset={:?}: {}",
+                violation.kind.name(), violation.data_offset, violation.index_offset, violation.message);
+        }
+        if report.violation_count > report.violations.len() as u64 {
+            println!("{} additional violations were not printed", report.violation_count - report.violations.len() as u64);
+        }
+        println!("Found {} violations", report.violation_count);
+    }
+    report.is_ok()
+}
+
+fn list_markers(controller: String, scope_name: String, stream_name: String, begin_utc: Option<String>, end_utc: Option<String>,
+                json: bool) {
+    let (begin_timestamp, end_timestamp) = match (PravegaTimestamp::try_from(begin_utc), PravegaTimestamp::try_from(end_utc)) {
+        (Ok(begin_timestamp), Ok(end_timestamp)) => (begin_timestamp, end_timestamp),
+        (Err(error), _) | (_, Err(error)) => {
+            eprintln!("Invalid begin or end time: {}", error);
+            std::process::exit(1);
+        },
+    };
+    let controller_uri = parse_controller_uri(controller).unwrap();
+    let client_config = ClientConfigBuilder::default()
+        .controller_uri(controller_uri)
+        .build()
+        .expect("creating config");
+    let client_factory = ClientFactory::new(client_config);
+    let marker_reader = client_factory.create_byte_reader(ScopedStream {
+        scope: Scope::from(scope_name),
+        stream: Stream::from(get_marker_stream_name(&stream_name)),
+    });
+    let markers = pravega_video::marker::list_markers(marker_reader, begin_timestamp, end_timestamp).unwrap();
+    for marker in markers.iter() {
+        if json {
+            println!("{}", serde_json::json!({
+                "timestamp": marker.timestamp.nanoseconds(),
+                "utc": marker.timestamp.to_iso_8601(),
+                "label": marker.label,
+                "payload": marker.payload,
+            }));
+        } else {
+            println!("{}  {}  {}", marker.timestamp, marker.label, marker.payload);
+        }
+    }
+}
+
+fn verify_report_to_json(scope_name: &str, stream_name: &str, report: &VerifyReport) -> serde_json::Value {
+    let violations: Vec<serde_json::Value> = report.violations.iter().map(|violation| {
+        serde_json::json!({
+            "kind": violation.kind.name(),
+            "data_offset": violation.data_offset,
+            "index_offset": violation.index_offset,
+            "message": violation.message,
+        })
+    }).collect();
+    serde_json::json!({
+        "scope": scope_name,
+        "stream": stream_name,
+        "ok": report.is_ok(),
+        "data_head_offset": report.data_head_offset,
+        "data_tail_offset": report.data_tail_offset,
+        "index_head_offset": report.index_head_offset,
+        "index_tail_offset": report.index_tail_offset,
+        "event_count": report.event_count,
+        "index_record_count": report.index_record_count,
+        "first_timestamp": report.first_timestamp.nanoseconds(),
+        "last_timestamp": report.last_timestamp.nanoseconds(),
+        "violation_count": report.violation_count,
+        "violations": violations,
+    })
 }
@@ -20,7 +20,7 @@ use glib::object::ObjectType;
 use gtk::prelude::*;
 use gtk::{Box, DrawingArea, Inhibit, Orientation, Window, WindowType};
 use pravega_video::timestamp::PravegaTimestamp;
-use std::{convert::TryInto, os::raw::c_void, time::SystemTime};
+use std::os::raw::c_void;
 use std::process;
 use std::ops;
 #[allow(unused_imports)]
@@ -80,8 +80,7 @@ impl Drop for AppWindow {
 // nanos_since_epoch is the number of nanoseconds since the TAI epoch.
 fn format_nanos_since_epoch(nanos_since_epoch: u64) -> String {
     let timestamp = PravegaTimestamp::from_nanoseconds(Some(nanos_since_epoch));
-    let system_time: SystemTime = timestamp.try_into().unwrap();
-    let datetime: chrono::DateTime<chrono::offset::Utc> = system_time.into();
+    let datetime = timestamp.to_utc_datetime().unwrap();
     let formatted_time = datetime.format("%Y-%m-%d %T.%3f");
     formatted_time.to_string()
 }
@@ -16,7 +16,7 @@ use pravega_client::client_factory::ClientFactory;
 use pravega_client_shared::{Scope, Stream, ScopedStream};
 
 use pravega_video::utils;
-use pravega_video::index::{IndexSearcher};
+use pravega_video::index::{IndexSearcher, SearchFilter};
 
 #[derive(Clap)]
 struct Opts {
@@ -63,9 +63,9 @@ fn main() {
     info!("Checking period is {} seconds",  opts.check_period);
 
     loop {
-        let first_record = index_searcher.get_first_record().unwrap();
+        let first_record = index_searcher.get_first_record(SearchFilter::Any).unwrap();
         info!("The first index record: timestamp={}", first_record.timestamp);
-        let last_record = index_searcher.get_last_record().unwrap();
+        let last_record = index_searcher.get_last_record(SearchFilter::Any).unwrap();
         info!("The last index record: timestamp={}", last_record.timestamp);
         let size = last_record.offset - first_record.offset;
         let size_in_mb = size / 1024 / 1024;
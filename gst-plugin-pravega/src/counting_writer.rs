@@ -41,6 +41,11 @@ impl<T: Write + Seek> CountingWriter<T> {
     pub fn get_mut(&mut self) -> &mut T {
         &mut self.inner
     }
+
+    /// Unwraps this writer, returning the underlying writer.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
 }
 
 impl<T: Write + Seek> Write for CountingWriter<T> {
@@ -14,9 +14,12 @@ mod numeric;
 mod fragmp4pay;
 mod pravegasink;
 mod pravegasrc;
+mod pre_roll;
 mod seekable_byte_stream_writer;
 mod seekable_take;
 mod timestampcvt;
+mod transaction_writer;
+mod write_queue;
 pub mod utils;
 
 fn plugin_init(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
@@ -15,44 +15,76 @@
 use glib::subclass::prelude::*;
 use gst::prelude::*;
 use gst::subclass::prelude::*;
-use gst::{gst_debug, gst_error, gst_fixme, gst_info, gst_log, gst_trace, gst_memdump};
+use gst::{gst_debug, gst_error, gst_fixme, gst_info, gst_log, gst_trace, gst_memdump, gst_warning};
 use gst_base::subclass::prelude::*;
 
+use std::cell::Cell;
 use std::cmp;
 use std::convert::TryInto;
-use std::io::{BufWriter, Seek, SeekFrom, Write};
-use std::sync::Mutex;
+use std::io::{BufWriter, ErrorKind, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::sync::mpsc::{self, Sender, Receiver, RecvTimeoutError};
 
+use chrono::format::{Item, StrftimeItems};
 use once_cell::sync::Lazy;
 
 use pravega_client::client_factory::ClientFactory;
-use pravega_client::byte::{ByteWriter, ByteReader};
-use pravega_client_shared::{Scope, Stream, StreamConfiguration, ScopedStream, Scaling, ScaleType};
-use pravega_video::event_serde::{EventWithHeader, EventWriter};
-use pravega_video::index::{IndexRecord, IndexRecordWriter, IndexSearcher, SearchMethod, get_index_stream_name};
-use pravega_video::timestamp::{PravegaTimestamp, SECOND};
+use pravega_video::archive::{Archiver, MediaStreamReaders, create_archive_target_from_uri};
+use pravega_video::caps::{CapsRecord, CapsRecordWriter, get_caps_stream_name};
+use pravega_video::event_serde::{BufferMetadata, EventWithHeader, EventWriter};
+use pravega_video::marker::{MarkerRecord, MarkerRecordWriter, get_marker_stream_name};
+use pravega_video::index::{IndexRecord, IndexRecordWriter, IndexSearcher, SearchFilter, SearchMethod, get_index_stream_name};
+use pravega_video::spool::{MediaStreamWriters, Spool};
+use pravega_video::storage::{ByteStreamReader, ByteStreamWriter, PravegaStorage, StorageBackend, create_storage_from_uri};
+use pravega_video::timestamp::{PravegaTimestamp, TimeDelta, SECOND};
 use pravega_video::utils;
 
 use crate::counting_writer::CountingWriter;
 use crate::numeric::u64_to_i64_saturating_sub;
+use crate::pre_roll::PreRollBuffer;
 use crate::seekable_byte_stream_writer::SeekableByteWriter;
+use crate::transaction_writer::TransactionWriter;
+use crate::utils::{clocktime_to_pravega, reference_timestamp};
+use crate::write_queue::{LeakyPolicy, PushResult, WriteQueue};
 
 const PROPERTY_NAME_STREAM: &str = "stream";
 const PROPERTY_NAME_CONTROLLER: &str = "controller";
 const PROPERTY_NAME_SEAL: &str = "seal";
+const PROPERTY_NAME_SEAL_ON_ROLLOVER: &str = "seal-on-rollover";
 const PROPERTY_NAME_BUFFER_SIZE: &str = "buffer-size";
 const PROPERTY_NAME_TIMESTAMP_MODE: &str = "timestamp-mode";
 const PROPERTY_NAME_INDEX_MIN_SEC: &str = "index-min-sec";
 const PROPERTY_NAME_INDEX_MAX_SEC: &str = "index-max-sec";
 const PROPERTY_NAME_ALLOW_CREATE_SCOPE: &str = "allow-create-scope";
 const PROPERTY_NAME_KEYCLOAK_FILE: &str = "keycloak-file";
+const PROPERTY_NAME_STORAGE_URI: &str = "storage-uri";
 const PROPERTY_NAME_RETENTION_TYPE: &str = "retention-type";
 const PROPERTY_NAME_RETENTION_DAYS: &str = "retention-days";
 const PROPERTY_NAME_RETENTION_BYTES: &str = "retention-bytes";
 const PROPERTY_NAME_RETENTION_MAINTENANCE_INTERVAL_SECONDS: &str = "retention-maintenance-interval-seconds";
+const PROPERTY_NAME_RETENTION_MAX_TRUNCATE_BYTES: &str = "retention-max-truncate-bytes";
+const PROPERTY_NAME_RETENTION_DRY_RUN: &str = "retention-dry-run";
+const PROPERTY_NAME_RETENTION_ARCHIVE_URI: &str = "retention-archive-uri";
+const PROPERTY_NAME_CHECKSUM: &str = "checksum";
+const PROPERTY_NAME_BUFFER_METADATA: &str = "buffer-metadata";
+const PROPERTY_NAME_TRANSACTIONAL: &str = "transactional";
+const PROPERTY_NAME_SPOOL_DIR: &str = "spool-dir";
+const PROPERTY_NAME_SPOOL_MAX_BYTES: &str = "spool-max-bytes";
+const PROPERTY_NAME_SPOOL_CONNECT_BACKOFF_SEC: &str = "spool-connect-backoff-sec";
+const PROPERTY_NAME_ASYNC_WRITE: &str = "async-write";
+const PROPERTY_NAME_WRITE_QUEUE_MAX_BYTES: &str = "write-queue-max-bytes";
+const PROPERTY_NAME_WRITE_QUEUE_MAX_SEC: &str = "write-queue-max-sec";
+const PROPERTY_NAME_WRITE_QUEUE_LEAKY: &str = "write-queue-leaky";
+const PROPERTY_NAME_WRITE_QUEUE_LATENCY_SEC: &str = "write-queue-latency-sec";
+const PROPERTY_NAME_COALESCE_MAX_BYTES: &str = "coalesce-max-bytes";
+const PROPERTY_NAME_COALESCE_MAX_SEC: &str = "coalesce-max-sec";
+const PROPERTY_NAME_TRIGGER_MODE: &str = "trigger-mode";
+const PROPERTY_NAME_PRE_ROLL_SEC: &str = "pre-roll-sec";
+const PROPERTY_NAME_POST_ROLL_SEC: &str = "post-roll-sec";
 
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::GEnum)]
 #[repr(u32)]
@@ -78,6 +110,14 @@ pub enum TimestampMode {
         nick = "tai"
     )]
     Tai = 2,
+    #[genum(
+        name = "Input buffers have a GstReferenceTimestampMeta with caps timestamp/x-ntp, timestamp/x-unix, or timestamp/x-tai. \
+                Use this for buffers from rtspsrc (add-reference-timestamp-meta=true) while PTS remains a normal running time. \
+                Buffers without this meta are timestamped using the offset from the last buffer that had it, \
+                or using the realtime clock if no buffer has had it.",
+        nick = "reference-timestamp-meta"
+    )]
+    ReferenceTimestampMeta = 3,
 }
 
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::GEnum)]
@@ -106,6 +146,37 @@ pub enum RetentionType {
     DaysAndBytes = 3,
 }
 
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::GEnum)]
+#[repr(u32)]
+#[genum(type_name = "GstWriteQueueLeaky")]
+pub enum WriteQueueLeaky {
+    #[genum(
+        name = "Block the pipeline until the writer thread has made room in the queue.",
+        nick = "block"
+    )]
+    Block = 0,
+    #[genum(
+        name = "Drop the oldest group of pictures in the queue. The next buffer written will be marked as a discontinuity.",
+        nick = "drop-oldest-gop"
+    )]
+    DropOldestGop = 1,
+    #[genum(
+        name = "Drop the buffer being rendered. The next buffer written will be marked as a discontinuity.",
+        nick = "drop-newest"
+    )]
+    DropNewest = 2,
+}
+
+impl From<WriteQueueLeaky> for LeakyPolicy {
+    fn from(leaky: WriteQueueLeaky) -> LeakyPolicy {
+        match leaky {
+            WriteQueueLeaky::Block => LeakyPolicy::Block,
+            WriteQueueLeaky::DropOldestGop => LeakyPolicy::DropOldestGop,
+            WriteQueueLeaky::DropNewest => LeakyPolicy::DropNewest,
+        }
+    }
+}
+
 #[derive(Debug)]
 enum RetentionPolicy {
     Days(f64),
@@ -133,27 +204,53 @@ struct RetentionMaintainer {
     element: super::PravegaSink,
     interval_seconds: u64,
     retention_policy: RetentionPolicy,
-    factory: ClientFactory,
-    index_searcher: IndexSearcher<ByteReader>,
-    index_writer: ByteWriter,
-    data_writer: ByteWriter,
+    max_truncate_bytes: u64,
+    dry_run: bool,
+    // If present, data is archived before it is truncated.
+    archive: Option<(Archiver, MediaStreamReaders)>,
+    scope_name: String,
+    stream_name: String,
+    index_searcher: IndexSearcher<Box<dyn ByteStreamReader>>,
+    index_writer: Box<dyn ByteStreamWriter>,
+    data_writer: Box<dyn ByteStreamWriter>,
+}
+
+/// The result of a successful retention maintenance cycle that truncated (or, in dry-run mode, would have truncated) the streams.
+#[derive(Debug)]
+struct RetentionTruncation {
+    head_timestamp: PravegaTimestamp,
+    data_offset: u64,
+    index_offset: u64,
+    bytes_deleted: u64,
+    limited: bool,
+    archived: bool,
 }
 
 impl RetentionMaintainer {
-    fn new(element: super::PravegaSink, interval_seconds: u64, retention_policy: RetentionPolicy, factory: ClientFactory, index_scoped_stream: ScopedStream, data_scoped_stream: ScopedStream) -> Self {
-        let index_reader = factory.create_byte_reader(index_scoped_stream.clone());
-        let index_writer = factory.create_byte_writer(index_scoped_stream);
-        let data_writer = factory.create_byte_writer(data_scoped_stream);
+    fn new(element: super::PravegaSink, interval_seconds: u64, retention_policy: RetentionPolicy, max_truncate_bytes: u64, dry_run: bool,
+           archiver: Option<Archiver>, storage: &dyn StorageBackend, scope_name: &str, index_stream_name: &str, data_stream_name: &str)
+           -> std::io::Result<Self> {
+        let index_reader = storage.create_reader(scope_name, index_stream_name)?;
+        let index_writer = storage.create_writer(scope_name, index_stream_name)?;
+        let data_writer = storage.create_writer(scope_name, data_stream_name)?;
         let index_searcher = IndexSearcher::new(index_reader);
-        Self {
+        let archive = match archiver {
+            Some(archiver) => Some((archiver, MediaStreamReaders::new(storage, scope_name, data_stream_name)?)),
+            None => None,
+        };
+        Ok(Self {
             element,
             interval_seconds,
             retention_policy,
-            factory,
+            max_truncate_bytes,
+            dry_run,
+            archive,
+            scope_name: scope_name.to_owned(),
+            stream_name: data_stream_name.to_owned(),
             index_searcher,
             index_writer,
             data_writer,
-        }
+        })
     }
 
     fn days_to_seconds(days: f64) -> i128 {
@@ -161,6 +258,101 @@ impl RetentionMaintainer {
         seconds.round() as i128
     }
 
+    /// Returns None if the index has no records, in which case there is nothing to truncate.
+    fn none_if_empty<T>(result: std::io::Result<T>) -> std::io::Result<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof || error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Truncates the index and data streams according to the retention policy.
+    /// At most max_truncate_bytes will be deleted from the data stream.
+    /// Returns None if there is nothing to truncate.
+    fn maintain(&mut self, seconds: Option<i128>, bytes: Option<u64>) -> std::io::Result<Option<RetentionTruncation>> {
+        let (head_record, head_index_offset) = match RetentionMaintainer::none_if_empty(
+                self.index_searcher.search_timestamp_and_return_index_offset(PravegaTimestamp::MIN, SearchMethod::After, SearchFilter::Any))? {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+
+        // Find the index record that the policy would make the new head. If multiple limits apply, the latest record wins.
+        let mut target: Option<(IndexRecord, u64)> = None;
+        if let Some(sec) = seconds {
+            let truncate_at_timestamp = PravegaTimestamp::now() - sec * SECOND;
+            gst_info!(CAT, obj: &self.element, "maintain: Truncating prior to {}", truncate_at_timestamp);
+            let result = RetentionMaintainer::none_if_empty(
+                self.index_searcher.search_timestamp_and_return_index_offset(truncate_at_timestamp, SearchMethod::Before, SearchFilter::Any))?;
+            target = cmp::max_by_key(target, result, |t| t.as_ref().map(|t| t.1));
+        }
+        if let Some(bytes) = bytes {
+            gst_info!(CAT, obj: &self.element, "maintain: Truncating larger than {} bytes", bytes);
+            let result = RetentionMaintainer::none_if_empty(
+                self.index_searcher.search_size_and_return_index_offset(bytes, SearchMethod::Before, SearchFilter::Any))?;
+            target = cmp::max_by_key(target, result, |t| t.as_ref().map(|t| t.1));
+        }
+        let (mut target_record, mut target_index_offset) = match target {
+            Some(target) if target.1 > head_index_offset => target,
+            _ => return Ok(None),
+        };
+
+        // Limit the number of bytes deleted in a single cycle.
+        // This protects against a misconfigured policy or clock deleting the entire stream at once.
+        let mut limited = false;
+        if self.max_truncate_bytes > 0 && target_record.offset - head_record.offset > self.max_truncate_bytes {
+            let (tail_record, _) = self.index_searcher.search_timestamp_and_return_index_offset(PravegaTimestamp::MAX, SearchMethod::Before, SearchFilter::Any)?;
+            let retain_bytes = tail_record.offset.saturating_sub(head_record.offset + self.max_truncate_bytes);
+            let (limited_record, limited_index_offset) = self.index_searcher.search_size_and_return_index_offset(retain_bytes, SearchMethod::Before, SearchFilter::Any)?;
+            gst_warning!(CAT, obj: &self.element, "maintain: Truncation at offset {} would delete more than {} bytes; truncating at offset {} instead",
+                target_record.offset, self.max_truncate_bytes, limited_record.offset);
+            if limited_index_offset <= head_index_offset {
+                return Ok(None);
+            }
+            target_record = limited_record;
+            target_index_offset = limited_index_offset;
+            limited = true;
+        }
+
+        let mut truncation = RetentionTruncation {
+            head_timestamp: target_record.timestamp,
+            data_offset: target_record.offset,
+            index_offset: target_index_offset,
+            bytes_deleted: target_record.offset - head_record.offset,
+            limited,
+            archived: false,
+        };
+        if self.dry_run {
+            gst_info!(CAT, obj: &self.element, "maintain: Dry run; would truncate {:?}", truncation);
+        } else {
+            // Data is only truncated after the archive has been confirmed.
+            if let Some((archiver, readers)) = self.archive.as_mut() {
+                let stats = archiver.archive(readers, &self.scope_name, &self.stream_name,
+                    truncation.data_offset, truncation.index_offset)?;
+                gst_info!(CAT, obj: &self.element, "maintain: Archived {:?}", stats);
+                truncation.archived = true;
+            }
+            self.index_writer.truncate_data_before(truncation.index_offset)?;
+            gst_info!(CAT, obj: &self.element, "maintain: Index truncated at offset {}", truncation.index_offset);
+            self.data_writer.truncate_data_before(truncation.data_offset)?;
+            gst_info!(CAT, obj: &self.element, "maintain: Data truncated at offset {}", truncation.data_offset);
+        }
+        Ok(Some(truncation))
+    }
+
+    /// Posts an element message describing a truncation.
+    fn post_truncation(&self, truncation: &RetentionTruncation) {
+        let structure = gst::Structure::builder(RETENTION_STATUS_MESSAGE_NAME)
+            .field("dry-run", &self.dry_run)
+            .field("head-timestamp", &truncation.head_timestamp.nanoseconds().unwrap_or_default())
+            .field("data-offset", &truncation.data_offset)
+            .field("bytes-deleted", &truncation.bytes_deleted)
+            .field("limited", &truncation.limited)
+            .field("archived", &truncation.archived)
+            .build();
+        let _ = self.element.post_message(gst::message::Element::builder(structure).src(Some(&self.element)).build());
+    }
+
     fn run(mut self, thread_stop_rx: Receiver<()>) -> Option<JoinHandle<()>> {
         let (seconds, bytes) = match self.retention_policy {
             RetentionPolicy::Days(days) => (Some(RetentionMaintainer::days_to_seconds(days)), None),
@@ -173,38 +365,32 @@ impl RetentionMaintainer {
             return None;
         }
 
-        gst_info!(CAT, obj: &self.element, "start: retention_maintainer_interval_seconds={}", self.interval_seconds);
+        gst_info!(CAT, obj: &self.element, "start: retention_maintainer_interval_seconds={}, max_truncate_bytes={}, dry_run={}",
+            self.interval_seconds, self.max_truncate_bytes, self.dry_run);
         let handle = thread::spawn(move || {
+            let interval = Duration::from_secs(self.interval_seconds);
+            let mut retry_backoff = RETENTION_MIN_RETRY_BACKOFF;
             loop {
-                if let Some(sec) = seconds {
-                    let truncate_at_timestamp = PravegaTimestamp::now() - sec * SECOND;
-                    gst_info!(CAT, obj: &self.element, "Truncating prior to {}", truncate_at_timestamp);
-
-                    let search_result = self.index_searcher.search_timestamp_and_return_index_offset(truncate_at_timestamp, SearchMethod::Before);
-                    if let Ok(result) = search_result {
-                        let runtime = self.factory.runtime();
-                        runtime.block_on(self.index_writer.truncate_data_before(result.1 as i64)).unwrap();
-                        gst_info!(CAT, obj: &self.element, "Index truncated at offset {}", result.1);
-                        runtime.block_on(self.data_writer.truncate_data_before(result.0.offset as i64)).unwrap();
-                        gst_info!(CAT, obj: &self.element, "Data truncated at offset {}", result.0.offset);
-                    }
-                }
-
-                if let Some(bytes) = bytes {
-                    gst_info!(CAT, obj: &self.element, "Truncating larger than {} bytes", bytes);
-
-                    let search_result = self.index_searcher.search_size_and_return_index_offset(bytes, SearchMethod::Before);
-                    if let Ok(result) = search_result {
-                        let runtime = self.factory.runtime();
-                        runtime.block_on(self.index_writer.truncate_data_before(result.1 as i64)).unwrap();
-                        gst_info!(CAT, obj: &self.element, "Index truncated at offset {}", result.1);
-                        runtime.block_on(self.data_writer.truncate_data_before(result.0.offset as i64)).unwrap();
-                        gst_info!(CAT, obj: &self.element, "Data truncated at offset {}", result.0.offset);
-                    }
-                }
+                // If maintenance fails, it is retried with exponential backoff, but never less often than the maintenance interval.
+                let wait = match self.maintain(seconds, bytes) {
+                    Ok(truncation) => {
+                        if let Some(truncation) = truncation {
+                            self.post_truncation(&truncation);
+                        }
+                        retry_backoff = RETENTION_MIN_RETRY_BACKOFF;
+                        interval
+                    },
+                    Err(error) => {
+                        let wait = cmp::min(retry_backoff, interval);
+                        gst::element_warning!(self.element, gst::ResourceError::Write,
+                            ["Retention maintenance failed; will retry in {:?}: {}", wait, error]);
+                        retry_backoff = cmp::min(2 * retry_backoff, RETENTION_MAX_RETRY_BACKOFF);
+                        wait
+                    },
+                };
 
                 // break the loop to stop the thread
-                match thread_stop_rx.recv_timeout(Duration::from_secs(self.interval_seconds)) {
+                match thread_stop_rx.recv_timeout(wait) {
                     Ok(_) | Err(RecvTimeoutError::Disconnected) => {
                         gst_info!(CAT, obj: &self.element, "Retention maintainer thread terminated");
                         break;
@@ -224,23 +410,73 @@ const DEFAULT_INDEX_MIN_SEC: f64 = 0.5;
 const DEFAULT_INDEX_MAX_SEC: f64 = 10.0;
 const DEFAULT_RETENTION_TYPE: RetentionType = RetentionType::None;
 const DEFAULT_RETENTION_MAINTENANCE_INTERVAL_SECONDS: u64 = 15 * 60;
-
-#[derive(Debug)]
+const DEFAULT_SPOOL_MAX_BYTES: u64 = 1024 * 1024 * 1024;
+const DEFAULT_WRITE_QUEUE_MAX_BYTES: u64 = 64 * 1024 * 1024;
+const DEFAULT_WRITE_QUEUE_MAX_SEC: f64 = 10.0;
+const DEFAULT_WRITE_QUEUE_LEAKY: WriteQueueLeaky = WriteQueueLeaky::Block;
+const DEFAULT_COALESCE_MAX_BYTES: usize = 0;
+const DEFAULT_COALESCE_MAX_SEC: f64 = 0.1;
+const MAX_COALESCE_MAX_BYTES: u32 = 1024 * 1024;
+const DEFAULT_PRE_ROLL_SEC: f64 = 5.0;
+const DEFAULT_POST_ROLL_SEC: f64 = 10.0;
+
+// While spooling, reconnection attempts begin with the interval spool-connect-backoff-sec and double up to the maximum.
+const DEFAULT_SPOOL_CONNECT_BACKOFF_SEC: f64 = 1.0;
+const SPOOL_MAX_CONNECT_BACKOFF: Duration = Duration::from_secs(60);
+// While spooling, a spool status message is posted with this interval.
+const SPOOL_STATUS_INTERVAL: Duration = Duration::from_secs(10);
+const SPOOL_STATUS_MESSAGE_NAME: &str = "pravega-spool";
+// When retention maintenance fails, retries begin with this interval and double up to the maximum.
+const RETENTION_MIN_RETRY_BACKOFF: Duration = Duration::from_secs(1);
+const RETENTION_MAX_RETRY_BACKOFF: Duration = Duration::from_secs(60);
+const RETENTION_STATUS_MESSAGE_NAME: &str = "pravega-retention";
+const ROLLOVER_MESSAGE_NAME: &str = "pravega-rollover";
+const SIGNAL_NAME_SWITCH_STREAM: &str = "switch-stream";
+const SIGNAL_NAME_TRIGGER: &str = "trigger";
+const SIGNAL_NAME_ADD_MARKER: &str = "add-marker";
+// A custom downstream event with this structure name adds a marker.
+// It has the fields label (string), payload (optional string), and timestamp (optional PTS as uint64).
+const MARKER_EVENT_NAME: &str = "pravega-marker";
+// A custom downstream event with this structure name triggers a recording.
+const TRIGGER_EVENT_NAME: &str = "pravega-trigger";
+const RECORDING_MESSAGE_NAME: &str = "pravega-recording";
+
+#[derive(Debug, Clone)]
 struct Settings {
     scope: Option<String>,
     stream: Option<String>,
     controller: Option<String>,
     seal: bool,
+    seal_on_rollover: bool,
     buffer_size: usize,
     timestamp_mode: TimestampMode,
     index_min_nanos: u64,
     index_max_nanos: u64,
     allow_create_scope: bool,
     keycloak_file: Option<String>,
+    storage_uri: Option<String>,
     retention_type: RetentionType,
     retention_days: Option<f64>,
     retention_bytes: Option<u64>,
     retention_maintenance_interval_seconds: u64,
+    retention_max_truncate_bytes: u64,
+    retention_dry_run: bool,
+    retention_archive_uri: Option<String>,
+    checksum: bool,
+    buffer_metadata: bool,
+    transactional: bool,
+    spool_dir: Option<String>,
+    spool_max_bytes: u64,
+    spool_connect_backoff_nanos: u64,
+    async_write: bool,
+    write_queue_max_bytes: u64,
+    write_queue_max_nanos: u64,
+    write_queue_leaky: WriteQueueLeaky,
+    coalesce_max_bytes: usize,
+    coalesce_max_nanos: u64,
+    trigger_mode: bool,
+    pre_roll_nanos: u64,
+    post_roll_nanos: u64,
 }
 
 impl Default for Settings {
@@ -250,16 +486,36 @@ impl Default for Settings {
             stream: None,
             controller: Some(DEFAULT_CONTROLLER.to_owned()),
             seal: false,
+            seal_on_rollover: false,
             buffer_size: DEFAULT_BUFFER_SIZE,
             timestamp_mode: DEFAULT_TIMESTAMP_MODE,
             index_min_nanos: (DEFAULT_INDEX_MIN_SEC * 1e9) as u64,
             index_max_nanos: (DEFAULT_INDEX_MAX_SEC * 1e9) as u64,
             allow_create_scope: true,
             keycloak_file: None,
+            storage_uri: None,
             retention_type: DEFAULT_RETENTION_TYPE,
             retention_days: None,
             retention_bytes: None,
             retention_maintenance_interval_seconds: DEFAULT_RETENTION_MAINTENANCE_INTERVAL_SECONDS,
+            retention_max_truncate_bytes: 0,
+            retention_dry_run: false,
+            retention_archive_uri: None,
+            checksum: false,
+            buffer_metadata: false,
+            transactional: false,
+            spool_dir: None,
+            spool_max_bytes: DEFAULT_SPOOL_MAX_BYTES,
+            spool_connect_backoff_nanos: (DEFAULT_SPOOL_CONNECT_BACKOFF_SEC * 1e9) as u64,
+            async_write: false,
+            write_queue_max_bytes: DEFAULT_WRITE_QUEUE_MAX_BYTES,
+            write_queue_max_nanos: (DEFAULT_WRITE_QUEUE_MAX_SEC * 1e9) as u64,
+            write_queue_leaky: DEFAULT_WRITE_QUEUE_LEAKY,
+            coalesce_max_bytes: DEFAULT_COALESCE_MAX_BYTES,
+            coalesce_max_nanos: (DEFAULT_COALESCE_MAX_SEC * 1e9) as u64,
+            trigger_mode: false,
+            pre_roll_nanos: (DEFAULT_PRE_ROLL_SEC * 1e9) as u64,
+            post_roll_nanos: (DEFAULT_POST_ROLL_SEC * 1e9) as u64,
         }
     }
 }
@@ -267,9 +523,15 @@ impl Default for Settings {
 enum State {
     Stopped,
     Started {
-        client_factory: ClientFactory,
-        writer: CountingWriter<BufWriter<SeekableByteWriter>>,
-        index_writer: ByteWriter,
+        writer: DataWriter,
+        index_writer: Box<dyn ByteStreamWriter>,
+        caps_writer: Box<dyn ByteStreamWriter>,
+        // None if the sink started while Pravega was unavailable and has not reconnected.
+        marker_writer: Option<Box<dyn ByteStreamWriter>>,
+        // Caps received by set_caps that have not been written to the caps stream.
+        pending_caps: Option<String>,
+        // Caps in the last caps record written by this instance.
+        written_caps: Option<String>,
         // First received PTS that is not None.
         first_valid_time: PravegaTimestamp,
         // PTS of last written index record.
@@ -278,21 +540,121 @@ enum State {
         final_timestamp: PravegaTimestamp,
         // The offset that will be written to the index upon end-of-stream.
         final_offset: Option<u64>,
+        // In transactional mode, the index record for the open transaction.
+        // It is written after the transaction is committed.
+        pending_index_record: Option<IndexRecord>,
+        // Small buffers that have not been written yet because they are being coalesced.
+        batch: Option<Batch>,
         buffers_written: u64,
         retention_thread_stop_tx: Sender<()>,
         retention_thread_handle: Option<JoinHandle<()>>,
+        // The following are used to reconnect to Pravega after spooling.
+        storage: Arc<dyn StorageBackend>,
+        scope_name: String,
+        stream_name: String,
+        buffer_size: usize,
+        spool: Option<Spool>,
+        // The initial interval between reconnection attempts while spooling.
+        spool_connect_backoff: Duration,
+        // Present while writing to the spool instead of Pravega.
+        spooling: Option<SpoolingState>,
+        // False if the sink started while Pravega was unavailable.
+        // The streams are then created, and the retention maintainer is started, upon reconnecting.
+        streams_created: bool,
+        // If the stream property is a template, the sink rolls over to a new stream when the formatted name changes.
+        stream_template: Option<String>,
+        // The scope and stream requested by the switch-stream signal.
+        pending_stream_switch: Option<(String, String)>,
     },
 }
 
+/// Consecutive small buffers that will be written as a single event.
+/// The timestamp, flags, and buffer metadata are those of the first buffer.
+struct Batch {
+    timestamp: PravegaTimestamp,
+    include_in_index: bool,
+    random_access: bool,
+    discontinuity: bool,
+    buffer_metadata: Option<BufferMetadata>,
+    payload: Vec<u8>,
+    buffer_lengths: Vec<usize>,
+    // When the first buffer was added. The batch is written when it is older than coalesce-max-sec.
+    created: Instant,
+}
+
+/// The state of a sink that is writing to the spool because Pravega is unavailable.
+#[derive(Debug)]
+struct SpoolingState {
+    next_connect_time: Instant,
+    connect_backoff: Duration,
+    next_status_time: Instant,
+    dropped_buffers: u64,
+}
+
+impl SpoolingState {
+    fn new(connect_backoff: Duration) -> Self {
+        let now = Instant::now();
+        SpoolingState {
+            next_connect_time: now + connect_backoff,
+            connect_backoff,
+            next_status_time: now + SPOOL_STATUS_INTERVAL,
+            dropped_buffers: 0,
+        }
+    }
+}
+
 impl Default for State {
     fn default() -> State {
         State::Stopped
     }
 }
 
+/// A buffer waiting to be written by the writer thread.
+struct QueuedBuffer {
+    buffer: gst::Buffer,
+    // The caps of the sink pad when the buffer was rendered.
+    caps: Option<gst::Caps>,
+}
+
+/// When async-write is enabled, buffers are written by a writer thread that receives them through a bounded queue.
+struct AsyncWriter {
+    queue: Arc<WriteQueue<QueuedBuffer>>,
+    thread_handle: JoinHandle<()>,
+}
+
+/// When coalescing is enabled, a thread writes the pending batch when it is older than coalesce-max-sec,
+/// so that buffers are not held indefinitely when no further buffers arrive.
+struct CoalesceFlusher {
+    thread_stop_tx: Sender<()>,
+    thread_handle: JoinHandle<()>,
+}
+
+/// When trigger-mode is enabled, buffers are held in a pre-roll buffer and are only written during a recording.
+/// A recording begins with the contents of the pre-roll buffer and ends at the first key frame after the post-roll period.
+struct Recorder {
+    pre_roll: PreRollBuffer<QueuedBuffer>,
+    post_roll_nanos: u64,
+    // While recording, the PTS (nanoseconds) after which the recording will stop at the next key frame.
+    stop_time: Option<u64>,
+    // Caps of the last buffer passed to write_buffer when async-write is disabled.
+    current_caps: Option<gst::Caps>,
+}
+
 pub struct PravegaSink {
     settings: Mutex<Settings>,
     state: Mutex<State>,
+    // This is separate from the state because the writer thread holds the state lock while writing.
+    async_writer: Mutex<Option<AsyncWriter>>,
+    // This is separate from the state because the flusher thread acquires the state lock.
+    coalesce_flusher: Mutex<Option<CoalesceFlusher>>,
+    recorder: Mutex<Option<Recorder>>,
+    // Set by the trigger signal or event. This is separate from the recorder so that it never blocks.
+    trigger_pending: AtomicBool,
+    // The PTS of the last buffer received by render. Markers without a timestamp are assigned this PTS.
+    last_render_pts: Mutex<gst::ClockTime>,
+    // In reference-timestamp-meta mode, the difference between the reference timestamp and the PTS
+    // of the last buffer written that had a reference timestamp meta.
+    reference_offset: Mutex<TimeDelta>,
 }
 
 static CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
@@ -303,6 +665,496 @@ static CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
     )
 });
 
+type DataWriter = TransactionWriter<CountingWriter<BufWriter<SeekableByteWriter>>>;
+
+fn new_data_writer(writer: Box<dyn ByteStreamWriter>, buffer_size: usize) -> Result<DataWriter, std::io::Error> {
+    let seekable_writer = SeekableByteWriter::new(writer)?;
+    let buf_writer = BufWriter::with_capacity(buffer_size, seekable_writer);
+    let counting_writer = CountingWriter::new(buf_writer)?;
+    Ok(TransactionWriter::new(counting_writer, EventWithHeader::max_atomic_write_size()))
+}
+
+/// Opens writers at the tail of the data, index, and caps streams.
+fn open_writers(storage: &dyn StorageBackend, scope_name: &str, stream_name: &str) -> Result<MediaStreamWriters, std::io::Error> {
+    let open_writer = |stream_name: &str| -> std::io::Result<Box<dyn ByteStreamWriter>> {
+        let mut writer = storage.create_writer(scope_name, stream_name)?;
+        writer.seek_to_tail()?;
+        Ok(writer)
+    };
+    Ok(MediaStreamWriters {
+        data: open_writer(stream_name)?,
+        index: open_writer(&get_index_stream_name(stream_name))?,
+        caps: open_writer(&get_caps_stream_name(stream_name))?,
+    })
+}
+
+/// Causes the next buffer to be written as if it were the first buffer written by this instance.
+/// It will be marked as a discontinuity, it will be indexed, and the caps will be written.
+fn begin_segment(
+    pending_caps: &mut Option<String>,
+    written_caps: &mut Option<String>,
+    last_index_time: &mut PravegaTimestamp,
+    buffers_written: &mut u64,
+) {
+    if pending_caps.is_none() {
+        *pending_caps = written_caps.take();
+    } else {
+        *written_caps = None;
+    }
+    *last_index_time = PravegaTimestamp::NONE;
+    *buffers_written = 0;
+}
+
+/// Posts an element message with the size and age of the spool.
+fn post_spool_status(element: &super::PravegaSink, spool: &Spool, spooling: bool, dropped_buffers: u64) {
+    let size_bytes = spool.size_bytes().unwrap_or_default();
+    let oldest_timestamp = spool.oldest_timestamp().unwrap_or(PravegaTimestamp::NONE);
+    let age_seconds = match (PravegaTimestamp::now().nanoseconds(), oldest_timestamp.nanoseconds()) {
+        (Some(now), Some(oldest)) if size_bytes > 0 => u64_to_i64_saturating_sub(now, oldest) as f64 * 1e-9,
+        _ => 0.0,
+    };
+    gst_info!(CAT, obj: element, "post_spool_status: spooling={}, size_bytes={}, age_seconds={}, dropped_buffers={}",
+        spooling, size_bytes, age_seconds, dropped_buffers);
+    let structure = gst::Structure::builder(SPOOL_STATUS_MESSAGE_NAME)
+        .field("spooling", &spooling)
+        .field("size-bytes", &size_bytes)
+        .field("max-bytes", &spool.max_bytes())
+        .field("age-seconds", &age_seconds)
+        .field("dropped-buffers", &dropped_buffers)
+        .build();
+    let _ = element.post_message(gst::message::Element::builder(structure).src(Some(element)).build());
+}
+
+/// Posts an element message when a triggered recording starts or stops.
+fn post_recording_status(element: &super::PravegaSink, recording: bool, pre_roll_buffers: u64) {
+    let structure = gst::Structure::builder(RECORDING_MESSAGE_NAME)
+        .field("recording", &recording)
+        .field("pre-roll-buffers", &pre_roll_buffers)
+        .build();
+    let _ = element.post_message(gst::message::Element::builder(structure).src(Some(element)).build());
+}
+
+/// Writes buffers from the queue until it is closed.
+/// If a buffer cannot be written, the queue is aborted so that render will return an error.
+fn run_writer_thread(element: super::PravegaSink, queue: Arc<WriteQueue<QueuedBuffer>>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let sink = PravegaSink::from_instance(&element);
+        let mut current_caps: Option<gst::Caps> = None;
+        while let Some((queued_buffer, discontinuity)) = queue.pop() {
+            if let Some(caps) = queued_buffer.caps {
+                if current_caps.as_ref() != Some(&caps) {
+                    if let Err(err) = sink.set_pending_caps(&caps) {
+                        err.log_with_object(&element);
+                    }
+                    current_caps = Some(caps);
+                }
+            }
+            let mut buffer = queued_buffer.buffer;
+            if discontinuity {
+                // Buffers before this one were dropped.
+                buffer.make_mut().set_flags(gst::BufferFlags::DISCONT);
+            }
+            if let Err(error) = sink.write_buffer(&element, &buffer) {
+                let discarded = queue.abort();
+                gst_error!(CAT, obj: &element, "Writer thread failed to write buffer: {:?}; discarded {} queued buffers", error, discarded);
+                break;
+            }
+        }
+        gst_info!(CAT, obj: &element, "Writer thread terminated");
+    })
+}
+
+/// Writes the pending batch whenever it becomes older than max_age, until a message is received on thread_stop_rx.
+fn run_coalesce_flusher(element: super::PravegaSink, max_age: Duration, thread_stop_rx: Receiver<()>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let sink = PravegaSink::from_instance(&element);
+        let mut wait = max_age;
+        loop {
+            match thread_stop_rx.recv_timeout(wait) {
+                Ok(_) | Err(RecvTimeoutError::Disconnected) => {
+                    gst_info!(CAT, obj: &element, "Coalesce flusher thread terminated");
+                    break;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+            wait = sink.flush_expired_batch(&element, max_age);
+        }
+    })
+}
+
+/// Writes a batch of buffers as a single event.
+fn write_batch(
+    element: &super::PravegaSink,
+    batch: &Batch,
+    event_writer: &mut EventWriter,
+    writer: &mut DataWriter,
+    transactional: bool,
+) -> Result<(), std::io::Error> {
+    let mut event = if batch.buffer_lengths.len() == 1 {
+        EventWithHeader::new(&batch.payload, batch.timestamp, batch.include_in_index, batch.random_access, batch.discontinuity)
+    } else {
+        EventWithHeader::new_batch(&batch.payload, batch.buffer_lengths.clone(), batch.timestamp,
+            batch.include_in_index, batch.random_access, batch.discontinuity)
+    };
+    event.header.buffer_metadata = batch.buffer_metadata;
+    if transactional {
+        writer.begin();
+    }
+    gst_memdump!(CAT, obj: element, "write_batch: writing event={:?}", event);
+    event_writer.write(&event, writer)?;
+    writer.end_event();
+    Ok(())
+}
+
+/// Commits the open transaction, if any, to the data stream and then writes its index record.
+fn commit_transaction(
+    element: &super::PravegaSink,
+    writer: &mut DataWriter,
+    index_writer: &mut Box<dyn ByteStreamWriter>,
+    pending_index_record: &mut Option<IndexRecord>,
+) -> Result<(), std::io::Error> {
+    let pending_len = writer.pending_len();
+    let num_writes = writer.commit()?;
+    if num_writes > 1 {
+        gst_warning!(CAT, obj: element,
+            "commit_transaction: Transaction of {} bytes exceeded the atomic write size and was committed with {} writes",
+            pending_len, num_writes);
+    }
+    if let Some(index_record) = pending_index_record.take() {
+        let mut index_record_writer = IndexRecordWriter::new();
+        index_record_writer.write(&index_record, index_writer)?;
+        index_writer.flush()?;
+        gst_debug!(CAT, obj: element, "commit_transaction: Committed {} bytes and wrote index record {:?}", pending_len, index_record);
+    }
+    Ok(())
+}
+
+/// Writes buffers that are waiting to be coalesced, commits the open transaction, and writes the final index record.
+/// If seal is true, the data, index, and caps streams are then sealed.
+/// This is called when stopping and when rolling over to a new stream.
+fn finish_streams(
+    element: &super::PravegaSink,
+    writer: &mut DataWriter,
+    index_writer: &mut Box<dyn ByteStreamWriter>,
+    caps_writer: &mut Box<dyn ByteStreamWriter>,
+    final_timestamp: &mut PravegaTimestamp,
+    final_offset: &mut Option<u64>,
+    pending_index_record: &mut Option<IndexRecord>,
+    batch: &mut Option<Batch>,
+    event_writer: &mut EventWriter,
+    transactional: bool,
+    seal: bool,
+) -> Result<(), gst::ErrorMessage> {
+    // Write buffers that are waiting to be coalesced.
+    if let Some(pending_batch) = batch.as_ref() {
+        write_batch(element, pending_batch, event_writer, writer, transactional).map_err(|error| {
+            gst::error_msg!(gst::ResourceError::Write, ["Failed to write batch: {}", error])
+        })?;
+        *batch = None;
+        *final_offset = Some(writer.seek(SeekFrom::Current(0)).map_err(|error| {
+            gst::error_msg!(gst::ResourceError::Write, ["Failed to get position of Pravega data stream: {}", error])
+        })?);
+    }
+
+    commit_transaction(element, writer, index_writer, pending_index_record).map_err(|error| {
+        gst::error_msg!(gst::ResourceError::Write, ["Failed to commit transaction: {}", error])
+    })?;
+
+    writer.flush().map_err(|error| {
+        gst::error_msg!(gst::ResourceError::Write, ["Failed to flush Pravega data stream: {}", error])
+    })?;
+
+    // Write final index record.
+    // The timestamp will be the the buffer timestamp + duration of the final buffer.
+    // The offset will be current write position.
+    if let Some(final_offset) = *final_offset {
+        if final_timestamp.is_some() {
+            let index_record = IndexRecord::new(*final_timestamp, final_offset,
+                false, false);
+            let mut index_record_writer = IndexRecordWriter::new();
+            index_record_writer.write(&index_record, index_writer).map_err(|error| {
+                gst::error_msg!(gst::ResourceError::Write, ["Failed to write Pravega index stream: {}", error])
+            })?;
+            gst_info!(CAT, obj: element, "finish_streams: Wrote final index record {:?}", index_record);
+        }
+    }
+
+    index_writer.flush().map_err(|error| {
+        gst::error_msg!(gst::ResourceError::Write, ["Failed to flush Pravega index stream: {}", error])
+    })?;
+
+    if seal {
+        gst_info!(CAT, obj: element, "finish_streams: Sealing streams");
+        let writer = writer.get_mut().get_mut().get_mut().get_mut();
+        writer.seal().map_err(|error| {
+            gst::error_msg!(gst::ResourceError::Write, ["Failed to seal Pravega data stream: {}", error])
+        })?;
+        index_writer.seal().map_err(|error| {
+            gst::error_msg!(gst::ResourceError::Write, ["Failed to seal Pravega index stream: {}", error])
+        })?;
+        caps_writer.seal().map_err(|error| {
+            gst::error_msg!(gst::ResourceError::Write, ["Failed to seal Pravega caps stream: {}", error])
+        })?;
+        gst_info!(CAT, obj: element, "finish_streams: Streams sealed");
+    }
+    Ok(())
+}
+
+/// Opens the spool of a stream if a spool directory is configured.
+/// If writers are provided, any data that was spooled by a previous instance is written to the streams.
+fn open_spool(
+    element: &super::PravegaSink,
+    settings: &Settings,
+    scope_name: &str,
+    stream_name: &str,
+    writers: Option<&mut MediaStreamWriters>,
+) -> Result<Option<Spool>, gst::ErrorMessage> {
+    match settings.spool_dir.clone() {
+        Some(spool_dir) => {
+            gst_info!(CAT, obj: element, "open_spool: spool_dir={}, spool_max_bytes={}", spool_dir, settings.spool_max_bytes);
+            let spool = Spool::new(Path::new(&spool_dir), scope_name, stream_name, settings.spool_max_bytes).map_err(|error| {
+                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create spool: {}", error])
+            })?;
+            if let Some(writers) = writers {
+                let stats = spool.replay(writers).map_err(|error| {
+                    gst::error_msg!(gst::ResourceError::Write, ["Failed to replay spool: {}", error])
+                })?;
+                gst_info!(CAT, obj: element, "open_spool: Replayed spool: {:?}", stats);
+            }
+            Ok(Some(spool))
+        },
+        None => Ok(None),
+    }
+}
+
+/// Creates the data, index, and caps streams, and optionally the scope.
+fn create_media_streams(
+    element: &super::PravegaSink,
+    storage: &dyn StorageBackend,
+    scope_name: &str,
+    stream_name: &str,
+    allow_create_scope: bool,
+) -> Result<(), gst::ErrorMessage> {
+    let index_stream_name = get_index_stream_name(stream_name);
+    let caps_stream_name = get_caps_stream_name(stream_name);
+    let marker_stream_name = get_marker_stream_name(stream_name);
+    gst_info!(CAT, obj: element, "create_media_streams: scope={}, stream={}, index_stream={}, caps_stream={}, marker_stream={}",
+        scope_name, stream_name, index_stream_name, caps_stream_name, marker_stream_name);
+
+    // Create scope.
+    if allow_create_scope {
+        storage.create_scope(scope_name).map_err(|error| {
+            gst::error_msg!(gst::ResourceError::Settings, ["Failed to create scope: {}", error])
+        })?;
+    }
+
+    // Create data stream.
+    storage.create_stream(scope_name, stream_name, utils::get_video_tags()).map_err(|error| {
+        gst::error_msg!(gst::ResourceError::Settings, ["Failed to create data stream: {}", error])
+    })?;
+
+    // Create index stream.
+    storage.create_stream(scope_name, &index_stream_name, None).map_err(|error| {
+        gst::error_msg!(gst::ResourceError::Settings, ["Failed to create index stream: {}", error])
+    })?;
+
+    // Create caps stream.
+    storage.create_stream(scope_name, &caps_stream_name, None).map_err(|error| {
+        gst::error_msg!(gst::ResourceError::Settings, ["Failed to create caps stream: {}", error])
+    })?;
+
+    // Create marker stream.
+    storage.create_stream(scope_name, &marker_stream_name, None).map_err(|error| {
+        gst::error_msg!(gst::ResourceError::Settings, ["Failed to create marker stream: {}", error])
+    })?;
+    Ok(())
+}
+
+fn open_marker_writer(storage: &dyn StorageBackend, scope_name: &str, stream_name: &str)
+        -> Result<Box<dyn ByteStreamWriter>, gst::ErrorMessage> {
+    storage.create_writer(scope_name, &get_marker_stream_name(stream_name)).map_err(|error| {
+        gst::error_msg!(gst::ResourceError::OpenWrite, ["Failed to open writer for marker stream: {}", error])
+    })
+}
+
+/// Streams that were opened without holding the state lock.
+struct OpenedStreams {
+    writers: MediaStreamWriters,
+    // The following are only present if the streams were created.
+    marker_writer: Option<Box<dyn ByteStreamWriter>>,
+    retention: Option<(Sender<()>, Option<JoinHandle<()>>)>,
+}
+
+/// Opens writers for the data, index, and caps streams.
+/// If create_streams is true, the streams are created first, and the marker writer and retention maintainer are started.
+/// This performs network I/O so it must not be called while holding the state or settings lock.
+fn open_streams(
+    element: &super::PravegaSink,
+    settings: &Settings,
+    storage: &dyn StorageBackend,
+    scope_name: &str,
+    stream_name: &str,
+    create_streams: bool,
+) -> Result<OpenedStreams, gst::ErrorMessage> {
+    if create_streams {
+        create_media_streams(element, storage, scope_name, stream_name, settings.allow_create_scope)?;
+    }
+    let writers = open_writers(storage, scope_name, stream_name).map_err(|error| {
+        gst::error_msg!(gst::ResourceError::OpenWrite, ["Failed to open writers: {}", error])
+    })?;
+    let (marker_writer, retention) = if create_streams {
+        let marker_writer = open_marker_writer(storage, scope_name, stream_name)?;
+        let retention = start_retention_maintainer(element, settings, storage, scope_name, stream_name)?;
+        (Some(marker_writer), Some(retention))
+    } else {
+        (None, None)
+    };
+    Ok(OpenedStreams { writers, marker_writer, retention })
+}
+
+/// The streams that the sink has rolled over from. They are finished without holding the state lock.
+struct PreviousStreams {
+    writer: DataWriter,
+    index_writer: Box<dyn ByteStreamWriter>,
+    caps_writer: Box<dyn ByteStreamWriter>,
+    final_timestamp: PravegaTimestamp,
+    final_offset: Option<u64>,
+    pending_index_record: Option<IndexRecord>,
+    batch: Option<Batch>,
+    retention_thread_stop_tx: Sender<()>,
+    retention_thread_handle: Option<JoinHandle<()>>,
+    scope_name: String,
+    stream_name: String,
+}
+
+/// Starts a thread that applies the retention policy in the settings to a stream.
+fn start_retention_maintainer(
+    element: &super::PravegaSink,
+    settings: &Settings,
+    storage: &dyn StorageBackend,
+    scope_name: &str,
+    stream_name: &str,
+) -> Result<(Sender<()>, Option<JoinHandle<()>>), gst::ErrorMessage> {
+    let retention_policy = RetentionPolicy::new(settings.retention_type, settings.retention_days, settings.retention_bytes).map_err(|error| {
+        gst::error_msg!(gst::ResourceError::Settings, ["Failed to create retention policy: {}", error])
+    })?;
+    gst_info!(CAT, obj: element, "start_retention_maintainer: retention_policy={:?}", retention_policy);
+
+    let archiver = match settings.retention_archive_uri.clone() {
+        Some(archive_uri) => {
+            gst_info!(CAT, obj: element, "start_retention_maintainer: retention_archive_uri={}", archive_uri);
+            let target = create_archive_target_from_uri(&archive_uri).map_err(|error| {
+                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create archive target: {}", error])
+            })?;
+            Some(Archiver::new(target))
+        },
+        None => None,
+    };
+    let retention_maintainer = RetentionMaintainer::new(element.clone(), settings.retention_maintenance_interval_seconds, retention_policy,
+        settings.retention_max_truncate_bytes, settings.retention_dry_run, archiver, storage, scope_name,
+        &get_index_stream_name(stream_name), stream_name).map_err(|error| {
+            gst::error_msg!(gst::ResourceError::OpenWrite, ["Failed to create retention maintainer: {}", error])
+        })?;
+    let (retention_thread_stop_tx, retention_thread_stop_rx) = mpsc::channel();
+    let retention_thread_handle = retention_maintainer.run(retention_thread_stop_rx);
+    Ok((retention_thread_stop_tx, retention_thread_handle))
+}
+
+/// Notifies the retention maintainer thread to stop and waits for it.
+fn stop_retention_maintainer(retention_thread_stop_tx: &Sender<()>, retention_thread_handle: &mut Option<JoinHandle<()>>) {
+    if let Some(handle) = retention_thread_handle.take() {
+        let _ = retention_thread_stop_tx.send(());
+        let _ = handle.join();
+    }
+}
+
+/// Converts a PTS or DTS to a PravegaTimestamp.
+/// In reference-timestamp-meta mode, reference_offset is added to the time.
+fn to_pravega_timestamp(element: &super::PravegaSink, timestamp_mode: TimestampMode, time: gst::ClockTime,
+        reference_offset: TimeDelta) -> PravegaTimestamp {
+    match timestamp_mode {
+        TimestampMode::RealtimeClock => realtime_clock_timestamp(element, time),
+        TimestampMode::Ntp => {
+            // When receiving from rtspsrc (ntp-sync=true ntp-time-source=running-time),
+            // pts will be the number of nanoseconds since the NTP epoch 1900-01-01 00:00:00 UTC
+            // of when the video frame was observed by the camera.
+            // Note: base_time is the value of the pipeline clock at the beginning of play. It is ignored.
+            PravegaTimestamp::from_ntp_nanoseconds(time.nseconds())
+        },
+        TimestampMode::Tai => {
+            PravegaTimestamp::from_nanoseconds(time.nseconds())
+        },
+        TimestampMode::ReferenceTimestampMeta => {
+            if reference_offset.is_some() {
+                clocktime_to_pravega(time) + reference_offset
+            } else {
+                // No buffer has had a reference timestamp meta yet.
+                realtime_clock_timestamp(element, time)
+            }
+        },
+    }
+}
+
+fn realtime_clock_timestamp(element: &super::PravegaSink, time: gst::ClockTime) -> PravegaTimestamp {
+    // pts is time between beginning of play and beginning of this buffer.
+    // base_time is the value of the pipeline clock (time since Unix epoch) at the beginning of play.
+    // The pipeline clock cannot distinguish a leap second from the following second,
+    // so a time in the second following a leap second is resolved to the earlier time, within the leap second.
+    match (element.base_time() + time).nseconds() {
+        Some(unix_nanoseconds) => {
+            match PravegaTimestamp::try_from_unix_nanoseconds_earlier(unix_nanoseconds) {
+                Ok(timestamp) => {
+                    if timestamp.is_leap_second() {
+                        gst_warning!(CAT, obj: element, "Unix time {} ns is ambiguous because of a leap second; using {}",
+                            unix_nanoseconds, timestamp);
+                    }
+                    timestamp
+                },
+                Err(error) => {
+                    gst_warning!(CAT, obj: element, "Unable to convert Unix time {} ns to a timestamp: {}", unix_nanoseconds, error);
+                    PravegaTimestamp::NONE
+                },
+            }
+        },
+        None => PravegaTimestamp::NONE,
+    }
+}
+
+/// Returns true if a stream name contains strftime conversion specifications such as %Y.
+fn is_stream_name_template(stream_name: &str) -> bool {
+    stream_name.contains('%')
+}
+
+/// Returns an error message if a stream name template contains an invalid conversion specification.
+fn validate_stream_name_template(template: &str) -> Result<(), String> {
+    if StrftimeItems::new(template).any(|item| item == Item::Error) {
+        return Err(format!("Stream name template '{}' is invalid", template));
+    }
+    Ok(())
+}
+
+/// Formats a stream name template with the UTC time of a timestamp.
+/// Returns None if the timestamp is not valid.
+fn format_stream_name(template: &str, timestamp: PravegaTimestamp) -> Option<String> {
+    timestamp.to_utc_datetime().map(|datetime| datetime.format(template).to_string())
+}
+
+/// Unwraps a data writer that failed and returns the bytes that it accepted but did not write to the stream,
+/// without attempting to write them again.
+/// Returns the offset of the first unwritten byte, the unwritten bytes that were committed, and the bytes
+/// in the open transaction. Bytes after end_offset, which must be at the end of an event, are discarded.
+fn take_unwritten_data(writer: DataWriter, end_offset: Option<u64>) -> Result<(u64, Vec<u8>, Vec<u8>), std::io::Error> {
+    let (mut counting_writer, mut transaction) = writer.into_parts();
+    let offset = counting_writer.seek(SeekFrom::Current(0))?;
+    let (_, buffered) = counting_writer.into_inner().into_parts();
+    let mut committed = buffered.unwrap_or_default();
+    let unwritten_offset = offset - committed.len() as u64;
+    let keep_len = end_offset.map_or(0, |end_offset| end_offset.saturating_sub(unwritten_offset) as usize);
+    committed.truncate(keep_len);
+    transaction.truncate(keep_len - committed.len());
+    Ok((unwritten_offset, committed, transaction))
+}
+
 impl PravegaSink {
     fn set_stream(
         &self,
@@ -342,140 +1194,1441 @@ impl PravegaSink {
         settings.controller = controller;
         Ok(())
     }
-}
 
-#[glib::object_subclass]
-impl ObjectSubclass for PravegaSink {
-    const NAME: &'static str = "PravegaSink";
-    type Type = super::PravegaSink;
-    type ParentType = gst_base::BaseSink;
+    /// Called periodically by the coalesce flusher thread.
+    /// If the pending batch is at least max_age old, it is written and the data stream is flushed.
+    /// In transactional mode, the batch becomes part of the open transaction and is committed with it.
+    /// Returns how long to wait before the next call.
+    fn flush_expired_batch(&self, element: &super::PravegaSink, max_age: Duration) -> Duration {
+        let (checksum, write_buffer_metadata, transactional) = {
+            let settings = self.settings.lock().unwrap();
+            (settings.checksum, settings.buffer_metadata, settings.transactional)
+        };
+        let mut state = self.state.lock().unwrap();
+        let result = if let State::Started {
+            ref mut writer,
+            ref mut final_offset,
+            ref mut batch,
+            ref spool,
+            ref spooling,
+            ..
+        } = *state {
+            let age = match batch.as_ref() {
+                Some(pending_batch) => pending_batch.created.elapsed(),
+                None => return max_age,
+            };
+            if age < max_age {
+                return max_age - age;
+            }
+            gst_debug!(CAT, obj: element, "flush_expired_batch: Writing batch of {} buffers after {:?}",
+                batch.as_ref().unwrap().buffer_lengths.len(), age);
+            let mut event_writer = EventWriter::with_checksum(checksum).with_buffer_metadata(write_buffer_metadata);
+            let result = (|| {
+                write_batch(element, batch.as_ref().unwrap(), &mut event_writer, writer, transactional)?;
+                *batch = None;
+                *final_offset = Some(writer.seek(SeekFrom::Current(0))?);
+                if !transactional {
+                    writer.flush()?;
+                }
+                Ok(())
+            })();
+            result.map_err(|error: std::io::Error| (error, spool.is_some() && spooling.is_none()))
+        } else {
+            return max_age;
+        };
+        drop(state);
+        match result {
+            Ok(()) => {},
+            Err((error, true)) => {
+                gst_warning!(CAT, obj: element, "flush_expired_batch: Failed to write batch: {}", error);
+                // The batch and any unwritten events are written to the spool.
+                if let Err(error) = self.start_spooling(element) {
+                    gst::element_error!(element, gst::ResourceError::Write, ["Failed to start spooling: {}", error]);
+                }
+            },
+            Err((error, false)) => {
+                gst::element_error!(element, gst::ResourceError::Write, ["Failed to write batch: {}", error]);
+            },
+        }
+        max_age
+    }
 
-    fn new() -> Self {
-        pravega_video::tracing::init();
-        Self {
-            settings: Mutex::new(Default::default()),
-            state: Mutex::new(Default::default()),
+    /// Replaces the Pravega writers with writers to the spool.
+    /// Complete events that were accepted by the Pravega data writer but not written, including any open transaction
+    /// and its index record, are moved to the spool. Buffers that are waiting to be coalesced will be written to the spool.
+    fn start_spooling(&self, element: &super::PravegaSink) -> Result<(), std::io::Error> {
+        let mut state = self.state.lock().unwrap();
+        if let State::Started {
+            ref mut writer,
+            ref mut index_writer,
+            ref mut caps_writer,
+            ref mut pending_caps,
+            ref mut written_caps,
+            ref mut last_index_time,
+            ref mut final_offset,
+            ref mut pending_index_record,
+            ref mut buffers_written,
+            buffer_size,
+            spool: Some(ref spool),
+            spool_connect_backoff,
+            ref mut spooling,
+            ..
+        } = *state {
+            gst_warning!(CAT, obj: element, "start_spooling: Unable to write to Pravega; writing to spool");
+            let spool_writers = spool.create_writers()?;
+            let old_writer = std::mem::replace(writer, new_data_writer(spool_writers.data, buffer_size)?);
+            *index_writer = spool_writers.index;
+            *caps_writer = spool_writers.caps;
+            let (unwritten_offset, committed, transaction) = take_unwritten_data(old_writer, *final_offset)?;
+            let spool_offset = writer.seek(SeekFrom::Current(0))?;
+            writer.write_all(&committed)?;
+            if !transaction.is_empty() {
+                writer.begin();
+                writer.write_all(&transaction)?;
+                writer.end_event();
+            }
+            gst_info!(CAT, obj: element, "start_spooling: Moved {} committed bytes and {} transaction bytes from Pravega offset {} to spool offset {}",
+                committed.len(), transaction.len(), unwritten_offset, spool_offset);
+            // The index record of the open transaction refers to the moved bytes.
+            // If its event was not moved, it will be written again when the failed buffer is written to the spool.
+            let unwritten_end = unwritten_offset + (committed.len() + transaction.len()) as u64;
+            *pending_index_record = pending_index_record.take()
+                .filter(|index_record| unwritten_offset <= index_record.offset && index_record.offset < unwritten_end)
+                .map(|index_record| IndexRecord {
+                    offset: spool_offset + index_record.offset - unwritten_offset,
+                    ..index_record
+                });
+            *final_offset = None;
+            begin_segment(pending_caps, written_caps, last_index_time, buffers_written);
+            *spooling = Some(SpoolingState::new(spool_connect_backoff));
+            post_spool_status(element, spool, true, 0);
         }
+        Ok(())
     }
-}
 
-impl ObjectImpl for PravegaSink {
-    fn constructed(&self, obj: &Self::Type) {
-        self.parent_constructed(obj);
-        obj.set_element_flags(gst::ElementFlags::PROVIDE_CLOCK | gst::ElementFlags::REQUIRE_CLOCK);
+    /// Called before each buffer is written, with the state lock held.
+    /// Rolls over to a new stream or reconnects to Pravega after spooling, if required.
+    /// The state lock is released while streams are created, opened, and sealed so that this network I/O
+    /// does not block add-marker, queries, and the flusher thread.
+    fn maintain_streams<'a>(
+        &'a self,
+        element: &super::PravegaSink,
+        mut state: MutexGuard<'a, State>,
+        buffer: &gst::Buffer,
+    ) -> (MutexGuard<'a, State>, Result<(), gst::FlowError>) {
+        if let Some((new_scope_name, new_stream_name)) = self.rollover_target(element, &mut state, buffer) {
+            return self.roll_over(element, state, new_scope_name, new_stream_name);
+        }
+        self.reconnect(element, state)
     }
 
-    fn properties() -> &'static [glib::ParamSpec] {
-        static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| { vec![
-            glib::ParamSpec::new_string(
-                PROPERTY_NAME_STREAM,
-                "Stream",
-                "scope/stream",
-                None,
-                glib::ParamFlags::WRITABLE,
-            ),
-            glib::ParamSpec::new_string(
-                PROPERTY_NAME_CONTROLLER,
-                "Controller",
-                "Pravega controller",
-                Some(DEFAULT_CONTROLLER),
-                glib::ParamFlags::WRITABLE,
-            ),
-            glib::ParamSpec::new_boolean(
-                PROPERTY_NAME_SEAL,
-                "Seal",
-                "Seal Pravega stream when stopped",
-                false,
-                glib::ParamFlags::WRITABLE,
-            ),
-            glib::ParamSpec::new_uint(
-                PROPERTY_NAME_BUFFER_SIZE,
-                "Buffer size",
-                "Size of buffer in number of bytes",
-                0,
-                std::u32::MAX,
-                DEFAULT_BUFFER_SIZE.try_into().unwrap(),
-                glib::ParamFlags::WRITABLE,
-            ),
-            glib::ParamSpec::new_enum(
-                PROPERTY_NAME_TIMESTAMP_MODE,
-                "Timestamp mode",
-                "Timestamp mode used by the input",
-                TimestampMode::static_type(),
-                DEFAULT_TIMESTAMP_MODE as i32,
-                glib::ParamFlags::WRITABLE,
-            ),
-            glib::ParamSpec::new_double(
-                PROPERTY_NAME_INDEX_MIN_SEC,
-                "Minimum index interval",
-                "The minimum number of seconds between index records",
-                0.0,
-                std::f64::INFINITY,
-                DEFAULT_INDEX_MIN_SEC.try_into().unwrap(),
-                glib::ParamFlags::WRITABLE,
-            ),
-            glib::ParamSpec::new_double(
-                PROPERTY_NAME_INDEX_MAX_SEC,
-                "Maximum index interval",
-                "Force index record if one has not been created in this many seconds, even at delta frames.",
-                0.0,
-                std::f64::INFINITY,
-                DEFAULT_INDEX_MAX_SEC.try_into().unwrap(),
-                glib::ParamFlags::WRITABLE,
-            ),
-            glib::ParamSpec::new_boolean(
-                PROPERTY_NAME_ALLOW_CREATE_SCOPE,
-                "Allow create scope",
-                "If true, the Pravega scope will be created if needed.",
-                true,
-                glib::ParamFlags::WRITABLE,
-            ),
-            glib::ParamSpec::new_string(
-                PROPERTY_NAME_KEYCLOAK_FILE,
+    /// While spooling, periodically attempts to reconnect to Pravega. When successful, the spool is replayed and
+    /// subsequent buffers are written to Pravega.
+    /// The streams are opened without holding the state lock. The spool is replayed while holding the state lock
+    /// so that nothing is written to the spool or to Pravega until the replay is complete.
+    fn reconnect<'a>(
+        &'a self,
+        element: &super::PravegaSink,
+        state: MutexGuard<'a, State>,
+    ) -> (MutexGuard<'a, State>, Result<(), gst::FlowError>) {
+        let now = Instant::now();
+        let target = match *state {
+            State::Started {
+                ref storage,
+                ref scope_name,
+                ref stream_name,
+                spooling: Some(ref spooling_state),
+                streams_created,
+                ..
+            } if now >= spooling_state.next_connect_time => Some((storage.clone(), scope_name.clone(), stream_name.clone(), !streams_created)),
+            _ => None,
+        };
+        let (storage, scope_name, stream_name, create_streams) = match target {
+            Some(target) => target,
+            None => return (state, Ok(())),
+        };
+        drop(state);
+
+        gst_debug!(CAT, obj: element, "reconnect: Attempting to reconnect");
+        let settings = self.settings.lock().unwrap().clone();
+        let opened = open_streams(element, &settings, storage.as_ref(), &scope_name, &stream_name, create_streams);
+
+        let mut state = self.state.lock().unwrap();
+        if let State::Started {
+            ref mut writer,
+            ref mut index_writer,
+            ref mut caps_writer,
+            ref mut marker_writer,
+            ref mut pending_caps,
+            ref mut written_caps,
+            ref mut last_index_time,
+            ref mut final_offset,
+            ref mut pending_index_record,
+            ref mut buffers_written,
+            ref mut retention_thread_stop_tx,
+            ref mut retention_thread_handle,
+            buffer_size,
+            spool: Some(ref spool),
+            ref mut spooling,
+            ref mut streams_created,
+            ..
+        } = *state {
+            if let Some(spooling_state) = spooling.as_mut() {
+                let result = opened
+                    .map_err(|error| std::io::Error::new(ErrorKind::Other, format!("{:?}", error)))
+                    .and_then(|opened| {
+                        if let Some((stop_tx, handle)) = opened.retention {
+                            *marker_writer = opened.marker_writer;
+                            *retention_thread_stop_tx = stop_tx;
+                            *retention_thread_handle = handle;
+                            *streams_created = true;
+                            gst_info!(CAT, obj: element, "reconnect: Created streams");
+                        }
+                        let mut primary = opened.writers;
+                        // Write everything, including any open transaction, to the spool before replaying it.
+                        commit_transaction(element, writer, index_writer, pending_index_record)?;
+                        writer.flush()?;
+                        index_writer.flush()?;
+                        caps_writer.flush()?;
+                        let stats = spool.replay(&mut primary)?;
+                        *writer = new_data_writer(primary.data, buffer_size)?;
+                        *index_writer = primary.index;
+                        *caps_writer = primary.caps;
+                        Ok(stats)
+                    });
+                match result {
+                    Ok(stats) => {
+                        gst_info!(CAT, obj: element, "reconnect: Reconnected and replayed spool: {:?}", stats);
+                        let dropped_buffers = spooling_state.dropped_buffers;
+                        *final_offset = None;
+                        begin_segment(pending_caps, written_caps, last_index_time, buffers_written);
+                        *spooling = None;
+                        post_spool_status(element, spool, false, dropped_buffers);
+                    },
+                    Err(error) => {
+                        gst_info!(CAT, obj: element, "reconnect: Unable to reconnect; will retry in {:?}: {}",
+                            spooling_state.connect_backoff, error);
+                        spooling_state.next_connect_time = now + spooling_state.connect_backoff;
+                        spooling_state.connect_backoff = cmp::min(2 * spooling_state.connect_backoff, SPOOL_MAX_CONNECT_BACKOFF);
+                    },
+                }
+            }
+        }
+        (state, Ok(()))
+    }
+
+    /// Called before each buffer is written while spooling.
+    /// Periodically posts the spool status.
+    /// Returns true if the buffer should be dropped because the spool is full.
+    fn maintain_spool(&self, element: &super::PravegaSink, state: &mut State) -> Result<bool, gst::FlowError> {
+        if let State::Started {
+            ref mut pending_caps,
+            ref mut written_caps,
+            ref mut last_index_time,
+            ref mut buffers_written,
+            spool: Some(ref spool),
+            spooling: Some(ref mut spooling_state),
+            ..
+        } = *state {
+            let now = Instant::now();
+            if now >= spooling_state.next_status_time {
+                post_spool_status(element, spool, true, spooling_state.dropped_buffers);
+                spooling_state.next_status_time = now + SPOOL_STATUS_INTERVAL;
+            }
+            let is_full = spool.is_full().map_err(|error| {
+                gst::element_error!(element, gst::ResourceError::Read, ["Failed to get spool size: {}", error]);
+                gst::FlowError::Error
+            })?;
+            if is_full {
+                if spooling_state.dropped_buffers == 0 {
+                    gst_warning!(CAT, obj: element, "maintain_spool: Spool is full; dropping buffers until reconnected");
+                }
+                spooling_state.dropped_buffers += 1;
+                // The next buffer written will be marked as a discontinuity.
+                begin_segment(pending_caps, written_caps, last_index_time, buffers_written);
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Returns the stream to roll over to before writing the buffer.
+    /// If the buffer is a key frame and a stream switch has been requested or the stream name template
+    /// produces a new name for the buffer timestamp, the sink rolls over to the new stream.
+    /// Rolling over is deferred while spooling.
+    fn rollover_target(&self, element: &super::PravegaSink, state: &mut State, buffer: &gst::Buffer) -> Option<(String, String)> {
+        if buffer.flags().contains(gst::BufferFlags::DELTA_UNIT) {
+            return None;
+        }
+        let timestamp_mode = self.settings.lock().unwrap().timestamp_mode;
+        let reference_offset = *self.reference_offset.lock().unwrap();
+        match *state {
+            State::Started {
+                ref scope_name,
+                ref stream_name,
+                ref spooling,
+                ref stream_template,
+                ref mut pending_stream_switch,
+                ..
+            } => {
+                if spooling.is_some() {
+                    return None;
+                }
+                let new_stream = match pending_stream_switch.take() {
+                    Some(new_stream) => Some(new_stream),
+                    None => stream_template.as_ref().and_then(|stream_template| {
+                        let timestamp = to_pravega_timestamp(element, timestamp_mode, buffer.pts(), reference_offset);
+                        format_stream_name(stream_template, timestamp).map(|stream_name| (scope_name.clone(), stream_name))
+                    }),
+                };
+                new_stream.filter(|(new_scope_name, new_stream_name)| new_scope_name != scope_name || new_stream_name != stream_name)
+            },
+            State::Stopped => None,
+        }
+    }
+
+    /// Begins writing to new streams and then finishes the previous streams as stop does, optionally sealing them.
+    /// The next buffer will be written as if it were the first buffer written by this instance.
+    /// The new streams are opened, and the previous streams are finished, without holding the state lock.
+    fn roll_over<'a>(
+        &'a self,
+        element: &super::PravegaSink,
+        state: MutexGuard<'a, State>,
+        new_scope_name: String,
+        new_stream_name: String,
+    ) -> (MutexGuard<'a, State>, Result<(), gst::FlowError>) {
+        let target = match *state {
+            State::Started { ref storage, buffer_size, .. } => Some((storage.clone(), buffer_size)),
+            State::Stopped => None,
+        };
+        let (storage, buffer_size) = match target {
+            Some(target) => target,
+            None => return (state, Ok(())),
+        };
+        drop(state);
+        let new_stream = format!("{}/{}", new_scope_name, new_stream_name);
+
+        let settings = self.settings.lock().unwrap().clone();
+        let opened = open_streams(element, &settings, storage.as_ref(), &new_scope_name, &new_stream_name, true)
+            .and_then(|mut opened| {
+                let new_spool = open_spool(element, &settings, &new_scope_name, &new_stream_name, Some(&mut opened.writers))?;
+                let MediaStreamWriters { data, index, caps } = opened.writers;
+                let new_writer = new_data_writer(data, buffer_size).map_err(|error| {
+                    gst::error_msg!(gst::ResourceError::OpenWrite, ["Failed to open writer for data stream: {}", error])
+                })?;
+                Ok((new_writer, index, caps, opened.marker_writer, opened.retention, new_spool))
+            });
+        let (new_writer, new_index_writer, new_caps_writer, new_marker_writer, new_retention, new_spool) = match opened {
+            Ok(opened) => opened,
+            Err(error) => {
+                element.post_error_message(error);
+                return (self.state.lock().unwrap(), Err(gst::FlowError::Error));
+            },
+        };
+        let (new_retention_thread_stop_tx, new_retention_thread_handle) = new_retention.unwrap_or_else(|| (mpsc::channel().0, None));
+
+        let mut state = self.state.lock().unwrap();
+        let previous = match *state {
+            State::Started {
+                ref mut writer,
+                ref mut index_writer,
+                ref mut caps_writer,
+                ref mut marker_writer,
+                ref mut pending_caps,
+                ref mut written_caps,
+                ref mut last_index_time,
+                ref mut final_timestamp,
+                ref mut final_offset,
+                ref mut pending_index_record,
+                ref mut batch,
+                ref mut buffers_written,
+                ref mut retention_thread_stop_tx,
+                ref mut retention_thread_handle,
+                ref mut scope_name,
+                ref mut stream_name,
+                ref mut spool,
+                ..
+            } => {
+                gst_info!(CAT, obj: element, "roll_over: Rolling over from {}/{} to {}/{}", scope_name, stream_name, new_scope_name, new_stream_name);
+                let previous = PreviousStreams {
+                    writer: std::mem::replace(writer, new_writer),
+                    index_writer: std::mem::replace(index_writer, new_index_writer),
+                    caps_writer: std::mem::replace(caps_writer, new_caps_writer),
+                    final_timestamp: std::mem::replace(final_timestamp, PravegaTimestamp::NONE),
+                    final_offset: final_offset.take(),
+                    pending_index_record: pending_index_record.take(),
+                    batch: batch.take(),
+                    retention_thread_stop_tx: std::mem::replace(retention_thread_stop_tx, new_retention_thread_stop_tx),
+                    retention_thread_handle: std::mem::replace(retention_thread_handle, new_retention_thread_handle),
+                    scope_name: std::mem::replace(scope_name, new_scope_name),
+                    stream_name: std::mem::replace(stream_name, new_stream_name),
+                };
+                *marker_writer = new_marker_writer;
+                *spool = new_spool;
+                begin_segment(pending_caps, written_caps, last_index_time, buffers_written);
+                Some(previous)
+            },
+            // The sink was stopped while the new streams were being opened.
+            State::Stopped => None,
+        };
+        let mut previous = match previous {
+            Some(previous) => previous,
+            None => return (state, Ok(())),
+        };
+        drop(state);
+
+        let mut event_writer = EventWriter::with_checksum(settings.checksum).with_buffer_metadata(settings.buffer_metadata);
+        let result = finish_streams(element, &mut previous.writer, &mut previous.index_writer, &mut previous.caps_writer,
+            &mut previous.final_timestamp, &mut previous.final_offset, &mut previous.pending_index_record, &mut previous.batch,
+            &mut event_writer, settings.transactional, settings.seal_on_rollover);
+        stop_retention_maintainer(&previous.retention_thread_stop_tx, &mut previous.retention_thread_handle);
+        if let Err(error) = result {
+            element.post_error_message(error);
+            return (self.state.lock().unwrap(), Err(gst::FlowError::Error));
+        }
+
+        let structure = gst::Structure::builder(ROLLOVER_MESSAGE_NAME)
+            .field("previous-stream", &format!("{}/{}", previous.scope_name, previous.stream_name))
+            .field("stream", &new_stream)
+            .field("sealed", &settings.seal_on_rollover)
+            .build();
+        let _ = element.post_message(gst::message::Element::builder(structure).src(Some(element)).build());
+        (self.state.lock().unwrap(), Ok(()))
+    }
+
+    /// Handles the switch-stream action signal.
+    /// If started, the sink will roll over to the new stream at the next key frame.
+    /// Otherwise, this is equivalent to setting the stream property.
+    fn switch_stream(&self, element: &super::PravegaSink, stream: &str) -> bool {
+        let components: Vec<&str> = stream.split('/').collect();
+        if components.len() != 2 {
+            gst_error!(CAT, obj: element, "switch_stream: stream '{}' is formatted incorrectly. It must be specified as scope/stream.", stream);
+            return false;
+        }
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            State::Started { ref mut pending_stream_switch, .. } => {
+                gst_info!(CAT, obj: element, "switch_stream: Will switch to {} at the next key frame", stream);
+                *pending_stream_switch = Some((components[0].to_owned(), components[1].to_owned()));
+                true
+            },
+            State::Stopped => {
+                drop(state);
+                self.set_stream(element, Some(stream.to_owned())).is_ok()
+            },
+        }
+    }
+
+    /// In reference-timestamp-meta mode, updates the reference offset from the buffer's reference timestamp meta.
+    /// Returns the current reference offset.
+    fn update_reference_offset(&self, element: &super::PravegaSink, buffer: &gst::Buffer) -> TimeDelta {
+        let mut reference_offset = self.reference_offset.lock().unwrap();
+        if self.settings.lock().unwrap().timestamp_mode != TimestampMode::ReferenceTimestampMeta {
+            return *reference_offset;
+        }
+        let pts = clocktime_to_pravega(buffer.pts());
+        let reference = reference_timestamp(buffer);
+        if pts.is_some() && reference.is_some() {
+            let offset = reference - pts;
+            if reference_offset.is_none() {
+                gst_info!(CAT, obj: element, "Using reference timestamp meta. First reference timestamp is {:?} at PTS {}.",
+                    reference, buffer.pts());
+            }
+            gst_trace!(CAT, obj: element, "update_reference_offset: reference={:?}, pts={}, offset={}", reference, buffer.pts(), offset);
+            *reference_offset = offset;
+        }
+        *reference_offset
+    }
+
+    /// Writes a buffer to the data stream, along with any index and caps records.
+    /// This is called by render or, when async-write is enabled, by the writer thread.
+    fn write_buffer(
+        &self,
+        element: &super::PravegaSink,
+        buffer: &gst::Buffer,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        self.write_buffers(element, std::slice::from_ref(buffer))
+    }
+
+    /// Writes buffers in order while holding the state lock.
+    /// The lock is only released to roll over, to reconnect after spooling, or to start spooling.
+    /// Each buffer is handled exactly as if it were written by write_buffer, so batches are still broken at
+    /// index records, discontinuities, and caps changes.
+    fn write_buffers(
+        &self,
+        element: &super::PravegaSink,
+        buffers: &[gst::Buffer],
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        gst_trace!(CAT, obj: element, "write_buffers: BEGIN: Writing {} buffers", buffers.len());
+        // If a storage error occurs and a spool is available, storage_failed will be set and an error will not be posted.
+        let render_buffer = |state: &mut State, buffer: &gst::Buffer, reference_offset: TimeDelta, storage_failed: &Cell<bool>| {
+            gst_trace!(CAT, obj: element, "write_buffer: Writing {:?}", buffer);
+            let drop_buffer = self.maintain_spool(element, state)?;
+            if drop_buffer {
+                gst_log!(CAT, obj: element, "write_buffer: Dropping buffer because spool is full");
+                return Ok(gst::FlowSuccess::Ok);
+            }
+            let (writer,
+                index_writer,
+                caps_writer,
+                pending_caps,
+                written_caps,
+                first_valid_time,
+                last_index_time,
+                final_timestamp,
+                final_offset,
+                pending_index_record,
+                batch,
+                buffers_written,
+                spool_fallback) = match *state {
+                State::Started {
+                    ref mut writer,
+                    ref mut index_writer,
+                    ref mut caps_writer,
+                    ref mut pending_caps,
+                    ref mut written_caps,
+                    ref mut first_valid_time,
+                    ref mut last_index_time,
+                    ref mut final_timestamp,
+                    ref mut final_offset,
+                    ref mut pending_index_record,
+                    ref mut batch,
+                    ref mut buffers_written,
+                    ref spool,
+                    ref spooling,
+                    ..
+                } => (writer,
+                    index_writer,
+                    caps_writer,
+                    pending_caps,
+                    written_caps,
+                    first_valid_time,
+                    last_index_time,
+                    final_timestamp,
+                    final_offset,
+                    pending_index_record,
+                    batch,
+                    buffers_written,
+                    spool.is_some() && spooling.is_none()),
+                State::Stopped => {
+                    gst::element_error!(element, gst::CoreError::Failed, ["Not started yet"]);
+                    return Err(gst::FlowError::Error);
+                }
+            };
+
+            let storage_error = |message: &str, error: std::io::Error| {
+                if spool_fallback {
+                    gst_warning!(CAT, obj: element, "write_buffer: {}: {}", message, error);
+                    storage_failed.set(true);
+                } else {
+                    gst::element_error!(element, gst::ResourceError::Write, ["{}: {}", message, error]);
+                }
+                gst::FlowError::Error
+            };
+
+            let pts = buffer.pts();
+            let duration = buffer.duration();
+
+            let map = buffer.map_readable().map_err(|_| {
+                gst::element_error!(element, gst::CoreError::Failed, ["Failed to map buffer"]);
+                gst::FlowError::Error
+            })?;
+            let payload = map.as_ref();
+
+            let (timestamp_mode, index_min_nanos, index_max_nanos, checksum, write_buffer_metadata, transactional,
+                    coalesce_max_bytes, coalesce_max_nanos) = {
+                let settings = self.settings.lock().unwrap();
+                (settings.timestamp_mode, settings.index_min_nanos, settings.index_max_nanos, settings.checksum,
+                    settings.buffer_metadata, settings.transactional, settings.coalesce_max_bytes, settings.coalesce_max_nanos)
+            };
+            let mut event_writer = EventWriter::with_checksum(checksum).with_buffer_metadata(write_buffer_metadata);
+
+            let to_pravega_timestamp = |time: gst::ClockTime| to_pravega_timestamp(element, timestamp_mode, time, reference_offset);
+            let timestamp = to_pravega_timestamp(pts);
+
+            if first_valid_time.is_none() {
+                *first_valid_time = timestamp;
+            }
+
+            // We only want to include key frames (non-delta units) in the index.
+            // However, if no key frame has been received in a while, force an index record.
+            // This is required for nvv4l2h264enc because it identifies all buffers as DELTA_UNIT.
+            let buffer_flags = buffer.flags();
+            let is_delta_unit = buffer_flags.contains(gst::BufferFlags::DELTA_UNIT);
+            let random_access = !is_delta_unit;
+            let include_in_index = match timestamp.nanoseconds() {
+                Some(timestamp) => {
+                    match last_index_time.nanoseconds() {
+                        Some(last_index_time) => {
+                            let interval_sec = u64_to_i64_saturating_sub(timestamp, last_index_time) as f64 * 1e-9;
+                            if is_delta_unit {
+                                // We are at a delta frame.
+                                if timestamp > last_index_time + index_max_nanos {
+                                    gst_fixme!(CAT, obj: element,
+                                        "write_buffer: Forcing index record at delta unit because no key frame has been received for {} sec", interval_sec);
+                                    true
+                                } else {
+                                    false
+                                }
+                            } else {
+                                // We are at a key frame.
+                                if timestamp < last_index_time + index_min_nanos {
+                                    gst_debug!(CAT, obj: element,
+                                        "write_buffer: Skipping creation of index record because an index record was created {} sec ago", interval_sec);
+                                    false
+                                } else {
+                                    gst_debug!(CAT, obj: element,
+                                        "write_buffer: Creating index record at key frame; last index record was created {} sec ago", interval_sec);
+                                    true
+                                }
+                            }
+                        },
+                        None => {
+                            // An index record has not been written by this element yet.
+                            // The timestamp is valid.
+                            if random_access {
+                                true
+                            } else {
+                                // We are at a delta frame.
+                                // Do not write an index record. unless no index record has been written for a while.
+                                match first_valid_time.nanoseconds() {
+                                    Some(first_valid_time) => {
+                                        if timestamp > first_valid_time + index_max_nanos {
+                                            let interval_sec = u64_to_i64_saturating_sub(timestamp, first_valid_time) as f64 * 1e-9;
+                                            gst_fixme!(CAT, obj: element,
+                                                "write_buffer: Forcing first index record at delta unit because no key frame has been received for {} sec", interval_sec);
+                                            true
+                                        } else {
+                                            false
+                                        }
+                                    },
+                                    None => {
+                                        // Should be unreachable.
+                                        false
+                                    },
+                                }
+                            }
+                        },
+                    }
+                },
+                None => {
+                    // Buffer has an invalid timestamp. Never index.
+                    false
+                },
+            };
+
+            // Small buffers are coalesced into a batch that is written as a single event.
+            // A buffer that is indexed, is a discontinuity, or has new caps begins a new batch,
+            // so the pending batch is written before this buffer.
+            let sync_after = buffer_flags.contains(gst::BufferFlags::SYNC_AFTER);
+            let coalesce = coalesce_max_bytes > 0 && payload.len() <= coalesce_max_bytes && !sync_after;
+            let new_caps = pending_caps.is_some() && *pending_caps != *written_caps;
+            let add_to_batch = coalesce && match batch {
+                Some(batch) => {
+                    !include_in_index
+                    && !buffer_flags.contains(gst::BufferFlags::DISCONT)
+                    && !buffer_flags.contains(gst::BufferFlags::RESYNC)
+                    && *buffers_written > 0
+                    && !new_caps
+                    && batch.payload.len() + payload.len() <= coalesce_max_bytes
+                    && batch.buffer_lengths.len() < EventWithHeader::max_batch_length()
+                    && match (timestamp.nanoseconds(), batch.timestamp.nanoseconds()) {
+                        (Some(timestamp), Some(batch_timestamp)) => timestamp < batch_timestamp.saturating_add(coalesce_max_nanos),
+                        _ => true,
+                    }
+                },
+                None => false,
+            };
+            if !add_to_batch {
+                // The batch is kept until it has been written so that it can be written to the spool upon failure.
+                if let Some(pending_batch) = batch.as_ref() {
+                    write_batch(element, pending_batch, &mut event_writer, writer, transactional).map_err(|error| {
+                        storage_error("Failed to write batch", error)
+                    })?;
+                    *batch = None;
+                    *final_offset = Some(writer.seek(SeekFrom::Current(0)).map_err(|error| {
+                        storage_error("Failed to get position of Pravega data stream", error)
+                    })?);
+                }
+            }
+
+            // Get the writer offset before writing. This offset will be used in the index.
+            let writer_offset = writer.seek(SeekFrom::Current(0)).map_err(|error| {
+                storage_error("Failed to get position of Pravega data stream", error)
+            })?;
+
+            gst_log!(CAT, obj: element, "write_buffer: timestamp={:?}, pts={}, base_time={}, duration={}, size={}, writer_offset={}",
+                timestamp, pts, element.base_time(), buffer.duration(), buffer.size(), writer_offset);
+
+            // Per the index constraints defined in index.rs, if we are writing an index record now,
+            // we must flush any data writes prior to this buffer, so that reads do not block waiting on this writer.
+            // In transactional mode, this is when the open transaction is committed, followed by its index record.
+            let flush = include_in_index;
+            if flush {
+                if transactional {
+                    commit_transaction(element, writer, index_writer, pending_index_record).map_err(|error| {
+                        storage_error("Failed to commit transaction", error)
+                    })?;
+                } else {
+                    writer.flush().map_err(|error| {
+                        storage_error("Failed to flush Pravega data stream", error)
+                    })?;
+                }
+            }
+
+            // Write caps record if the caps have changed.
+            // This is written before the buffer so that readers will find the caps record when they read the buffer.
+            // Pending caps are cleared only after they are written so that they can be written to the spool upon failure.
+            let caps_to_write = pending_caps.clone().filter(|caps| written_caps.as_ref() != Some(caps));
+            if let Some(caps) = caps_to_write {
+                let caps_record = CapsRecord::new(timestamp, writer_offset, caps);
+                let mut caps_record_writer = CapsRecordWriter::new();
+                caps_record_writer.write(&caps_record, caps_writer).map_err(|err| {
+                    storage_error("Failed to write caps", err)
+                })?;
+                caps_writer.flush().map_err(|error| {
+                    storage_error("Failed to flush Pravega caps stream", error)
+                })?;
+                gst_info!(CAT, obj: element, "write_buffer: Wrote caps record {:?}", caps_record);
+                *written_caps = Some(caps_record.caps);
+            }
+            *pending_caps = None;
+
+            // Record a discontinuity if any of the following are true:
+            //   1) upstream has indicated a discontinuity (or resync) in the buffer
+            //   2) this will be the first buffer written to the data stream from this instance
+            //   3) this will be the first index record written from this instance
+            // A caps change is not a discontinuity. pravegasrc finds the new caps record by the offset of the buffer.
+            let discontinuity =
+                   buffer_flags.contains(gst::BufferFlags::DISCONT)
+                || buffer_flags.contains(gst::BufferFlags::RESYNC)
+                || *buffers_written == 0
+                || (include_in_index && last_index_time.nanoseconds().is_none());
+            if discontinuity {
+                gst_debug!(CAT, obj: element, "write_buffer: Recording discontinuity");
+            }
+
+            // Write index record.
+            // We write the index record before the buffer so that any readers blocked on reading the
+            // index will unblock as soon as possible.
+            // In transactional mode, the index record is held until the transaction is committed.
+            if include_in_index && transactional {
+                let index_record = IndexRecord::new(timestamp, writer_offset,
+                    random_access, discontinuity);
+                gst_debug!(CAT, obj: element, "write_buffer: Beginning transaction for index record {:?}", index_record);
+                *pending_index_record = Some(index_record);
+                *last_index_time = timestamp;
+            } else if include_in_index {
+                let index_record = IndexRecord::new(timestamp, writer_offset,
+                    random_access, discontinuity);
+                let mut index_record_writer = IndexRecordWriter::new();
+                index_record_writer.write(&index_record, index_writer).map_err(|err| {
+                    storage_error("Failed to write index", err)
+                })?;
+                gst_debug!(CAT, obj: element, "write_buffer: Wrote index record {:?}", index_record);
+                *last_index_time = timestamp;
+            }
+
+            // Write buffer to Pravega byte stream.
+            // If buffer is greater than ~8 MiB, it will be fragmented into multiple atomic writes, each with an EventHeader.
+            // The fragments are marked with the continuation and more-fragments flags so that pravegasrc can reassemble them.
+            // In the event of an ungraceful pravegasink termination before all fragments are written,
+            // pravegasrc will discard the incomplete chain of fragments.
+            // It will also mark the first buffer after starting as a discontinuity,
+            // allowing elements downstream from pravegasrc to reinitialize.
+            // Small buffers are not written now. Instead, they are appended to the pending batch.
+            let buffer_metadata = if write_buffer_metadata {
+                Some(BufferMetadata {
+                    dts: to_pravega_timestamp(buffer.dts()),
+                    duration: duration.nanoseconds(),
+                    header: buffer_flags.contains(gst::BufferFlags::HEADER),
+                    gap: buffer_flags.contains(gst::BufferFlags::GAP),
+                    droppable: buffer_flags.contains(gst::BufferFlags::DROPPABLE),
+                    marker: buffer_flags.contains(gst::BufferFlags::MARKER),
+                })
+            } else {
+                None
+            };
+            if coalesce {
+                // This buffer will be written later as part of a batch.
+                let batch = batch.get_or_insert_with(|| Batch {
+                    timestamp,
+                    include_in_index,
+                    random_access,
+                    discontinuity,
+                    buffer_metadata,
+                    payload: Vec::new(),
+                    buffer_lengths: Vec::new(),
+                    created: Instant::now(),
+                });
+                batch.payload.extend_from_slice(payload);
+                batch.buffer_lengths.push(payload.len());
+            } else {
+                if transactional {
+                    writer.begin();
+                }
+                let mut pos_to_write = 0;
+                loop {
+                    let length_to_write = usize::min(payload.len() - pos_to_write, event_writer.max_payload_size());
+                    if length_to_write == 0 { break };
+                    let continuation = pos_to_write > 0;
+                    let more_fragments = pos_to_write + length_to_write < payload.len();
+                    let event = if !continuation {
+                        // Buffer metadata is only written with the first fragment.
+                        let mut event = EventWithHeader::new_fragment(&payload[pos_to_write..pos_to_write+length_to_write],
+                            timestamp, include_in_index, random_access, discontinuity, false, more_fragments);
+                        event.header.buffer_metadata = buffer_metadata;
+                        event
+                    } else {
+                        gst_debug!(CAT, obj: element, "write_buffer: buffer exceeds atomic write size and has been fragmented; writing additional payload of {} bytes", length_to_write);
+                        // Additional writes must not be indexed and must not be marked as a discontinuity as that would reset the demuxer.
+                        EventWithHeader::new_fragment(&payload[pos_to_write..pos_to_write+length_to_write],
+                            timestamp, false, false, false, true, more_fragments)
+                    };
+                    gst_memdump!(CAT, obj: element, "write_buffer: writing event={:?}", event);
+                    event_writer.write(&event, writer).map_err(|err| {
+                        storage_error("Failed to write buffer", err)
+                    })?;
+                    writer.end_event();
+                    pos_to_write += length_to_write;
+                }
+            }
+            *buffers_written += 1;
+
+            // Get the writer offset after writing.
+            let writer_offset_end = writer.seek(SeekFrom::Current(0)).map_err(|error| {
+                storage_error("Failed to get position of Pravega data stream", error)
+            })?;
+            gst_trace!(CAT, obj: element, "write_buffer: wrote {} bytes from offset {} to {}",
+                writer_offset_end - writer_offset, writer_offset, writer_offset_end);
+
+            // Flush after writing if the buffer contains the SYNC_AFTER flag. This is normally not used.
+            if sync_after {
+                writer.flush().map_err(|error| {
+                    storage_error("Failed to flush Pravega data stream", error)
+                })?;
+                index_writer.flush().map_err(|error| {
+                    storage_error("Failed to flush Pravega index stream", error)
+                })?;
+                gst_debug!(CAT, obj: element, "write_buffer: Streams flushed because SYNC_AFTER flag was set");
+            }
+
+            // Maintain values that may be written to the index on end-of-stream.
+            // Per the index constraints defined in index.rs, the timestamp in the index record must
+            // be strictly greater than the timestamp in the data stream.
+            if timestamp.is_some() {
+                // If duration of the buffer is reported as 0, we record it as if it had a 1 nanosecond duration.
+                let duration = cmp::max(1, duration.nanoseconds().unwrap_or_default());
+                *final_timestamp = PravegaTimestamp::from_nanoseconds(
+                    timestamp.nanoseconds().map(|t| t + duration));
+            }
+            *final_offset = Some(writer_offset_end);
+
+            Ok(gst::FlowSuccess::Ok)
+        };
+        let storage_failed = Cell::new(false);
+        let mut result = Ok(gst::FlowSuccess::Ok);
+        let mut state = self.state.lock().unwrap();
+        for buffer in buffers {
+            let reference_offset = self.update_reference_offset(element, buffer);
+            let (guard, maintained) = self.maintain_streams(element, state, buffer);
+            state = guard;
+            result = maintained.and_then(|_| render_buffer(&mut state, buffer, reference_offset, &storage_failed));
+            if storage_failed.replace(false) {
+                // Write this buffer and subsequent buffers to the spool.
+                drop(state);
+                let spooling_result = self.start_spooling(element);
+                state = self.state.lock().unwrap();
+                result = match spooling_result {
+                    Ok(()) => render_buffer(&mut state, buffer, reference_offset, &storage_failed),
+                    Err(error) => {
+                        gst::element_error!(element, gst::ResourceError::Write, ["Failed to start spooling: {}", error]);
+                        Err(gst::FlowError::Error)
+                    },
+                };
+            }
+            if result.is_err() {
+                break;
+            }
+        }
+        gst_trace!(CAT, obj: element, "write_buffers: END: result={:?}", result);
+        result
+    }
+
+    /// Passes a buffer to the writer thread.
+    fn queue_buffer(
+        &self,
+        element: &super::PravegaSink,
+        queue: &WriteQueue<QueuedBuffer>,
+        queued_buffer: QueuedBuffer,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let size = queued_buffer.buffer.size();
+        let random_access = !queued_buffer.buffer.flags().contains(gst::BufferFlags::DELTA_UNIT);
+        match queue.push(queued_buffer, size, random_access) {
+            PushResult::Queued(0) => Ok(gst::FlowSuccess::Ok),
+            PushResult::Queued(dropped) => {
+                gst_warning!(CAT, obj: element, "queue_buffer: Write queue is full; dropped {} buffers from the oldest group of pictures", dropped);
+                Ok(gst::FlowSuccess::Ok)
+            },
+            PushResult::Dropped => {
+                gst_debug!(CAT, obj: element, "queue_buffer: Write queue is full; dropped buffer; {} buffers dropped so far", queue.dropped());
+                Ok(gst::FlowSuccess::Ok)
+            },
+            PushResult::Flushing => Err(gst::FlowError::Flushing),
+            PushResult::Closed => {
+                // The writer thread has already posted an error.
+                Err(gst::FlowError::Error)
+            },
+        }
+    }
+
+    /// Handles a buffer when trigger-mode is enabled.
+    /// Outside of a recording, the buffer is added to the pre-roll buffer.
+    /// When a recording is triggered, the pre-roll buffer is written, beginning with a discontinuity.
+    fn record_buffer(
+        &self,
+        element: &super::PravegaSink,
+        recorder: &mut Recorder,
+        queue: Option<&WriteQueue<QueuedBuffer>>,
+        queued_buffer: QueuedBuffer,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let pts = queued_buffer.buffer.pts().nseconds();
+        let random_access = !queued_buffer.buffer.flags().contains(gst::BufferFlags::DELTA_UNIT);
+        let triggered = self.trigger_pending.swap(false, Ordering::SeqCst);
+        // The post-roll period is measured from the first buffer after the trigger.
+        let trigger_stop_time = match (triggered, pts) {
+            (true, Some(pts)) => Some(pts.saturating_add(recorder.post_roll_nanos)),
+            (true, None) => {
+                gst_debug!(CAT, obj: element, "record_buffer: Deferring trigger until a buffer with a timestamp is received");
+                self.trigger_pending.store(true, Ordering::SeqCst);
+                None
+            },
+            (false, _) => None,
+        };
+        match (recorder.stop_time, trigger_stop_time) {
+            (Some(stop_time), Some(trigger_stop_time)) => {
+                gst_debug!(CAT, obj: element, "record_buffer: Extending recording");
+                recorder.stop_time = Some(cmp::max(stop_time, trigger_stop_time));
+                self.write_recorded_buffer(element, recorder, queue, queued_buffer)
+            },
+            (Some(stop_time), None) => {
+                if random_access && pts.map_or(false, |pts| pts > stop_time) {
+                    gst_info!(CAT, obj: element, "record_buffer: Stopping recording");
+                    recorder.stop_time = None;
+                    recorder.pre_roll.push(queued_buffer, pts, random_access);
+                    post_recording_status(element, false, 0);
+                    Ok(gst::FlowSuccess::Ok)
+                } else {
+                    self.write_recorded_buffer(element, recorder, queue, queued_buffer)
+                }
+            },
+            (None, Some(trigger_stop_time)) => {
+                recorder.stop_time = Some(trigger_stop_time);
+                recorder.pre_roll.push(queued_buffer, pts, random_access);
+                let buffers = recorder.pre_roll.drain();
+                gst_info!(CAT, obj: element, "record_buffer: Starting recording with {} pre-roll buffers", buffers.len());
+                post_recording_status(element, true, buffers.len() as u64);
+                for (i, mut queued_buffer) in buffers.into_iter().enumerate() {
+                    if i == 0 {
+                        // The recording begins with a discontinuity so that each recording has its own index record.
+                        queued_buffer.buffer.make_mut().set_flags(gst::BufferFlags::DISCONT);
+                    }
+                    self.write_recorded_buffer(element, recorder, queue, queued_buffer)?;
+                }
+                Ok(gst::FlowSuccess::Ok)
+            },
+            (None, None) => {
+                recorder.pre_roll.push(queued_buffer, pts, random_access);
+                Ok(gst::FlowSuccess::Ok)
+            },
+        }
+    }
+
+    /// Writes a buffer during a recording.
+    fn write_recorded_buffer(
+        &self,
+        element: &super::PravegaSink,
+        recorder: &mut Recorder,
+        queue: Option<&WriteQueue<QueuedBuffer>>,
+        queued_buffer: QueuedBuffer,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        match queue {
+            Some(queue) => self.queue_buffer(element, queue, queued_buffer),
+            None => {
+                if let Some(caps) = queued_buffer.caps {
+                    if recorder.current_caps.as_ref() != Some(&caps) {
+                        if let Err(err) = self.set_pending_caps(&caps) {
+                            err.log_with_object(element);
+                        }
+                        recorder.current_caps = Some(caps);
+                    }
+                }
+                self.write_buffer(element, &queued_buffer.buffer)
+            },
+        }
+    }
+
+    /// Writes a marker record to the marker stream.
+    /// If pts is not valid, the PTS of the last buffer received is used.
+    /// Markers are not spooled, so a marker added while Pravega is unavailable is dropped.
+    fn add_marker(&self, element: &super::PravegaSink, label: String, payload: String, pts: gst::ClockTime) -> bool {
+        let pts = if pts.is_some() { pts } else { *self.last_render_pts.lock().unwrap() };
+        let timestamp_mode = self.settings.lock().unwrap().timestamp_mode;
+        let reference_offset = *self.reference_offset.lock().unwrap();
+        let timestamp = to_pravega_timestamp(element, timestamp_mode, pts, reference_offset);
+        let marker_record = MarkerRecord::new(timestamp, label, payload);
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            State::Started {
+                ref mut marker_writer,
+                ref spooling,
+                ..
+            } => {
+                let marker_writer = match marker_writer {
+                    Some(marker_writer) if spooling.is_none() => marker_writer,
+                    _ => {
+                        gst_warning!(CAT, obj: element, "add_marker: Dropping marker because Pravega is unavailable: {:?}", marker_record);
+                        return false;
+                    },
+                };
+                let mut marker_record_writer = MarkerRecordWriter::new();
+                let result = marker_record_writer.write(&marker_record, marker_writer)
+                    .and_then(|_| marker_writer.flush());
+                match result {
+                    Ok(()) => {
+                        gst_info!(CAT, obj: element, "add_marker: Wrote marker record {:?}", marker_record);
+                        true
+                    },
+                    Err(error) => {
+                        gst::element_warning!(element, gst::ResourceError::Write, ["Failed to write marker: {}", error]);
+                        false
+                    },
+                }
+            },
+            State::Stopped => {
+                gst_warning!(CAT, obj: element, "add_marker: Dropping marker because not started: {:?}", marker_record);
+                false
+            },
+        }
+    }
+
+    /// Handles the trigger action signal and event.
+    /// A recording will start, or the current recording will be extended, with the next buffer.
+    fn trigger(&self, element: &super::PravegaSink) -> bool {
+        if self.settings.lock().unwrap().trigger_mode {
+            gst_info!(CAT, obj: element, "trigger: Triggered");
+            self.trigger_pending.store(true, Ordering::SeqCst);
+            true
+        } else {
+            gst_warning!(CAT, obj: element, "trigger: Ignoring trigger because {} is false", PROPERTY_NAME_TRIGGER_MODE);
+            false
+        }
+    }
+
+    fn set_pending_caps(&self, caps: &gst::Caps) -> Result<(), gst::LoggableError> {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            State::Started {
+                ref mut pending_caps,
+                ..
+            } => {
+                // The caps record will be written with the next buffer so that it has the correct timestamp and offset.
+                *pending_caps = Some(caps.to_string());
+                Ok(())
+            },
+            State::Stopped => {
+                Err(gst::loggable_error!(CAT, "Not started yet"))
+            }
+        }
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for PravegaSink {
+    const NAME: &'static str = "PravegaSink";
+    type Type = super::PravegaSink;
+    type ParentType = gst_base::BaseSink;
+
+    fn new() -> Self {
+        pravega_video::tracing::init();
+        Self {
+            settings: Mutex::new(Default::default()),
+            state: Mutex::new(Default::default()),
+            async_writer: Mutex::new(None),
+            coalesce_flusher: Mutex::new(None),
+            recorder: Mutex::new(None),
+            trigger_pending: AtomicBool::new(false),
+            last_render_pts: Mutex::new(gst::ClockTime::none()),
+            reference_offset: Mutex::new(TimeDelta::none()),
+        }
+    }
+}
+
+impl ObjectImpl for PravegaSink {
+    fn constructed(&self, obj: &Self::Type) {
+        self.parent_constructed(obj);
+        obj.set_element_flags(gst::ElementFlags::PROVIDE_CLOCK | gst::ElementFlags::REQUIRE_CLOCK);
+    }
+
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| { vec![
+            glib::ParamSpec::new_string(
+                PROPERTY_NAME_STREAM,
+                "Stream",
+                "scope/stream. The stream may be a template with strftime conversion specifications such as cams/lobby-%Y%m%d. \
+                The template is formatted with the UTC time of each key frame, and when the name changes, \
+                the sink rolls over to a new stream.",
+                None,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_string(
+                PROPERTY_NAME_CONTROLLER,
+                "Controller",
+                "Pravega controller",
+                Some(DEFAULT_CONTROLLER),
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_boolean(
+                PROPERTY_NAME_SEAL,
+                "Seal",
+                "Seal Pravega stream when stopped",
+                false,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_boolean(
+                PROPERTY_NAME_SEAL_ON_ROLLOVER,
+                "Seal on rollover",
+                "Seal the previous Pravega stream when rolling over to a new stream",
+                false,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_uint(
+                PROPERTY_NAME_BUFFER_SIZE,
+                "Buffer size",
+                "Size of buffer in number of bytes",
+                0,
+                std::u32::MAX,
+                DEFAULT_BUFFER_SIZE.try_into().unwrap(),
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_enum(
+                PROPERTY_NAME_TIMESTAMP_MODE,
+                "Timestamp mode",
+                "Timestamp mode used by the input",
+                TimestampMode::static_type(),
+                DEFAULT_TIMESTAMP_MODE as i32,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_double(
+                PROPERTY_NAME_INDEX_MIN_SEC,
+                "Minimum index interval",
+                "The minimum number of seconds between index records",
+                0.0,
+                std::f64::INFINITY,
+                DEFAULT_INDEX_MIN_SEC.try_into().unwrap(),
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_double(
+                PROPERTY_NAME_INDEX_MAX_SEC,
+                "Maximum index interval",
+                "Force index record if one has not been created in this many seconds, even at delta frames.",
+                0.0,
+                std::f64::INFINITY,
+                DEFAULT_INDEX_MAX_SEC.try_into().unwrap(),
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_boolean(
+                PROPERTY_NAME_ALLOW_CREATE_SCOPE,
+                "Allow create scope",
+                "If true, the Pravega scope will be created if needed.",
+                true,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_string(
+                PROPERTY_NAME_KEYCLOAK_FILE,
                 "Keycloak file",
                 "The filename containing the Keycloak credentials JSON. If missing or empty, authentication will be disabled.",
                 None,
                 glib::ParamFlags::WRITABLE,
             ),
+            glib::ParamSpec::new_string(
+                PROPERTY_NAME_STORAGE_URI,
+                "Storage URI",
+                "If missing or empty, streams will be stored in Pravega. If a file URI such as file:///var/lib/video, streams will be stored in this local directory with the same format. If memory://name, streams will be stored in the memory of this process, which is useful for testing. The controller will not be used if this is set.",
+                None,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_enum(
+                PROPERTY_NAME_RETENTION_TYPE,
+                "Retention type",
+                "If 'days', data older than 'retention-days' will be deleted from the stream. If 'bytes', the oldest data will be deleted so that the data size does not exceed 'retention-bytes'. If daysAndBytes, the oldest data will be deleted if it is older than retention-days or the data size exceeds retention-bytes.",
+                RetentionType::static_type(),
+                DEFAULT_RETENTION_TYPE as i32,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_double(
+                PROPERTY_NAME_RETENTION_DAYS,
+                "Retention days",
+                "The number of days that the video stream will be retained.",
+                0.0,
+                std::f64::INFINITY,
+                0.0,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_uint64(
+                PROPERTY_NAME_RETENTION_BYTES,
+                "Retention bytes",
+                "The number of bytes that the video stream will be retained.",
+                0,
+                std::u64::MAX,
+                0,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_uint64(
+                PROPERTY_NAME_RETENTION_MAINTENANCE_INTERVAL_SECONDS,
+                "Retention maintenance interval seconds",
+                "The oldest data will be deleted from the stream with this interval, according to the retention policy.",
+                0,
+                std::u64::MAX,
+                DEFAULT_RETENTION_MAINTENANCE_INTERVAL_SECONDS,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_uint64(
+                PROPERTY_NAME_RETENTION_MAX_TRUNCATE_BYTES,
+                "Retention maximum truncate bytes",
+                "The maximum number of bytes that will be deleted from the stream in a single retention maintenance interval. \
+                This protects against a misconfigured retention policy or clock deleting the entire stream at once. \
+                If 0, there is no limit.",
+                0,
+                std::u64::MAX,
+                0,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_boolean(
+                PROPERTY_NAME_RETENTION_DRY_RUN,
+                "Retention dry run",
+                "If true, the retention policy will be evaluated and reported with element messages, but no data will be deleted.",
+                false,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_string(
+                PROPERTY_NAME_RETENTION_ARCHIVE_URI,
+                "Retention archive URI",
+                "If set, data will be archived before it is deleted by the retention policy. \
+                If a file URI such as file:///var/archive, data will be archived to this local directory. \
+                If an S3 URI such as s3://bucket/prefix, data will be archived to an S3-compatible object store \
+                using the credentials and endpoint in the environment variables AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY, \
+                AWS_REGION, and AWS_ENDPOINT_URL. This requires the plugin to be built with the s3 feature. \
+                Data is deleted only after the archive has been confirmed. The archive can be played with pravegasrc.",
+                None,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_boolean(
+                PROPERTY_NAME_CHECKSUM,
+                "Checksum",
+                "If true, a CRC32C checksum will be written with each event so that readers can detect corruption. \
+                Streams written with checksums cannot be read by versions of pravegasrc that do not support them.",
+                false,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_boolean(
+                PROPERTY_NAME_BUFFER_METADATA,
+                "Buffer metadata",
+                "If true, the DTS, duration, and HEADER, GAP, DROPPABLE, and MARKER flags of each buffer will be written \
+                with each event and restored by pravegasrc. \
+                This allows codecs with frame reordering to be stored without gdppay.",
+                false,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_boolean(
+                PROPERTY_NAME_TRANSACTIONAL,
+                "Transactional",
+                "If true, the buffers between index records (usually a group of pictures) will be held in memory \
+                and then committed to the data stream along with their index record. \
+                Readers will never observe a partially written group of pictures, \
+                and an ungraceful termination will not leave a partial group of pictures at the end of the stream. \
+                Atomicity is only guaranteed when a group of pictures is 8 MiB or smaller.",
+                false,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_string(
+                PROPERTY_NAME_SPOOL_DIR,
+                "Spool directory",
+                "If set, buffers will be written to this local directory when Pravega is unavailable. \
+                Pravega will be retried with exponential backoff. When it becomes available, \
+                the spooled buffers will be written to Pravega and normal operation will resume. \
+                If missing or empty, an error will occur when Pravega is unavailable.",
+                None,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_uint64(
+                PROPERTY_NAME_SPOOL_MAX_BYTES,
+                "Spool maximum bytes",
+                "When the spool reaches this size, buffers will be dropped until Pravega becomes available.",
+                0,
+                std::u64::MAX,
+                DEFAULT_SPOOL_MAX_BYTES,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_double(
+                PROPERTY_NAME_SPOOL_CONNECT_BACKOFF_SEC,
+                "Spool connect backoff seconds",
+                "While spooling, the number of seconds before the first attempt to reconnect to Pravega. \
+                The interval doubles after each failed attempt, up to 60 seconds.",
+                0.0,
+                SPOOL_MAX_CONNECT_BACKOFF.as_secs_f64(),
+                DEFAULT_SPOOL_CONNECT_BACKOFF_SEC,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_boolean(
+                PROPERTY_NAME_ASYNC_WRITE,
+                "Async write",
+                "If true, buffers will be written to Pravega by a background thread so that a slow Pravega server \
+                does not block the pipeline. Buffers are passed to this thread through a queue limited by \
+                write-queue-max-bytes and write-queue-max-sec. When the queue is full, write-queue-leaky determines \
+                whether the pipeline blocks or buffers are dropped.",
+                false,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_uint64(
+                PROPERTY_NAME_WRITE_QUEUE_MAX_BYTES,
+                "Write queue maximum bytes",
+                "The maximum number of bytes in the write queue. If 0, there is no limit.",
+                0,
+                std::u64::MAX,
+                DEFAULT_WRITE_QUEUE_MAX_BYTES,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_double(
+                PROPERTY_NAME_WRITE_QUEUE_MAX_SEC,
+                "Write queue maximum seconds",
+                "The maximum number of seconds that a buffer can wait in the write queue. If 0, there is no limit.",
+                0.0,
+                std::f64::INFINITY,
+                DEFAULT_WRITE_QUEUE_MAX_SEC,
+                glib::ParamFlags::WRITABLE,
+            ),
             glib::ParamSpec::new_enum(
-                PROPERTY_NAME_RETENTION_TYPE,
-                "Retention type",
-                "If 'days', data older than 'retention-days' will be deleted from the stream. If 'bytes', the oldest data will be deleted so that the data size does not exceed 'retention-bytes'. If daysAndBytes, the oldest data will be deleted if it is older than retention-days or the data size exceeds retention-bytes.",
-                RetentionType::static_type(),
-                DEFAULT_RETENTION_TYPE as i32,
+                PROPERTY_NAME_WRITE_QUEUE_LEAKY,
+                "Write queue leaky",
+                "Determines what happens when a buffer is rendered and the write queue is full. \
+                If 'block', the pipeline will wait. If 'drop-oldest-gop', the oldest group of pictures in the queue will be dropped. \
+                If 'drop-newest', the buffer being rendered will be dropped.",
+                WriteQueueLeaky::static_type(),
+                DEFAULT_WRITE_QUEUE_LEAKY as i32,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_uint(
+                PROPERTY_NAME_COALESCE_MAX_BYTES,
+                "Coalesce maximum bytes",
+                "If greater than 0, consecutive buffers up to this size will be coalesced into a single event of at most this size. \
+                A buffer that is indexed, a discontinuity, or has new caps will begin a new event. \
+                pravegasrc restores the original buffer boundaries unless split-batches=false.",
+                0,
+                MAX_COALESCE_MAX_BYTES,
+                DEFAULT_COALESCE_MAX_BYTES as u32,
                 glib::ParamFlags::WRITABLE,
             ),
             glib::ParamSpec::new_double(
-                PROPERTY_NAME_RETENTION_DAYS,
-                "Retention days",
-                "The number of days that the video stream will be retained.",
+                PROPERTY_NAME_COALESCE_MAX_SEC,
+                "Coalesce maximum seconds",
+                "The timestamps of buffers coalesced into a single event will differ by less than this number of seconds. \
+                Buffers are also not held for longer than this number of seconds of wall-clock time when no further buffers arrive.",
                 0.0,
                 std::f64::INFINITY,
-                0.0,
+                DEFAULT_COALESCE_MAX_SEC,
                 glib::ParamFlags::WRITABLE,
             ),
-            glib::ParamSpec::new_uint64(
-                PROPERTY_NAME_RETENTION_BYTES,
-                "Retention bytes",
-                "The number of bytes that the video stream will be retained.",
-                0,
-                std::u64::MAX,
-                0,
+            glib::ParamSpec::new_boolean(
+                PROPERTY_NAME_TRIGGER_MODE,
+                "Trigger mode",
+                "If true, buffers are held in a pre-roll buffer and are only written when a recording is triggered \
+                by the trigger action signal or a custom downstream event named pravega-trigger. \
+                A recording begins with the last pre-roll-sec seconds of buffers and ends at the first key frame \
+                that is post-roll-sec seconds after the last trigger.",
+                false,
                 glib::ParamFlags::WRITABLE,
             ),
-            glib::ParamSpec::new_uint64(
-                PROPERTY_NAME_RETENTION_MAINTENANCE_INTERVAL_SECONDS,
-                "Retention maintenance interval seconds",
-                "The oldest data will be deleted from the stream with this interval, according to the retention policy.",
-                0,
-                std::u64::MAX,
-                DEFAULT_RETENTION_MAINTENANCE_INTERVAL_SECONDS,
+            glib::ParamSpec::new_double(
+                PROPERTY_NAME_PRE_ROLL_SEC,
+                "Pre-roll seconds",
+                "In trigger mode, the minimum number of seconds of buffers that are held before a trigger. \
+                The pre-roll buffer always begins with a key frame.",
+                0.0,
+                std::f64::INFINITY,
+                DEFAULT_PRE_ROLL_SEC,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_double(
+                PROPERTY_NAME_POST_ROLL_SEC,
+                "Post-roll seconds",
+                "In trigger mode, the number of seconds to continue recording after the last trigger",
+                0.0,
+                std::f64::INFINITY,
+                DEFAULT_POST_ROLL_SEC,
                 glib::ParamFlags::WRITABLE,
             ),
+            glib::ParamSpec::new_double(
+                PROPERTY_NAME_WRITE_QUEUE_LATENCY_SEC,
+                "Write queue latency seconds",
+                "The number of seconds that the oldest buffer in the write queue has been waiting",
+                0.0,
+                std::f64::INFINITY,
+                0.0,
+                glib::ParamFlags::READABLE,
+            ),
         ]});
         PROPERTIES.as_ref()
     }
 
+    fn signals() -> &'static [glib::subclass::Signal] {
+        static SIGNALS: Lazy<Vec<glib::subclass::Signal>> = Lazy::new(|| { vec![
+            // Switch to a new scope/stream at the next key frame while playing.
+            glib::subclass::Signal::builder(
+                SIGNAL_NAME_SWITCH_STREAM,
+                &[String::static_type().into()],
+                bool::static_type().into(),
+            )
+            .action()
+            .class_handler(|_, args| {
+                let element = args[0].get::<super::PravegaSink>().expect("signal arg");
+                let stream = args[1].get::<String>().expect("signal arg");
+                let sink = PravegaSink::from_instance(&element);
+                Some(sink.switch_stream(&element, &stream).to_value())
+            })
+            .build(),
+            // Add a marker with a label and payload at the last buffer received.
+            glib::subclass::Signal::builder(
+                SIGNAL_NAME_ADD_MARKER,
+                &[String::static_type().into(), String::static_type().into()],
+                bool::static_type().into(),
+            )
+            .action()
+            .class_handler(|_, args| {
+                let element = args[0].get::<super::PravegaSink>().expect("signal arg");
+                let label = args[1].get::<String>().expect("signal arg");
+                let payload = args[2].get::<Option<String>>().expect("signal arg").unwrap_or_default();
+                let sink = PravegaSink::from_instance(&element);
+                Some(sink.add_marker(&element, label, payload, gst::ClockTime::none()).to_value())
+            })
+            .build(),
+            // Start a recording, or extend the current recording, when trigger-mode is enabled.
+            glib::subclass::Signal::builder(
+                SIGNAL_NAME_TRIGGER,
+                &[],
+                bool::static_type().into(),
+            )
+            .action()
+            .class_handler(|_, args| {
+                let element = args[0].get::<super::PravegaSink>().expect("signal arg");
+                let sink = PravegaSink::from_instance(&element);
+                Some(sink.trigger(&element).to_value())
+            })
+            .build(),
+        ]});
+        SIGNALS.as_ref()
+    }
+
     fn set_property(
         &self,
         obj: &Self::Type,
@@ -522,6 +2675,19 @@ impl ObjectImpl for PravegaSink {
                     gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_SEAL, err);
                 }
             },
+            PROPERTY_NAME_SEAL_ON_ROLLOVER => {
+                let res: Result<(), glib::Error> = match value.get::<bool>() {
+                    Ok(seal_on_rollover) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.seal_on_rollover = seal_on_rollover;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_SEAL_ON_ROLLOVER, err);
+                }
+            },
             PROPERTY_NAME_BUFFER_SIZE => {
                 let res: Result<(), glib::Error> = match value.get::<u32>() {
                     Ok(buffer_size) => {
@@ -604,6 +2770,23 @@ impl ObjectImpl for PravegaSink {
                     gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_KEYCLOAK_FILE, err);
                 }
             },
+            PROPERTY_NAME_STORAGE_URI => {
+                let res: Result<(), glib::Error> = match value.get::<String>() {
+                    Ok(storage_uri) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.storage_uri = if storage_uri.is_empty() {
+                            None
+                        } else {
+                            Some(storage_uri)
+                        };
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_STORAGE_URI, err);
+                }
+            },
             PROPERTY_NAME_RETENTION_TYPE => {
                 let res: Result<(), glib::Error> = match value.get::<RetentionType>() {
                     Ok(retention_type) => {
@@ -655,10 +2838,263 @@ impl ObjectImpl for PravegaSink {
                 if let Err(err) = res {
                     gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_RETENTION_MAINTENANCE_INTERVAL_SECONDS, err);
                 }
-            },       
+            },
+            PROPERTY_NAME_RETENTION_MAX_TRUNCATE_BYTES => {
+                let res: Result<(), glib::Error> = match value.get::<u64>() {
+                    Ok(bytes) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.retention_max_truncate_bytes = bytes;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_RETENTION_MAX_TRUNCATE_BYTES, err);
+                }
+            },
+            PROPERTY_NAME_RETENTION_DRY_RUN => {
+                let res: Result<(), glib::Error> = match value.get::<bool>() {
+                    Ok(dry_run) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.retention_dry_run = dry_run;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_RETENTION_DRY_RUN, err);
+                }
+            },
+            PROPERTY_NAME_RETENTION_ARCHIVE_URI => {
+                let res: Result<(), glib::Error> = match value.get::<String>() {
+                    Ok(archive_uri) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.retention_archive_uri = if archive_uri.is_empty() {
+                            None
+                        } else {
+                            Some(archive_uri)
+                        };
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_RETENTION_ARCHIVE_URI, err);
+                }
+            },
+            PROPERTY_NAME_CHECKSUM => {
+                let res: Result<(), glib::Error> = match value.get::<bool>() {
+                    Ok(checksum) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.checksum = checksum;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_CHECKSUM, err);
+                }
+            },
+            PROPERTY_NAME_BUFFER_METADATA => {
+                let res: Result<(), glib::Error> = match value.get::<bool>() {
+                    Ok(buffer_metadata) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.buffer_metadata = buffer_metadata;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_BUFFER_METADATA, err);
+                }
+            },
+            PROPERTY_NAME_TRANSACTIONAL => {
+                let res: Result<(), glib::Error> = match value.get::<bool>() {
+                    Ok(transactional) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.transactional = transactional;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_TRANSACTIONAL, err);
+                }
+            },
+            PROPERTY_NAME_SPOOL_DIR => {
+                let res: Result<(), glib::Error> = match value.get::<String>() {
+                    Ok(spool_dir) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.spool_dir = if spool_dir.is_empty() {
+                            None
+                        } else {
+                            Some(spool_dir)
+                        };
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_SPOOL_DIR, err);
+                }
+            },
+            PROPERTY_NAME_SPOOL_MAX_BYTES => {
+                let res: Result<(), glib::Error> = match value.get::<u64>() {
+                    Ok(spool_max_bytes) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.spool_max_bytes = spool_max_bytes;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_SPOOL_MAX_BYTES, err);
+                }
+            },
+            PROPERTY_NAME_SPOOL_CONNECT_BACKOFF_SEC => {
+                let res: Result<(), glib::Error> = match value.get::<f64>() {
+                    Ok(spool_connect_backoff_sec) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.spool_connect_backoff_nanos = (spool_connect_backoff_sec * 1e9) as u64;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_SPOOL_CONNECT_BACKOFF_SEC, err);
+                }
+            },
+            PROPERTY_NAME_ASYNC_WRITE => {
+                let res: Result<(), glib::Error> = match value.get::<bool>() {
+                    Ok(async_write) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.async_write = async_write;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_ASYNC_WRITE, err);
+                }
+            },
+            PROPERTY_NAME_WRITE_QUEUE_MAX_BYTES => {
+                let res: Result<(), glib::Error> = match value.get::<u64>() {
+                    Ok(write_queue_max_bytes) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.write_queue_max_bytes = write_queue_max_bytes;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_WRITE_QUEUE_MAX_BYTES, err);
+                }
+            },
+            PROPERTY_NAME_WRITE_QUEUE_MAX_SEC => {
+                let res: Result<(), glib::Error> = match value.get::<f64>() {
+                    Ok(write_queue_max_sec) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.write_queue_max_nanos = (write_queue_max_sec * 1e9) as u64;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_WRITE_QUEUE_MAX_SEC, err);
+                }
+            },
+            PROPERTY_NAME_WRITE_QUEUE_LEAKY => {
+                let res: Result<(), glib::Error> = match value.get::<WriteQueueLeaky>() {
+                    Ok(write_queue_leaky) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.write_queue_leaky = write_queue_leaky;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_WRITE_QUEUE_LEAKY, err);
+                }
+            },
+            PROPERTY_NAME_COALESCE_MAX_BYTES => {
+                let res: Result<(), glib::Error> = match value.get::<u32>() {
+                    Ok(coalesce_max_bytes) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.coalesce_max_bytes = coalesce_max_bytes as usize;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_COALESCE_MAX_BYTES, err);
+                }
+            },
+            PROPERTY_NAME_COALESCE_MAX_SEC => {
+                let res: Result<(), glib::Error> = match value.get::<f64>() {
+                    Ok(coalesce_max_sec) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.coalesce_max_nanos = (coalesce_max_sec * 1e9) as u64;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_COALESCE_MAX_SEC, err);
+                }
+            },
+            PROPERTY_NAME_TRIGGER_MODE => {
+                let res: Result<(), glib::Error> = match value.get::<bool>() {
+                    Ok(trigger_mode) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.trigger_mode = trigger_mode;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_TRIGGER_MODE, err);
+                }
+            },
+            PROPERTY_NAME_PRE_ROLL_SEC => {
+                let res: Result<(), glib::Error> = match value.get::<f64>() {
+                    Ok(pre_roll_sec) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.pre_roll_nanos = (pre_roll_sec * 1e9) as u64;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_PRE_ROLL_SEC, err);
+                }
+            },
+            PROPERTY_NAME_POST_ROLL_SEC => {
+                let res: Result<(), glib::Error> = match value.get::<f64>() {
+                    Ok(post_roll_sec) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.post_roll_nanos = (post_roll_sec * 1e9) as u64;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_POST_ROLL_SEC, err);
+                }
+            },
         _ => unimplemented!(),
         };
     }
+
+    fn property(&self, _obj: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            PROPERTY_NAME_WRITE_QUEUE_LATENCY_SEC => {
+                let async_writer = self.async_writer.lock().unwrap();
+                let latency = async_writer.as_ref().map_or(Duration::ZERO, |async_writer| async_writer.queue.latency());
+                latency.as_secs_f64().to_value()
+            },
+            _ => unimplemented!(),
+        }
+    }
 }
 
 impl ElementImpl for PravegaSink {
@@ -719,121 +3155,148 @@ impl BaseSinkImpl for PravegaSink {
             let scope_name: String = settings.scope.clone().ok_or_else(|| {
                 gst::error_msg!(gst::ResourceError::Settings, ["Scope is not defined"])
             })?;
-            let stream_name = settings.stream.clone().ok_or_else(|| {
-                gst::error_msg!(gst::ResourceError::Settings, ["Stream is not defined"])
-            })?;
-            let index_stream_name = get_index_stream_name(&stream_name);
-            let scope = Scope::from(scope_name);
-            let stream = Stream::from(stream_name);
-            let index_stream = Stream::from(index_stream_name);
-            gst_info!(CAT, obj: element, "start: scope={}, stream={}, index_stream={}", scope, stream, index_stream);
-            gst_info!(CAT, obj: element, "start: timestamp_mode={:?}", settings.timestamp_mode);
-
-            let controller = settings.controller.clone().ok_or_else(|| {
-                gst::error_msg!(gst::ResourceError::Settings, ["Controller is not defined"])
-            })?;
-            gst_info!(CAT, obj: element, "start: controller={}", controller);
-            let keycloak_file = settings.keycloak_file.clone();
-            gst_info!(CAT, obj: element, "start: keycloak_file={:?}", keycloak_file);
-            let config = utils::create_client_config(controller, keycloak_file).map_err(|error| {
-                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create Pravega client config: {}", error])
+            let stream_name = settings.stream.clone().ok_or_else(|| {
+                gst::error_msg!(gst::ResourceError::Settings, ["Stream is not defined"])
             })?;
-            gst_debug!(CAT, obj: element, "start: config={:?}", config);
-            gst_info!(CAT, obj: element, "start: controller_uri={}:{}", config.controller_uri.domain_name(), config.controller_uri.port());
-            gst_info!(CAT, obj: element, "start: is_tls_enabled={}", config.is_tls_enabled);
-            gst_info!(CAT, obj: element, "start: is_auth_enabled={}", config.is_auth_enabled);
-
-            let client_factory = ClientFactory::new(config);
-            let controller_client = client_factory.controller_client();
-            let runtime = client_factory.runtime();
-
-            // Create scope.
-            gst_info!(CAT, obj: element, "start: allow_create_scope={}", settings.allow_create_scope);
-            if settings.allow_create_scope {
-                runtime.block_on(controller_client.create_scope(&scope)).map_err(|error| {
-                    gst::error_msg!(gst::ResourceError::Settings, ["Failed to create Pravega scope: {:?}", error])
+            // If the stream name is a template, the first stream is named using the current time.
+            // The sink will roll over to a new stream when a key frame has a timestamp that produces a different name.
+            let (stream_name, stream_template) = if is_stream_name_template(&stream_name) {
+                validate_stream_name_template(&stream_name).map_err(|error| {
+                    gst::error_msg!(gst::ResourceError::Settings, ["{}", error])
                 })?;
-            }
+                gst_info!(CAT, obj: element, "start: stream_template={}", stream_name);
+                let now = PravegaTimestamp::now();
+                let first_stream_name = format_stream_name(&stream_name, now).ok_or_else(|| {
+                    gst::error_msg!(gst::ResourceError::Settings, ["Unable to name stream from template '{}' at time {:?}", stream_name, now])
+                })?;
+                (first_stream_name, Some(stream_name))
+            } else {
+                (stream_name, None)
+            };
+            gst_info!(CAT, obj: element, "start: scope={}, stream={}", scope_name, stream_name);
+            gst_info!(CAT, obj: element, "start: timestamp_mode={:?}", settings.timestamp_mode);
 
-            // Create data stream.
-            let stream_config = StreamConfiguration {
-                scoped_stream: ScopedStream {
-                    scope: scope.clone(),
-                    stream: stream.clone(),
+            let storage: Arc<dyn StorageBackend> = match settings.storage_uri.clone() {
+                Some(storage_uri) => {
+                    gst_info!(CAT, obj: element, "start: storage_uri={}", storage_uri);
+                    Arc::from(create_storage_from_uri(&storage_uri).map_err(|error| {
+                        gst::error_msg!(gst::ResourceError::Settings, ["Failed to create storage: {}", error])
+                    })?)
                 },
-                scaling: Scaling {
-                    scale_type: ScaleType::FixedNumSegments,
-                    min_num_segments: 1,
-                    ..Default::default()
+                None => {
+                    let controller = settings.controller.clone().ok_or_else(|| {
+                        gst::error_msg!(gst::ResourceError::Settings, ["Controller is not defined"])
+                    })?;
+                    gst_info!(CAT, obj: element, "start: controller={}", controller);
+                    let keycloak_file = settings.keycloak_file.clone();
+                    gst_info!(CAT, obj: element, "start: keycloak_file={:?}", keycloak_file);
+                    let config = utils::create_client_config(controller, keycloak_file).map_err(|error| {
+                        gst::error_msg!(gst::ResourceError::Settings, ["Failed to create Pravega client config: {}", error])
+                    })?;
+                    gst_debug!(CAT, obj: element, "start: config={:?}", config);
+                    gst_info!(CAT, obj: element, "start: controller_uri={}:{}", config.controller_uri.domain_name(), config.controller_uri.port());
+                    gst_info!(CAT, obj: element, "start: is_tls_enabled={}", config.is_tls_enabled);
+                    gst_info!(CAT, obj: element, "start: is_auth_enabled={}", config.is_auth_enabled);
+                    Arc::new(PravegaStorage::new(ClientFactory::new(config)))
                 },
-                retention: Default::default(),
-                tags: utils::get_video_tags(),
             };
-            runtime.block_on(controller_client.create_stream(&stream_config)).map_err(|error| {
-                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create Pravega data stream: {:?}", error])
-            })?;
 
-            // Create index stream.
-            let index_stream_config = StreamConfiguration {
-                scoped_stream: ScopedStream {
-                    scope: scope.clone(),
-                    stream: index_stream.clone(),
+            gst_info!(CAT, obj: element, "start: allow_create_scope={}", settings.allow_create_scope);
+            let primary_writers = create_media_streams(element, storage.as_ref(), &scope_name, &stream_name, settings.allow_create_scope)
+                .and_then(|_| open_writers(storage.as_ref(), &scope_name, &stream_name).map_err(|error| {
+                    gst::error_msg!(gst::ResourceError::OpenWrite, ["Failed to open writers: {}", error])
+                }));
+
+            // If Pravega is unavailable and a spool is configured, the sink starts by writing to the spool.
+            let (writers, spool, streams_created) = match primary_writers {
+                Ok(mut writers) => {
+                    gst_info!(CAT, obj: element, "start: Opened writers for data, index, and caps");
+                    let spool = open_spool(element, &settings, &scope_name, &stream_name, Some(&mut writers))?;
+                    (writers, spool, true)
                 },
-                scaling: Scaling {
-                    scale_type: ScaleType::FixedNumSegments,
-                    min_num_segments: 1,
-                    ..Default::default()
+                Err(error) if settings.spool_dir.is_some() => {
+                    gst_warning!(CAT, obj: element, "start: Unable to open Pravega streams; writing to spool: {:?}", error);
+                    let spool = open_spool(element, &settings, &scope_name, &stream_name, None)?.unwrap();
+                    let writers = spool.create_writers().map_err(|error| {
+                        gst::error_msg!(gst::ResourceError::OpenWrite, ["Failed to open spool writers: {}", error])
+                    })?;
+                    (writers, Some(spool), false)
                 },
-                retention: Default::default(),
-                tags: None,
-            };
-            runtime.block_on(controller_client.create_stream(&index_stream_config)).map_err(|error| {
-                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create Pravega index stream: {:?}", error])
-            })?;
-
-            let scoped_stream = ScopedStream {
-                scope: scope.clone(),
-                stream: stream.clone(),
-            };
-            let mut writer = client_factory.create_byte_writer(scoped_stream.clone());
-            gst_info!(CAT, obj: element, "start: Opened Pravega writer for data");
-            writer.seek_to_tail();
-
-            let index_scoped_stream = ScopedStream {
-                scope: scope.clone(),
-                stream: index_stream.clone(),
+                Err(error) => return Err(error),
             };
-            let mut index_writer = client_factory.create_byte_writer(index_scoped_stream.clone());
-            gst_info!(CAT, obj: element, "start: Opened Pravega writer for index");
-            index_writer.seek_to_tail();
 
-            let seekable_writer = SeekableByteWriter::new(writer).unwrap();
             gst_info!(CAT, obj: element, "start: Buffer size is {}", settings.buffer_size);
-            let buf_writer = BufWriter::with_capacity(settings.buffer_size, seekable_writer);
-            let counting_writer = CountingWriter::new(buf_writer).unwrap();
-
-            let retention_policy = RetentionPolicy::new(settings.retention_type, settings.retention_days, settings.retention_bytes).map_err(|error| {
-                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create retention policy: {}", error])
+            gst_info!(CAT, obj: element, "start: transactional={}", settings.transactional);
+            let MediaStreamWriters { data: writer, index: index_writer, caps: caps_writer } = writers;
+            let transaction_writer = new_data_writer(writer, settings.buffer_size).map_err(|error| {
+                gst::error_msg!(gst::ResourceError::OpenWrite, ["Failed to open writer for data stream: {}", error])
             })?;
-            gst_info!(CAT, obj: element, "start: retention_policy={:?}", retention_policy);
 
-            let retention_maintainer = RetentionMaintainer::new(element.clone(), settings.retention_maintenance_interval_seconds, retention_policy, client_factory.clone(),
-                index_scoped_stream, scoped_stream);
-            let (retention_thread_stop_tx, retention_thread_stop_rx) = mpsc::channel();
-            let retention_thread_handle = retention_maintainer.run(retention_thread_stop_rx);
+            let (marker_writer, retention_thread_stop_tx, retention_thread_handle) = if streams_created {
+                let marker_writer = open_marker_writer(storage.as_ref(), &scope_name, &stream_name)?;
+                let (retention_thread_stop_tx, retention_thread_handle) =
+                    start_retention_maintainer(element, &settings, storage.as_ref(), &scope_name, &stream_name)?;
+                (Some(marker_writer), retention_thread_stop_tx, retention_thread_handle)
+            } else {
+                (None, mpsc::channel().0, None)
+            };
 
+            let spool_connect_backoff = Duration::from_nanos(settings.spool_connect_backoff_nanos);
             *state = State::Started {
-                client_factory,
-                writer: counting_writer,
+                writer: transaction_writer,
                 index_writer,
+                caps_writer,
+                marker_writer,
+                pending_caps: None,
+                written_caps: None,
                 first_valid_time: PravegaTimestamp::NONE,
                 last_index_time: PravegaTimestamp::NONE,
                 final_timestamp: PravegaTimestamp::NONE,
                 final_offset: None,
+                batch: None,
+                pending_index_record: None,
                 buffers_written: 0,
                 retention_thread_stop_tx,
                 retention_thread_handle,
+                storage,
+                scope_name,
+                stream_name,
+                buffer_size: settings.buffer_size,
+                spool,
+                spool_connect_backoff,
+                spooling: if streams_created { None } else { Some(SpoolingState::new(spool_connect_backoff)) },
+                streams_created,
+                stream_template,
+                pending_stream_switch: None,
             };
+            if let State::Started { spool: Some(ref spool), spooling: Some(_), .. } = *state {
+                post_spool_status(element, spool, true, 0);
+            }
+            if settings.async_write {
+                gst_info!(CAT, obj: element, "start: write_queue_max_bytes={}, write_queue_max_nanos={}, write_queue_leaky={:?}",
+                    settings.write_queue_max_bytes, settings.write_queue_max_nanos, settings.write_queue_leaky);
+                let queue = Arc::new(WriteQueue::new(
+                    settings.write_queue_max_bytes.try_into().unwrap_or(usize::MAX),
+                    Duration::from_nanos(settings.write_queue_max_nanos),
+                    settings.write_queue_leaky.into()));
+                let thread_handle = run_writer_thread(element.clone(), queue.clone());
+                *self.async_writer.lock().unwrap() = Some(AsyncWriter { queue, thread_handle });
+            }
+            if settings.coalesce_max_bytes > 0 {
+                let (thread_stop_tx, thread_stop_rx) = mpsc::channel();
+                let thread_handle = run_coalesce_flusher(element.clone(), Duration::from_nanos(settings.coalesce_max_nanos), thread_stop_rx);
+                *self.coalesce_flusher.lock().unwrap() = Some(CoalesceFlusher { thread_stop_tx, thread_handle });
+            }
+            self.trigger_pending.store(false, Ordering::SeqCst);
+            if settings.trigger_mode {
+                gst_info!(CAT, obj: element, "start: pre_roll_nanos={}, post_roll_nanos={}", settings.pre_roll_nanos, settings.post_roll_nanos);
+                *self.recorder.lock().unwrap() = Some(Recorder {
+                    pre_roll: PreRollBuffer::new(settings.pre_roll_nanos),
+                    post_roll_nanos: settings.post_roll_nanos,
+                    stop_time: None,
+                    current_caps: None,
+                });
+            }
             gst_info!(CAT, obj: element, "start: Started");
             Ok(())
         })();
@@ -841,294 +3304,118 @@ impl BaseSinkImpl for PravegaSink {
         result
     }
 
+    fn set_caps(&self, element: &Self::Type, caps: &gst::Caps) -> Result<(), gst::LoggableError> {
+        gst_info!(CAT, obj: element, "set_caps: caps={}", caps);
+        if self.async_writer.lock().unwrap().is_some() || self.recorder.lock().unwrap().is_some() {
+            // The caps are passed to the writer thread or recorder with each buffer.
+            return Ok(());
+        }
+        self.set_pending_caps(caps)
+    }
+
     fn render(
         &self,
         element: &Self::Type,
         buffer: &gst::Buffer,
     ) -> Result<gst::FlowSuccess, gst::FlowError> {
-        gst_trace!(CAT, obj: element, "render: BEGIN: Rendering {:?}", buffer);
-        let result = (|| {
-            let mut state = self.state.lock().unwrap();
-            let (writer,
-                index_writer,
-                first_valid_time,
-                last_index_time,
-                final_timestamp,
-                final_offset,
-                buffers_written) = match *state {
-                State::Started {
-                    ref mut writer,
-                    ref mut index_writer,
-                    ref mut first_valid_time,
-                    ref mut last_index_time,
-                    ref mut final_timestamp,
-                    ref mut final_offset,
-                    ref mut buffers_written,
-                    ..
-                } => (writer,
-                    index_writer,
-                    first_valid_time,
-                    last_index_time,
-                    final_timestamp,
-                    final_offset,
-                    buffers_written),
-                State::Stopped => {
-                    gst::element_error!(element, gst::CoreError::Failed, ["Not started yet"]);
-                    return Err(gst::FlowError::Error);
-                }
-            };
-
-            let pts = buffer.pts();
-            let duration = buffer.duration();
-
-            let map = buffer.map_readable().map_err(|_| {
-                gst::element_error!(element, gst::CoreError::Failed, ["Failed to map buffer"]);
-                gst::FlowError::Error
-            })?;
-            let payload = map.as_ref();
-
-            let (timestamp_mode, index_min_nanos, index_max_nanos) = {
-                let settings = self.settings.lock().unwrap();
-                (settings.timestamp_mode, settings.index_min_nanos, settings.index_max_nanos)
-            };
-
-            let timestamp = match timestamp_mode {
-                TimestampMode::RealtimeClock => {
-                    // pts is time between beginning of play and beginning of this buffer.
-                    // base_time is the value of the pipeline clock (time since Unix epoch) at the beginning of play.
-                    PravegaTimestamp::from_unix_nanoseconds((element.base_time() + pts).nseconds())
-                },
-                TimestampMode::Ntp => {
-                    // When receiving from rtspsrc (ntp-sync=true ntp-time-source=running-time),
-                    // pts will be the number of nanoseconds since the NTP epoch 1900-01-01 00:00:00 UTC
-                    // of when the video frame was observed by the camera.
-                    // Note: base_time is the value of the pipeline clock at the beginning of play. It is ignored.
-                    PravegaTimestamp::from_ntp_nanoseconds(pts.nseconds())
-                },
-                TimestampMode::Tai => {
-                    PravegaTimestamp::from_nanoseconds(pts.nseconds())
-                }
-            };
-
-            if first_valid_time.is_none() {
-                *first_valid_time = timestamp;
-            }
-
-            // Get the writer offset before writing. This offset will be used in the index.
-            let writer_offset = writer.seek(SeekFrom::Current(0)).unwrap();
-
-            gst_log!(CAT, obj: element, "render: timestamp={:?}, pts={}, base_time={}, duration={}, size={}, writer_offset={}",
-                timestamp, pts, element.base_time(), buffer.duration(), buffer.size(), writer_offset);
-
-            // We only want to include key frames (non-delta units) in the index.
-            // However, if no key frame has been received in a while, force an index record.
-            // This is required for nvv4l2h264enc because it identifies all buffers as DELTA_UNIT.
-            let buffer_flags = buffer.flags();
-            let is_delta_unit = buffer_flags.contains(gst::BufferFlags::DELTA_UNIT);
-            let random_access = !is_delta_unit;
-            let include_in_index = match timestamp.nanoseconds() {
-                Some(timestamp) => {
-                    match last_index_time.nanoseconds() {
-                        Some(last_index_time) => {
-                            let interval_sec = u64_to_i64_saturating_sub(timestamp, last_index_time) as f64 * 1e-9;
-                            if is_delta_unit {
-                                // We are at a delta frame.
-                                if timestamp > last_index_time + index_max_nanos {
-                                    gst_fixme!(CAT, obj: element,
-                                        "render: Forcing index record at delta unit because no key frame has been received for {} sec", interval_sec);
-                                    true
-                                } else {
-                                    false
-                                }
-                            } else {
-                                // We are at a key frame.
-                                if timestamp < last_index_time + index_min_nanos {
-                                    gst_debug!(CAT, obj: element,
-                                        "render: Skipping creation of index record because an index record was created {} sec ago", interval_sec);
-                                    false
-                                } else {
-                                    gst_debug!(CAT, obj: element,
-                                        "render: Creating index record at key frame; last index record was created {} sec ago", interval_sec);
-                                    true
-                                }
-                            }
-                        },
-                        None => {
-                            // An index record has not been written by this element yet.
-                            // The timestamp is valid.
-                            if random_access {
-                                true
-                            } else {
-                                // We are at a delta frame.
-                                // Do not write an index record. unless no index record has been written for a while.
-                                match first_valid_time.nanoseconds() {
-                                    Some(first_valid_time) => {
-                                        if timestamp > first_valid_time + index_max_nanos {
-                                            let interval_sec = u64_to_i64_saturating_sub(timestamp, first_valid_time) as f64 * 1e-9;
-                                            gst_fixme!(CAT, obj: element,
-                                                "render: Forcing first index record at delta unit because no key frame has been received for {} sec", interval_sec);
-                                            true
-                                        } else {
-                                            false
-                                        }
-                                    },
-                                    None => {
-                                        // Should be unreachable.
-                                        false
-                                    },
-                                }
-                            }
-                        },
-                    }
-                },
-                None => {
-                    // Buffer has an invalid timestamp. Never index.
-                    false
-                },
-            };
-
-            // Per the index constraints defined in index.rs, if we are writing an index record now,
-            // we must flush any data writes prior to this buffer, so that reads do not block waiting on this writer.
-            let flush = include_in_index;
-            if flush {
-                writer.flush().map_err(|error| {
-                    gst::element_error!(element, gst::CoreError::Failed, ["Failed to flush Pravega data stream: {}", error]);
-                    gst::FlowError::Error
-                })?;
-            }
-
-            // Record a discontinuity if any of the following are true:
-            //   1) upstream has indicated a discontinuity (or resync) in the buffer
-            //   3) this will be the first buffer written to the data stream from this instance
-            //   2) this will be the first index record written from this instance
-            let discontinuity =
-                   buffer_flags.contains(gst::BufferFlags::DISCONT)
-                || buffer_flags.contains(gst::BufferFlags::RESYNC)
-                || *buffers_written == 0
-                || (include_in_index && last_index_time.nanoseconds().is_none());
-            if discontinuity {
-                gst_debug!(CAT, obj: element, "render: Recording discontinuity");
-            }
-
-            // Write index record.
-            // We write the index record before the buffer so that any readers blocked on reading the
-            // index will unblock as soon as possible.
-            if include_in_index {
-                let index_record = IndexRecord::new(timestamp, writer_offset,
-                    random_access, discontinuity);
-                let mut index_record_writer = IndexRecordWriter::new();
-                index_record_writer.write(&index_record, index_writer).map_err(|err| {
-                    gst::element_error!(
-                        element,
-                        gst::ResourceError::Write,
-                        ["Failed to write index: {}", err]
-                    );
-                    gst::FlowError::Error
-                })?;
-                gst_debug!(CAT, obj: element, "render: Wrote index record {:?}", index_record);
-                *last_index_time = timestamp;
-            }
-
-            // Write buffer to Pravega byte stream.
-            // If buffer is greater than ~8 MiB, it will be fragmented into multiple atomic writes, each with an EventHeader.
-            // Once fragmented, buffers will not be reassembled by pravegasrc.
-            // However, demuxers such as qtdemux can correctly handled fragmented buffers.
-            // In the event of an ungraceful pravegasink termination before all fragments are written,
-            // it will mark the first buffer after starting as a discontinuity,
-            // allowing elements downstream from pravegasrc to reinitialize.
-            let mut pos_to_write = 0;
-            loop {
-                let length_to_write = usize::min(payload.len() - pos_to_write, EventWithHeader::max_payload_size());
-                if length_to_write == 0 { break };
-                let event = if pos_to_write == 0 {
-                    EventWithHeader::new(&payload[pos_to_write..pos_to_write+length_to_write],
-                        timestamp, include_in_index, random_access, discontinuity)
-                } else {
-                    gst_debug!(CAT, obj: element, "render: buffer exceeds atomic write size and has been fragmented; writing additional payload of {} bytes", length_to_write);
-                    // Additional writes must not be indexed and must not be marked as a discontinuity as that would reset the demuxer.
-                    EventWithHeader::new(&payload[pos_to_write..pos_to_write+length_to_write],
-                        timestamp, false, false, false)
-                };
-                gst_memdump!(CAT, obj: element, "render: writing event={:?}", event);
-                let mut event_writer = EventWriter::new();
-                event_writer.write(&event, writer).map_err(|err| {
-                    gst::element_error!(
-                        element,
-                        gst::ResourceError::Write,
-                        ["Failed to write buffer: {}", err]
-                    );
-                    gst::FlowError::Error
-                })?;
-                pos_to_write += length_to_write;
-            }
-            *buffers_written += 1;
-
-            // Get the writer offset after writing.
-            let writer_offset_end = writer.seek(SeekFrom::Current(0)).unwrap();
-            gst_trace!(CAT, obj: element, "render: wrote {} bytes from offset {} to {}",
-                writer_offset_end - writer_offset, writer_offset, writer_offset_end);
-
-            // Flush after writing if the buffer contains the SYNC_AFTER flag. This is normally not used.
-            let sync_after = buffer_flags.contains(gst::BufferFlags::SYNC_AFTER);
-            if sync_after {
-                writer.flush().map_err(|error| {
-                    gst::element_error!(element, gst::CoreError::Failed, ["Failed to flush Pravega data stream: {}", error]);
-                    gst::FlowError::Error
-                })?;
-                index_writer.flush().map_err(|error| {
-                    gst::element_error!(element, gst::CoreError::Failed, ["Failed to flush Pravega index stream: {}", error]);
-                    gst::FlowError::Error
-                })?;
-                gst_debug!(CAT, obj: element, "render: Streams flushed because SYNC_AFTER flag was set");
-            }
+        if buffer.pts().is_some() {
+            *self.last_render_pts.lock().unwrap() = buffer.pts();
+        }
+        let queue = self.async_writer.lock().unwrap().as_ref().map(|async_writer| async_writer.queue.clone());
+        let mut recorder = self.recorder.lock().unwrap();
+        if let Some(recorder) = recorder.as_mut() {
+            let caps = element.static_pad("sink").and_then(|pad| pad.current_caps());
+            let queued_buffer = QueuedBuffer { buffer: buffer.clone(), caps };
+            return self.record_buffer(element, recorder, queue.as_deref(), queued_buffer);
+        }
+        drop(recorder);
+        match queue {
+            Some(queue) => {
+                let caps = element.static_pad("sink").and_then(|pad| pad.current_caps());
+                self.queue_buffer(element, &queue, QueuedBuffer { buffer: buffer.clone(), caps })
+            },
+            None => self.write_buffer(element, buffer),
+        }
+    }
 
-            // Maintain values that may be written to the index on end-of-stream.
-            // Per the index constraints defined in index.rs, the timestamp in the index record must
-            // be strictly greater than the timestamp in the data stream.
-            if timestamp.is_some() {
-                // If duration of the buffer is reported as 0, we record it as if it had a 1 nanosecond duration.
-                let duration = cmp::max(1, duration.nanoseconds().unwrap_or_default());
-                *final_timestamp = PravegaTimestamp::from_nanoseconds(
-                    timestamp.nanoseconds().map(|t| t + duration));
+    fn render_list(
+        &self,
+        element: &Self::Type,
+        list: &gst::BufferList,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        if self.async_writer.lock().unwrap().is_some() || self.recorder.lock().unwrap().is_some() {
+            // The writer thread and the recorder handle one buffer at a time.
+            for buffer in list.iter_owned() {
+                self.render(element, &buffer)?;
             }
-            *final_offset = Some(writer_offset_end);
-
-            Ok(gst::FlowSuccess::Ok)
-        })();
-        gst_trace!(CAT, obj: element, "render: END: result={:?}", result);
-        result
+            return Ok(gst::FlowSuccess::Ok);
+        }
+        let buffers: Vec<gst::Buffer> = list.iter_owned().collect();
+        if let Some(pts) = buffers.iter().rev().map(|buffer| buffer.pts()).find(|pts| pts.is_some()) {
+            *self.last_render_pts.lock().unwrap() = pts;
+        }
+        self.write_buffers(element, &buffers)
     }
 
     fn stop(&self, element: &Self::Type) -> Result<(), gst::ErrorMessage> {
         gst_info!(CAT, obj: element, "stop: BEGIN");
         let result = (|| {
-            let seal = {
+            let (seal, checksum, write_buffer_metadata, transactional) = {
                 let settings = self.settings.lock().unwrap();
-                settings.seal
+                (settings.seal, settings.checksum, settings.buffer_metadata, settings.transactional)
             };
 
+            // Buffers in the pre-roll buffer are discarded.
+            *self.recorder.lock().unwrap() = None;
+            *self.last_render_pts.lock().unwrap() = gst::ClockTime::none();
+            *self.reference_offset.lock().unwrap() = TimeDelta::none();
+
+            // Write all queued buffers and then stop the writer thread.
+            let async_writer = self.async_writer.lock().unwrap().take();
+            if let Some(async_writer) = async_writer {
+                async_writer.queue.close();
+                let _ = async_writer.thread_handle.join();
+                gst_info!(CAT, obj: element, "stop: Writer thread stopped");
+            }
+
+            // The pending batch is written below by finish_streams.
+            let coalesce_flusher = self.coalesce_flusher.lock().unwrap().take();
+            if let Some(coalesce_flusher) = coalesce_flusher {
+                let _ = coalesce_flusher.thread_stop_tx.send(());
+                let _ = coalesce_flusher.thread_handle.join();
+            }
+
             let mut state = self.state.lock().unwrap();
             let (writer,
                 index_writer,
-                client_factory,
+                caps_writer,
                 final_timestamp,
                 final_offset,
+                pending_index_record,
+                batch,
+                spooling,
                 retention_thread_stop_tx,
                 retention_thread_handle) = match *state {
                 State::Started {
                     ref mut writer,
                     ref mut index_writer,
-                    ref mut client_factory,
+                    ref mut caps_writer,
                     ref mut final_timestamp,
                     ref mut final_offset,
+                    ref mut pending_index_record,
+                    ref mut batch,
+                    ref spooling,
                     ref mut retention_thread_stop_tx,
                     ref mut retention_thread_handle,
                     ..
                 } => (writer,
                     index_writer,
-                    client_factory,
+                    caps_writer,
                     final_timestamp,
                     final_offset,
+                    pending_index_record,
+                    batch,
+                    spooling,
                     retention_thread_stop_tx,
                     retention_thread_handle),
                 State::Stopped => {
@@ -1139,46 +3426,14 @@ impl BaseSinkImpl for PravegaSink {
                 }
             };
 
-            writer.flush().map_err(|error| {
-                gst::error_msg!(gst::ResourceError::Write, ["Failed to flush Pravega data stream: {}", error])
-            })?;
-
-            // Write final index record.
-            // The timestamp will be the the buffer timestamp + duration of the final buffer.
-            // The offset will be current write position.
-            if let Some(final_offset) = *final_offset {
-                if final_timestamp.is_some() {
-                    let index_record = IndexRecord::new(*final_timestamp, final_offset,
-                        false, false);
-                    let mut index_record_writer = IndexRecordWriter::new();
-                    index_record_writer.write(&index_record, index_writer).map_err(|error| {
-                        gst::error_msg!(gst::ResourceError::Write, ["Failed to write Pravega index stream: {}", error])
-                    })?;
-                    gst_info!(CAT, obj: element, "stop: Wrote final index record {:?}", index_record);
-                }
-            }
-
-            index_writer.flush().map_err(|error| {
-                gst::error_msg!(gst::ResourceError::Write, ["Failed to flush Pravega index stream: {}", error])
-            })?;
-
-            if seal {
-                gst_info!(CAT, obj: element, "stop: Sealing streams");
-                let writer = writer.get_mut().get_mut().get_mut();
-                client_factory.runtime().block_on(writer.seal()).map_err(|error| {
-                    gst::error_msg!(gst::ResourceError::Write, ["Failed to seal Pravega data stream: {}", error])
-                })?;
-                client_factory.runtime().block_on(index_writer.seal()).map_err(|error| {
-                    gst::error_msg!(gst::ResourceError::Write, ["Failed to seal Pravega index stream: {}", error])
-                })?;
-                gst_info!(CAT, obj: element, "stop: Streams sealed");
+            if seal && spooling.is_some() {
+                gst_warning!(CAT, obj: element, "stop: Streams will not be sealed because Pravega is unavailable and data remains in the spool");
             }
+            let mut event_writer = EventWriter::with_checksum(checksum).with_buffer_metadata(write_buffer_metadata);
+            finish_streams(element, writer, index_writer, caps_writer, final_timestamp, final_offset, pending_index_record, batch,
+                &mut event_writer, transactional, seal && spooling.is_none())?;
 
-            // notify to stop the retention maintainer thread
-            if let Some(_) = retention_thread_handle {
-                let _ = retention_thread_stop_tx.send(());
-                retention_thread_handle.take().map(JoinHandle::join);
-            }
+            stop_retention_maintainer(retention_thread_stop_tx, retention_thread_handle);
 
             *state = State::Stopped;
             Ok(())
@@ -1186,4 +3441,59 @@ impl BaseSinkImpl for PravegaSink {
         gst_info!(CAT, obj: element, "stop: END: result={:?}", result);
         result
     }
+
+    fn event(&self, element: &Self::Type, event: gst::Event) -> bool {
+        let is_trigger = match event.view() {
+            gst::EventView::CustomDownstream(_) | gst::EventView::CustomDownstreamOob(_) => {
+                event.structure().map_or(false, |structure| structure.name() == TRIGGER_EVENT_NAME)
+            },
+            _ => false,
+        };
+        if is_trigger {
+            self.trigger(element);
+            return true;
+        }
+        if let gst::EventView::CustomDownstream(_) = event.view() {
+            if let Some(structure) = event.structure().filter(|structure| structure.name() == MARKER_EVENT_NAME) {
+                match structure.get::<String>("label") {
+                    Ok(label) => {
+                        let payload = structure.get::<String>("payload").unwrap_or_default();
+                        let pts = structure.get::<u64>("timestamp").map_or(gst::ClockTime::none(), gst::ClockTime::from_nseconds);
+                        self.add_marker(element, label, payload, pts);
+                    },
+                    Err(_) => {
+                        gst_warning!(CAT, obj: element, "event: Ignoring {} event without a label: {}", MARKER_EVENT_NAME, structure);
+                    },
+                }
+                return true;
+            }
+        }
+        if let gst::EventView::Eos(_) = event.view() {
+            // Ensure that all buffers have been written before end-of-stream is reported.
+            let queue = self.async_writer.lock().unwrap().as_ref().map(|async_writer| async_writer.queue.clone());
+            if let Some(queue) = queue {
+                gst_debug!(CAT, obj: element, "event: Waiting for write queue to drain");
+                if !queue.wait_until_empty() {
+                    gst_debug!(CAT, obj: element, "event: Stopped waiting for write queue because it is flushing");
+                }
+            }
+        }
+        self.parent_event(element, event)
+    }
+
+    fn unlock(&self, element: &Self::Type) -> Result<(), gst::ErrorMessage> {
+        gst_debug!(CAT, obj: element, "unlock");
+        if let Some(async_writer) = self.async_writer.lock().unwrap().as_ref() {
+            async_writer.queue.set_flushing(true);
+        }
+        Ok(())
+    }
+
+    fn unlock_stop(&self, element: &Self::Type) -> Result<(), gst::ErrorMessage> {
+        gst_debug!(CAT, obj: element, "unlock_stop");
+        if let Some(async_writer) = self.async_writer.lock().unwrap().as_ref() {
+            async_writer.queue.set_flushing(false);
+        }
+        Ok(())
+    }
 }
@@ -16,27 +16,33 @@ use glib::subclass::prelude::*;
 use gst::ClockTime;
 use gst::prelude::*;
 use gst::subclass::prelude::*;
-use gst::{gst_debug, gst_error, gst_info, gst_log, gst_trace, gst_memdump};
+use gst::{gst_debug, gst_error, gst_info, gst_log, gst_trace, gst_memdump, gst_warning};
 use gst_base::prelude::*;
 use gst_base::subclass::prelude::*;
 
+use std::cmp;
 use std::convert::{TryInto, TryFrom};
+use std::collections::VecDeque;
 use std::io::{BufReader, ErrorKind, Seek, SeekFrom};
 use std::sync::{Arc, Mutex};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::u8;
 
 use once_cell::sync::Lazy;
 
 use pravega_client::client_factory::ClientFactory;
-use pravega_client::byte::ByteReader;
-use pravega_client_shared::{Scope, Stream, StreamConfiguration, ScopedStream, Scaling, ScaleType};
-use pravega_video::event_serde::EventReader;
-use pravega_video::index::{IndexSearcher, get_index_stream_name};
+use pravega_video::caps::{CapsHistory, get_caps_stream_name};
+use pravega_video::event_serde::{ChecksumStatus, EventReader, FragmentReassembler, FragmentStatus};
+use pravega_video::index::{IndexSearcher, SearchFilter, get_index_stream_name};
+use pravega_video::marker::{MarkerHistory, get_marker_stream_name};
+use pravega_video::storage::{ByteStreamReader, PravegaStorage, StorageBackend, create_storage_from_uri};
 use pravega_video::timestamp::PravegaTimestamp;
 use pravega_video::utils;
+use pravega_video::utils::CurrentHead;
 use crate::counting_reader::CountingReader;
 use crate::seekable_take::SeekableTake;
-use crate::utils::{clocktime_to_pravega, pravega_to_clocktime};
+use crate::utils::{clocktime_to_pravega, pravega_to_clocktime, REFERENCE_TIMESTAMP_TAI, REFERENCE_TIMESTAMP_UNIX};
 
 const PROPERTY_NAME_STREAM: &str = "stream";
 const PROPERTY_NAME_CONTROLLER: &str = "controller";
@@ -47,8 +53,15 @@ const PROPERTY_NAME_START_TIMESTAMP: &str = "start-timestamp";
 const PROPERTY_NAME_END_TIMESTAMP: &str = "end-timestamp";
 const PROPERTY_NAME_START_UTC: &str = "start-utc";
 const PROPERTY_NAME_END_UTC: &str = "end-utc";
+const PROPERTY_NAME_START_MARKER: &str = "start-marker";
 const PROPERTY_NAME_ALLOW_CREATE_SCOPE: &str = "allow-create-scope";
 const PROPERTY_NAME_KEYCLOAK_FILE: &str = "keycloak-file";
+const PROPERTY_NAME_STORAGE_URI: &str = "storage-uri";
+const PROPERTY_NAME_CHECKSUM_MISMATCH_POLICY: &str = "checksum-mismatch-policy";
+const PROPERTY_NAME_CHECKSUM_MISMATCHES: &str = "checksum-mismatches";
+const PROPERTY_NAME_RESYNC_MODE: &str = "resync-mode";
+const PROPERTY_NAME_SPLIT_BATCHES: &str = "split-batches";
+const PROPERTY_NAME_TIMESTAMP_MODE: &str = "timestamp-mode";
 
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::GEnum)]
 #[repr(u32)]
@@ -79,6 +92,12 @@ pub enum StartMode {
         nick = "timestamp"
     )]
     Timestamp = 3,
+    #[genum(
+        name = "Start at the random-access point on or immediately before \
+                the most recent marker with the label specified by start-marker.",
+        nick = "marker"
+    )]
+    Marker = 4,
 }
 
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::GEnum)]
@@ -111,12 +130,88 @@ pub enum EndMode {
     Timestamp = 3,
 }
 
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::GEnum)]
+#[repr(u32)]
+#[genum(type_name = "GstChecksumMismatchPolicy")]
+pub enum ChecksumMismatchPolicy {
+    #[genum(
+        name = "Post an error message and stop.",
+        nick = "error"
+    )]
+    Error = 0,
+    #[genum(
+        name = "Post a warning message and drop the corrupt buffer. \
+                The next buffer will have the DISCONT flag.",
+        nick = "drop"
+    )]
+    Drop = 1,
+    #[genum(
+        name = "Post a warning message and output the corrupt buffer.",
+        nick = "warn"
+    )]
+    Warn = 2,
+}
+
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::GEnum)]
+#[repr(u32)]
+#[genum(type_name = "GstResyncMode")]
+pub enum ResyncMode {
+    #[genum(
+        name = "Do not validate event headers. \
+                An invalid event header will cause an error.",
+        nick = "none"
+    )]
+    None = 0,
+    #[genum(
+        name = "Validate each event header. \
+                If an event header is invalid, scan forward for the next valid event header.",
+        nick = "scan"
+    )]
+    Scan = 1,
+    #[genum(
+        name = "Validate each event header. \
+                If an event header is invalid, continue at the next random-access index record. \
+                If there is no such index record, scan forward for the next valid event header.",
+        nick = "index"
+    )]
+    Index = 2,
+}
+
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::GEnum)]
+#[repr(u32)]
+#[genum(type_name = "GstOutputTimestampMode")]
+pub enum OutputTimestampMode {
+    #[genum(
+        name = "Output buffer timestamps are nanoseconds \
+                since 1970-01-01 00:00:00 TAI International Atomic Time, including leap seconds.",
+        nick = "tai"
+    )]
+    Tai = 0,
+    #[genum(
+        name = "Output buffer timestamps are nanoseconds since the first random-access point in the stream, \
+                or its DTS if earlier, which has timestamp 0. \
+                Each buffer has a GstReferenceTimestampMeta with caps timestamp/x-tai and timestamp/x-unix \
+                containing the original absolute time. \
+                Seek positions are relative to it. \
+                The start-timestamp property is still nanoseconds since 1970-01-01 00:00:00 TAI.",
+        nick = "relative"
+    )]
+    Relative = 1,
+}
+
 const DEFAULT_CONTROLLER: &str = "127.0.0.1:9090";
 const DEFAULT_BUFFER_SIZE: usize = 128*1024;
 const DEFAULT_START_MODE: StartMode = StartMode::Earliest;
 const DEFAULT_END_MODE: EndMode = EndMode::Unbounded;
 const DEFAULT_START_TIMESTAMP: u64 = 0;
 const DEFAULT_END_TIMESTAMP: u64 = u64::MAX;
+const DEFAULT_CHECKSUM_MISMATCH_POLICY: ChecksumMismatchPolicy = ChecksumMismatchPolicy::Error;
+const DEFAULT_RESYNC_MODE: ResyncMode = ResyncMode::None;
+const DEFAULT_SPLIT_BATCHES: bool = true;
+const DEFAULT_TIMESTAMP_MODE: OutputTimestampMode = OutputTimestampMode::Tai;
+
+static REFERENCE_TIMESTAMP_TAI_CAPS: Lazy<gst::Caps> = Lazy::new(|| gst::Caps::new_simple(REFERENCE_TIMESTAMP_TAI, &[]));
+static REFERENCE_TIMESTAMP_UNIX_CAPS: Lazy<gst::Caps> = Lazy::new(|| gst::Caps::new_simple(REFERENCE_TIMESTAMP_UNIX, &[]));
 
 #[derive(Debug)]
 struct Settings {
@@ -128,8 +223,14 @@ struct Settings {
     end_mode: EndMode,
     start_timestamp: u64,
     end_timestamp: u64,
+    start_marker: Option<String>,
     allow_create_scope: bool,
     keycloak_file: Option<String>,
+    storage_uri: Option<String>,
+    checksum_mismatch_policy: ChecksumMismatchPolicy,
+    resync_mode: ResyncMode,
+    split_batches: bool,
+    timestamp_mode: OutputTimestampMode,
 }
 
 impl Default for Settings {
@@ -143,8 +244,14 @@ impl Default for Settings {
             end_mode: DEFAULT_END_MODE,
             start_timestamp: DEFAULT_START_TIMESTAMP,
             end_timestamp: DEFAULT_END_TIMESTAMP,
+            start_marker: None,
             allow_create_scope: true,
             keycloak_file: None,
+            storage_uri: None,
+            checksum_mismatch_policy: DEFAULT_CHECKSUM_MISMATCH_POLICY,
+            resync_mode: DEFAULT_RESYNC_MODE,
+            split_batches: DEFAULT_SPLIT_BATCHES,
+            timestamp_mode: DEFAULT_TIMESTAMP_MODE,
         }
     }
 }
@@ -152,11 +259,35 @@ impl Default for Settings {
 enum State {
     Stopped,
     Started {
-        reader: Arc<Mutex<CountingReader<BufReader<SeekableTake<ByteReader>>>>>,
-        index_searcher: Arc<Mutex<IndexSearcher<ByteReader>>>,
+        reader: Arc<Mutex<CountingReader<BufReader<SeekableTake<Box<dyn ByteStreamReader>>>>>>,
+        index_searcher: Arc<Mutex<IndexSearcher<Box<dyn ByteStreamReader>>>>,
+        // None if the stream has no caps stream.
+        caps_history: Arc<Mutex<Option<CapsHistory<Box<dyn ByteStreamReader>>>>>,
+        checksum_mismatch_policy: ChecksumMismatchPolicy,
+        resync_mode: ResyncMode,
+        split_batches: bool,
+        timestamp_mode: OutputTimestampMode,
+        // If start-mode=marker, the timestamp of the marker located when starting.
+        start_marker_timestamp: PravegaTimestamp,
     },
 }
 
+/// The group of pictures being read during reverse playback.
+/// Groups of pictures are read in reverse order. Events within a group of pictures are read in forward order.
+#[derive(Debug)]
+struct ReverseGop {
+    // The data stream offset of the random-access event that begins the group of pictures.
+    start_offset: u64,
+    // The data stream offset after the last event in the group of pictures.
+    end_offset: u64,
+    // The timestamp of the random-access index record at start_offset.
+    timestamp: PravegaTimestamp,
+    // Reading ends after the group of pictures that begins on or before this timestamp.
+    segment_start: PravegaTimestamp,
+    // True until the first buffer of the group of pictures has been output.
+    first: bool,
+}
+
 impl Default for State {
     fn default() -> State {
         State::Stopped
@@ -166,6 +297,24 @@ impl Default for State {
 pub struct PravegaSrc {
     settings: Mutex<Settings>,
     state: Mutex<State>,
+    // The number of events that failed checksum verification since this element was created.
+    checksum_mismatches: AtomicU64,
+    // All events before this offset in the data stream were read before the caps history was last refreshed,
+    // so their caps records have been read. pravegasink writes a caps record before the event it applies to.
+    caps_known_offset: AtomicU64,
+    // Buffers split from a batch that have not been returned by create yet.
+    pending_buffers: Mutex<VecDeque<gst::Buffer>>,
+    // If timestamp-mode=relative, the timestamp that is output as 0.
+    // This is the first random-access point in the stream when starting,
+    // or the first buffer if the index is empty.
+    origin: Mutex<PravegaTimestamp>,
+    // Set to true by the first seek after starting.
+    seeked: AtomicBool,
+    // Set to true when the segment has the TRICKMODE_KEY_UNITS flag.
+    // Only random-access events at indexed positions will be output.
+    key_units: AtomicBool,
+    // Present when the segment has a negative rate.
+    reverse_gop: Mutex<Option<ReverseGop>>,
 }
 
 static CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
@@ -215,6 +364,295 @@ impl PravegaSrc {
         settings.controller = controller;
         Ok(())
     }
+
+    /// Find the offset at which reading should continue after an invalid event header at offset.
+    /// On success, the reader will be positioned at the returned offset.
+    fn resync(
+        &self,
+        element: &super::PravegaSrc,
+        reader: &mut CountingReader<BufReader<SeekableTake<Box<dyn ByteStreamReader>>>>,
+        index_searcher: &Mutex<IndexSearcher<Box<dyn ByteStreamReader>>>,
+        resync_mode: ResyncMode,
+        offset: u64,
+    ) -> Result<u64, std::io::Error> {
+        if resync_mode == ResyncMode::Index {
+            let index_record = index_searcher.lock().unwrap().search_offset_after(offset, SearchFilter::RandomAccess);
+            gst_debug!(CAT, obj: element, "resync: index_record={:?}", index_record);
+            match index_record {
+                Ok(index_record) => {
+                    return reader.seek(SeekFrom::Start(index_record.offset));
+                },
+                Err(err) if err.kind() == ErrorKind::NotFound => {
+                    gst_info!(CAT, obj: element, "resync: no random-access index record after offset {}; scanning", offset);
+                },
+                Err(err) => return Err(err),
+            }
+        }
+        EventReader::new().scan_for_header(reader, offset + 1)
+    }
+
+    /// Set the caps of the src pad to the caps recorded for the event at offset in the data stream.
+    /// If the event was read after the caps history was last refreshed, new caps records will be read from the caps stream first.
+    /// read_offset is the offset in the data stream up to which bytes have been read, including buffered bytes.
+    /// Streams written without caps records will not have caps.
+    fn update_caps(
+        &self,
+        element: &super::PravegaSrc,
+        caps_history: &Mutex<Option<CapsHistory<Box<dyn ByteStreamReader>>>>,
+        offset: u64,
+        read_offset: u64,
+    ) -> Result<(), gst::FlowError> {
+        let mut caps_history = caps_history.lock().unwrap();
+        let caps_history = match caps_history.as_mut() {
+            Some(caps_history) => caps_history,
+            None => return Ok(()),
+        };
+        if offset >= self.caps_known_offset.load(Ordering::Relaxed) {
+            caps_history.refresh().map_err(|err| {
+                gst::element_error!(element, gst::ResourceError::Read, ["Failed to read caps stream: {}", err]);
+                gst::FlowError::Error
+            })?;
+            self.caps_known_offset.fetch_max(read_offset, Ordering::Relaxed);
+            gst_debug!(CAT, obj: element, "update_caps: refreshed caps history at offset {}; caps_known_offset={}",
+                offset, self.caps_known_offset.load(Ordering::Relaxed));
+        }
+        if let Some(caps_record) = caps_history.caps_at_offset(offset) {
+            let caps = gst::Caps::from_str(&caps_record.caps).map_err(|err| {
+                gst::element_error!(element, gst::StreamError::Format, ["Failed to parse caps '{}': {}", caps_record.caps, err]);
+                gst::FlowError::Error
+            })?;
+            let current_caps = element.static_pad("src").unwrap().current_caps();
+            if current_caps.as_ref() != Some(&caps) {
+                gst_info!(CAT, obj: element, "update_caps: setting caps from {:?}", caps_record);
+                element.set_caps(&caps).map_err(|err| {
+                    gst::element_error!(element, gst::CoreError::Negotiation, ["Failed to set caps {}: {}", caps, err]);
+                    gst::FlowError::NotNegotiated
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// If timestamp-mode=relative and the origin has not been determined, set it to the first random-access point in the index.
+    /// If the event at the first random-access point has an earlier DTS, the origin is set to the DTS instead.
+    /// This is the earliest DTS in the first group of pictures because DTS increases in decoding order,
+    /// so no output PTS or DTS will precede the origin.
+    /// The reader is returned to its original position.
+    /// Returns the origin, which is None if timestamp-mode=tai or the index is empty.
+    fn resolve_origin(
+        &self,
+        element: &super::PravegaSrc,
+        reader: &mut CountingReader<BufReader<SeekableTake<Box<dyn ByteStreamReader>>>>,
+        index_searcher: &mut IndexSearcher<Box<dyn ByteStreamReader>>,
+        timestamp_mode: OutputTimestampMode,
+    ) -> PravegaTimestamp {
+        let mut origin = self.origin.lock().unwrap();
+        if timestamp_mode == OutputTimestampMode::Relative && origin.is_none() {
+            if let Ok(index_record) = index_searcher.get_first_record(SearchFilter::RandomAccess) {
+                let dts = (|| -> std::io::Result<PravegaTimestamp> {
+                    let position = reader.stream_position()?;
+                    reader.seek(SeekFrom::Start(index_record.offset))?;
+                    let mut event_reader = EventReader::new();
+                    let required_buffer_length = event_reader.read_required_buffer_length(reader);
+                    let dts = required_buffer_length.and_then(|required_buffer_length| {
+                        let mut read_buffer: Vec<u8> = vec![0; required_buffer_length];
+                        let event = event_reader.read_event(reader, &mut read_buffer[..])?;
+                        Ok(event.header.buffer_metadata.map_or(PravegaTimestamp::NONE, |buffer_metadata| buffer_metadata.dts))
+                    });
+                    reader.seek(SeekFrom::Start(position))?;
+                    dts
+                })().unwrap_or_else(|err| {
+                    gst_warning!(CAT, obj: element, "resolve_origin: unable to read DTS at offset {}: {}", index_record.offset, err);
+                    PravegaTimestamp::NONE
+                });
+                *origin = earliest_timestamp(index_record.timestamp, dts);
+                gst_info!(CAT, obj: element, "resolve_origin: origin={:?}, index_record={:?}, dts={:?}", *origin, index_record, dts);
+            }
+        }
+        *origin
+    }
+
+    /// In key-unit trick mode, position the reader at the next random-access index record after offset.
+    /// If there is no such index record, the reader is not moved and reading continues sequentially.
+    fn skip_to_next_key_unit(
+        &self,
+        element: &super::PravegaSrc,
+        reader: &mut CountingReader<BufReader<SeekableTake<Box<dyn ByteStreamReader>>>>,
+        index_searcher: &Mutex<IndexSearcher<Box<dyn ByteStreamReader>>>,
+        offset: u64,
+    ) -> Result<(), gst::FlowError> {
+        let index_record = index_searcher.lock().unwrap().search_offset_after(offset, SearchFilter::RandomAccess);
+        gst_debug!(CAT, obj: element, "skip_to_next_key_unit: index_record={:?}", index_record);
+        match index_record {
+            Ok(index_record) => {
+                reader.seek(SeekFrom::Start(index_record.offset)).map_err(|err| {
+                    gst::element_error!(element, gst::ResourceError::Seek, ["Failed to seek to offset {}: {}", index_record.offset, err]);
+                    gst::FlowError::Error
+                })?;
+            },
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                gst_debug!(CAT, obj: element, "skip_to_next_key_unit: no random-access index record after offset {}", offset);
+            },
+            Err(err) => {
+                gst_warning!(CAT, obj: element, "skip_to_next_key_unit: failed to search index: {}", err);
+            },
+        }
+        Ok(())
+    }
+
+    /// Prepare for reverse playback by locating the group of pictures that contains the segment stop position.
+    /// If the segment has no stop position, reverse playback begins at the end of the indexed data.
+    /// The segment start, time, stop, and position will be set using the output time.
+    fn seek_reverse(
+        &self,
+        element: &super::PravegaSrc,
+        reader: &mut CountingReader<BufReader<SeekableTake<Box<dyn ByteStreamReader>>>>,
+        index_searcher: &mut IndexSearcher<Box<dyn ByteStreamReader>>,
+        segment: &mut gst::FormattedSegment<gst::format::Time>,
+        origin: PravegaTimestamp,
+    ) -> bool {
+        let stop_timestamp = if segment.stop().is_some() {
+            from_input_clocktime(segment.stop(), origin)
+        } else {
+            match index_searcher.get_last_record(SearchFilter::Any) {
+                Ok(index_record) => index_record.timestamp,
+                Err(err) => {
+                    gst_error!(CAT, obj: element, "seek_reverse: Unable to get last record from index: {}", err);
+                    return false;
+                },
+            }
+        };
+        let start_timestamp = from_input_clocktime(segment.start(), origin);
+        gst_info!(CAT, obj: element, "seek_reverse: start_timestamp={:?}, stop_timestamp={:?}", start_timestamp, stop_timestamp);
+        let index_record = match index_searcher.search_timestamp(stop_timestamp, SearchFilter::RandomAccess) {
+            Ok(index_record) => index_record,
+            Err(err) => {
+                gst_error!(CAT, obj: element, "seek_reverse: Unable to search index: {}", err);
+                return false;
+            },
+        };
+        gst_info!(CAT, obj: element, "seek_reverse: index_record={:?}", index_record);
+        let end_offset = match index_searcher.search_offset_after(index_record.offset, SearchFilter::RandomAccess) {
+            Ok(next_index_record) => next_index_record.offset,
+            Err(err) if err.kind() == ErrorKind::NotFound => match reader.seek(SeekFrom::End(0)) {
+                Ok(end_offset) => end_offset,
+                Err(err) => {
+                    gst_error!(CAT, obj: element, "seek_reverse: Unable to seek to end of data stream: {}", err);
+                    return false;
+                },
+            },
+            Err(err) => {
+                gst_error!(CAT, obj: element, "seek_reverse: Unable to search index: {}", err);
+                return false;
+            },
+        };
+        if let Err(err) = reader.seek(SeekFrom::Start(index_record.offset)) {
+            gst_error!(CAT, obj: element, "seek_reverse: Unable to seek to offset {}: {}", index_record.offset, err);
+            return false;
+        }
+        segment.set_start(to_output_clocktime(start_timestamp, origin));
+        segment.set_time(to_output_clocktime(start_timestamp, origin));
+        segment.set_stop(to_output_clocktime(stop_timestamp, origin));
+        segment.set_position(to_output_clocktime(stop_timestamp, origin));
+        let gop = ReverseGop {
+            start_offset: index_record.offset,
+            end_offset,
+            timestamp: index_record.timestamp,
+            segment_start: start_timestamp,
+            first: true,
+        };
+        gst_info!(CAT, obj: element, "seek_reverse: gop={:?}, segment={:?}", gop, segment);
+        *self.reverse_gop.lock().unwrap() = Some(gop);
+        true
+    }
+
+    /// During reverse playback, position the reader at the group of pictures before the current one.
+    /// Returns Eos if the current group of pictures begins on or before the segment start or is the first in the index.
+    fn previous_gop(
+        &self,
+        element: &super::PravegaSrc,
+        reader: &mut CountingReader<BufReader<SeekableTake<Box<dyn ByteStreamReader>>>>,
+        index_searcher: &Mutex<IndexSearcher<Box<dyn ByteStreamReader>>>,
+        gop: &mut ReverseGop,
+    ) -> Result<(), gst::FlowError> {
+        if gop.timestamp <= gop.segment_start {
+            gst_info!(CAT, obj: element, "previous_gop: reached segment start");
+            return Err(gst::FlowError::Eos);
+        }
+        let index_record = index_searcher.lock().unwrap().search_offset_before(gop.start_offset, SearchFilter::RandomAccess);
+        gst_debug!(CAT, obj: element, "previous_gop: index_record={:?}", index_record);
+        match index_record {
+            Ok(index_record) => {
+                reader.seek(SeekFrom::Start(index_record.offset)).map_err(|err| {
+                    gst::element_error!(element, gst::ResourceError::Seek, ["Failed to seek to offset {}: {}", index_record.offset, err]);
+                    gst::FlowError::Error
+                })?;
+                gop.end_offset = gop.start_offset;
+                gop.start_offset = index_record.offset;
+                gop.timestamp = index_record.timestamp;
+                gop.first = true;
+                Ok(())
+            },
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                gst_info!(CAT, obj: element, "previous_gop: reached first random-access index record");
+                Err(gst::FlowError::Eos)
+            },
+            Err(err) => {
+                gst::element_error!(element, gst::ResourceError::Read, ["Failed to search index: {}", err]);
+                Err(gst::FlowError::Error)
+            },
+        }
+    }
+
+    /// Discard an incomplete chain of fragments, if any, and post a warning message.
+    /// Returns true if fragments were discarded.
+    fn discard_fragments(
+        &self,
+        element: &super::PravegaSrc,
+        fragment_reassembler: &mut FragmentReassembler,
+        offset: u64,
+        reason: &str,
+    ) -> bool {
+        if !fragment_reassembler.is_incomplete() {
+            return false;
+        }
+        let fragment_count = fragment_reassembler.fragment_count();
+        let discarded_bytes = fragment_reassembler.reset();
+        let message = format!("Discarded incomplete chain of {} fragments ({} bytes) before offset {}: {}",
+            fragment_count, discarded_bytes, offset, reason);
+        gst_warning!(CAT, obj: element, "{}", message);
+        gst::element_warning!(element, gst::StreamError::Demux, ["{}", message]);
+        true
+    }
+}
+
+/// Returns the earlier of two timestamps, ignoring timestamps that are None.
+fn earliest_timestamp(a: PravegaTimestamp, b: PravegaTimestamp) -> PravegaTimestamp {
+    match (a.is_some(), b.is_some()) {
+        (true, true) => cmp::min(a, b),
+        (true, false) => a,
+        _ => b,
+    }
+}
+
+/// Converts a Pravega timestamp to an output PTS, DTS, or segment time.
+/// If origin is not None, the result is relative to origin.
+fn to_output_clocktime(timestamp: PravegaTimestamp, origin: PravegaTimestamp) -> ClockTime {
+    match origin.nanoseconds() {
+        Some(origin) => ClockTime(timestamp.nanoseconds().map(|t| t.saturating_sub(origin))),
+        None => pravega_to_clocktime(timestamp),
+    }
+}
+
+/// Converts an input segment time to a Pravega timestamp.
+/// If origin is not None, the time is relative to origin.
+fn from_input_clocktime(time: ClockTime, origin: PravegaTimestamp) -> PravegaTimestamp {
+    match (time.nanoseconds(), origin.nanoseconds()) {
+        (Some(time), Some(origin)) => {
+            PravegaTimestamp::from_nanoseconds(Some(origin.saturating_add(time)))
+        },
+        _ => clocktime_to_pravega(time),
+    }
 }
 
 #[glib::object_subclass]
@@ -228,6 +666,13 @@ impl ObjectSubclass for PravegaSrc {
         Self {
             settings: Mutex::new(Default::default()),
             state: Mutex::new(Default::default()),
+            checksum_mismatches: AtomicU64::new(0),
+            caps_known_offset: AtomicU64::new(0),
+            pending_buffers: Mutex::new(VecDeque::new()),
+            origin: Mutex::new(PravegaTimestamp::NONE),
+            seeked: AtomicBool::new(false),
+            key_units: AtomicBool::new(false),
+            reverse_gop: Mutex::new(None),
         }
     }
 }
@@ -315,6 +760,14 @@ impl ObjectImpl for PravegaSrc {
                 None,
                 glib::ParamFlags::WRITABLE,
             ),
+            glib::ParamSpec::new_string(
+                PROPERTY_NAME_START_MARKER,
+                "Start marker",
+                "If start-mode=marker, this is the label of the marker at which to start. \
+                If there are multiple markers with this label, the most recent is used.",
+                None,
+                glib::ParamFlags::WRITABLE,
+            ),
             glib::ParamSpec::new_boolean(
                 PROPERTY_NAME_ALLOW_CREATE_SCOPE,
                 "Allow create scope",
@@ -329,6 +782,56 @@ impl ObjectImpl for PravegaSrc {
                 None,
                 glib::ParamFlags::WRITABLE,
             ),
+            glib::ParamSpec::new_string(
+                PROPERTY_NAME_STORAGE_URI,
+                "Storage URI",
+                "If missing or empty, streams will be read from Pravega. If a file URI such as file:///var/lib/video, streams will be read from this local directory. If memory://name, streams will be read from the memory of this process, which is useful for testing. The controller will not be used if this is set.",
+                None,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_enum(
+                PROPERTY_NAME_CHECKSUM_MISMATCH_POLICY,
+                "Checksum mismatch policy",
+                "The action to take when an event fails checksum verification",
+                ChecksumMismatchPolicy::static_type(),
+                DEFAULT_CHECKSUM_MISMATCH_POLICY as i32,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_uint64(
+                PROPERTY_NAME_CHECKSUM_MISMATCHES,
+                "Checksum mismatches",
+                "The number of events that failed checksum verification",
+                0,
+                std::u64::MAX,
+                0,
+                glib::ParamFlags::READABLE,
+            ),
+            glib::ParamSpec::new_enum(
+                PROPERTY_NAME_RESYNC_MODE,
+                "Resync mode",
+                "Determines how to continue reading after an invalid event header. \
+                This can be used to recover from a corrupt data stream.",
+                ResyncMode::static_type(),
+                DEFAULT_RESYNC_MODE as i32,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_boolean(
+                PROPERTY_NAME_SPLIT_BATCHES,
+                "Split batches",
+                "If true, events containing buffers coalesced by pravegasink will be split into the original buffers. \
+                Only the first buffer of each event will have a timestamp. \
+                If false, each event will be emitted as a single buffer, which is suitable only for byte-stream formats such as MPEG TS.",
+                DEFAULT_SPLIT_BATCHES,
+                glib::ParamFlags::WRITABLE,
+            ),
+            glib::ParamSpec::new_enum(
+                PROPERTY_NAME_TIMESTAMP_MODE,
+                "Timestamp mode",
+                "Timestamp mode used by the output",
+                OutputTimestampMode::static_type(),
+                DEFAULT_TIMESTAMP_MODE as i32,
+                glib::ParamFlags::WRITABLE,
+            ),
         ]});
         PROPERTIES.as_ref()
     }
@@ -471,6 +974,23 @@ impl ObjectImpl for PravegaSrc {
                     gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_ALLOW_CREATE_SCOPE, err);
                 }
             },
+            PROPERTY_NAME_START_MARKER => {
+                let res: Result<(), glib::Error> = match value.get::<String>() {
+                    Ok(start_marker) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.start_marker = if start_marker.is_empty() {
+                            None
+                        } else {
+                            Some(start_marker)
+                        };
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_START_MARKER, err);
+                }
+            },
             PROPERTY_NAME_KEYCLOAK_FILE => {
                 let res: Result<(), glib::Error> = match value.get::<String>() {
                     Ok(keycloak_file) => {
@@ -488,9 +1008,87 @@ impl ObjectImpl for PravegaSrc {
                     gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_KEYCLOAK_FILE, err);
                 }
             },
+            PROPERTY_NAME_STORAGE_URI => {
+                let res: Result<(), glib::Error> = match value.get::<String>() {
+                    Ok(storage_uri) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.storage_uri = if storage_uri.is_empty() {
+                            None
+                        } else {
+                            Some(storage_uri)
+                        };
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_STORAGE_URI, err);
+                }
+            },
+            PROPERTY_NAME_CHECKSUM_MISMATCH_POLICY => {
+                let res: Result<(), glib::Error> = match value.get::<ChecksumMismatchPolicy>() {
+                    Ok(checksum_mismatch_policy) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.checksum_mismatch_policy = checksum_mismatch_policy;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_CHECKSUM_MISMATCH_POLICY, err);
+                }
+            },
+            PROPERTY_NAME_RESYNC_MODE => {
+                let res: Result<(), glib::Error> = match value.get::<ResyncMode>() {
+                    Ok(resync_mode) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.resync_mode = resync_mode;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_RESYNC_MODE, err);
+                }
+            },
+            PROPERTY_NAME_SPLIT_BATCHES => {
+                let res: Result<(), glib::Error> = match value.get::<bool>() {
+                    Ok(split_batches) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.split_batches = split_batches;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_SPLIT_BATCHES, err);
+                }
+            },
+            PROPERTY_NAME_TIMESTAMP_MODE => {
+                let res: Result<(), glib::Error> = match value.get::<OutputTimestampMode>() {
+                    Ok(timestamp_mode) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        settings.timestamp_mode = timestamp_mode;
+                        Ok(())
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_TIMESTAMP_MODE, err);
+                }
+            },
         _ => unimplemented!(),
         };
     }
+
+    fn property(&self, _obj: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            PROPERTY_NAME_CHECKSUM_MISMATCHES => {
+                self.checksum_mismatches.load(Ordering::Relaxed).to_value()
+            },
+            _ => unimplemented!(),
+        }
+    }
 }
 
 impl ElementImpl for PravegaSrc {
@@ -540,90 +1138,118 @@ impl BaseSrcImpl for PravegaSrc {
                 gst::error_msg!(gst::ResourceError::Settings, ["Stream is not defined"])
             })?;
             let index_stream_name = get_index_stream_name(&stream_name);
-            let scope = Scope::from(scope_name);
-            let stream = Stream::from(stream_name);
-            let index_stream = Stream::from(index_stream_name);
-            gst_info!(CAT, obj: element, "start: scope={}, stream={}, index_stream={}", scope, stream, index_stream);
+            let caps_stream_name = get_caps_stream_name(&stream_name);
+            let marker_stream_name = get_marker_stream_name(&stream_name);
+            gst_info!(CAT, obj: element, "start: scope={}, stream={}, index_stream={}, caps_stream={}, marker_stream={}",
+                scope_name, stream_name, index_stream_name, caps_stream_name, marker_stream_name);
             gst_info!(CAT, obj: element, "start: start_mode={:?}, end_mode={:?}", settings.start_mode, settings.end_mode);
+            gst_info!(CAT, obj: element, "start: resync_mode={:?}", settings.resync_mode);
+            gst_info!(CAT, obj: element, "start: split_batches={}", settings.split_batches);
+            gst_info!(CAT, obj: element, "start: timestamp_mode={:?}", settings.timestamp_mode);
 
-            let controller = settings.controller.clone().ok_or_else(|| {
-                gst::error_msg!(gst::ResourceError::Settings, ["Controller is not defined"])
-            })?;
-            gst_info!(CAT, obj: element, "start: controller={}", controller);
-            let keycloak_file = settings.keycloak_file.clone();
-            gst_info!(CAT, obj: element, "start: keycloak_file={:?}", keycloak_file);
-            let config = utils::create_client_config(controller, keycloak_file).map_err(|error| {
-                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create Pravega client config: {}", error])
-            })?;
-            gst_debug!(CAT, obj: element, "start: config={:?}", config);
-            gst_info!(CAT, obj: element, "start: controller_uri={}:{}", config.controller_uri.domain_name(), config.controller_uri.port());
-            gst_info!(CAT, obj: element, "start: is_tls_enabled={}", config.is_tls_enabled);
-            gst_info!(CAT, obj: element, "start: is_auth_enabled={}", config.is_auth_enabled);
-
-            let client_factory = ClientFactory::new(config);
-            let controller_client = client_factory.controller_client();
-            let runtime = client_factory.runtime();
+            let storage: Box<dyn StorageBackend> = match settings.storage_uri.clone() {
+                Some(storage_uri) => {
+                    gst_info!(CAT, obj: element, "start: storage_uri={}", storage_uri);
+                    create_storage_from_uri(&storage_uri).map_err(|error| {
+                        gst::error_msg!(gst::ResourceError::Settings, ["Failed to create storage: {}", error])
+                    })?
+                },
+                None => {
+                    let controller = settings.controller.clone().ok_or_else(|| {
+                        gst::error_msg!(gst::ResourceError::Settings, ["Controller is not defined"])
+                    })?;
+                    gst_info!(CAT, obj: element, "start: controller={}", controller);
+                    let keycloak_file = settings.keycloak_file.clone();
+                    gst_info!(CAT, obj: element, "start: keycloak_file={:?}", keycloak_file);
+                    let config = utils::create_client_config(controller, keycloak_file).map_err(|error| {
+                        gst::error_msg!(gst::ResourceError::Settings, ["Failed to create Pravega client config: {}", error])
+                    })?;
+                    gst_debug!(CAT, obj: element, "start: config={:?}", config);
+                    gst_info!(CAT, obj: element, "start: controller_uri={}:{}", config.controller_uri.domain_name(), config.controller_uri.port());
+                    gst_info!(CAT, obj: element, "start: is_tls_enabled={}", config.is_tls_enabled);
+                    gst_info!(CAT, obj: element, "start: is_auth_enabled={}", config.is_auth_enabled);
+                    Box::new(PravegaStorage::new(ClientFactory::new(config)))
+                },
+            };
 
             // Create scope.
             gst_info!(CAT, obj: element, "start: allow_create_scope={}", settings.allow_create_scope);
             if settings.allow_create_scope {
-                runtime.block_on(controller_client.create_scope(&scope)).map_err(|error| {
-                    gst::error_msg!(gst::ResourceError::Settings, ["Failed to create Pravega scope: {:?}", error])
+                storage.create_scope(&scope_name).map_err(|error| {
+                    gst::error_msg!(gst::ResourceError::Settings, ["Failed to create scope: {}", error])
                 })?;
             }
 
             // Create data stream.
-            let stream_config = StreamConfiguration {
-                scoped_stream: ScopedStream {
-                    scope: scope.clone(),
-                    stream: stream.clone(),
-                },
-                scaling: Scaling {
-                    scale_type: ScaleType::FixedNumSegments,
-                    min_num_segments: 1,
-                    ..Default::default()
-                },
-                retention: Default::default(),
-                tags: utils::get_video_tags(),
-            };
-            runtime.block_on(controller_client.create_stream(&stream_config)).map_err(|error| {
-                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create Pravega data stream: {:?}", error])
+            storage.create_stream(&scope_name, &stream_name, utils::get_video_tags()).map_err(|error| {
+                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create data stream: {}", error])
             })?;
 
             // Create index stream.
-            let index_stream_config = StreamConfiguration {
-                scoped_stream: ScopedStream {
-                    scope: scope.clone(),
-                    stream: index_stream.clone(),
-                },
-                scaling: Scaling {
-                    scale_type: ScaleType::FixedNumSegments,
-                    min_num_segments: 1,
-                    ..Default::default()
-                },
-                retention: Default::default(),
-                tags: None,
-            };
-            runtime.block_on(controller_client.create_stream(&index_stream_config)).map_err(|error| {
-                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create Pravega index stream: {:?}", error])
+            storage.create_stream(&scope_name, &index_stream_name, None).map_err(|error| {
+                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create index stream: {}", error])
             })?;
 
-            let scoped_stream = ScopedStream {
-                scope: scope.clone(),
-                stream: stream.clone(),
-            };
-            let mut reader = client_factory.create_byte_reader(scoped_stream);
-            gst_info!(CAT, obj: element, "start: Opened Pravega reader for data");
+            let mut reader = storage.create_reader(&scope_name, &stream_name).map_err(|error| {
+                gst::error_msg!(gst::ResourceError::OpenRead, ["Failed to open reader for data stream: {}", error])
+            })?;
+            gst_info!(CAT, obj: element, "start: Opened reader for data");
 
-            let index_scoped_stream = ScopedStream {
-                scope: scope.clone(),
-                stream: index_stream.clone(),
-            };
-            let index_reader = client_factory.create_byte_reader(index_scoped_stream);
-            gst_info!(CAT, obj: element, "start: Opened Pravega reader for index");
+            let index_reader = storage.create_reader(&scope_name, &index_stream_name).map_err(|error| {
+                gst::error_msg!(gst::ResourceError::OpenRead, ["Failed to open reader for index stream: {}", error])
+            })?;
+            gst_info!(CAT, obj: element, "start: Opened reader for index");
 
             let mut index_searcher = IndexSearcher::new(index_reader);
 
+            // Streams written by older versions of pravegasink do not have a caps stream.
+            let caps_stream_exists = storage.stream_exists(&scope_name, &caps_stream_name).map_err(|error| {
+                gst::error_msg!(gst::ResourceError::OpenRead, ["Failed to check caps stream: {}", error])
+            })?;
+            let caps_history = if caps_stream_exists {
+                let caps_reader = storage.create_reader(&scope_name, &caps_stream_name).map_err(|error| {
+                    gst::error_msg!(gst::ResourceError::OpenRead, ["Failed to open reader for caps stream: {}", error])
+                })?;
+                gst_info!(CAT, obj: element, "start: Opened reader for caps");
+                let mut caps_history = CapsHistory::new(caps_reader);
+                caps_history.refresh().map_err(|error| {
+                    gst::error_msg!(gst::ResourceError::Read, ["Failed to read Pravega caps stream: {}", error])
+                })?;
+                gst_info!(CAT, obj: element, "start: caps_records={:?}", caps_history.records());
+                Some(caps_history)
+            } else {
+                gst_info!(CAT, obj: element, "start: caps stream does not exist");
+                None
+            };
+
+            // Locate the marker at which to start.
+            let start_marker_timestamp = if settings.start_mode == StartMode::Marker {
+                let start_marker = settings.start_marker.clone().ok_or_else(|| {
+                    gst::error_msg!(gst::ResourceError::Settings, ["{} is not defined", PROPERTY_NAME_START_MARKER])
+                })?;
+                // Streams written by older versions of pravegasink do not have a marker stream.
+                let marker_stream_exists = storage.stream_exists(&scope_name, &marker_stream_name).map_err(|error| {
+                    gst::error_msg!(gst::ResourceError::OpenRead, ["Failed to check marker stream: {}", error])
+                })?;
+                if !marker_stream_exists {
+                    return Err(gst::error_msg!(gst::ResourceError::NotFound, ["Marker with label '{}' was not found", start_marker]));
+                }
+                let marker_reader = storage.create_reader(&scope_name, &marker_stream_name).map_err(|error| {
+                    gst::error_msg!(gst::ResourceError::OpenRead, ["Failed to open reader for marker stream: {}", error])
+                })?;
+                let mut marker_history = MarkerHistory::new(marker_reader);
+                marker_history.refresh().map_err(|error| {
+                    gst::error_msg!(gst::ResourceError::Read, ["Failed to read Pravega marker stream: {}", error])
+                })?;
+                let marker_record = marker_history.last_with_label(&start_marker).ok_or_else(|| {
+                    gst::error_msg!(gst::ResourceError::NotFound, ["Marker with label '{}' was not found", start_marker])
+                })?;
+                gst_info!(CAT, obj: element, "start: start marker_record={:?}", marker_record);
+                marker_record.timestamp
+            } else {
+                PravegaTimestamp::NONE
+            };
+
             // TODO: Run below based on CAT threshold.
             // gst_debug!(CAT, obj: element, "index_records={:?}", index_searcher.get_index_records());
 
@@ -637,14 +1263,15 @@ impl BaseSrcImpl for PravegaSrc {
                 },
                 EndMode::LatestIndexed => {
                     // Determine Pravega stream offset for this timestamp by searching the index.
-                    let index_record = index_searcher.get_last_record().unwrap();
+                    // This includes the final index record written at end-of-stream.
+                    let index_record = index_searcher.get_last_record(SearchFilter::Any).unwrap();
                     gst_info!(CAT, obj: element, "start: end index_record={:?}", index_record);
                     index_record.offset
                 },
                 EndMode::Timestamp => {
                     let end_timestamp = PravegaTimestamp::from_nanoseconds(Some(settings.end_timestamp));
                     // Determine Pravega stream offset for this timestamp by searching the index.
-                    let index_record = index_searcher.search_timestamp_after(end_timestamp).unwrap();
+                    let index_record = index_searcher.search_timestamp_after(end_timestamp, SearchFilter::Any).unwrap();
                     gst_info!(CAT, obj: element, "start: end index_record={:?}", index_record);
                     index_record.offset
                 },
@@ -658,7 +1285,20 @@ impl BaseSrcImpl for PravegaSrc {
             *state = State::Started {
                 reader: Arc::new(Mutex::new(counting_reader)),
                 index_searcher: Arc::new(Mutex::new(index_searcher)),
+                caps_history: Arc::new(Mutex::new(caps_history)),
+                checksum_mismatch_policy: settings.checksum_mismatch_policy,
+                resync_mode: settings.resync_mode,
+                split_batches: settings.split_batches,
+                timestamp_mode: settings.timestamp_mode,
+                start_marker_timestamp,
             };
+            self.pending_buffers.lock().unwrap().clear();
+            *self.origin.lock().unwrap() = PravegaTimestamp::NONE;
+            self.seeked.store(false, Ordering::Relaxed);
+            // The caps history was refreshed above but no events have been read yet.
+            self.caps_known_offset.store(0, Ordering::Relaxed);
+            self.key_units.store(false, Ordering::Relaxed);
+            *self.reverse_gop.lock().unwrap() = None;
             gst_info!(CAT, obj: element, "start: Started");
             Ok(())
         })();
@@ -682,11 +1322,19 @@ impl BaseSrcImpl for PravegaSrc {
     ///    The input segment time will be the number of nanoseconds since 1970-01-01 0:00:00 TAI.
     ///
     /// When using the index:
-    /// 1) This method will find the last index record before or equal to the desired time.
+    /// 1) This method will find the last random-access index record before or equal to the desired time.
     /// 2) The Pravega reader offset and the segment times will be set using
     ///    the values from the located index record.
     /// 3) The segment times will be set so that each buffer will have a PTS and position equal to
     ///    the number of nanoseconds since 1970-01-01 0:00:00 TAI.
+    ///
+    /// If timestamp-mode=relative, input and output times are relative to the origin,
+    /// the first random-access point in the stream.
+    ///
+    /// If the segment has the TRICKMODE_KEY_UNITS flag, create() will output only random-access events
+    /// located using the index. The rate is applied downstream.
+    ///
+    /// If the segment has a negative rate, see seek_reverse.
     fn do_seek(&self, src: &Self::Type, segment: &mut gst::Segment) -> bool {
         gst_info!(CAT, obj: src, "do_seek: BEGIN: segment={:?}", segment);
         let result = (|| {
@@ -707,22 +1355,33 @@ impl BaseSrcImpl for PravegaSrc {
                         // The index will be used to find a last random-access point before or on the specified timestamp.
                         PravegaTimestamp::from_nanoseconds(Some(settings.start_timestamp))
                     },
+                    StartMode::Marker => {
+                        // The marker timestamp is obtained from the state below.
+                        PravegaTimestamp::NONE
+                    },
                 };
                 (settings.start_mode, start_timestamp)
             };
 
             let mut state = self.state.lock().unwrap();
 
-            let (reader, index_searcher) = match *state {
+            let (reader, index_searcher, timestamp_mode, start_marker_timestamp) = match *state {
                 State::Started {
                     ref mut reader,
                     ref mut index_searcher,
+                    timestamp_mode,
+                    start_marker_timestamp,
                     ..
-                } => (reader, index_searcher),
+                } => (reader, index_searcher, timestamp_mode, start_marker_timestamp),
                 State::Stopped => {
                     panic!("Not started yet");
                 }
             };
+            let initial_seek_start_timestamp = if start_mode == StartMode::Marker {
+                start_marker_timestamp
+            } else {
+                initial_seek_start_timestamp
+            };
 
             let reader = reader.clone();
             let index_searcher = index_searcher.clone();
@@ -730,14 +1389,34 @@ impl BaseSrcImpl for PravegaSrc {
             let mut reader = reader.lock().unwrap();
             let mut index_searcher = index_searcher.lock().unwrap();
 
+            // Buffers split from the last batch are from the old position.
+            self.pending_buffers.lock().unwrap().clear();
+            *self.reverse_gop.lock().unwrap() = None;
+
             let segment = segment.downcast_mut::<gst::format::Time>().unwrap();
 
+            // In key-unit trick mode, buffers keep their timestamps so the rate is applied downstream.
+            let key_units = segment.flags().contains(gst::SegmentFlags::TRICKMODE_KEY_UNITS);
+            self.key_units.store(key_units, Ordering::Relaxed);
+            segment.set_applied_rate(1.0);
+            gst_info!(CAT, obj: src, "do_seek: rate={}, key_units={}", segment.rate(), key_units);
+
+            // If timestamp-mode=relative, buffer and segment times are relative to this origin.
+            let origin = self.resolve_origin(src, &mut reader, &mut index_searcher, timestamp_mode);
+
+            if segment.rate() < 0.0 {
+                return self.seek_reverse(src, &mut reader, &mut index_searcher, segment, origin);
+            }
+
             // In the input segment parameter, start, position, and time are all set to the desired timestamp.
             // If this is the initial seek, these will be all 0, and we will seek to the first record in the index.
+            // If timestamp-mode=relative, a later seek to 0 is a seek to the origin.
+            let first_seek = !self.seeked.swap(true, Ordering::Relaxed);
             let initial_seek =
                 segment.time().nseconds().unwrap() == 0 &&
                 segment.start().nseconds().unwrap() == 0 &&
-                segment.position().nseconds().unwrap() == 0;
+                segment.position().nseconds().unwrap() == 0 &&
+                (first_seek || timestamp_mode == OutputTimestampMode::Tai);
             gst_info!(CAT, obj: src, "do_seek: initial_seek={}", initial_seek);
             let no_seek = initial_seek && start_mode == StartMode::NoSeek;
             let seek_using_index = !no_seek;
@@ -745,18 +1424,22 @@ impl BaseSrcImpl for PravegaSrc {
                 let requested_seek_timestamp = if initial_seek {
                     initial_seek_start_timestamp
                 } else {
-                    clocktime_to_pravega(segment.time())
+                    from_input_clocktime(segment.time(), origin)
                 };
                 gst_info!(CAT, obj: src, "do_seek: seeking to timestamp={:?}", requested_seek_timestamp);
                 // Determine the stream offset for this timestamp by searching the index.
-                let index_record = index_searcher.search_timestamp(requested_seek_timestamp);
+                // Only random-access records are considered so that decoding can begin at the located offset.
+                let index_record = index_searcher.search_timestamp(requested_seek_timestamp, SearchFilter::RandomAccess);
                 gst_info!(CAT, obj: src, "do_seek: index_record={:?}", index_record);
                 match index_record {
                     Ok(index_record) => {
-                        segment.set_start(ClockTime(index_record.timestamp.nanoseconds()));
-                        segment.set_time(ClockTime(index_record.timestamp.nanoseconds()));
+                        segment.set_start(to_output_clocktime(index_record.timestamp, origin));
+                        segment.set_time(to_output_clocktime(index_record.timestamp, origin));
                         segment.set_position(0);
-                        reader.seek(SeekFrom::Start(index_record.offset)).unwrap();
+                        if let Err(err) = reader.seek(SeekFrom::Start(index_record.offset)) {
+                            gst_error!(CAT, obj: src, "do_seek: Unable to seek to offset {}: {}", index_record.offset, err);
+                            return false;
+                        }
                         gst_info!(CAT, obj: src, "do_seek: seeked to indexed position; segment={:?}", segment);
                         true
                     },
@@ -777,8 +1460,17 @@ impl BaseSrcImpl for PravegaSrc {
                 segment.set_start(0);
                 segment.set_time(0);
                 segment.set_position(0);
-                let head_offset = reader.get_ref().get_ref().get_ref().current_head().unwrap();
-                reader.seek(SeekFrom::Start(head_offset)).unwrap();
+                let head_offset = match reader.get_ref().get_ref().get_ref().current_head() {
+                    Ok(head_offset) => head_offset,
+                    Err(err) => {
+                        gst_error!(CAT, obj: src, "do_seek: Unable to get head of data stream: {}", err);
+                        return false;
+                    },
+                };
+                if let Err(err) = reader.seek(SeekFrom::Start(head_offset)) {
+                    gst_error!(CAT, obj: src, "do_seek: Unable to seek to offset {}: {}", head_offset, err);
+                    return false;
+                }
                 gst_info!(CAT, obj: src, "do_seek: Starting at head of data stream because start-mode=no-seek; segment={:?}", segment);
                 true
             }
@@ -793,6 +1485,7 @@ impl BaseSrcImpl for PravegaSrc {
             match query.view_mut() {
                 // The Seeking query will return the current start and end timestamps
                 // as nanoseconds since the TAI epoch 1970-01-01 00:00:00 TAI.
+                // If timestamp-mode=relative, these will be relative to the origin.
                 gst::QueryView::Seeking(ref mut q) => {
                     let fmt = q.format();
                     if fmt == gst::Format::Time {
@@ -801,11 +1494,12 @@ impl BaseSrcImpl for PravegaSrc {
                         // Get a temporary lock on state to get the index_searcher.
                         // This lock is released before index_searcher performs I/O.
                         let mut state = self.state.lock().unwrap();
-                        let index_searcher = match *state {
+                        let (index_searcher, timestamp_mode) = match *state {
                             State::Started {
                                 ref mut index_searcher,
+                                timestamp_mode,
                                 ..
-                            } => index_searcher,
+                            } => (index_searcher, timestamp_mode),
                             State::Stopped => {
                                 return false;
                             }
@@ -814,14 +1508,14 @@ impl BaseSrcImpl for PravegaSrc {
                         drop(state);
                         let mut index_searcher = index_searcher.lock().unwrap();
 
-                        let start = match index_searcher.get_first_record() {
+                        let start = match index_searcher.get_first_record(SearchFilter::RandomAccess) {
                             Ok(start) => start,
                             Err(err) => {
                                 gst_error!(CAT, obj: src, "query: Unable to get first record from index: {}", err);
                                 return false;
                             }
                         };
-                        let end = match index_searcher.get_last_record() {
+                        let end = match index_searcher.get_last_record(SearchFilter::Any) {
                             Ok(end) => end,
                             Err(err) => {
                                 gst_error!(CAT, obj: src, "query: Unable to get last record from index: {}", err);
@@ -829,7 +1523,13 @@ impl BaseSrcImpl for PravegaSrc {
                             }
                         };
                         gst_info!(CAT, obj: src, "query: start={:?}, end={:?}", start, end);
-                        q.set(true, ClockTime(start.timestamp.nanoseconds()), ClockTime(end.timestamp.nanoseconds()));
+                        // The origin is resolved by the initial seek, which reads the data stream.
+                        let origin = *self.origin.lock().unwrap();
+                        if timestamp_mode == OutputTimestampMode::Relative && origin.is_none() {
+                            gst_debug!(CAT, obj: src, "query: origin has not been resolved");
+                            return false;
+                        }
+                        q.set(true, to_output_clocktime(start.timestamp, origin), to_output_clocktime(end.timestamp, origin));
                         return true;
                     };
                     false
@@ -854,6 +1554,9 @@ impl BaseSrcImpl for PravegaSrc {
                 ));
             }
             *state = State::Stopped;
+            self.pending_buffers.lock().unwrap().clear();
+            *self.reverse_gop.lock().unwrap() = None;
+            *self.origin.lock().unwrap() = PravegaTimestamp::NONE;
             Ok(())
         })();
         gst_info!(CAT, obj: element, "stop: END: result={:?}", result);
@@ -866,13 +1569,24 @@ impl PushSrcImpl for PravegaSrc {
         gst_trace!(CAT, obj: element, "create: BEGIN");
         let result = (|| {
 
+            // Return any buffers remaining from the last batch before reading the next event.
+            if let Some(buffer) = self.pending_buffers.lock().unwrap().pop_front() {
+                return Ok(buffer);
+            }
+
             let mut state = self.state.lock().unwrap();
 
-            let reader = match *state {
+            let (reader, index_searcher, caps_history, checksum_mismatch_policy, resync_mode, split_batches, timestamp_mode) = match *state {
                 State::Started {
                     ref mut reader,
+                    ref mut index_searcher,
+                    ref mut caps_history,
+                    checksum_mismatch_policy,
+                    resync_mode,
+                    split_batches,
+                    timestamp_mode,
                     ..
-                } => reader,
+                } => (reader, index_searcher, caps_history, checksum_mismatch_policy, resync_mode, split_batches, timestamp_mode),
                 State::Stopped => {
                     gst::element_error!(element, gst::CoreError::Failed, ["Not started yet"]);
                     panic!("Not started yet");
@@ -880,65 +1594,269 @@ impl PushSrcImpl for PravegaSrc {
             };
 
             let reader = reader.clone();
+            let index_searcher = index_searcher.clone();
+            let caps_history = caps_history.clone();
             drop(state);
             let mut reader = reader.lock().unwrap();
             let reader = &mut (*reader);
 
-            let mut event_reader = EventReader::new();
-            let offset = reader.stream_position().unwrap();
-            let required_buffer_length = event_reader.read_required_buffer_length(reader).map_err(|err| {
-                if err.kind() == ErrorKind::UnexpectedEof {
-                    gst_info!(CAT, obj: element, "create: reached EOF when trying to read event length");
-                    gst::FlowError::Eos
-                } else {
-                    gst::element_error!(element, gst::CoreError::Failed, ["Failed to read event length from stream: {}", err]);
-                    gst::FlowError::Error
+            let key_units = self.key_units.load(Ordering::Relaxed);
+            let mut reverse_gop = self.reverse_gop.lock().unwrap();
+
+            // Set to true if a corrupt event was dropped or corrupt bytes were skipped.
+            // The next buffer will be marked as a discontinuity.
+            let mut dropped_event = false;
+
+            // Buffers that were too large for a single event were written as a chain of fragments.
+            // Fragments are read until the chain is complete, so a chain never spans calls to create.
+            let mut fragment_reassembler = FragmentReassembler::new();
+            // The offset of the first fragment in the current chain.
+            let mut chain_offset = 0;
+
+            loop {
+                let mut event_reader = EventReader::new().with_header_validation(resync_mode != ResyncMode::None);
+                let offset = reader.stream_position().unwrap();
+
+                // During reverse playback, continue with the previous group of pictures after reaching the end of the current one.
+                if let Some(gop) = reverse_gop.as_mut() {
+                    if offset >= gop.end_offset {
+                        self.discard_fragments(element, &mut fragment_reassembler, offset, "end of group of pictures");
+                        self.previous_gop(element, reader, &index_searcher, gop)?;
+                        continue;
+                    }
                 }
-            })?;
 
-            // TODO: Read directly into GstBuffer.
-            let mut read_buffer: Vec<u8> = vec![0; required_buffer_length];
-            let event = event_reader.read_event(reader, &mut read_buffer[..]).map_err(|err| {
-                if err.kind() == ErrorKind::UnexpectedEof {
-                    gst_info!(CAT, obj: element, "create: reached EOF when trying to read event payload");
-                    gst::FlowError::Eos
-                } else {
-                    gst::element_error!(element, gst::CoreError::Failed, ["Failed to read event payload from stream: {}", err]);
-                    gst::FlowError::Error
+                // When resync is enabled, the fixed header is validated before the rest of the event is read.
+                let required_buffer_length = match event_reader.read_required_buffer_length(reader) {
+                    Ok(required_buffer_length) => required_buffer_length,
+                    Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                        gst_info!(CAT, obj: element, "create: reached EOF when trying to read event header");
+                        self.discard_fragments(element, &mut fragment_reassembler, offset, "end of stream");
+                        return Err(gst::FlowError::Eos);
+                    },
+                    Err(err) if err.kind() == ErrorKind::InvalidData && resync_mode != ResyncMode::None => {
+                        gst_warning!(CAT, obj: element, "create: invalid event header at offset {}: {}", offset, err);
+                        self.discard_fragments(element, &mut fragment_reassembler, offset, "invalid event header");
+                        let resync_offset = self.resync(element, reader, &index_searcher, resync_mode, offset).map_err(|resync_err| {
+                            if resync_err.kind() == ErrorKind::UnexpectedEof {
+                                gst::element_warning!(element, gst::StreamError::Demux,
+                                    ["Skipped corrupt bytes from offset {} to end of stream: {}", offset, err]);
+                                gst::FlowError::Eos
+                            } else {
+                                gst::element_error!(element, gst::CoreError::Failed, ["Failed to resync after offset {}: {}", offset, resync_err]);
+                                gst::FlowError::Error
+                            }
+                        })?;
+                        gst::element_warning!(element, gst::StreamError::Demux,
+                            ["Skipped corrupt bytes from offset {} to {}: {}", offset, resync_offset, err]);
+                        dropped_event = true;
+                        continue;
+                    },
+                    Err(err) => {
+                        gst::element_error!(element, gst::CoreError::Failed, ["Failed to read event length from stream: {}", err]);
+                        return Err(gst::FlowError::Error);
+                    },
+                };
+
+                // TODO: Read directly into GstBuffer.
+                let mut read_buffer: Vec<u8> = vec![0; required_buffer_length];
+                let mut event = event_reader.read_event(reader, &mut read_buffer[..]).map_err(|err| {
+                    if err.kind() == ErrorKind::UnexpectedEof {
+                        gst_info!(CAT, obj: element, "create: reached EOF when trying to read event payload");
+                        self.discard_fragments(element, &mut fragment_reassembler, offset, "end of stream");
+                        gst::FlowError::Eos
+                    } else {
+                        gst::element_error!(element, gst::CoreError::Failed, ["Failed to read event payload from stream: {}", err]);
+                        gst::FlowError::Error
+                    }
+                })?;
+                gst_memdump!(CAT, obj: element, "create: event={:?}", event);
+                let offset_end = reader.stream_position().unwrap();
+
+                if let ChecksumStatus::Invalid { expected, actual } = event_reader.checksum_status() {
+                    let checksum_mismatches = self.checksum_mismatches.fetch_add(1, Ordering::Relaxed) + 1;
+                    let message = format!("Checksum mismatch in event at offset {}: expected {:08x}, actual {:08x}; checksum_mismatches={}",
+                        offset, expected, actual, checksum_mismatches);
+                    match checksum_mismatch_policy {
+                        ChecksumMismatchPolicy::Error => {
+                            gst::element_error!(element, gst::StreamError::Decode, ["{}", message]);
+                            return Err(gst::FlowError::Error);
+                        },
+                        ChecksumMismatchPolicy::Drop => {
+                            gst_warning!(CAT, obj: element, "create: {}; dropping event", message);
+                            gst::element_warning!(element, gst::StreamError::Decode, ["{}", message]);
+                            self.discard_fragments(element, &mut fragment_reassembler, offset, "checksum mismatch");
+                            dropped_event = true;
+                            continue;
+                        },
+                        ChecksumMismatchPolicy::Warn => {
+                            gst_warning!(CAT, obj: element, "create: {}", message);
+                            gst::element_warning!(element, gst::StreamError::Decode, ["{}", message]);
+                        },
+                    }
                 }
-            })?;
-            gst_memdump!(CAT, obj: element, "create: event={:?}", event);
-            let offset_end = reader.stream_position().unwrap();
-
-            let mut gst_buffer = gst::Buffer::with_size(event.payload.len()).unwrap();
-            {
-                let buffer_ref = gst_buffer.get_mut().unwrap();
-
-                let segment = element
-                    .segment()
-                    .downcast::<gst::format::Time>()
-                    .unwrap();
-                gst_trace!(CAT, obj: element, "create: segment={:?}", segment);
-                let pts = pravega_to_clocktime(event.header.timestamp);
-                gst_log!(CAT, obj: element, "create: timestamp={:?}, pts={}, payload_len={}",
-                    event.header.timestamp, pts, event.payload.len());
-
-                buffer_ref.set_pts(pts);
-                buffer_ref.set_offset(offset);
-                buffer_ref.set_offset_end(offset_end);
-                if !event.header.random_access {
-                    buffer_ref.set_flags(gst::BufferFlags::DELTA_UNIT);
+
+                let buffer_lengths = std::mem::take(&mut event.buffer_lengths);
+
+                // Join fragments.
+                if !event.header.continuation
+                        && self.discard_fragments(element, &mut fragment_reassembler, offset, "missing last fragment") {
+                    dropped_event = true;
                 }
-                if event.header.discontinuity {
-                    buffer_ref.set_flags(gst::BufferFlags::DISCONT);
+                let reassembled;
+                let status = match fragment_reassembler.push(&event) {
+                    Ok(status) => status,
+                    Err(err) if err.kind() == ErrorKind::InvalidData => {
+                        self.discard_fragments(element, &mut fragment_reassembler, offset, &err.to_string());
+                        dropped_event = true;
+                        continue;
+                    },
+                    Err(err) => {
+                        gst::element_error!(element, gst::StreamError::Demux, ["Failed to join fragments: {}", err]);
+                        return Err(gst::FlowError::Error);
+                    },
+                };
+                let (header, payload, offset) = match status {
+                    FragmentStatus::NotFragmented => (event.header, event.payload, offset),
+                    FragmentStatus::Incomplete => {
+                        if fragment_reassembler.fragment_count() == 1 {
+                            chain_offset = offset;
+                        }
+                        gst_debug!(CAT, obj: element, "create: read fragment {} at offset {}",
+                            fragment_reassembler.fragment_count(), offset);
+                        continue;
+                    },
+                    FragmentStatus::Complete => {
+                        gst_debug!(CAT, obj: element, "create: reassembled {} fragments from offset {} to {}",
+                            fragment_reassembler.fragment_count(), chain_offset, offset_end);
+                        reassembled = fragment_reassembler.take();
+                        (reassembled.0, &reassembled.1[..], chain_offset)
+                    },
+                    FragmentStatus::Orphan => {
+                        gst_warning!(CAT, obj: element, "create: dropping fragment without first fragment at offset {}", offset);
+                        dropped_event = true;
+                        continue;
+                    },
+                };
+
+                // Bytes up to this offset have been read from the data stream, so any caps records for them have been written.
+                let read_offset = reader.stream_position().unwrap() + reader.get_ref().buffer().len() as u64;
+                self.update_caps(element, &caps_history, offset, read_offset)?;
+
+                // In key-unit trick mode, delta units are read only if the index has no later random-access record.
+                if key_units && !header.random_access {
+                    gst_trace!(CAT, obj: element, "create: skipping delta unit at offset {} in key-unit trick mode", offset);
+                    dropped_event = true;
+                    continue;
                 }
 
-                let mut buffer_map = buffer_ref.map_writable().unwrap();
-                let slice = buffer_map.as_mut_slice();
-                slice.copy_from_slice(event.payload);
-            }
+                // If the index was empty when starting, the origin is the PTS or earlier DTS of the first buffer read.
+                let origin = {
+                    let mut origin = self.origin.lock().unwrap();
+                    if timestamp_mode == OutputTimestampMode::Relative && origin.is_none() && header.timestamp.is_some() {
+                        let dts = header.buffer_metadata.map_or(PravegaTimestamp::NONE, |buffer_metadata| buffer_metadata.dts);
+                        *origin = earliest_timestamp(header.timestamp, dts);
+                        gst_info!(CAT, obj: element, "create: origin={:?}", *origin);
+                    }
+                    *origin
+                };
 
-            Ok(gst_buffer)
+                let mut gst_buffer = gst::Buffer::with_size(payload.len()).unwrap();
+                {
+                    let buffer_ref = gst_buffer.get_mut().unwrap();
+
+                    let segment = element
+                        .segment()
+                        .downcast::<gst::format::Time>()
+                        .unwrap();
+                    gst_trace!(CAT, obj: element, "create: segment={:?}", segment);
+                    let pts = to_output_clocktime(header.timestamp, origin);
+                    gst_log!(CAT, obj: element, "create: timestamp={:?}, pts={}, payload_len={}",
+                        header.timestamp, pts, payload.len());
+
+                    buffer_ref.set_pts(pts);
+                    if timestamp_mode == OutputTimestampMode::Relative && header.timestamp.is_some() {
+                        gst::ReferenceTimestampMeta::add(buffer_ref, &REFERENCE_TIMESTAMP_TAI_CAPS,
+                            pravega_to_clocktime(header.timestamp), ClockTime::none());
+                        if let Some(unix_nanoseconds) = header.timestamp.to_unix_nanoseconds() {
+                            gst::ReferenceTimestampMeta::add(buffer_ref, &REFERENCE_TIMESTAMP_UNIX_CAPS,
+                                ClockTime::from_nseconds(unix_nanoseconds), ClockTime::none());
+                        }
+                    }
+                    buffer_ref.set_offset(offset);
+                    buffer_ref.set_offset_end(offset_end);
+                    if !header.random_access {
+                        buffer_ref.set_flags(gst::BufferFlags::DELTA_UNIT);
+                    }
+                    // During reverse playback, the first buffer of each group of pictures is a discontinuity.
+                    let first_in_gop = reverse_gop.as_mut().map_or(false, |gop| std::mem::replace(&mut gop.first, false));
+                    if header.discontinuity || dropped_event || key_units || first_in_gop {
+                        buffer_ref.set_flags(gst::BufferFlags::DISCONT);
+                    }
+                    if let Some(buffer_metadata) = header.buffer_metadata {
+                        buffer_ref.set_dts(to_output_clocktime(buffer_metadata.dts, origin));
+                        buffer_ref.set_duration(ClockTime(buffer_metadata.duration));
+                        if buffer_metadata.header {
+                            buffer_ref.set_flags(gst::BufferFlags::HEADER);
+                        }
+                        if buffer_metadata.gap {
+                            buffer_ref.set_flags(gst::BufferFlags::GAP);
+                        }
+                        if buffer_metadata.droppable {
+                            buffer_ref.set_flags(gst::BufferFlags::DROPPABLE);
+                        }
+                        if buffer_metadata.marker {
+                            buffer_ref.set_flags(gst::BufferFlags::MARKER);
+                        }
+                        gst_log!(CAT, obj: element, "create: buffer_metadata={:?}", buffer_metadata);
+                    }
+
+                    let mut buffer_map = buffer_ref.map_writable().unwrap();
+                    let slice = buffer_map.as_mut_slice();
+                    slice.copy_from_slice(payload);
+                }
+
+                if key_units {
+                    match reverse_gop.as_mut() {
+                        // The rest of the group of pictures is skipped.
+                        Some(gop) => gop.end_offset = offset_end,
+                        None => self.skip_to_next_key_unit(element, reader, &index_searcher, offset)?,
+                    }
+                }
+
+                // Split an event containing coalesced buffers into the original buffers.
+                // The first buffer has the timestamp and flags from the event header.
+                // The other buffers have no timestamp and are marked as delta units.
+                // In key-unit trick mode, the other buffers are dropped.
+                if split_batches && buffer_lengths.len() > 1 {
+                    gst_log!(CAT, obj: element, "create: splitting batch of {} buffers", buffer_lengths.len());
+                    if !key_units {
+                        let mut pending_buffers = self.pending_buffers.lock().unwrap();
+                        let mut pos = buffer_lengths[0];
+                        for length in &buffer_lengths[1..] {
+                            let mut buffer = gst_buffer.copy_region(gst::BufferCopyFlags::MEMORY, pos, Some(*length)).unwrap();
+                            {
+                                let buffer_ref = buffer.get_mut().unwrap();
+                                buffer_ref.set_offset(offset);
+                                buffer_ref.set_offset_end(offset_end);
+                                buffer_ref.set_flags(gst::BufferFlags::DELTA_UNIT);
+                            }
+                            pending_buffers.push_back(buffer);
+                            pos += length;
+                        }
+                    }
+                    let mut first_buffer = gst_buffer.copy_region(gst::BufferCopyFlags::FLAGS | gst::BufferCopyFlags::TIMESTAMPS
+                        | gst::BufferCopyFlags::META | gst::BufferCopyFlags::MEMORY, 0, Some(buffer_lengths[0])).unwrap();
+                    {
+                        let buffer_ref = first_buffer.get_mut().unwrap();
+                        buffer_ref.set_offset_end(offset_end);
+                        buffer_ref.set_duration(gst_buffer.duration());
+                    }
+                    return Ok(first_buffer);
+                }
+
+                return Ok(gst_buffer);
+            }
         })();
         gst_trace!(CAT, obj: element, "create: END: result={:?}", result);
         result
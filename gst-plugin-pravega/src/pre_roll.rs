@@ -0,0 +1,128 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+
+struct PreRollItem<T> {
+    item: T,
+    // Nanoseconds. None if the item does not have a timestamp.
+    timestamp: Option<u64>,
+    random_access: bool,
+}
+
+/// A ring buffer that holds the most recent groups of pictures.
+///
+/// The buffer always begins with a random access item.
+/// The oldest group of pictures, which begins with the oldest item and ends before the next random access item,
+/// is removed when the remaining groups of pictures span at least the duration.
+/// A duration of 0 holds only the current group of pictures.
+pub struct PreRollBuffer<T> {
+    items: VecDeque<PreRollItem<T>>,
+    duration: u64,
+    // The timestamp of the newest item that has a timestamp.
+    newest_timestamp: Option<u64>,
+}
+
+impl<T> PreRollBuffer<T> {
+    /// Duration is in nanoseconds.
+    pub fn new(duration: u64) -> PreRollBuffer<T> {
+        PreRollBuffer {
+            items: VecDeque::new(),
+            duration,
+            newest_timestamp: None,
+        }
+    }
+
+    /// Adds an item to the buffer and removes groups of pictures that are no longer needed.
+    /// Returns the number of items that were removed.
+    /// If the buffer is empty and the item is not a random access item, the item is not added and 1 is returned.
+    pub fn push(&mut self, item: T, timestamp: Option<u64>, random_access: bool) -> usize {
+        if self.items.is_empty() && !random_access {
+            return 1;
+        }
+        if timestamp.is_some() {
+            self.newest_timestamp = timestamp;
+        }
+        self.items.push_back(PreRollItem { item, timestamp, random_access });
+        self.trim()
+    }
+
+    fn trim(&mut self) -> usize {
+        let mut removed = 0;
+        while let Some(next_gop) = self.items.iter().skip(1).position(|item| item.random_access).map(|i| i + 1) {
+            let remaining_duration = match (self.newest_timestamp, self.items[next_gop].timestamp) {
+                (Some(newest_timestamp), Some(next_gop_timestamp)) => newest_timestamp.saturating_sub(next_gop_timestamp),
+                // Without timestamps, keep only the current group of pictures.
+                _ => u64::MAX,
+            };
+            if remaining_duration < self.duration {
+                break;
+            }
+            self.items.drain(..next_gop);
+            removed += next_gop;
+        }
+        removed
+    }
+
+    /// Removes and returns all items, oldest first.
+    pub fn drain(&mut self) -> Vec<T> {
+        self.newest_timestamp = None;
+        self.items.drain(..).map(|item| item.item).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Pushes items that are 100 nanoseconds apart. Every item that is a multiple of gop_size is a random access item.
+    fn push_items(buffer: &mut PreRollBuffer<u64>, items: std::ops::Range<u64>, gop_size: u64) {
+        for i in items {
+            buffer.push(i, Some(i * 100), i % gop_size == 0);
+        }
+    }
+
+    #[test]
+    fn test_pre_roll_buffer_keeps_duration() {
+        let mut buffer = PreRollBuffer::new(500);
+        push_items(&mut buffer, 0..13, 3);
+        // Item 12 is at 1200 ns. Items 6 to 12 span 600 ns, which is at least the duration, so items 0 to 5 are removed.
+        // Items 9 to 12 span only 300 ns, so items 6 to 8 are kept.
+        assert_eq!(buffer.drain(), vec![6, 7, 8, 9, 10, 11, 12]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_pre_roll_buffer_begins_with_random_access_item() {
+        let mut buffer = PreRollBuffer::new(1000);
+        assert_eq!(buffer.push(1, Some(100), false), 1);
+        assert_eq!(buffer.push(2, Some(200), false), 1);
+        push_items(&mut buffer, 3..6, 3);
+        assert_eq!(buffer.drain(), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn test_pre_roll_buffer_zero_duration() {
+        let mut buffer = PreRollBuffer::new(0);
+        push_items(&mut buffer, 0..8, 3);
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.drain(), vec![6, 7]);
+    }
+}
@@ -10,16 +10,16 @@
 
 #![allow(dead_code)]
 
-use pravega_client::byte::ByteWriter;
+use pravega_video::storage::ByteStreamWriter;
 use std::io::{Error, ErrorKind, Result, Seek, SeekFrom, Write};
 
-/// A ByteWriter that implements Seek.
+/// A ByteStreamWriter that implements Seek.
 pub struct SeekableByteWriter {
-    inner: ByteWriter,
+    inner: Box<dyn ByteStreamWriter>,
 }
 
 impl SeekableByteWriter {
-    pub fn new(writer: ByteWriter) -> Result<SeekableByteWriter> {
+    pub fn new(writer: Box<dyn ByteStreamWriter>) -> Result<SeekableByteWriter> {
         let writer = SeekableByteWriter {
             inner: writer
         };
@@ -27,12 +27,12 @@ impl SeekableByteWriter {
     }
 
     /// Gets a reference to the underlying reader.
-    pub fn get_ref(&self) -> &ByteWriter {
+    pub fn get_ref(&self) -> &Box<dyn ByteStreamWriter> {
         &self.inner
     }
 
     /// Gets a mutable reference to the underlying writer.
-    pub fn get_mut(&mut self) -> &mut ByteWriter {
+    pub fn get_mut(&mut self) -> &mut Box<dyn ByteStreamWriter> {
         &mut self.inner
     }
 }
@@ -50,7 +50,7 @@ impl Write for SeekableByteWriter {
 impl Seek for SeekableByteWriter {
     fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
         match pos {
-            SeekFrom::Current(0) => Ok(self.inner.current_write_offset() as u64),
+            SeekFrom::Current(0) => Ok(self.inner.current_offset()),
             _ => Err(Error::new(ErrorKind::InvalidInput, "Seek is not allowed")),
         }
     }
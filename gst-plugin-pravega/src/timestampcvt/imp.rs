@@ -17,7 +17,7 @@ use gst::{gst_debug, gst_error, gst_warning, gst_info, gst_log, gst_trace};
 use once_cell::sync::Lazy;
 use pravega_video::timestamp::{PravegaTimestamp, MSECOND};
 use std::sync::Mutex;
-use crate::utils::{pravega_to_clocktime, now_ntp_clocktime};
+use crate::utils::{pravega_to_clocktime, now_ntp_clocktime, reference_timestamp};
 
 pub const ELEMENT_NAME: &str = "timestampcvt";
 const ELEMENT_CLASS_NAME: &str = "TimestampCvt";
@@ -51,6 +51,15 @@ pub enum InputTimestampMode {
         nick = "relative"
     )]
     Relative = 1,
+    #[genum(
+        name = "Input buffers have a GstReferenceTimestampMeta with caps timestamp/x-ntp, timestamp/x-unix, or timestamp/x-tai. \
+                Use this for buffers from rtspsrc (add-reference-timestamp-meta=true). \
+                The offset to TAI time will be calculated as the difference between the reference timestamp and the PTS \
+                of the last buffer that had this meta. \
+                Until a buffer has this meta, the offset will be calculated as in relative mode.",
+        nick = "reference-timestamp-meta"
+    )]
+    ReferenceTimestampMeta = 2,
 }
 
 const DEFAULT_INPUT_TIMESTAMP_MODE: InputTimestampMode = InputTimestampMode::Ntp;
@@ -143,6 +152,29 @@ impl TimestampCvt {
                         }
                     ClockTime::from_nseconds((input_nanos as i128 + state.pts_offset_nanos.unwrap()) as u64)
                 },
+                InputTimestampMode::ReferenceTimestampMeta => {
+                    let reference_ntp_nanos = reference_timestamp(&buffer).to_unix_nanoseconds()
+                        .map(|unix_nanos| unix_nanos + PravegaTimestamp::UNIX_TO_NTP_SECONDS * 1_000_000_000);
+                    match reference_ntp_nanos {
+                        Some(reference_ntp_nanos) => {
+                            if state.pts_offset_nanos.is_none() {
+                                gst_info!(CAT, obj: pad, "Input buffer PTS timestamps will be adjusted using the reference timestamp meta.");
+                            }
+                            state.pts_offset_nanos = Some(reference_ntp_nanos as i128 - input_nanos as i128);
+                        },
+                        None => {
+                            if state.pts_offset_nanos.is_none() {
+                                let now_ntp = now_ntp_clocktime();
+                                state.pts_offset_nanos = Some(now_ntp.nanoseconds().unwrap() as i128 - input_nanos as i128);
+                                gst_info!(CAT, obj: pad,
+                                    "Input buffer does not have a reference timestamp meta. \
+                                    PTS timestamps will be adjusted by {} nanoseconds to synchronize with the current system time.",
+                                    state.pts_offset_nanos.unwrap());
+                            }
+                        },
+                    }
+                    ClockTime::from_nseconds((input_nanos as i128 + state.pts_offset_nanos.unwrap()) as u64)
+                },
                 _ => input_pts
             };
             let output_pts = if state.prev_input_pts.is_some() {
@@ -0,0 +1,274 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+#![allow(dead_code)]
+
+use std::io::{Result, Seek, SeekFrom, Write};
+
+/// A writer that can group writes into transactions.
+///
+/// When no transaction is open, writes are passed directly to the inner writer.
+/// When a transaction is open, writes are held in memory until commit() is called.
+/// Upon commit, the pending bytes are written to the inner writer in as few writes as possible,
+/// each no larger than max_write_size, and the inner writer is flushed after each write.
+/// If the inner writer is a Pravega byte stream, each of these writes will be atomic.
+/// Writes are only split at boundaries marked with end_event(), so that an event is never split across writes.
+pub struct TransactionWriter<T: Write + Seek> {
+    inner: T,
+    max_write_size: usize,
+    pending: Option<Vec<u8>>,
+    // Lengths of pending at the end of each event.
+    event_boundaries: Vec<usize>,
+}
+
+impl<T: Write + Seek> TransactionWriter<T> {
+    pub fn new(writer: T, max_write_size: usize) -> TransactionWriter<T> {
+        TransactionWriter {
+            inner: writer,
+            max_write_size,
+            pending: None,
+            event_boundaries: Vec::new(),
+        }
+    }
+
+    /// Opens a transaction. Subsequent writes will be held in memory until commit() is called.
+    /// If a transaction is already open, this does nothing.
+    pub fn begin(&mut self) {
+        if self.pending.is_none() {
+            self.pending = Some(Vec::new());
+            self.event_boundaries.clear();
+        }
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Returns the number of bytes that have been written in the open transaction.
+    pub fn pending_len(&self) -> usize {
+        self.pending.as_ref().map_or(0, |pending| pending.len())
+    }
+
+    /// Marks the end of an event in the open transaction.
+    /// The pending bytes will only be split into multiple writes at these boundaries.
+    pub fn end_event(&mut self) {
+        if let Some(pending) = &self.pending {
+            self.event_boundaries.push(pending.len());
+        }
+    }
+
+    /// Writes all pending bytes to the inner writer and closes the transaction.
+    /// Returns the number of writes used. If this is greater than 1, a reader may observe a partial transaction.
+    /// If no transaction is open, this does nothing and returns 0.
+    /// If a write fails, the bytes that were not accepted by the inner writer remain pending.
+    pub fn commit(&mut self) -> Result<usize> {
+        let pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => return Ok(0),
+        };
+        let mut event_boundaries = std::mem::take(&mut self.event_boundaries);
+        if event_boundaries.last() != Some(&pending.len()) {
+            event_boundaries.push(pending.len());
+        }
+        let mut chunk_ends = Vec::new();
+        let mut start = 0;
+        let mut end = 0;
+        for &boundary in event_boundaries.iter() {
+            if boundary - start > self.max_write_size && end > start {
+                chunk_ends.push(end);
+                start = end;
+            }
+            end = boundary;
+        }
+        if end > start {
+            chunk_ends.push(end);
+        }
+        let mut start = 0;
+        for &end in chunk_ends.iter() {
+            let result = self.inner.write_all(&pending[start..end]);
+            if result.is_ok() {
+                start = end;
+            }
+            if let Err(error) = result.and_then(|_| self.inner.flush()) {
+                self.restore_pending(pending, start, event_boundaries);
+                return Err(error);
+            }
+        }
+        Ok(chunk_ends.len())
+    }
+
+    /// Reopens the transaction with the pending bytes that follow start.
+    fn restore_pending(&mut self, pending: Vec<u8>, start: usize, event_boundaries: Vec<usize>) {
+        if start < pending.len() {
+            self.event_boundaries = event_boundaries.into_iter()
+                .filter(|&boundary| boundary > start)
+                .map(|boundary| boundary - start)
+                .collect();
+            self.pending = Some(pending[start..].to_vec());
+        }
+    }
+
+    /// Discards all pending bytes and closes the transaction.
+    /// Returns the number of bytes discarded.
+    pub fn abort(&mut self) -> usize {
+        self.event_boundaries.clear();
+        self.pending.take().map_or(0, |pending| pending.len())
+    }
+
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Unwraps this writer, returning the underlying writer and the bytes in the open transaction, if any.
+    pub fn into_parts(self) -> (T, Vec<u8>) {
+        (self.inner, self.pending.unwrap_or_default())
+    }
+}
+
+impl<T: Write + Seek> Write for TransactionWriter<T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        match &mut self.pending {
+            Some(pending) => {
+                pending.extend_from_slice(buf);
+                Ok(buf.len())
+            },
+            None => self.inner.write(buf),
+        }
+    }
+
+    /// Flushes the inner writer. This does not commit the open transaction.
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: Write + Seek> Seek for TransactionWriter<T> {
+    /// Only SeekFrom::Current(0) is supported when a transaction is open.
+    /// It returns the offset that the next written byte will have once the transaction is committed.
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        match pos {
+            SeekFrom::Current(0) => Ok(self.inner.seek(pos)? + self.pending_len() as u64),
+            _ if self.pending.is_some() => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                "Seek is not allowed in a transaction")),
+            _ => self.inner.seek(pos),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Records the size of each write.
+    struct RecordingWriter {
+        cursor: Cursor<Vec<u8>>,
+        writes: Vec<usize>,
+    }
+
+    impl Write for RecordingWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.writes.push(buf.len());
+            self.cursor.write(buf)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for RecordingWriter {
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+            self.cursor.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_transaction_writer() {
+        let inner = RecordingWriter { cursor: Cursor::new(Vec::new()), writes: Vec::new() };
+        let mut writer = TransactionWriter::new(inner, 10);
+
+        // Writes outside of a transaction are passed through.
+        writer.write_all(&[1; 3]).unwrap();
+        assert_eq!(writer.get_ref().writes, vec![3]);
+
+        // Writes in a transaction are held until commit.
+        writer.begin();
+        for _ in 0..3 {
+            writer.write_all(&[2; 4]).unwrap();
+            writer.end_event();
+        }
+        assert_eq!(writer.stream_position().unwrap(), 15);
+        assert_eq!(writer.get_ref().writes, vec![3]);
+        // 12 bytes exceeds the maximum write size so the events must be split into 2 writes.
+        assert_eq!(writer.commit().unwrap(), 2);
+        assert_eq!(writer.get_ref().writes, vec![3, 8, 4]);
+        assert!(!writer.in_transaction());
+
+        // Aborted transactions are never written.
+        writer.begin();
+        writer.write_all(&[3; 5]).unwrap();
+        writer.end_event();
+        assert_eq!(writer.abort(), 5);
+        assert_eq!(writer.commit().unwrap(), 0);
+        assert_eq!(writer.stream_position().unwrap(), 15);
+        assert_eq!(writer.get_ref().cursor.get_ref().len(), 15);
+    }
+
+    /// Fails every write after the first.
+    struct FailingWriter {
+        cursor: Cursor<Vec<u8>>,
+    }
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            if self.cursor.get_ref().is_empty() {
+                self.cursor.write(buf)
+            } else {
+                Err(std::io::Error::new(std::io::ErrorKind::Other, "write failed"))
+            }
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for FailingWriter {
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+            self.cursor.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_transaction_writer_failed_commit() {
+        let inner = FailingWriter { cursor: Cursor::new(Vec::new()) };
+        let mut writer = TransactionWriter::new(inner, 10);
+        writer.begin();
+        for i in 0..3 {
+            writer.write_all(&[i; 4]).unwrap();
+            writer.end_event();
+        }
+        // The first write succeeds and the second fails.
+        assert!(writer.commit().is_err());
+        // The bytes that were not written remain in the open transaction.
+        assert!(writer.in_transaction());
+        assert_eq!(writer.pending_len(), 4);
+        let (inner, pending) = writer.into_parts();
+        assert_eq!(inner.cursor.get_ref(), &vec![0, 0, 0, 0, 1, 1, 1, 1]);
+        assert_eq!(pending, vec![2; 4]);
+    }
+}
@@ -24,3 +24,29 @@ pub fn pravega_to_clocktime(t: PravegaTimestamp) -> ClockTime {
 pub fn now_ntp_clocktime() -> ClockTime {
     ClockTime::from_nseconds(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64 + PravegaTimestamp::UNIX_TO_NTP_SECONDS * 1_000_000_000)
 }
+
+/// Caps of a GstReferenceTimestampMeta with nanoseconds since the NTP epoch 1900-01-01 00:00:00 UTC, not including leap seconds.
+pub const REFERENCE_TIMESTAMP_NTP: &str = "timestamp/x-ntp";
+/// Caps of a GstReferenceTimestampMeta with nanoseconds since the Unix epoch 1970-01-01 00:00:00 UTC, not including leap seconds.
+pub const REFERENCE_TIMESTAMP_UNIX: &str = "timestamp/x-unix";
+/// Caps of a GstReferenceTimestampMeta with nanoseconds since 1970-01-01 00:00:00 TAI, including leap seconds.
+pub const REFERENCE_TIMESTAMP_TAI: &str = "timestamp/x-tai";
+
+/// Returns the timestamp of the first GstReferenceTimestampMeta on the buffer with NTP, Unix, or TAI caps
+/// and a valid timestamp.
+/// Returns PravegaTimestamp::NONE if the buffer has no such meta.
+pub fn reference_timestamp(buffer: &gst::BufferRef) -> PravegaTimestamp {
+    for meta in buffer.iter_meta::<gst::ReferenceTimestampMeta>() {
+        let timestamp = meta.timestamp().nanoseconds();
+        let timestamp = match meta.reference().structure(0).map(|s| s.name()) {
+            Some(REFERENCE_TIMESTAMP_NTP) => PravegaTimestamp::from_ntp_nanoseconds(timestamp),
+            Some(REFERENCE_TIMESTAMP_UNIX) => PravegaTimestamp::from_unix_nanoseconds(timestamp),
+            Some(REFERENCE_TIMESTAMP_TAI) => PravegaTimestamp::from_nanoseconds(timestamp),
+            _ => continue,
+        };
+        if timestamp.is_some() {
+            return timestamp;
+        }
+    }
+    PravegaTimestamp::NONE
+}
@@ -0,0 +1,324 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Determines what happens when an item is pushed to a full queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeakyPolicy {
+    /// Wait until the consumer has made room.
+    Block,
+    /// Drop the oldest group of pictures, which begins with the oldest item and ends before the next random access item.
+    DropOldestGop,
+    /// Drop the item being pushed.
+    DropNewest,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PushResult {
+    /// The item was queued. This is the number of older items that were dropped to make room for it.
+    Queued(usize),
+    /// The queue was full and the item was dropped.
+    Dropped,
+    /// The queue is flushing and the item was dropped.
+    Flushing,
+    /// The queue is closed and the item was dropped.
+    Closed,
+}
+
+struct QueuedItem<T> {
+    item: T,
+    size: usize,
+    random_access: bool,
+    discontinuity: bool,
+    enqueue_time: Instant,
+}
+
+struct Inner<T> {
+    items: VecDeque<QueuedItem<T>>,
+    bytes: usize,
+    // True while the consumer is processing the last item returned by pop().
+    busy: bool,
+    flushing: bool,
+    closed: bool,
+    // True if items were dropped after the last queued item.
+    discontinuity_pending: bool,
+    dropped: u64,
+}
+
+/// A bounded queue used to pass items from a producer thread to a consumer thread.
+///
+/// The queue is full when the total size of the queued items would exceed max_bytes
+/// or when the oldest item has been queued for longer than max_time.
+/// A limit of 0 disables that limit. An empty queue will always accept an item.
+/// When items are dropped, the next item returned by pop() will be marked as a discontinuity.
+pub struct WriteQueue<T> {
+    inner: Mutex<Inner<T>>,
+    cond: Condvar,
+    max_bytes: usize,
+    max_time: Duration,
+    leaky: LeakyPolicy,
+}
+
+impl<T> WriteQueue<T> {
+    pub fn new(max_bytes: usize, max_time: Duration, leaky: LeakyPolicy) -> WriteQueue<T> {
+        WriteQueue {
+            inner: Mutex::new(Inner {
+                items: VecDeque::new(),
+                bytes: 0,
+                busy: false,
+                flushing: false,
+                closed: false,
+                discontinuity_pending: false,
+                dropped: 0,
+            }),
+            cond: Condvar::new(),
+            max_bytes,
+            max_time,
+            leaky,
+        }
+    }
+
+    fn is_full(&self, inner: &Inner<T>, size: usize, now: Instant) -> bool {
+        match inner.items.front() {
+            Some(oldest) => {
+                (self.max_bytes > 0 && inner.bytes + size > self.max_bytes)
+                || (self.max_time > Duration::ZERO && now.saturating_duration_since(oldest.enqueue_time) >= self.max_time)
+            },
+            None => false,
+        }
+    }
+
+    /// Drops the oldest item and all following items up to the next random access item.
+    /// Returns the number of items dropped.
+    fn drop_oldest_gop(inner: &mut Inner<T>) -> usize {
+        let mut dropped = 0;
+        while let Some(item) = inner.items.pop_front() {
+            inner.bytes -= item.size;
+            dropped += 1;
+            match inner.items.front() {
+                Some(next) if !next.random_access => {},
+                _ => break,
+            }
+        }
+        match inner.items.front_mut() {
+            Some(next) => next.discontinuity = true,
+            None => inner.discontinuity_pending = true,
+        }
+        inner.dropped += dropped as u64;
+        dropped
+    }
+
+    /// Adds an item to the queue, applying the leaky policy if the queue is full.
+    pub fn push(&self, item: T, size: usize, random_access: bool) -> PushResult {
+        let mut inner = self.inner.lock().unwrap();
+        let mut dropped = 0;
+        loop {
+            if inner.closed {
+                return PushResult::Closed;
+            }
+            if inner.flushing {
+                return PushResult::Flushing;
+            }
+            if !self.is_full(&inner, size, Instant::now()) {
+                break;
+            }
+            match self.leaky {
+                LeakyPolicy::Block => {
+                    inner = self.cond.wait(inner).unwrap();
+                },
+                LeakyPolicy::DropOldestGop => {
+                    dropped += WriteQueue::drop_oldest_gop(&mut inner);
+                },
+                LeakyPolicy::DropNewest => {
+                    inner.discontinuity_pending = true;
+                    inner.dropped += 1;
+                    return PushResult::Dropped;
+                },
+            }
+        }
+        let discontinuity = std::mem::take(&mut inner.discontinuity_pending);
+        inner.bytes += size;
+        inner.items.push_back(QueuedItem {
+            item,
+            size,
+            random_access,
+            discontinuity,
+            enqueue_time: Instant::now(),
+        });
+        self.cond.notify_all();
+        PushResult::Queued(dropped)
+    }
+
+    /// Removes the oldest item from the queue, waiting until one is available.
+    /// The returned bool is true if items were dropped immediately before this item.
+    /// The item is considered in progress until the next call to pop().
+    /// Returns None when the queue is closed and all items have been removed.
+    pub fn pop(&self) -> Option<(T, bool)> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.busy = false;
+        self.cond.notify_all();
+        loop {
+            if let Some(item) = inner.items.pop_front() {
+                inner.bytes -= item.size;
+                inner.busy = true;
+                self.cond.notify_all();
+                return Some((item.item, item.discontinuity));
+            }
+            if inner.closed {
+                return None;
+            }
+            inner = self.cond.wait(inner).unwrap();
+        }
+    }
+
+    /// Waits until all queued items have been processed by the consumer, the queue is aborted,
+    /// or the queue is flushing.
+    /// Returns true if all queued items have been processed.
+    pub fn wait_until_empty(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        while (!inner.items.is_empty() || inner.busy) && !inner.flushing {
+            inner = self.cond.wait(inner).unwrap();
+        }
+        inner.items.is_empty() && !inner.busy
+    }
+
+    /// When flushing, push() will not wait and will drop all items.
+    pub fn set_flushing(&self, flushing: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.flushing = flushing;
+        self.cond.notify_all();
+    }
+
+    /// Rejects new items. The consumer will receive the items that remain in the queue.
+    pub fn close(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.closed = true;
+        self.cond.notify_all();
+    }
+
+    /// Rejects new items and discards the items that remain in the queue.
+    /// Returns the number of items discarded.
+    pub fn abort(&self) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        inner.closed = true;
+        inner.busy = false;
+        inner.bytes = 0;
+        let discarded = inner.items.len();
+        inner.items.clear();
+        self.cond.notify_all();
+        discarded
+    }
+
+    /// Returns the time that the oldest item has been in the queue.
+    pub fn latency(&self) -> Duration {
+        let inner = self.inner.lock().unwrap();
+        inner.items.front().map_or(Duration::ZERO, |oldest| oldest.enqueue_time.elapsed())
+    }
+
+    /// Returns the total size of the queued items.
+    pub fn bytes(&self) -> usize {
+        self.inner.lock().unwrap().bytes
+    }
+
+    /// Returns the number of items that have been dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.inner.lock().unwrap().dropped
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    /// Pushes items numbered from 0. Every item that is a multiple of gop_size is a random access item.
+    fn push_items(queue: &WriteQueue<u32>, items: std::ops::Range<u32>, gop_size: u32) -> Vec<PushResult> {
+        items.map(|i| queue.push(i, 10, i % gop_size == 0)).collect()
+    }
+
+    fn pop_all(queue: &WriteQueue<u32>) -> Vec<(u32, bool)> {
+        queue.close();
+        std::iter::from_fn(|| queue.pop()).collect()
+    }
+
+    #[test]
+    fn test_write_queue_drop_oldest_gop() {
+        let queue = WriteQueue::new(50, Duration::ZERO, LeakyPolicy::DropOldestGop);
+        let results = push_items(&queue, 0..7, 3);
+        assert_eq!(results[..5], [PushResult::Queued(0), PushResult::Queued(0), PushResult::Queued(0),
+            PushResult::Queued(0), PushResult::Queued(0)]);
+        // Items 0, 1, and 2 are dropped to make room for item 5.
+        assert_eq!(results[5], PushResult::Queued(3));
+        assert_eq!(results[6], PushResult::Queued(0));
+        assert_eq!(queue.bytes(), 40);
+        assert_eq!(queue.dropped(), 3);
+        assert_eq!(pop_all(&queue), vec![(3, true), (4, false), (5, false), (6, false)]);
+    }
+
+    #[test]
+    fn test_write_queue_drop_newest() {
+        let queue = WriteQueue::new(30, Duration::ZERO, LeakyPolicy::DropNewest);
+        let results = push_items(&queue, 0..5, 1);
+        assert_eq!(results, vec![PushResult::Queued(0), PushResult::Queued(0), PushResult::Queued(0),
+            PushResult::Dropped, PushResult::Dropped]);
+        assert_eq!(queue.pop(), Some((0, false)));
+        assert_eq!(queue.push(5, 10, true), PushResult::Queued(0));
+        assert_eq!(pop_all(&queue), vec![(1, false), (2, false), (5, true)]);
+    }
+
+    #[test]
+    fn test_write_queue_max_time() {
+        let queue = WriteQueue::new(0, Duration::from_millis(10), LeakyPolicy::DropNewest);
+        assert_eq!(queue.push(0, 10, true), PushResult::Queued(0));
+        assert_eq!(queue.push(1, 10, true), PushResult::Queued(0));
+        thread::sleep(Duration::from_millis(20));
+        assert!(queue.latency() >= Duration::from_millis(10));
+        assert_eq!(queue.push(2, 10, true), PushResult::Dropped);
+    }
+
+    #[test]
+    fn test_write_queue_block() {
+        let queue = Arc::new(WriteQueue::new(20, Duration::ZERO, LeakyPolicy::Block));
+        let consumer = {
+            let queue = queue.clone();
+            thread::spawn(move || std::iter::from_fn(|| queue.pop()).collect::<Vec<_>>())
+        };
+        // The producer blocks until the consumer has made room, so no items are dropped.
+        let results = push_items(&queue, 0..10, 1);
+        assert!(results.iter().all(|result| *result == PushResult::Queued(0)));
+        assert!(queue.wait_until_empty());
+        assert_eq!(queue.bytes(), 0);
+        queue.close();
+        assert_eq!(queue.push(10, 10, true), PushResult::Closed);
+        let items = consumer.join().unwrap();
+        assert_eq!(items, (0..10).map(|i| (i, false)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_write_queue_wait_until_empty_flushing() {
+        let queue = Arc::new(WriteQueue::new(0, Duration::ZERO, LeakyPolicy::Block));
+        assert_eq!(queue.push(0, 10, true), PushResult::Queued(0));
+        // There is no consumer, so the waiter returns only when the queue is flushing.
+        let waiter = {
+            let queue = queue.clone();
+            thread::spawn(move || queue.wait_until_empty())
+        };
+        thread::sleep(Duration::from_millis(20));
+        queue.set_flushing(true);
+        assert!(!waiter.join().unwrap());
+        assert_eq!(queue.push(1, 10, true), PushResult::Flushing);
+    }
+}
@@ -0,0 +1,1105 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+//! These tests use in-memory storage (storage-uri=memory://name) so they do not require a Pravega server.
+
+use gst::ClockTime;
+use gst::prelude::*;
+use pravega_video::caps::get_caps_stream_name;
+use pravega_video::event_serde::{EventWithHeader, EventWriter};
+use pravega_video::index::{IndexRecord, IndexRecordReader, get_index_stream_name};
+use pravega_video::marker::{MarkerRecord, get_marker_stream_name, list_markers};
+use pravega_video::storage::{ByteStreamWriter, MemoryStorage, StorageBackend};
+use pravega_video::timestamp::PravegaTimestamp;
+use pravega_video::utils::CurrentHead;
+use std::convert::TryFrom;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::str::FromStr;
+
+const SCOPE: &str = "test";
+const FIRST_PTS_SECONDS: u64 = 1_600_000_000;
+const FRAME_MSECONDS: u64 = 100;
+
+fn init() {
+    use std::sync::Once;
+    static INIT: Once = Once::new();
+
+    INIT.call_once(|| {
+        gst::init().unwrap();
+        gstpravega::plugin_register_static().unwrap();
+    });
+}
+
+/// A temporary directory that is deleted when dropped, even if the test fails.
+struct TempDir(std::path::PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("gst-plugin-pravega-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        TempDir(path)
+    }
+
+    fn to_str(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn frame_pts(frame: u64) -> ClockTime {
+    FIRST_PTS_SECONDS * gst::SECOND + frame * FRAME_MSECONDS * gst::MSECOND
+}
+
+fn make_sink(storage_name: &str, stream_name: &str) -> gst::Element {
+    let sink = gst::ElementFactory::make("pravegasink", None).unwrap();
+    sink.set_property_from_str("storage-uri", &format!("memory://{}", storage_name));
+    sink.set_property_from_str("stream", &format!("{}/{}", SCOPE, stream_name));
+    sink.set_property_from_str("timestamp-mode", "tai");
+    sink.set_property_from_str("index-min-sec", "0");
+    sink
+}
+
+/// Push frames to a harness. Every frame that is a multiple of key_frame_interval is a key frame.
+fn make_frame(frame: u64, key_frame_interval: u64) -> gst::Buffer {
+    let mut buffer = gst::Buffer::from_slice(frame.to_be_bytes());
+    {
+        let buffer = buffer.get_mut().unwrap();
+        buffer.set_pts(frame_pts(frame));
+        buffer.set_duration(FRAME_MSECONDS * gst::MSECOND);
+        if frame % key_frame_interval != 0 {
+            buffer.set_flags(gst::BufferFlags::DELTA_UNIT);
+        }
+    }
+    buffer
+}
+
+fn push_frames(h: &mut gst_check::Harness, frames: std::ops::Range<u64>, key_frame_interval: u64) {
+    for frame in frames {
+        assert_eq!(h.push(make_frame(frame, key_frame_interval)), Ok(gst::FlowSuccess::Ok));
+    }
+}
+
+/// Write frames to a stream with pravegasink. Every frame that is a multiple of key_frame_interval is a key frame and is indexed.
+fn write_frames(storage_name: &str, stream_name: &str, num_frames: u64, key_frame_interval: u64, seal: bool) {
+    let sink = make_sink(storage_name, stream_name);
+    sink.set_property_from_str("seal", if seal { "true" } else { "false" });
+    let mut h = gst_check::Harness::with_element(&sink, Some("sink"), None);
+    h.set_src_caps_str("application/x-test");
+    h.play();
+    push_frames(&mut h, 0..num_frames, key_frame_interval);
+    assert!(h.push_event(gst::event::Eos::new()));
+    // Dropping the harness stops the element which flushes and optionally seals the streams.
+}
+
+fn make_src(storage_name: &str, stream_name: &str) -> gst::Element {
+    let src = gst::ElementFactory::make("pravegasrc", None).unwrap();
+    src.set_property_from_str("storage-uri", &format!("memory://{}", storage_name));
+    src.set_property_from_str("stream", &format!("{}/{}", SCOPE, stream_name));
+    src
+}
+
+fn pull_frame(h: &mut gst_check::Harness) -> u64 {
+    let buffer = h.pull().unwrap();
+    let map = buffer.map_readable().unwrap();
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(map.as_slice());
+    let frame = u64::from_be_bytes(bytes);
+    assert_eq!(buffer.pts(), frame_pts(frame));
+    frame
+}
+
+fn pull_until_eos(h: &mut gst_check::Harness) {
+    loop {
+        let event = h.pull_event().unwrap();
+        if let gst::EventView::Eos(_) = event.view() {
+            return;
+        }
+    }
+}
+
+/// Returns the index record at the specified position in the index.
+fn read_index_record(storage: &MemoryStorage, stream_name: &str, position: u64) -> IndexRecord {
+    let mut reader = storage.create_reader(SCOPE, &get_index_stream_name(stream_name)).unwrap();
+    reader.seek(SeekFrom::Start(position * IndexRecord::RECORD_SIZE as u64)).unwrap();
+    IndexRecordReader::new().read(&mut reader).unwrap()
+}
+
+#[test]
+fn test_write_and_read_sealed() {
+    init();
+    let storage_name = "test_write_and_read_sealed";
+    let stream_name = "stream1";
+    write_frames(storage_name, stream_name, 20, 1, true);
+    let src = make_src(storage_name, stream_name);
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for expected_frame in 0..20 {
+        assert_eq!(pull_frame(&mut h), expected_frame);
+    }
+    pull_until_eos(&mut h);
+}
+
+#[test]
+fn test_start_timestamp() {
+    init();
+    let storage_name = "test_start_timestamp";
+    let stream_name = "stream1";
+    write_frames(storage_name, stream_name, 20, 1, true);
+    let src = make_src(storage_name, stream_name);
+    src.set_property_from_str("start-mode", "timestamp");
+    // Start between frames 12 and 13. The source should start at the preceding index record.
+    src.set_property_from_str("start-timestamp", &(frame_pts(12) + 50 * gst::MSECOND).nseconds().unwrap().to_string());
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for expected_frame in 12..20 {
+        assert_eq!(pull_frame(&mut h), expected_frame);
+    }
+    pull_until_eos(&mut h);
+}
+
+#[test]
+fn test_start_earliest_after_truncation() {
+    init();
+    let storage_name = "test_start_earliest_after_truncation";
+    let stream_name = "stream1";
+    write_frames(storage_name, stream_name, 20, 1, true);
+    let storage = MemoryStorage::named(storage_name);
+    let index_record = read_index_record(&storage, stream_name, 5);
+    storage.create_writer(SCOPE, &get_index_stream_name(stream_name)).unwrap()
+        .truncate_data_before(5 * IndexRecord::RECORD_SIZE as u64).unwrap();
+    storage.create_writer(SCOPE, stream_name).unwrap()
+        .truncate_data_before(index_record.offset).unwrap();
+    let src = make_src(storage_name, stream_name);
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for expected_frame in 5..20 {
+        assert_eq!(pull_frame(&mut h), expected_frame);
+    }
+    pull_until_eos(&mut h);
+}
+
+/// An unbounded reader should wait at the tail of an unsealed stream, continue to read after
+/// the stream is truncated behind it, and end when the stream is sealed.
+#[test]
+fn test_truncate_and_seal_during_read() {
+    init();
+    let storage_name = "test_truncate_and_seal_during_read";
+    let stream_name = "stream1";
+    write_frames(storage_name, stream_name, 20, 1, false);
+    let storage = MemoryStorage::named(storage_name);
+    let src = make_src(storage_name, stream_name);
+    src.set_property_from_str("end-mode", "unbounded");
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for expected_frame in 0..10 {
+        assert_eq!(pull_frame(&mut h), expected_frame);
+    }
+    let index_record = read_index_record(&storage, stream_name, 5);
+    storage.create_writer(SCOPE, stream_name).unwrap()
+        .truncate_data_before(index_record.offset).unwrap();
+    for expected_frame in 10..20 {
+        assert_eq!(pull_frame(&mut h), expected_frame);
+    }
+    storage.create_writer(SCOPE, stream_name).unwrap().seal().unwrap();
+    pull_until_eos(&mut h);
+}
+
+/// With timestamp-mode=realtime-clock, a buffer in the second following a leap second is ambiguous.
+/// It should be resolved to the earlier time, within the leap second, rather than written without a timestamp.
+#[test]
+fn test_realtime_clock_leap_second() {
+    init();
+    let storage_name = "test_realtime_clock_leap_second";
+    let stream_name = "stream1";
+    let storage = MemoryStorage::named(storage_name);
+    let sink = make_sink(storage_name, stream_name);
+    sink.set_property_from_str("timestamp-mode", "realtime-clock");
+    sink.set_property_from_str("seal", "true");
+    let mut h = gst_check::Harness::with_element(&sink, Some("sink"), None);
+    h.set_src_caps_str("application/x-test");
+    h.play();
+    // The leap second 2016-12-31T23:59:60Z was followed by Unix time 1483228800.
+    sink.set_base_time(1_483_228_800 * gst::SECOND);
+    let mut buffer = gst::Buffer::from_slice(0u64.to_be_bytes());
+    buffer.get_mut().unwrap().set_pts(500 * gst::MSECOND);
+    assert_eq!(h.push(buffer), Ok(gst::FlowSuccess::Ok));
+    assert!(h.push_event(gst::event::Eos::new()));
+    drop(h);
+    let expected_timestamp = PravegaTimestamp::try_from(Some("2016-12-31T23:59:60.500000000Z")).unwrap();
+    let index_record = read_index_record(&storage, stream_name, 0);
+    assert_eq!(index_record.timestamp, expected_timestamp);
+    assert!(index_record.timestamp.is_leap_second());
+}
+
+/// A caps change should not be recorded as a discontinuity. pravegasrc should set the new caps at the buffer where they changed.
+#[test]
+fn test_caps_change() {
+    init();
+    let storage_name = "test_caps_change";
+    let stream_name = "stream1";
+    let sink = make_sink(storage_name, stream_name);
+    sink.set_property_from_str("seal", "true");
+    let mut h = gst_check::Harness::with_element(&sink, Some("sink"), None);
+    h.set_src_caps_str("application/x-test, variant=(int)1");
+    h.play();
+    push_frames(&mut h, 0..5, 10);
+    h.set_src_caps_str("application/x-test, variant=(int)2");
+    push_frames(&mut h, 5..10, 10);
+    assert!(h.push_event(gst::event::Eos::new()));
+    drop(h);
+
+    let src = make_src(storage_name, stream_name);
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for expected_frame in 0..10 {
+        let buffer = h.pull().unwrap();
+        assert_eq!(buffer.pts(), frame_pts(expected_frame));
+        assert_eq!(buffer.flags().contains(gst::BufferFlags::DISCONT), expected_frame == 0);
+        let expected_caps = gst::Caps::from_str(if expected_frame < 5 {
+            "application/x-test, variant=(int)1"
+        } else {
+            "application/x-test, variant=(int)2"
+        }).unwrap();
+        assert_eq!(h.sinkpad().unwrap().current_caps(), Some(expected_caps));
+    }
+    pull_until_eos(&mut h);
+}
+
+/// Streams written by older versions of pravegasink do not have caps or marker streams.
+/// pravegasrc should read them without creating the missing streams.
+#[test]
+fn test_missing_companion_streams() {
+    init();
+    let storage_name = "test_missing_companion_streams";
+    let storage = MemoryStorage::named(storage_name);
+    write_frames(storage_name, "stream1", 10, 1, true);
+    // Copy only the data and index streams.
+    let streams = vec![("stream1".to_owned(), "stream2".to_owned()),
+        (get_index_stream_name("stream1"), get_index_stream_name("stream2"))];
+    for (source, destination) in streams.iter() {
+        let mut reader = storage.create_reader(SCOPE, source).unwrap();
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).unwrap();
+        storage.create_stream(SCOPE, destination, None).unwrap();
+        let mut writer = storage.create_writer(SCOPE, destination).unwrap();
+        writer.write_all(&bytes).unwrap();
+        writer.seal().unwrap();
+    }
+
+    let src = make_src(storage_name, "stream2");
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for expected_frame in 0..10 {
+        assert_eq!(pull_frame(&mut h), expected_frame);
+    }
+    pull_until_eos(&mut h);
+    drop(h);
+    assert!(!storage.stream_exists(SCOPE, &get_caps_stream_name("stream2")).unwrap());
+    assert!(!storage.stream_exists(SCOPE, &get_marker_stream_name("stream2")).unwrap());
+
+    // A start marker cannot be found in a stream without a marker stream.
+    let src = make_src(storage_name, "stream2");
+    src.set_property_from_str("start-mode", "marker");
+    src.set_property_from_str("start-marker", "first");
+    assert!(src.set_state(gst::State::Paused).is_err());
+    src.set_state(gst::State::Null).unwrap();
+    assert!(!storage.stream_exists(SCOPE, &get_marker_stream_name("stream2")).unwrap());
+}
+
+/// In transactional mode, a group of pictures and its index record should not be written until the next index record.
+#[test]
+fn test_transactional() {
+    init();
+    let storage_name = "test_transactional";
+    let stream_name = "stream1";
+    let storage = MemoryStorage::named(storage_name);
+    let sink = make_sink(storage_name, stream_name);
+    sink.set_property_from_str("transactional", "true");
+    sink.set_property_from_str("seal", "true");
+    let mut h = gst_check::Harness::with_element(&sink, Some("sink"), None);
+    h.set_src_caps_str("application/x-test");
+    h.play();
+    push_frames(&mut h, 0..12, 5);
+    // Frames 0 to 9 have been committed. Frames 10 and 11 are in the open transaction.
+    let index_tail = storage.create_writer(SCOPE, &get_index_stream_name(stream_name)).unwrap().seek_to_tail().unwrap();
+    assert_eq!(index_tail, 2 * IndexRecord::RECORD_SIZE as u64);
+    let data_tail = storage.create_writer(SCOPE, stream_name).unwrap().seek_to_tail().unwrap();
+    assert!(h.push_event(gst::event::Eos::new()));
+    drop(h);
+    // Stopping commits the open transaction. It begins where the data stream previously ended.
+    assert_eq!(read_index_record(&storage, stream_name, 2).offset, data_tail);
+    let src = make_src(storage_name, stream_name);
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for expected_frame in 0..12 {
+        assert_eq!(pull_frame(&mut h), expected_frame);
+    }
+    pull_until_eos(&mut h);
+}
+
+/// An incomplete chain of fragments at the end of a sealed stream should be discarded with a warning.
+#[test]
+fn test_truncated_fragment_chain() {
+    init();
+    let storage_name = "test_truncated_fragment_chain";
+    let stream_name = "stream1";
+    let storage = MemoryStorage::named(storage_name);
+    write_frames(storage_name, stream_name, 10, 5, false);
+
+    // Append the first fragment and half of the last fragment of a buffer, as if the writer failed while writing it.
+    let mut writer = storage.create_writer(SCOPE, stream_name).unwrap();
+    writer.seek_to_tail().unwrap();
+    let payload = [9u8; 300];
+    let timestamp = PravegaTimestamp::from_nanoseconds(frame_pts(10).nseconds());
+    let mut event_writer = EventWriter::new();
+    event_writer.write(&EventWithHeader::new_fragment(&payload[..100], timestamp, false, false, false, false, true), &mut writer).unwrap();
+    let mut last_fragment = Vec::new();
+    event_writer.write(&EventWithHeader::new_fragment(&payload[100..], timestamp, false, false, false, true, false), &mut last_fragment).unwrap();
+    writer.write_all(&last_fragment[..last_fragment.len() / 2]).unwrap();
+    writer.flush().unwrap();
+    writer.seal().unwrap();
+
+    let src = make_src(storage_name, stream_name);
+    let bus = gst::Bus::new();
+    src.set_bus(Some(&bus));
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for expected_frame in 0..10 {
+        assert_eq!(pull_frame(&mut h), expected_frame);
+    }
+    pull_until_eos(&mut h);
+    let warning = bus.pop_filtered(&[gst::MessageType::Warning]).unwrap();
+    match warning.view() {
+        gst::MessageView::Warning(warning) => assert!(warning.error().to_string().contains("Discarded incomplete chain of 1 fragments")),
+        _ => unreachable!(),
+    }
+}
+
+/// When the storage is unavailable, buffers should be written to the spool and then written to the storage
+/// when it becomes available.
+#[test]
+fn test_spool() {
+    init();
+    let storage_name = "test_spool";
+    let stream_name = "stream1";
+    let spool_dir = TempDir::new("spool");
+    let storage = MemoryStorage::named(storage_name);
+    let sink = make_sink(storage_name, stream_name);
+    sink.set_property_from_str("spool-dir", spool_dir.to_str());
+    // Attempt to reconnect with each buffer.
+    sink.set_property_from_str("spool-connect-backoff-sec", "0");
+    // Do not buffer writes so that no data is lost when the storage becomes unavailable.
+    sink.set_property_from_str("buffer-size", "0");
+    sink.set_property_from_str("seal", "true");
+    let bus = gst::Bus::new();
+    sink.set_bus(Some(&bus));
+    let mut h = gst_check::Harness::with_element(&sink, Some("sink"), None);
+    h.set_src_caps_str("application/x-test");
+    h.play();
+    push_frames(&mut h, 0..5, 1);
+    storage.set_available(false);
+    push_frames(&mut h, 5..10, 1);
+    storage.set_available(true);
+    push_frames(&mut h, 10..15, 1);
+    assert!(h.push_event(gst::event::Eos::new()));
+    drop(h);
+
+    let spool_status: Vec<bool> = std::iter::from_fn(|| bus.pop_filtered(&[gst::MessageType::Element]))
+        .filter_map(|message| match message.view() {
+            gst::MessageView::Element(element) => element.structure()
+                .filter(|s| s.name() == "pravega-spool")
+                .map(|s| s.get::<bool>("spooling").unwrap()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(spool_status.first(), Some(&true));
+    assert_eq!(spool_status.last(), Some(&false));
+
+    let src = make_src(storage_name, stream_name);
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for expected_frame in 0..15 {
+        assert_eq!(pull_frame(&mut h), expected_frame);
+    }
+    pull_until_eos(&mut h);
+}
+
+/// When the storage is unavailable when the sink starts, buffers should be written to the spool,
+/// and the streams should be created when the storage becomes available.
+#[test]
+fn test_spool_at_start() {
+    init();
+    let storage_name = "test_spool_at_start";
+    let stream_name = "stream1";
+    let spool_dir = TempDir::new("spool-at-start");
+    let storage = MemoryStorage::named(storage_name);
+    storage.set_available(false);
+    let sink = make_sink(storage_name, stream_name);
+    sink.set_property_from_str("spool-dir", spool_dir.to_str());
+    sink.set_property_from_str("spool-connect-backoff-sec", "0");
+    sink.set_property_from_str("seal", "true");
+    let mut h = gst_check::Harness::with_element(&sink, Some("sink"), None);
+    h.set_src_caps_str("application/x-test");
+    h.play();
+    push_frames(&mut h, 0..5, 1);
+    // Markers are dropped until the streams have been created.
+    let added = sink.emit_by_name("add-marker", &[&"spooling", &""]).unwrap().unwrap();
+    assert!(!added.get::<bool>().unwrap());
+    storage.set_available(true);
+    push_frames(&mut h, 5..10, 1);
+    // The marker writer is opened when the sink reconnects.
+    let added = sink.emit_by_name("add-marker", &[&"connected", &""]).unwrap().unwrap();
+    assert!(added.get::<bool>().unwrap());
+    assert!(h.push_event(gst::event::Eos::new()));
+    drop(h);
+
+    let marker_reader = storage.create_reader(SCOPE, &get_marker_stream_name(stream_name)).unwrap();
+    let markers = list_markers(marker_reader, PravegaTimestamp::NONE, PravegaTimestamp::NONE).unwrap();
+    assert_eq!(markers.iter().map(|marker| marker.label.as_str()).collect::<Vec<_>>(), vec!["connected"]);
+
+    let src = make_src(storage_name, stream_name);
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for expected_frame in 0..10 {
+        assert_eq!(pull_frame(&mut h), expected_frame);
+    }
+    pull_until_eos(&mut h);
+}
+
+/// With async-write, buffers should be written by the writer thread and the queue should be empty after end-of-stream.
+#[test]
+fn test_async_write() {
+    init();
+    let storage_name = "test_async_write";
+    let stream_name = "stream1";
+    let sink = make_sink(storage_name, stream_name);
+    sink.set_property_from_str("async-write", "true");
+    sink.set_property_from_str("write-queue-leaky", "block");
+    sink.set_property_from_str("seal", "true");
+    let mut h = gst_check::Harness::with_element(&sink, Some("sink"), None);
+    h.set_src_caps_str("application/x-test");
+    h.play();
+    push_frames(&mut h, 0..20, 5);
+    assert!(h.push_event(gst::event::Eos::new()));
+    let latency = sink.property("write-queue-latency-sec").unwrap().get::<f64>().unwrap();
+    assert_eq!(latency, 0.0);
+    drop(h);
+
+    let src = make_src(storage_name, stream_name);
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for expected_frame in 0..20 {
+        assert_eq!(pull_frame(&mut h), expected_frame);
+    }
+    pull_until_eos(&mut h);
+}
+
+/// Small buffers should be coalesced into one event per group of pictures.
+/// By default, pravegasrc should emit the original buffers.
+/// With split-batches=false, it should emit each event as a single buffer.
+#[test]
+fn test_coalesce() {
+    init();
+    let storage_name = "test_coalesce";
+    let stream_name = "stream1";
+    let sink = make_sink(storage_name, stream_name);
+    sink.set_property_from_str("coalesce-max-bytes", "1024");
+    sink.set_property_from_str("coalesce-max-sec", "1.0");
+    sink.set_property_from_str("seal", "true");
+    let mut h = gst_check::Harness::with_element(&sink, Some("sink"), None);
+    h.set_src_caps_str("application/x-test");
+    h.play();
+    push_frames(&mut h, 0..20, 5);
+    assert!(h.push_event(gst::event::Eos::new()));
+    drop(h);
+
+    // With split-batches=false, each group of pictures is a single buffer.
+    let src = make_src(storage_name, stream_name);
+    src.set_property_from_str("split-batches", "false");
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for first_frame in (0..20).step_by(5) {
+        let buffer = h.pull().unwrap();
+        assert_eq!(buffer.size(), 5 * 8);
+        assert_eq!(buffer.pts(), frame_pts(first_frame));
+        assert!(!buffer.flags().contains(gst::BufferFlags::DELTA_UNIT));
+    }
+    pull_until_eos(&mut h);
+    drop(h);
+
+    // By default, only the first buffer of each group of pictures has a timestamp.
+    let src = make_src(storage_name, stream_name);
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for expected_frame in 0..20 {
+        let buffer = h.pull().unwrap();
+        let map = buffer.map_readable().unwrap();
+        assert_eq!(map.as_slice(), expected_frame.to_be_bytes());
+        if expected_frame % 5 == 0 {
+            assert_eq!(buffer.pts(), frame_pts(expected_frame));
+            assert!(!buffer.flags().contains(gst::BufferFlags::DELTA_UNIT));
+        } else {
+            assert_eq!(buffer.pts(), ClockTime::none());
+            assert!(buffer.flags().contains(gst::BufferFlags::DELTA_UNIT));
+        }
+    }
+    pull_until_eos(&mut h);
+}
+
+/// Buffers pushed as a buffer list should be batched and indexed the same as individual buffers.
+#[test]
+fn test_coalesce_buffer_list() {
+    init();
+    let storage_name = "test_coalesce_buffer_list";
+    let stream_name = "stream1";
+    let storage = MemoryStorage::named(storage_name);
+    let sink = make_sink(storage_name, stream_name);
+    sink.set_property_from_str("coalesce-max-bytes", "1024");
+    sink.set_property_from_str("coalesce-max-sec", "1.0");
+    sink.set_property_from_str("seal", "true");
+    let mut h = gst_check::Harness::with_element(&sink, Some("sink"), None);
+    h.set_src_caps_str("application/x-test");
+    h.play();
+    let mut list = gst::BufferList::new_sized(20);
+    {
+        let list = list.get_mut().unwrap();
+        for frame in 0..20 {
+            list.add(make_frame(frame, 5));
+        }
+    }
+    assert_eq!(h.srcpad().unwrap().push_list(list), Ok(gst::FlowSuccess::Ok));
+    assert!(h.push_event(gst::event::Eos::new()));
+    drop(h);
+
+    // Each group of pictures begins a new batch with its own index record.
+    for (position, first_frame) in (0..20).step_by(5).enumerate() {
+        let index_record = read_index_record(&storage, stream_name, position as u64);
+        assert!(index_record.random_access);
+        assert_eq!(index_record.timestamp.nanoseconds(), frame_pts(first_frame).nseconds());
+    }
+
+    let src = make_src(storage_name, stream_name);
+    src.set_property_from_str("split-batches", "false");
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for first_frame in (0..20).step_by(5) {
+        let buffer = h.pull().unwrap();
+        assert_eq!(buffer.size(), 5 * 8);
+        assert_eq!(buffer.pts(), frame_pts(first_frame));
+    }
+    pull_until_eos(&mut h);
+}
+
+/// A batch should be written after coalesce-max-sec of wall-clock time even if no further buffers arrive.
+#[test]
+fn test_coalesce_wall_clock() {
+    init();
+    let storage_name = "test_coalesce_wall_clock";
+    let stream_name = "stream1";
+    let storage = MemoryStorage::named(storage_name);
+    let sink = make_sink(storage_name, stream_name);
+    sink.set_property_from_str("coalesce-max-bytes", "1024");
+    sink.set_property_from_str("coalesce-max-sec", "0.2");
+    sink.set_property_from_str("seal", "true");
+    let mut h = gst_check::Harness::with_element(&sink, Some("sink"), None);
+    h.set_src_caps_str("application/x-test");
+    h.play();
+    push_frames(&mut h, 0..3, 10);
+    let data_tail = || storage.create_writer(SCOPE, stream_name).unwrap().seek_to_tail().unwrap();
+    assert_eq!(data_tail(), 0);
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    assert!(data_tail() > 0);
+    drop(h);
+
+    let src = make_src(storage_name, stream_name);
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for expected_frame in 0..3u64 {
+        let buffer = h.pull().unwrap();
+        let map = buffer.map_readable().unwrap();
+        assert_eq!(map.as_slice(), expected_frame.to_be_bytes());
+    }
+    pull_until_eos(&mut h);
+}
+
+/// Returns the fields of the next pravega-retention element message.
+fn wait_for_retention_message(bus: &gst::Bus) -> gst::Structure {
+    loop {
+        let message = bus.timed_pop_filtered(10 * gst::SECOND, &[gst::MessageType::Element]).unwrap();
+        if let gst::MessageView::Element(element) = message.view() {
+            if let Some(structure) = element.structure().filter(|s| s.name() == "pravega-retention") {
+                return structure.to_owned();
+            }
+        }
+    }
+}
+
+/// Retention should delete at most retention-max-truncate-bytes per interval and report the new head.
+/// With retention-dry-run, nothing should be deleted.
+#[test]
+fn test_retention_max_truncate_bytes_and_dry_run() {
+    init();
+    let storage_name = "test_retention_max_truncate_bytes_and_dry_run";
+    let stream_name = "stream1";
+    write_frames(storage_name, stream_name, 20, 1, false);
+    let storage = MemoryStorage::named(storage_name);
+    let index_record = read_index_record(&storage, stream_name, 5);
+    let data_head = || storage.create_reader(SCOPE, stream_name).unwrap().current_head().unwrap();
+
+    let run_retention = |dry_run: bool| {
+        let sink = make_sink(storage_name, stream_name);
+        // All frames are older than the retention period.
+        sink.set_property_from_str("retention-type", "days");
+        sink.set_property_from_str("retention-days", "1");
+        sink.set_property_from_str("retention-max-truncate-bytes", &index_record.offset.to_string());
+        sink.set_property_from_str("retention-dry-run", if dry_run { "true" } else { "false" });
+        let bus = gst::Bus::new();
+        sink.set_bus(Some(&bus));
+        let mut h = gst_check::Harness::with_element(&sink, Some("sink"), None);
+        h.play();
+        let structure = wait_for_retention_message(&bus);
+        drop(h);
+        structure
+    };
+
+    let structure = run_retention(true);
+    assert_eq!(structure.get::<bool>("dry-run").unwrap(), true);
+    assert_eq!(structure.get::<u64>("bytes-deleted").unwrap(), index_record.offset);
+    assert_eq!(data_head(), 0);
+
+    let structure = run_retention(false);
+    assert_eq!(structure.get::<bool>("dry-run").unwrap(), false);
+    assert_eq!(structure.get::<bool>("limited").unwrap(), true);
+    assert_eq!(structure.get::<u64>("head-timestamp").unwrap(), index_record.timestamp.nanoseconds().unwrap());
+    assert_eq!(structure.get::<u64>("bytes-deleted").unwrap(), index_record.offset);
+    assert_eq!(data_head(), index_record.offset);
+
+    storage.create_writer(SCOPE, stream_name).unwrap().seal().unwrap();
+    let src = make_src(storage_name, stream_name);
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for expected_frame in 5..20 {
+        assert_eq!(pull_frame(&mut h), expected_frame);
+    }
+    pull_until_eos(&mut h);
+}
+
+/// Retention with retention-archive-uri should archive data before truncating it, and pravegasrc should be able to play the archive.
+#[test]
+fn test_retention_archive() {
+    init();
+    let storage_name = "test_retention_archive";
+    let stream_name = "stream1";
+    let archive_dir = TempDir::new("archive");
+    write_frames(storage_name, stream_name, 20, 1, false);
+    let storage = MemoryStorage::named(storage_name);
+    let index_record = read_index_record(&storage, stream_name, 5);
+
+    let sink = make_sink(storage_name, stream_name);
+    sink.set_property_from_str("retention-type", "days");
+    sink.set_property_from_str("retention-days", "1");
+    sink.set_property_from_str("retention-max-truncate-bytes", &index_record.offset.to_string());
+    sink.set_property_from_str("retention-archive-uri", &format!("file://{}", archive_dir.to_str()));
+    let bus = gst::Bus::new();
+    sink.set_bus(Some(&bus));
+    let mut h = gst_check::Harness::with_element(&sink, Some("sink"), None);
+    h.play();
+    let structure = wait_for_retention_message(&bus);
+    drop(h);
+    assert_eq!(structure.get::<bool>("archived").unwrap(), true);
+    assert_eq!(storage.create_reader(SCOPE, stream_name).unwrap().current_head().unwrap(), index_record.offset);
+
+    let src = gst::ElementFactory::make("pravegasrc", None).unwrap();
+    src.set_property_from_str("storage-uri", &format!("file://{}", archive_dir.to_str()));
+    src.set_property_from_str("stream", &format!("{}/{}", SCOPE, stream_name));
+    src.set_property_from_str("end-mode", "latest");
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for expected_frame in 0..5 {
+        assert_eq!(pull_frame(&mut h), expected_frame);
+    }
+    pull_until_eos(&mut h);
+}
+
+/// Returns the element messages with the specified name that have been posted to the bus.
+fn pop_element_messages(bus: &gst::Bus, name: &str) -> Vec<gst::Structure> {
+    std::iter::from_fn(|| bus.pop_filtered(&[gst::MessageType::Element]))
+        .filter_map(|message| match message.view() {
+            gst::MessageView::Element(element) => element.structure()
+                .filter(|s| s.name() == name)
+                .map(|s| s.to_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Reads a stream with pravegasrc and checks that it contains exactly the expected frames.
+fn assert_frames(storage_name: &str, stream_name: &str, expected_frames: std::ops::Range<u64>) {
+    let src = make_src(storage_name, stream_name);
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for expected_frame in expected_frames {
+        assert_eq!(pull_frame(&mut h), expected_frame);
+    }
+    pull_until_eos(&mut h);
+}
+
+/// The switch-stream signal should roll over to the new stream at the next key frame.
+#[test]
+fn test_switch_stream() {
+    init();
+    let storage_name = "test_switch_stream";
+    let sink = make_sink(storage_name, "stream1");
+    sink.set_property_from_str("seal", "true");
+    sink.set_property_from_str("seal-on-rollover", "true");
+    let bus = gst::Bus::new();
+    sink.set_bus(Some(&bus));
+    let mut h = gst_check::Harness::with_element(&sink, Some("sink"), None);
+    h.set_src_caps_str("application/x-test");
+    h.play();
+    push_frames(&mut h, 0..10, 5);
+    let switched = sink.emit_by_name("switch-stream", &[&format!("{}/stream2", SCOPE)]).unwrap().unwrap();
+    assert!(switched.get::<bool>().unwrap());
+    // Frames 10 to 14 are a single group of pictures, so the switch happens at frame 10.
+    push_frames(&mut h, 10..20, 5);
+    assert!(h.push_event(gst::event::Eos::new()));
+    drop(h);
+
+    let rollovers = pop_element_messages(&bus, "pravega-rollover");
+    assert_eq!(rollovers.len(), 1);
+    assert_eq!(rollovers[0].get::<String>("previous-stream").unwrap(), format!("{}/stream1", SCOPE));
+    assert_eq!(rollovers[0].get::<String>("stream").unwrap(), format!("{}/stream2", SCOPE));
+    assert_frames(storage_name, "stream1", 0..10);
+    assert_frames(storage_name, "stream2", 10..20);
+}
+
+/// When the stream is a template, the sink should roll over when the formatted name changes.
+#[test]
+fn test_stream_template() {
+    init();
+    let storage_name = "test_stream_template";
+    let template = "stream-%Y%m%d-%H%M";
+    let stream_name = |frame: u64| {
+        PravegaTimestamp::from_nanoseconds(Some(frame_pts(frame).nseconds().unwrap()))
+            .to_utc_datetime().unwrap().format(template).to_string()
+    };
+    let sink = make_sink(storage_name, template);
+    sink.set_property_from_str("seal", "true");
+    sink.set_property_from_str("seal-on-rollover", "true");
+    let mut h = gst_check::Harness::with_element(&sink, Some("sink"), None);
+    h.set_src_caps_str("application/x-test");
+    h.play();
+    // Frames are 100 milliseconds apart, so frame 600 is one minute after frame 0.
+    push_frames(&mut h, 0..10, 5);
+    push_frames(&mut h, 600..610, 5);
+    assert!(h.push_event(gst::event::Eos::new()));
+    drop(h);
+
+    assert_ne!(stream_name(0), stream_name(600));
+    assert_frames(storage_name, &stream_name(0), 0..10);
+    assert_frames(storage_name, &stream_name(600), 600..610);
+}
+
+/// In trigger mode, only the pre-roll and post-roll around each trigger should be written,
+/// and each recording should begin with a discontinuity.
+#[test]
+fn test_trigger_mode() {
+    init();
+    let storage_name = "test_trigger_mode";
+    let stream_name = "stream1";
+    let storage = MemoryStorage::named(storage_name);
+    let sink = make_sink(storage_name, stream_name);
+    sink.set_property_from_str("trigger-mode", "true");
+    sink.set_property_from_str("pre-roll-sec", "1.0");
+    sink.set_property_from_str("post-roll-sec", "1.0");
+    sink.set_property_from_str("seal", "true");
+    let bus = gst::Bus::new();
+    sink.set_bus(Some(&bus));
+    let mut h = gst_check::Harness::with_element(&sink, Some("sink"), None);
+    h.set_src_caps_str("application/x-test");
+    h.play();
+    push_frames(&mut h, 0..30, 5);
+    // The pre-roll buffer begins at the key frame at 1.5 seconds. Recording stops at the key frame at 4.5 seconds.
+    let triggered = sink.emit_by_name("trigger", &[]).unwrap().unwrap();
+    assert!(triggered.get::<bool>().unwrap());
+    push_frames(&mut h, 30..60, 5);
+    assert!(h.push_event(gst::event::CustomDownstream::new(gst::Structure::new_empty("pravega-trigger"))));
+    push_frames(&mut h, 60..70, 5);
+    assert!(h.push_event(gst::event::Eos::new()));
+    drop(h);
+
+    let recording: Vec<bool> = pop_element_messages(&bus, "pravega-recording").iter()
+        .map(|s| s.get::<bool>("recording").unwrap())
+        .collect();
+    assert_eq!(recording, vec![true, false, true]);
+
+    // Every key frame is indexed. The second recording begins at the seventh index record.
+    assert!(read_index_record(&storage, stream_name, 0).discontinuity);
+    assert!(!read_index_record(&storage, stream_name, 5).discontinuity);
+    let index_record = read_index_record(&storage, stream_name, 6);
+    assert!(index_record.discontinuity);
+    assert_eq!(index_record.timestamp.nanoseconds(), frame_pts(50).nseconds());
+
+    let src = make_src(storage_name, stream_name);
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for expected_frame in (15..45).chain(50..70) {
+        assert_eq!(pull_frame(&mut h), expected_frame);
+    }
+    pull_until_eos(&mut h);
+}
+
+/// Markers added with the add-marker signal and the pravega-marker event should be listed in order of timestamp,
+/// and pravegasrc should start at the most recent marker with a label.
+#[test]
+fn test_markers() {
+    init();
+    let storage_name = "test_markers";
+    let stream_name = "stream1";
+    let storage = MemoryStorage::named(storage_name);
+    let sink = make_sink(storage_name, stream_name);
+    sink.set_property_from_str("seal", "true");
+    let mut h = gst_check::Harness::with_element(&sink, Some("sink"), None);
+    h.set_src_caps_str("application/x-test");
+    h.play();
+    push_frames(&mut h, 0..15, 10);
+    // This marker is assigned the timestamp of frame 14.
+    let added = sink.emit_by_name("add-marker", &[&"first", &r#"{"n":1}"#]).unwrap().unwrap();
+    assert!(added.get::<bool>().unwrap());
+    let structure = gst::Structure::builder("pravega-marker")
+        .field("label", &"second")
+        .field("timestamp", &frame_pts(5).nseconds().unwrap())
+        .build();
+    assert!(h.push_event(gst::event::CustomDownstream::new(structure)));
+    push_frames(&mut h, 15..35, 10);
+    // This marker is assigned the timestamp of frame 34.
+    sink.emit_by_name("add-marker", &[&"first", &r#"{"n":2}"#]).unwrap();
+    push_frames(&mut h, 35..40, 10);
+    assert!(h.push_event(gst::event::Eos::new()));
+    drop(h);
+
+    let timestamp = |frame: u64| PravegaTimestamp::from_nanoseconds(frame_pts(frame).nseconds());
+    let marker_reader = storage.create_reader(SCOPE, &get_marker_stream_name(stream_name)).unwrap();
+    let markers = list_markers(marker_reader, PravegaTimestamp::NONE, PravegaTimestamp::NONE).unwrap();
+    assert_eq!(markers, vec![
+        MarkerRecord::new(timestamp(5), "second".to_owned(), "".to_owned()),
+        MarkerRecord::new(timestamp(14), "first".to_owned(), r#"{"n":1}"#.to_owned()),
+        MarkerRecord::new(timestamp(34), "first".to_owned(), r#"{"n":2}"#.to_owned()),
+    ]);
+    let marker_reader = storage.create_reader(SCOPE, &get_marker_stream_name(stream_name)).unwrap();
+    let markers = list_markers(marker_reader, timestamp(10), timestamp(30)).unwrap();
+    assert_eq!(markers.len(), 1);
+    assert_eq!(markers[0].timestamp, timestamp(14));
+
+    // Playback starts at the key frame on or before frame 34.
+    let src = make_src(storage_name, stream_name);
+    src.set_property_from_str("start-mode", "marker");
+    src.set_property_from_str("start-marker", "first");
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for expected_frame in 30..40 {
+        assert_eq!(pull_frame(&mut h), expected_frame);
+    }
+    pull_until_eos(&mut h);
+}
+
+#[test]
+fn test_relative_timestamp_mode() {
+    init();
+    let storage_name = "test_relative_timestamp_mode";
+    let stream_name = "stream1";
+    write_frames(storage_name, stream_name, 20, 1, true);
+    let src = make_src(storage_name, stream_name);
+    src.set_property_from_str("timestamp-mode", "relative");
+    src.set_property_from_str("start-mode", "timestamp");
+    src.set_property_from_str("start-timestamp", &frame_pts(12).nseconds().unwrap().to_string());
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    // Timestamps are relative to the first frame in the stream, not the first frame read.
+    for expected_frame in 12..20 {
+        let buffer = h.pull().unwrap();
+        assert_eq!(buffer.pts(), expected_frame * FRAME_MSECONDS * gst::MSECOND);
+        let references: Vec<(String, ClockTime)> = buffer.iter_meta::<gst::ReferenceTimestampMeta>()
+            .map(|meta| (meta.reference().structure(0).unwrap().name().to_owned(), meta.timestamp()))
+            .collect();
+        let timestamp = PravegaTimestamp::from_nanoseconds(frame_pts(expected_frame).nseconds());
+        assert_eq!(references, vec![
+            ("timestamp/x-tai".to_owned(), frame_pts(expected_frame)),
+            ("timestamp/x-unix".to_owned(), ClockTime(timestamp.to_unix_nanoseconds())),
+        ]);
+    }
+    pull_until_eos(&mut h);
+}
+
+/// If timestamp-mode=relative, the origin should be the DTS of the first key frame when it precedes its PTS,
+/// so that no DTS is clamped. A seek position should always be relative to the same origin.
+#[test]
+fn test_relative_timestamp_mode_dts() {
+    init();
+    let storage_name = "test_relative_timestamp_mode_dts";
+    let stream_name = "stream1";
+    let dts_delay = 200 * gst::MSECOND;
+    let sink = make_sink(storage_name, stream_name);
+    sink.set_property_from_str("buffer-metadata", "true");
+    sink.set_property_from_str("seal", "true");
+    let mut h = gst_check::Harness::with_element(&sink, Some("sink"), None);
+    h.set_src_caps_str("application/x-test");
+    h.play();
+    for frame in 0..20 {
+        let mut buffer = gst::Buffer::from_slice(frame.to_be_bytes());
+        {
+            let buffer = buffer.get_mut().unwrap();
+            buffer.set_pts(frame_pts(frame));
+            buffer.set_dts(frame_pts(frame) - dts_delay);
+            buffer.set_duration(FRAME_MSECONDS * gst::MSECOND);
+            if frame % 5 != 0 {
+                buffer.set_flags(gst::BufferFlags::DELTA_UNIT);
+            }
+        }
+        assert_eq!(h.push(buffer), Ok(gst::FlowSuccess::Ok));
+    }
+    assert!(h.push_event(gst::event::Eos::new()));
+    drop(h);
+
+    let src = make_src(storage_name, stream_name);
+    src.set_property_from_str("timestamp-mode", "relative");
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    for expected_frame in 0..20 {
+        let buffer = h.pull().unwrap();
+        assert_eq!(buffer.pts(), expected_frame * FRAME_MSECONDS * gst::MSECOND + dts_delay);
+        assert_eq!(buffer.dts(), expected_frame * FRAME_MSECONDS * gst::MSECOND);
+    }
+    pull_until_eos(&mut h);
+    drop(h);
+
+    // Seek to the relative PTS of frame 10.
+    let src = make_src(storage_name, stream_name);
+    src.set_property_from_str("timestamp-mode", "relative");
+    let seek = gst::event::Seek::new(1.0, gst::SeekFlags::FLUSH, gst::SeekType::Set,
+        10 * FRAME_MSECONDS * gst::MSECOND + dts_delay, gst::SeekType::None, ClockTime::none());
+    let mut h = start_src_with_seek(&src, seek, 1.0);
+    for expected_frame in 10..20 {
+        let buffer = h.pull().unwrap();
+        assert_eq!(buffer.pts(), expected_frame * FRAME_MSECONDS * gst::MSECOND + dts_delay);
+        assert_eq!(buffer.dts(), expected_frame * FRAME_MSECONDS * gst::MSECOND);
+    }
+    pull_until_eos(&mut h);
+    drop(h);
+
+    // A seek position is relative to the origin even if it is greater than the absolute time of the origin.
+    // This seek is beyond the end of the stream so it starts at the last key frame.
+    let src = make_src(storage_name, stream_name);
+    src.set_property_from_str("timestamp-mode", "relative");
+    let seek = gst::event::Seek::new(1.0, gst::SeekFlags::FLUSH, gst::SeekType::Set,
+        frame_pts(10), gst::SeekType::None, ClockTime::none());
+    let mut h = start_src_with_seek(&src, seek, 1.0);
+    for expected_frame in 15..20 {
+        let buffer = h.pull().unwrap();
+        assert_eq!(buffer.pts(), expected_frame * FRAME_MSECONDS * gst::MSECOND + dts_delay);
+    }
+    pull_until_eos(&mut h);
+}
+
+/// Create a harness for a source that performs the seek when it starts.
+/// Returns after the segment event, which must have the specified rate and an applied rate of 1.0.
+fn start_src_with_seek(src: &gst::Element, seek: gst::Event, rate: f64) -> gst_check::Harness {
+    assert!(src.send_event(seek));
+    let mut h = gst_check::Harness::with_element(src, None, Some("src"));
+    h.play();
+    loop {
+        let event = h.pull_event().unwrap();
+        if let gst::EventView::Segment(segment) = event.view() {
+            let segment = segment.segment();
+            assert_eq!(segment.rate(), rate);
+            assert_eq!(segment.applied_rate(), 1.0);
+            return h;
+        }
+    }
+}
+
+#[test]
+fn test_key_unit_trick_mode() {
+    init();
+    let storage_name = "test_key_unit_trick_mode";
+    let stream_name = "stream1";
+    let sink = make_sink(storage_name, stream_name);
+    sink.set_property_from_str("seal", "true");
+    let mut h = gst_check::Harness::with_element(&sink, Some("sink"), None);
+    h.set_src_caps_str("application/x-test");
+    h.play();
+    push_frames(&mut h, 0..40, 10);
+    assert!(h.push_event(gst::event::Eos::new()));
+    drop(h);
+
+    let src = make_src(storage_name, stream_name);
+    // The seek is performed when the source starts.
+    let seek = gst::event::Seek::new(4.0, gst::SeekFlags::FLUSH | gst::SeekFlags::TRICKMODE | gst::SeekFlags::TRICKMODE_KEY_UNITS,
+        gst::SeekType::Set, frame_pts(10), gst::SeekType::None, ClockTime::none());
+    assert!(src.send_event(seek));
+    let mut h = gst_check::Harness::with_element(&src, None, Some("src"));
+    h.play();
+    loop {
+        let event = h.pull_event().unwrap();
+        if let gst::EventView::Segment(segment) = event.view() {
+            let segment = segment.segment();
+            assert_eq!(segment.rate(), 4.0);
+            assert_eq!(segment.applied_rate(), 1.0);
+            break;
+        }
+    }
+    // Only key frames are output.
+    for expected_frame in &[10, 20, 30] {
+        let buffer = h.pull().unwrap();
+        assert_eq!(buffer.pts(), frame_pts(*expected_frame));
+        assert!(!buffer.flags().contains(gst::BufferFlags::DELTA_UNIT));
+    }
+    pull_until_eos(&mut h);
+}
+
+#[test]
+fn test_reverse_playback() {
+    init();
+    let storage_name = "test_reverse_playback";
+    let stream_name = "stream1";
+    write_frames(storage_name, stream_name, 40, 10, true);
+
+    // Each group of pictures is output in forward order, beginning with the group of pictures that contains the stop position.
+    let src = make_src(storage_name, stream_name);
+    let seek = gst::event::Seek::new(-1.0, gst::SeekFlags::FLUSH,
+        gst::SeekType::Set, frame_pts(15), gst::SeekType::Set, frame_pts(35));
+    let mut h = start_src_with_seek(&src, seek, -1.0);
+    for gop in &[30, 20, 10] {
+        for expected_frame in *gop..*gop + 10 {
+            let buffer = h.pull().unwrap();
+            assert_eq!(buffer.pts(), frame_pts(expected_frame));
+            assert_eq!(buffer.flags().contains(gst::BufferFlags::DISCONT), expected_frame == *gop);
+        }
+    }
+    pull_until_eos(&mut h);
+    drop(h);
+
+    // In key-unit trick mode, only the first frame of each group of pictures is output.
+    let src = make_src(storage_name, stream_name);
+    let seek = gst::event::Seek::new(-8.0, gst::SeekFlags::FLUSH | gst::SeekFlags::TRICKMODE | gst::SeekFlags::TRICKMODE_KEY_UNITS,
+        gst::SeekType::Set, ClockTime::from_nseconds(0), gst::SeekType::None, ClockTime::none());
+    let mut h = start_src_with_seek(&src, seek, -8.0);
+    for expected_frame in &[30, 20, 10, 0] {
+        let buffer = h.pull().unwrap();
+        assert_eq!(buffer.pts(), frame_pts(*expected_frame));
+        assert!(buffer.flags().contains(gst::BufferFlags::DISCONT));
+    }
+    pull_until_eos(&mut h);
+}
@@ -93,6 +93,50 @@ fn test_timestampcvt_start_at_zero() {
     println!("test_timestampcvt_start_at_zero: END");
 }
 
+#[test]
+fn test_timestampcvt_reference_timestamp_meta() {
+    println!("test_timestampcvt_reference_timestamp_meta: BEGIN");
+    init();
+    let filter = gst::ElementFactory::make("timestampcvt", None).unwrap();
+    filter.set_property_from_str("input-timestamp-mode", "reference-timestamp-meta");
+    let mut h = gst_check::Harness::with_element(&filter, Some("sink"), Some("src"));
+    h.set_src_caps_str("data");
+    h.set_sink_caps_str("data");
+    h.play();
+
+    let reference = gst::Caps::new_simple("timestamp/x-unix", &[]);
+    let first_reference_time = ClockTime::from_nseconds(PravegaTimestamp::now().to_unix_nanoseconds().unwrap());
+    let first_expected_pts = pravega_to_clocktime(PravegaTimestamp::from_unix_nanoseconds(first_reference_time.nseconds()));
+    println!("PTS is a running time starting at 0.");
+    push_with_reference_and_validate(&mut h, 0 * gst::MSECOND, &reference, first_reference_time,
+        first_expected_pts);
+    push_with_reference_and_validate(&mut h, 1000 * gst::MSECOND, &reference, first_reference_time + 1000 * gst::MSECOND,
+        first_expected_pts + 1000 * gst::MSECOND);
+    println!("Buffer without a reference timestamp meta uses the previous offset.");
+    push_and_validate(&mut h, 2000 * gst::MSECOND, Some(first_expected_pts + 2000 * gst::MSECOND));
+    println!("Reference timestamp drifts from PTS.");
+    push_with_reference_and_validate(&mut h, 3000 * gst::MSECOND, &reference, first_reference_time + 3010 * gst::MSECOND,
+        first_expected_pts + 3010 * gst::MSECOND);
+
+    println!("test_timestampcvt_reference_timestamp_meta: END");
+}
+
+fn push_with_reference_and_validate(harness: &mut gst_check::Harness, input_pts: ClockTime, reference: &gst::Caps,
+        reference_time: ClockTime, expected_output_pts: ClockTime) {
+    let buffer = {
+        let mut buffer = gst::Buffer::with_size(64).unwrap();
+        {
+            let buffer_mut = buffer.get_mut().unwrap();
+            buffer_mut.set_pts(input_pts);
+            gst::ReferenceTimestampMeta::add(buffer_mut, reference, reference_time, ClockTime::none());
+        }
+        buffer
+    };
+    let result = harness.push_and_pull(buffer).unwrap();
+    println!("push_with_reference_and_validate: input_pts={:?}, reference_time={:?}, output={:?}", input_pts, reference_time, result);
+    assert_eq!(result.pts(), expected_output_pts)
+}
+
 fn push_and_validate(harness: &mut gst_check::Harness, input_pts: ClockTime, expected_output_pts: Option<ClockTime>) {
     let buffer = {
         let mut buffer = gst::Buffer::with_size(64).unwrap();
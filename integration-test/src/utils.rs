@@ -18,7 +18,7 @@ use gstpravega::utils::clocktime_to_pravega;
 use pravega_client_config::ClientConfig;
 use pravega_client::client_factory::ClientFactory;
 use pravega_client_shared::{Scope, Stream, ScopedStream};
-use pravega_video::index::{IndexSearcher, SearchMethod, get_index_stream_name};
+use pravega_video::index::{IndexSearcher, SearchFilter, SearchMethod, get_index_stream_name};
 use pravega_video::timestamp::{PravegaTimestamp, TimeDelta, SECOND, NSECOND};
 use std::convert::TryFrom;
 use std::fmt;
@@ -507,7 +507,7 @@ pub fn truncate_stream(client_config: ClientConfig, scope_name: String, stream_n
     let index_reader = client_factory.create_byte_reader(index_scoped_stream.clone());
     let mut index_searcher = IndexSearcher::new(index_reader);
     let index_record = index_searcher.search_timestamp_and_return_index_offset(
-        truncate_before_timestamp, SearchMethod::Before).unwrap();
+        truncate_before_timestamp, SearchMethod::Before, SearchFilter::Any).unwrap();
         info!("Truncating prior to {:?}", index_record);
     runtime.block_on(index_writer.truncate_data_before(index_record.1 as i64)).unwrap();
     info!("Index truncated at offset {}", index_record.1);
@@ -10,8 +10,11 @@
 
 use clap::Clap;
 use pravega_client::client_factory::ClientFactory;
+use pravega_video::event_serde::ChecksumMismatchPolicy;
+use pravega_video::storage::{PravegaStorage, create_storage_from_uri};
 use pravega_video::utils;
 use std::path::Path;
+use std::sync::Arc;
 
 use tracing_subscriber::fmt::format::FmtSpan;
 #[allow(unused_imports)]
@@ -25,12 +28,19 @@ struct Opts {
     /// Pravega controller in format "tcp://127.0.0.1:9090"
     #[clap(long, env = "PRAVEGA_CONTROLLER_URI", default_value = "tcp://127.0.0.1:9090")]
     pravega_controller_uri: String,
+    /// Read streams from this storage instead of Pravega, such as file:///var/lib/video for streams written by
+    /// pravegasink with the storage-uri property. Scopes and streams cannot be listed.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_STORAGE_URI")]
+    storage_uri: Option<String>,
     /// The filename containing the Keycloak credentials JSON. If missing or empty, authentication will be disabled.
     #[clap(long, env = "KEYCLOAK_SERVICE_ACCOUNT_FILE", default_value = "", setting(clap::ArgSettings::AllowEmptyValues))]
     keycloak_service_account_file: String,
     /// Directory containing static files and templates.
     #[clap(long, env = "PRAVEGA_VIDEO_SERVER_RESOURCE_DIR", default_value = "./resources")]
     resource_dir: String,
+    /// The action to take when a media segment contains an event that fails checksum verification: error, drop, or warn.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_CHECKSUM_MISMATCH_POLICY", default_value = "error")]
+    checksum_mismatch_policy: ChecksumMismatchPolicy,
 }
 
 fn main() {
@@ -48,28 +58,39 @@ fn main() {
     let static_dir_name = format!("{}/static", opts.resource_dir);
     ensure_extra_files(opts.resource_dir.clone());
 
-    // Let Pravega ClientFactory create the Tokio runtime. It will also be used by Warp.
-
-    let config = utils::create_client_config(opts.pravega_controller_uri, Some(opts.keycloak_service_account_file)).expect("creating config");
-    let client_factory = ClientFactory::new(config);
-    let client_factory_db = client_factory.clone();
-    let runtime = client_factory.runtime();
-
-    runtime.block_on(async {
-        let db = models::new(client_factory_db);
-        let api = filters::get_all_filters(db);
-        let ui = ui::get_all_filters();
-        let static_dir = warp::path("static").and(warp::fs::dir(static_dir_name));
-        // let redirect = warp::path::end().map(|| {
-        //     warp::redirect::temporary(Uri::from_static("/static/hls-js.html"))
-        // });
-
-        let routes = api
-            .or(ui)
-            .or(static_dir)
-            .with(warp::trace::request());
-        warp::serve(routes).run(([0, 0, 0, 0], 3030)).await;
-    })
+    match opts.storage_uri {
+        Some(storage_uri) => {
+            info!("Reading streams from {}", storage_uri);
+            let storage = create_storage_from_uri(&storage_uri).expect("creating storage");
+            let db = models::new(Arc::from(storage), None, opts.checksum_mismatch_policy);
+            let runtime = tokio::runtime::Runtime::new().expect("creating runtime");
+            runtime.block_on(serve(db, static_dir_name));
+        },
+        None => {
+            // Let Pravega ClientFactory create the Tokio runtime. It will also be used by Warp.
+            let config = utils::create_client_config(opts.pravega_controller_uri, Some(opts.keycloak_service_account_file)).expect("creating config");
+            let client_factory = ClientFactory::new(config);
+            let storage = PravegaStorage::new(client_factory.clone());
+            let db = models::new(Arc::new(storage), Some(client_factory.clone()), opts.checksum_mismatch_policy);
+            let runtime = client_factory.runtime();
+            runtime.block_on(serve(db, static_dir_name));
+        },
+    }
+}
+
+async fn serve(db: models::Db, static_dir_name: String) {
+    let api = filters::get_all_filters(db);
+    let ui = ui::get_all_filters();
+    let static_dir = warp::path("static").and(warp::fs::dir(static_dir_name));
+    // let redirect = warp::path::end().map(|| {
+    //     warp::redirect::temporary(Uri::from_static("/static/hls-js.html"))
+    // });
+
+    let routes = api
+        .or(ui)
+        .or(static_dir)
+        .with(warp::trace::request());
+    warp::serve(routes).run(([0, 0, 0, 0], 3030)).await;
 }
 
 fn ensure_extra_files(resource_dir: String) {
@@ -84,7 +105,7 @@ fn ensure_extra_files(resource_dir: String) {
 
 mod filters {
     use super::handlers;
-    use super::models::{Db, GetMediaSegmentOptions, GetM3u8PlaylistOptions};
+    use super::models::{Db, GetMediaSegmentOptions, GetM3u8PlaylistOptions, ListMarkersOptions};
     use warp::Filter;
 
     pub fn get_all_filters(
@@ -92,6 +113,7 @@ mod filters {
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
         get_media_segment(db.clone())
             .or(get_m3u8_playlist(db.clone()))
+            .or(list_markers(db.clone()))
             .or(list_video_streams(db.clone()))
             .or(list_scopes(db.clone()))
     }
@@ -120,6 +142,18 @@ mod filters {
             .with(warp::compression::gzip())
     }
 
+    /// List the markers of a stream in order of timestamp
+    /// GET /scopes/my_scope/streams/my_stream/markers?begin=2021-04-19T00:00:00Z&end=2021-04-20T00:00:00Z
+    pub fn list_markers(
+        db: Db,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("scopes" / String / "streams" / String / "markers" )
+            .and(warp::get())
+            .and(warp::query::<ListMarkersOptions>())
+            .and(with_db(db))
+            .and_then(handlers::list_markers)
+    }
+
     /// List scopes this player has access to
     /// GET /scopes
     pub fn list_scopes(
@@ -187,9 +221,24 @@ mod ui {
 }
 
 mod handlers {
-    use std::convert::Infallible;
-    use super::models::{Db, GetMediaSegmentOptions, GetM3u8PlaylistOptions};
+    use chrono::{DateTime, Utc};
+    use pravega_video::timestamp::PravegaTimestamp;
+    use std::convert::{Infallible, TryFrom};
+    use super::models::{Db, GetMediaSegmentOptions, GetM3u8PlaylistOptions, ListMarkersOptions};
     use super::*;
+    use warp::Reply;
+    use warp::http::StatusCode;
+
+    /// Returns a 400 response if the begin or end time of a request cannot be converted to a timestamp.
+    fn check_query_times(begin: Option<DateTime<Utc>>, end: Option<DateTime<Utc>>) -> Option<warp::reply::Response> {
+        match PravegaTimestamp::try_from(begin).and_then(|_| PravegaTimestamp::try_from(end)) {
+            Ok(_) => None,
+            Err(e) => {
+                warn!("Invalid begin or end time: {}", e);
+                Some(warp::reply::with_status(format!("Invalid begin or end time: {}", e), StatusCode::BAD_REQUEST).into_response())
+            },
+        }
+    }
 
     pub async fn get_media_segment(
         scope_name: String,
@@ -206,8 +255,30 @@ mod handlers {
         opts: GetM3u8PlaylistOptions,
         db: Db,
     ) -> Result<impl warp::Reply, Infallible> {
+        if let Some(response) = check_query_times(opts.begin, opts.end) {
+            return Ok(response);
+        }
         let playlist = db.get_m3u8_playlist(scope_name, stream_name, opts).await.unwrap();
-        Ok(warp::reply::with_header(playlist, "content-type", "application/x-mpegURL"))
+        Ok(warp::reply::with_header(playlist, "content-type", "application/x-mpegURL").into_response())
+    }
+
+    pub async fn list_markers(
+        scope_name: String,
+        stream_name: String,
+        opts: ListMarkersOptions,
+        db: Db,
+    ) -> Result<impl warp::Reply, Infallible> {
+        if let Some(response) = check_query_times(opts.begin, opts.end) {
+            return Ok(response);
+        }
+        match db.list_markers(scope_name, stream_name, opts).await {
+            Ok(Some(markers)) => Ok(warp::reply::json(&markers).into_response()),
+            Ok(None) => Ok(warp::reply::with_status("Stream not found".to_owned(), StatusCode::NOT_FOUND).into_response()),
+            Err(e) => {
+                warn!("Unable to list markers: {}", e);
+                Ok(warp::reply::with_status(format!("Unable to list markers: {}", e), StatusCode::INTERNAL_SERVER_ERROR).into_response())
+            },
+        }
     }
 
     pub async fn list_scopes(
@@ -234,23 +305,39 @@ mod models {
     use futures::{StreamExt, future};
     use hyper::body::{Body, Bytes};
     use pravega_client::client_factory::ClientFactory;
-    use pravega_client_shared::{Scope, ScopedStream, Stream};
+    use pravega_client_shared::Scope;
     use pravega_controller_client::paginator::{list_streams_for_tag, list_scopes};
-    use pravega_video::{event_serde::{EventReader}, index::IndexSearcher};
-    use pravega_video::index::{IndexRecord, IndexRecordReader, SearchMethod, get_index_stream_name};
+    use pravega_video::caps::{CapsHistory, caps_to_content_type, get_caps_stream_name};
+    use pravega_video::{event_serde::{ChecksumStatus, EventReader, FragmentReassembler, FragmentStatus}, index::IndexSearcher};
+    use pravega_video::index::{IndexRecord, IndexRecordReader, SearchFilter, SearchMethod, get_index_stream_name};
+    use pravega_video::marker::{MarkerRecord, get_marker_stream_name, list_markers};
+    use pravega_video::storage::StorageBackend;
     use pravega_video::timestamp::PravegaTimestamp;
     use serde_derive::{Deserialize, Serialize};
-    use std::convert::Infallible;
-    use std::io::{ErrorKind, Read, Seek, SeekFrom};
+    use std::convert::{Infallible, TryFrom};
+    use std::io::{Error, ErrorKind, Read, Seek, SeekFrom};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU64, Ordering};
     use super::*;
 
     #[derive(Clone)]
     pub struct Db {
-        pub client_factory: ClientFactory,
+        pub storage: Arc<dyn StorageBackend>,
+        /// None if the storage is not Pravega. Listing scopes and streams requires Pravega.
+        pub client_factory: Option<ClientFactory>,
+        pub checksum_mismatch_policy: ChecksumMismatchPolicy,
+        /// The number of events that failed checksum verification since the server started.
+        pub checksum_mismatches: Arc<AtomicU64>,
     }
 
-    pub fn new(client_factory: ClientFactory) -> Db {
-        Db { client_factory }
+    pub fn new(storage: Arc<dyn StorageBackend>, client_factory: Option<ClientFactory>,
+               checksum_mismatch_policy: ChecksumMismatchPolicy) -> Db {
+        Db {
+            storage,
+            client_factory,
+            checksum_mismatch_policy,
+            checksum_mismatches: Arc::new(AtomicU64::new(0)),
+        }
     }
 
     // The query parameters for get_media_segment.
@@ -269,6 +356,28 @@ mod models {
         pub end: Option<DateTime<Utc>>,
     }
 
+    // The query parameters for list_markers.
+    #[derive(Debug, Deserialize)]
+    pub struct ListMarkersOptions {
+        pub begin: Option<DateTime<Utc>>,
+        pub end: Option<DateTime<Utc>>,
+    }
+
+    #[derive(Debug, Deserialize, Serialize, Clone)]
+    pub struct ListMarkersResult {
+        pub markers: Vec<ListMarkersRecord>,
+    }
+
+    #[derive(Debug, Deserialize, Serialize, Clone)]
+    pub struct ListMarkersRecord {
+        /// Nanoseconds since 1970-01-01 00:00:00 TAI
+        pub timestamp: Option<u64>,
+        /// The timestamp in RFC 3339 format
+        pub utc: Option<String>,
+        pub label: String,
+        pub payload: String,
+    }
+
     #[derive(Debug, Deserialize, Serialize, Clone)]
     pub struct ListScopesResult {
         pub scopes: Vec<ListScopesRecord>,
@@ -309,26 +418,50 @@ mod models {
             // Use spawn_blocking to allow Pravega non-async methods to block this thread.
             // See https://stackoverflow.com/a/65452213/5890553.
 
-            let chunks = tokio::task::spawn_blocking(move || {
+            let (chunks, content_type) = tokio::task::spawn_blocking(move || {
                 let span = span!(Level::INFO, "get_media_segment: SPAWNED THREAD");
                 span.in_scope(|| {
                     info!("BEGIN");
-                    let client_factory = self.client_factory;
-                    let scoped_stream = ScopedStream {
-                        scope: Scope::from(scope_name),
-                        stream: Stream::from(stream_name),
+                    let storage = self.storage;
+                    let checksum_mismatch_policy = self.checksum_mismatch_policy;
+                    let checksum_mismatches = self.checksum_mismatches;
+                    // Determine the content type from the caps recorded for the beginning of the media segment.
+                    let caps_history = storage.create_reader(&scope_name, &get_caps_stream_name(&stream_name))
+                        .map(CapsHistory::new)
+                        .and_then(|mut caps_history| caps_history.refresh().map(|_| caps_history));
+                    let content_type = match caps_history {
+                        Ok(caps_history) => caps_history.caps_at_offset(opts.begin).and_then(|record| caps_to_content_type(&record.caps)),
+                        Err(e) => {
+                            warn!("Unable to read caps stream: {}", e);
+                            None
+                        },
                     };
-                    let mut reader = client_factory.create_byte_reader(scoped_stream);
-                    info!("Opened Pravega reader");
+                    // "video/mp4" appears to work for MP4 and MPEG TS.
+                    let content_type = content_type.unwrap_or("video/mp4");
+                    info!("content_type={}", content_type);
+
+                    let mut reader = storage.create_reader(&scope_name, &stream_name)?;
+                    info!("Opened reader");
 
-                    reader.seek(SeekFrom::Start(opts.begin)).unwrap();
+                    reader.seek(SeekFrom::Start(opts.begin))?;
                     let limit = opts.end - opts.begin;
                     let mut reader = reader.take(limit);
 
                     let mut chunks: Vec<Result<Bytes, std::io::Error>> = Vec::new();
+                    // Joins buffers that were written as a chain of fragments.
+                    let mut fragment_reassembler = FragmentReassembler::new();
+                    let discard_fragments = |fragment_reassembler: &mut FragmentReassembler, offset: u64, reason: &str| {
+                        if fragment_reassembler.is_incomplete() {
+                            let fragment_count = fragment_reassembler.fragment_count();
+                            let discarded_bytes = fragment_reassembler.reset();
+                            warn!("Discarded incomplete chain of {} fragments ({} bytes) before offset {}: {}",
+                                fragment_count, discarded_bytes, offset, reason);
+                        }
+                    };
 
                     loop {
                         let mut event_reader = EventReader::new();
+                        let offset = opts.end - reader.limit();
                         let required_buffer_length =
                             match event_reader.read_required_buffer_length(&mut reader) {
                                 Ok(n) => n,
@@ -348,12 +481,51 @@ mod models {
                             Err(e) => return Err(e),
                         };
                         trace!("event={:?}", event);
-                        chunks.push(Ok(Bytes::copy_from_slice(&event.payload)));
+                        if let ChecksumStatus::Invalid { expected, actual } = event_reader.checksum_status() {
+                            let count = checksum_mismatches.fetch_add(1, Ordering::Relaxed) + 1;
+                            let message = format!("Checksum mismatch in event at offset {}: expected {:08x}, actual {:08x}; checksum_mismatches={}",
+                                offset, expected, actual, count);
+                            match checksum_mismatch_policy {
+                                ChecksumMismatchPolicy::Error => {
+                                    error!("{}", message);
+                                    return Err(Error::new(ErrorKind::InvalidData, message));
+                                },
+                                ChecksumMismatchPolicy::Drop => {
+                                    warn!("{}; dropping event", message);
+                                    discard_fragments(&mut fragment_reassembler, offset, "checksum mismatch");
+                                    continue;
+                                },
+                                ChecksumMismatchPolicy::Warn => {
+                                    warn!("{}", message);
+                                },
+                            }
+                        }
+                        if !event.header.continuation {
+                            discard_fragments(&mut fragment_reassembler, offset, "missing last fragment");
+                        }
+                        let status = match fragment_reassembler.push(&event) {
+                            Ok(status) => status,
+                            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                                discard_fragments(&mut fragment_reassembler, offset, &e.to_string());
+                                continue;
+                            },
+                            Err(e) => return Err(e),
+                        };
+                        match status {
+                            FragmentStatus::NotFragmented => chunks.push(Ok(Bytes::copy_from_slice(&event.payload))),
+                            FragmentStatus::Incomplete => {},
+                            FragmentStatus::Complete => {
+                                let (_, payload) = fragment_reassembler.take();
+                                chunks.push(Ok(Bytes::from(payload)));
+                            },
+                            FragmentStatus::Orphan => warn!("Dropping fragment without first fragment at offset {}", offset),
+                        }
                     }
+                    discard_fragments(&mut fragment_reassembler, opts.end, "end of media segment");
                     info!("Created {} chunks", chunks.len());
                     assert!(reader.limit() == 0);
                     info!("END");
-                    Ok(chunks)
+                    Ok((chunks, content_type))
                 })
             })
             .await
@@ -363,9 +535,6 @@ mod models {
             trace!("get_media_segment: spawn_blocking done");
             let stream = futures_util::stream::iter(chunks);
             let body = Body::wrap_stream(stream);
-            // TODO: Get content type from Pravega stream tag. For now "video/mp4" appears to work for MP4 and MPEG TS.
-            // let content_type = "video/MP2T";
-            let content_type = "video/mp4";
             Ok(warp::reply::with_header(warp::reply::Response::new(body), "content-type", content_type))
         }
 
@@ -379,8 +548,8 @@ mod models {
             info!("get_m3u8_playlist: BEGIN: scope_name={}, stream_name={}, begin={:?}, end={:?}", scope_name, stream_name, opts.begin, opts.end);
 
             let index_stream_name = get_index_stream_name(&stream_name);
-            let begin_timestamp = PravegaTimestamp::from(opts.begin).or(PravegaTimestamp::MIN);
-            let end_timestamp = PravegaTimestamp::from(opts.end).or(PravegaTimestamp::MAX);
+            let begin_timestamp = PravegaTimestamp::try_from(opts.begin)?.or(PravegaTimestamp::MIN);
+            let end_timestamp = PravegaTimestamp::try_from(opts.end)?.or(PravegaTimestamp::MAX);
             info!("get_m3u8_playlist: begin_timestamp={}, end_timestamp={}", begin_timestamp, end_timestamp);
             assert!(begin_timestamp <= end_timestamp);
 
@@ -391,19 +560,14 @@ mod models {
                 let span = span!(Level::INFO, "get_m3u8_playlist: SPAWNED THREAD");
                 span.in_scope(|| {
                     info!("BEGIN");
-                    let client_factory = self.client_factory;
-                    let scoped_stream = ScopedStream {
-                        scope: Scope::from(scope_name),
-                        stream: Stream::from(index_stream_name),
-                    };
-                    let index_reader = client_factory.create_byte_reader(scoped_stream);
-                    info!("Opened Pravega reader");
+                    let index_reader = self.storage.create_reader(&scope_name, &index_stream_name)?;
+                    info!("Opened reader");
 
                     let mut index_searcher = IndexSearcher::new(index_reader);
                     let begin_index_record = index_searcher.search_timestamp_and_return_index_offset(
-                        begin_timestamp, SearchMethod::After)?;
+                        begin_timestamp, SearchMethod::After, SearchFilter::Any)?;
                     let end_index_record = index_searcher.search_timestamp_and_return_index_offset(
-                        end_timestamp, SearchMethod::After)?;
+                        end_timestamp, SearchMethod::After, SearchFilter::Any)?;
                     // Determine whether we can possibly get more data in the future.
                     // If the caller specified an end time and we already have an index record beyond this, then
                     // future appends will not affect our result.
@@ -536,12 +700,56 @@ mod models {
             Ok(playlist)
         }
 
+        /// Returns None if the data stream does not exist.
+        /// A data stream without a marker stream, such as one written by an older version of pravegasink, has no markers.
+        pub async fn list_markers(
+            self,
+            scope_name: String,
+            stream_name: String,
+            opts: ListMarkersOptions,
+        ) -> anyhow::Result<Option<ListMarkersResult>> {
+            info!("list_markers: scope_name={}, stream_name={}, begin={:?}, end={:?}", scope_name, stream_name, opts.begin, opts.end);
+            let begin_timestamp = PravegaTimestamp::try_from(opts.begin)?;
+            let end_timestamp = PravegaTimestamp::try_from(opts.end)?;
+
+            // Use spawn_blocking to allow Pravega non-async methods to block this thread.
+            let markers = tokio::task::spawn_blocking(move || -> Result<Option<Vec<MarkerRecord>>, std::io::Error> {
+                if !self.storage.stream_exists(&scope_name, &stream_name)? {
+                    return Ok(None);
+                }
+                let marker_stream_name = get_marker_stream_name(&stream_name);
+                if !self.storage.stream_exists(&scope_name, &marker_stream_name)? {
+                    return Ok(Some(Vec::new()));
+                }
+                let marker_reader = self.storage.create_reader(&scope_name, &marker_stream_name)?;
+                Ok(Some(list_markers(marker_reader, begin_timestamp, end_timestamp)?))
+            })
+            .await??;
+
+            Ok(markers.map(|markers| {
+                let markers: Vec<_> = markers.into_iter().map(|marker| ListMarkersRecord {
+                    timestamp: marker.timestamp.nanoseconds(),
+                    utc: marker.timestamp.to_iso_8601(),
+                    label: marker.label,
+                    payload: marker.payload,
+                }).collect();
+                ListMarkersResult { markers }
+            }))
+        }
+
         pub async fn list_scopes(
             self
         ) -> anyhow::Result<ListScopesResult> {
 
             info!("list_scopes");
-            let controller_client = self.client_factory.controller_client();
+            let client_factory = match self.client_factory {
+                Some(client_factory) => client_factory,
+                None => {
+                    warn!("list_scopes: Listing scopes requires Pravega");
+                    return Ok(ListScopesResult { scopes: Vec::new() });
+                },
+            };
+            let controller_client = client_factory.controller_client();
             let mut scopes = Vec::new();
             let mut had_error = false;
 
@@ -571,7 +779,14 @@ mod models {
         ) -> anyhow::Result<ListStreamsResult> {
 
             info!("list_video_streams: scope_name={}", scope_name.clone());
-            let controller_client = self.client_factory.controller_client();
+            let client_factory = match self.client_factory {
+                Some(client_factory) => client_factory,
+                None => {
+                    warn!("list_video_streams: Listing streams requires Pravega");
+                    return Ok(ListStreamsResult { streams: Vec::new() });
+                },
+            };
+            let controller_client = client_factory.controller_client();
             let scope = Scope { name : scope_name.clone() };
             let mut streams = Vec::new();
             let mut had_error = false;
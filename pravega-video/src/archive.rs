@@ -0,0 +1,332 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Module for archiving the oldest data of a media stream before it is truncated.
+
+use crate::caps::get_caps_stream_name;
+use crate::index::{IndexRecord, IndexRecordReader, get_index_stream_name};
+use crate::storage::{ByteStreamReader, StorageBackend, parse_file_storage_uri};
+use crate::timestamp::PravegaTimestamp;
+use crate::utils::CurrentHead;
+#[cfg(feature = "s3")]
+use crate::s3::S3ArchiveTarget;
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use tracing::{debug, info};
+
+/// The data, index, and caps readers of a media stream.
+pub struct MediaStreamReaders {
+    pub data: Box<dyn ByteStreamReader>,
+    pub index: Box<dyn ByteStreamReader>,
+    pub caps: Box<dyn ByteStreamReader>,
+}
+
+impl MediaStreamReaders {
+    pub fn new(storage: &dyn StorageBackend, scope_name: &str, stream_name: &str) -> Result<MediaStreamReaders, Error> {
+        Ok(MediaStreamReaders {
+            data: storage.create_reader(scope_name, stream_name)?,
+            index: storage.create_reader(scope_name, &get_index_stream_name(stream_name))?,
+            caps: storage.create_reader(scope_name, &get_caps_stream_name(stream_name))?,
+        })
+    }
+}
+
+/// A place to store archived objects, such as a local directory or an S3 bucket.
+pub trait ArchiveTarget: Send + Sync {
+    /// Stores an object, replacing any existing object with the same key.
+    /// Returns only after the object has been confirmed to be stored with the expected size.
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), Error>;
+
+    /// Returns true if an object with this key exists.
+    fn exists(&self, key: &str) -> Result<bool, Error>;
+}
+
+/// Returns the archive target for a URI.
+///   - file:///var/archive stores objects in a local directory. See [DirectoryArchiveTarget].
+///   - s3://bucket/prefix stores objects in an S3-compatible bucket. This requires the s3 feature.
+///     See `S3ArchiveTarget` in s3.rs.
+pub fn create_archive_target_from_uri(uri: &str) -> Result<Box<dyn ArchiveTarget>, Error> {
+    if let Some(root) = parse_file_storage_uri(uri) {
+        Ok(Box::new(DirectoryArchiveTarget::new(root)))
+    } else if let Some(path) = uri.strip_prefix("s3://") {
+        create_s3_archive_target(uri, path)
+    } else {
+        Err(Error::new(ErrorKind::InvalidInput, format!("Unsupported archive URI {}", uri)))
+    }
+}
+
+#[cfg(feature = "s3")]
+fn create_s3_archive_target(uri: &str, path: &str) -> Result<Box<dyn ArchiveTarget>, Error> {
+    let (bucket, prefix) = match path.find('/') {
+        Some(i) => (&path[..i], path[i + 1..].trim_end_matches('/')),
+        None => (path, ""),
+    };
+    if bucket.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("Archive URI {} does not specify a bucket", uri)));
+    }
+    Ok(Box::new(S3ArchiveTarget::from_env(bucket, prefix)?))
+}
+
+#[cfg(not(feature = "s3"))]
+fn create_s3_archive_target(uri: &str, _path: &str) -> Result<Box<dyn ArchiveTarget>, Error> {
+    Err(Error::new(ErrorKind::InvalidInput, format!("Archive URI {} requires pravega-video to be built with the s3 feature", uri)))
+}
+
+/// Stores archived objects as files in a local directory.
+/// Each object is written to a temporary file, synced, and then renamed.
+pub struct DirectoryArchiveTarget {
+    root: PathBuf,
+}
+
+impl DirectoryArchiveTarget {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+        }
+    }
+}
+
+impl ArchiveTarget for DirectoryArchiveTarget {
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), Error> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp_path = path.with_extension("tmp");
+        {
+            let mut file = File::create(&temp_path)?;
+            file.write_all(data)?;
+            file.sync_all()?;
+        }
+        fs::rename(&temp_path, &path)?;
+        let size = fs::metadata(&path)?.len();
+        if size != data.len() as u64 {
+            return Err(Error::new(ErrorKind::Other, format!("Archived file {} has size {}, expected {}",
+                path.display(), size, data.len())));
+        }
+        Ok(())
+    }
+
+    fn exists(&self, key: &str) -> Result<bool, Error> {
+        Ok(self.root.join(key).exists())
+    }
+}
+
+/// The result of archiving a range of a media stream.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ArchiveStats {
+    pub data_bytes: u64,
+    pub index_bytes: u64,
+    pub caps_bytes: u64,
+    pub objects: u64,
+    pub first_timestamp: PravegaTimestamp,
+    pub last_timestamp: PravegaTimestamp,
+}
+
+/**
+   Exports the oldest data of a media stream to an archive target before it is truncated.
+
+   The archive uses the same layout as [FileStorage](crate::storage::FileStorage), with the original offsets.
+   For each of the data, index, and caps streams, the archive contains the directory scope/stream with:
+
+   - Chunk files named with the 20-digit offset of their first byte, such as 00000000000000000000.chunk.
+   - A file named "head" that contains the offset of the first archived byte.
+
+   Additionally, a JSON manifest named scope/stream/OFFSET.manifest.json describes each archived range of the data stream.
+
+   Because index records refer to the original offsets, a local archive directory (or a copy of an S3 archive)
+   can be played with pravegasrc by setting its storage-uri property to file:///path/to/archive.
+   The archive is never sealed because more data may be archived later, so pravegasrc must also have end-mode=latest
+   (or an end timestamp) to stop at the end of the archived data instead of waiting for more data.
+
+   The caps stream is small and is never truncated, so it is archived in its entirety each time.
+   Each range is archived in chunks that begin at the current head of the stream, so if a stream is archived
+   but then fails to be truncated, archiving it again will replace the same chunks.
+   If data is truncated without being archived, the archive will have a gap, and reading the data stream
+   at the gap will fail with an error.
+*/
+pub struct Archiver {
+    target: Box<dyn ArchiveTarget>,
+    chunk_size: u64,
+}
+
+impl Archiver {
+    pub const DEFAULT_CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+
+    pub fn new(target: Box<dyn ArchiveTarget>) -> Self {
+        Self {
+            target,
+            chunk_size: Archiver::DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Sets the maximum size of each chunk object.
+    pub fn with_chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Archives the data stream from its head to data_end_offset, the index stream from its head to index_end_offset,
+    /// and the entire caps stream.
+    /// The streams may be truncated at these offsets when this returns successfully.
+    pub fn archive(&self, readers: &mut MediaStreamReaders, scope_name: &str, stream_name: &str,
+                   data_end_offset: u64, index_end_offset: u64) -> Result<ArchiveStats, Error> {
+        let mut stats = ArchiveStats::default();
+        let index_stream_name = get_index_stream_name(stream_name);
+        let caps_stream_name = get_caps_stream_name(stream_name);
+        let data_head = readers.data.current_head()?;
+        if data_end_offset <= data_head {
+            return Ok(stats);
+        }
+        info!("Archiver::archive: Archiving {}/{} from offset {} to {}", scope_name, stream_name, data_head, data_end_offset);
+
+        // The index and caps are archived after the data so that they only refer to archived data.
+        let (_, data_bytes) = self.archive_stream(&mut readers.data, scope_name, stream_name, Some(data_end_offset), &mut stats)?;
+        let (index_head, index_bytes) = self.archive_stream(&mut readers.index, scope_name, &index_stream_name, Some(index_end_offset), &mut stats)?;
+        let (_, caps_bytes) = self.archive_stream(&mut readers.caps, scope_name, &caps_stream_name, None, &mut stats)?;
+        stats.data_bytes = data_bytes;
+        stats.index_bytes = index_bytes;
+        stats.caps_bytes = caps_bytes;
+
+        // Get the range of timestamps from the archived index records.
+        let record_size = IndexRecord::RECORD_SIZE as u64;
+        if index_bytes >= record_size {
+            let mut index_record_reader = IndexRecordReader::new();
+            readers.index.seek(SeekFrom::Start(index_head))?;
+            stats.first_timestamp = index_record_reader.read(&mut readers.index)?.timestamp;
+            readers.index.seek(SeekFrom::Start(index_head + (index_bytes / record_size - 1) * record_size))?;
+            stats.last_timestamp = index_record_reader.read(&mut readers.index)?.timestamp;
+        }
+
+        let manifest = serde_json::json!({
+            "format": "pravega-video-archive",
+            "version": 1,
+            "scope": scope_name,
+            "stream": stream_name,
+            "index_stream": index_stream_name,
+            "caps_stream": caps_stream_name,
+            "data_begin_offset": data_head,
+            "data_end_offset": data_end_offset,
+            "index_begin_offset": index_head,
+            "index_end_offset": index_head + index_bytes,
+            "first_timestamp": stats.first_timestamp.nanoseconds(),
+            "last_timestamp": stats.last_timestamp.nanoseconds(),
+            "first_utc": stats.first_timestamp.to_iso_8601(),
+            "last_utc": stats.last_timestamp.to_iso_8601(),
+            "archived_utc": PravegaTimestamp::now().to_iso_8601(),
+        });
+        let manifest_key = format!("{}/{}/{:020}.manifest.json", scope_name, stream_name, data_head);
+        self.target.put(&manifest_key, manifest.to_string().as_bytes())?;
+        stats.objects += 1;
+        info!("Archiver::archive: Archived {:?}", stats);
+        Ok(stats)
+    }
+
+    /// Archives a stream from its head to end_offset, or to its tail if end_offset is None.
+    /// Returns the head and the number of bytes archived.
+    fn archive_stream(&self, reader: &mut Box<dyn ByteStreamReader>, scope_name: &str, stream_name: &str,
+                      end_offset: Option<u64>, stats: &mut ArchiveStats) -> Result<(u64, u64), Error> {
+        let head = reader.current_head()?;
+        let tail = reader.seek(SeekFrom::End(0))?;
+        let end = end_offset.map_or(tail, |end_offset| u64::min(end_offset, tail));
+        if end <= head {
+            return Ok((head, 0));
+        }
+        reader.seek(SeekFrom::Start(head))?;
+        let mut chunk = Vec::new();
+        let mut offset = head;
+        while offset < end {
+            let length = u64::min(self.chunk_size, end - offset);
+            chunk.resize(length as usize, 0);
+            reader.read_exact(&mut chunk)?;
+            let key = format!("{}/{}/{:020}.chunk", scope_name, stream_name, offset);
+            self.target.put(&key, &chunk)?;
+            debug!("Archiver::archive_stream: Archived {} ({} bytes)", key, length);
+            stats.objects += 1;
+            offset += length;
+        }
+        // The head is written once, after the first chunk, so that it refers to the oldest archived data.
+        let head_key = format!("{}/{}/head", scope_name, stream_name);
+        if !self.target.exists(&head_key)? {
+            self.target.put(&head_key, head.to_string().as_bytes())?;
+            stats.objects += 1;
+        }
+        Ok((head, end - head))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_serde::{EventReader, EventWithHeader, EventWriter};
+    use crate::index::IndexRecordWriter;
+    use crate::storage::{FileStorage, MemoryStorage};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// Archive a stream in two steps, truncating after each, and then read the archive with FileStorage.
+    #[test]
+    fn test_archive_to_directory() {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let dir = std::env::temp_dir().join(format!("pravega-video-test-archive-{}", nanos));
+        let (scope, stream) = ("scope1", "stream1");
+        let index_stream = get_index_stream_name(stream);
+        let storage = MemoryStorage::new();
+        storage.create_scope(scope).unwrap();
+        for stream_name in &[stream.to_owned(), index_stream.clone(), get_caps_stream_name(stream)] {
+            storage.create_stream(scope, stream_name, None).unwrap();
+        }
+
+        // Write 10 indexed events.
+        let mut data_writer = storage.create_writer(scope, stream).unwrap();
+        let mut index_writer = storage.create_writer(scope, &index_stream).unwrap();
+        let mut index_records = Vec::new();
+        for i in 0..10u64 {
+            let timestamp = PravegaTimestamp::from_nanoseconds(Some(1_000 * (i + 1)));
+            let index_record = IndexRecord::new(timestamp, data_writer.current_offset(), true, i == 0);
+            IndexRecordWriter::new().write(&index_record, &mut index_writer).unwrap();
+            index_records.push(index_record);
+            let payload = vec![i as u8; 100];
+            EventWriter::new().write(&EventWithHeader::new(&payload, timestamp, true, true, i == 0), &mut data_writer).unwrap();
+        }
+
+        // Archive and truncate before events 4 and 7. Use a small chunk size to create multiple chunks.
+        let archiver = Archiver::new(Box::new(DirectoryArchiveTarget::new(&dir))).with_chunk_size(250);
+        let mut readers = MediaStreamReaders::new(&storage, scope, stream).unwrap();
+        for &i in &[4, 7] {
+            let index_end_offset = i as u64 * IndexRecord::RECORD_SIZE as u64;
+            let stats = archiver.archive(&mut readers, scope, stream, index_records[i].offset, index_end_offset).unwrap();
+            assert_eq!(stats.last_timestamp, index_records[i - 1].timestamp);
+            index_writer.truncate_data_before(index_end_offset).unwrap();
+            data_writer.truncate_data_before(index_records[i].offset).unwrap();
+        }
+
+        // The archive contains events 0 to 6 with the original offsets.
+        let archive = FileStorage::new(&dir);
+        let mut archive_index_reader = archive.create_reader(scope, &index_stream).unwrap();
+        assert_eq!(archive_index_reader.current_head().unwrap(), 0);
+        assert_eq!(archive_index_reader.seek(SeekFrom::End(0)).unwrap(), 7 * IndexRecord::RECORD_SIZE as u64);
+        archive_index_reader.seek(SeekFrom::Start(0)).unwrap();
+        let mut archive_data_reader = archive.create_reader(scope, stream).unwrap();
+        assert_eq!(archive_data_reader.seek(SeekFrom::End(0)).unwrap(), index_records[7].offset);
+        let mut event_reader = EventReader::new();
+        let mut buffer = vec![0; 1024];
+        for expected_record in &index_records[..7] {
+            let index_record = IndexRecordReader::new().read(&mut archive_index_reader).unwrap();
+            assert_eq!(&index_record, expected_record);
+            archive_data_reader.seek(SeekFrom::Start(index_record.offset)).unwrap();
+            event_reader.read_required_buffer_length(&mut archive_data_reader).unwrap();
+            let event = event_reader.read_event(&mut archive_data_reader, &mut buffer).unwrap();
+            assert_eq!(event.header.timestamp, index_record.timestamp);
+        }
+        assert!(dir.join(scope).join(stream).join(format!("{:020}.manifest.json", index_records[4].offset)).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
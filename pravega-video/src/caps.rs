@@ -0,0 +1,204 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Module for writing and reading the caps of a media stream in a Pravega stream.
+
+use crate::record_history::{HistoryRecord, RecordHistory};
+use crate::timestamp::PravegaTimestamp;
+use crate::utils::CurrentHead;
+use std::convert::TryInto;
+use std::io::{Error, ErrorKind, Read, Write, Seek};
+
+pub fn get_caps_stream_name(stream_name: &str) -> String {
+    format!("{}-caps", stream_name)
+}
+
+/// Returns the HTTP content type for a media stream with the given caps, if known.
+/// Only the media type (the part before the first comma) is considered.
+pub fn caps_to_content_type(caps: &str) -> Option<&'static str> {
+    let media_type = caps.split(',').next().unwrap_or_default().trim();
+    match media_type {
+        "video/mpegts" => Some("video/MP2T"),
+        "video/quicktime" => Some("video/mp4"),
+        "video/webm" => Some("video/webm"),
+        "video/x-matroska" => Some("video/x-matroska"),
+        "image/jpeg" => Some("image/jpeg"),
+        _ => None,
+    }
+}
+
+/// Records the caps (the GStreamer capabilities, which describe the format of the buffers)
+/// that apply to a media stream beginning at a byte offset.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CapsRecord {
+    /// The timestamp of the first buffer written with these caps.
+    pub timestamp: PravegaTimestamp,
+    /// The byte offset in the data stream of the first event written with these caps.
+    pub offset: u64,
+    /// The caps, serialized as a string.
+    pub caps: String,
+}
+
+/**
+   A caps stream contains a caps record for each time that the caps of the associated data stream changed.
+   The caps of an event in the data stream are given by the caps record with the greatest offset that is
+   less than or equal to the offset of the event.
+
+   Caps records have the following variable-length encoding:
+
+   version (8-bit unsigned int):
+      Currently 1.
+   reserved (24 bits):
+      Must be written as 0.
+   caps_length (32-bit BE unsigned int):
+      The number of bytes in caps.
+   timestamp (64-bit BE unsigned int):
+      See [crate::event_serde::EventWriter] for a description of timestamp.
+   offset (64-bit BE unsigned int):
+      The byte offset in the data stream of the first event with these caps.
+   caps (caps_length bytes):
+      The caps serialized as a string, encoded as UTF-8.
+
+   Records are written in order of increasing offset.
+   Unlike the index, the caps stream is not truncated when the data stream is truncated,
+   as its size is usually insignificant.
+*/
+impl CapsRecord {
+    pub const VERSION: u8 = 1;
+    const HEADER_SIZE: usize = 24;
+    // Caps longer than this are assumed to be the result of corruption.
+    const MAX_CAPS_LENGTH: usize = 1024 * 1024;
+
+    pub fn new(timestamp: PravegaTimestamp, offset: u64, caps: String) -> Self {
+        Self {
+            timestamp,
+            offset,
+            caps,
+        }
+    }
+}
+
+pub struct CapsRecordWriter {
+}
+
+impl CapsRecordWriter {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn write<W>(&mut self, record: &CapsRecord, writer: &mut W) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        let caps_bytes = record.caps.as_bytes();
+        if caps_bytes.len() > CapsRecord::MAX_CAPS_LENGTH {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Caps of {} bytes exceeds {} bytes",
+                caps_bytes.len(), CapsRecord::MAX_CAPS_LENGTH)));
+        }
+        let mut bytes_to_write = vec![0; CapsRecord::HEADER_SIZE + caps_bytes.len()];
+        bytes_to_write[0] = CapsRecord::VERSION;
+        bytes_to_write[4..8].copy_from_slice(&(caps_bytes.len() as u32).to_be_bytes()[..]);
+        bytes_to_write[8..16].copy_from_slice(&record.timestamp.nanoseconds().unwrap_or_default().to_be_bytes()[..]);
+        bytes_to_write[16..24].copy_from_slice(&record.offset.to_be_bytes()[..]);
+        bytes_to_write[CapsRecord::HEADER_SIZE..].copy_from_slice(caps_bytes);
+        // Caps records must be written atomically so that a reader never reads a partial record.
+        writer.write_all(&bytes_to_write)?;
+        Ok(())
+    }
+}
+
+pub struct CapsRecordReader {
+}
+
+impl CapsRecordReader {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Reads a caps record. Returns the record and its length in bytes.
+    pub fn read<R>(&mut self, rdr: &mut R) -> Result<(CapsRecord, u64), Error>
+    where
+        R: Read,
+    {
+        let mut header = [0; CapsRecord::HEADER_SIZE];
+        rdr.read_exact(&mut header)?;
+        let version = header[0];
+        if version != CapsRecord::VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Unsupported caps record version {}", version)));
+        }
+        let caps_length = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+        if caps_length > CapsRecord::MAX_CAPS_LENGTH {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Invalid caps length {}", caps_length)));
+        }
+        let timestamp = u64::from_be_bytes(header[8..16].try_into().unwrap());
+        let offset = u64::from_be_bytes(header[16..24].try_into().unwrap());
+        let mut caps_bytes = vec![0; caps_length];
+        rdr.read_exact(&mut caps_bytes)?;
+        let caps = String::from_utf8(caps_bytes).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let record = CapsRecord {
+            timestamp: PravegaTimestamp::from_nanoseconds(if timestamp == 0 { None } else { Some(timestamp) }),
+            offset,
+            caps,
+        };
+        Ok((record, (CapsRecord::HEADER_SIZE + caps_length) as u64))
+    }
+}
+
+impl HistoryRecord for CapsRecord {
+    fn read_record<R: Read>(rdr: &mut R) -> Result<(Self, u64), Error> {
+        CapsRecordReader::new().read(rdr)
+    }
+}
+
+/// Maintains the list of caps records in a caps stream.
+/// The caps stream can be stored in any object that implements Read and Seek, including a Pravega stream.
+pub type CapsHistory<R> = RecordHistory<R, CapsRecord>;
+
+impl<R: Read + Seek + CurrentHead> RecordHistory<R, CapsRecord> {
+    /// Returns the caps record that applies to the event at the specified offset in the data stream.
+    /// Returns None if the event precedes all caps records.
+    pub fn caps_at_offset(&self, offset: u64) -> Option<&CapsRecord> {
+        self.records().iter().rev().find(|record| record.offset <= offset)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::caps::{CapsHistory, CapsRecord, CapsRecordWriter, caps_to_content_type};
+    use crate::timestamp::PravegaTimestamp;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_caps_at_offset() {
+        let records = vec![
+            CapsRecord::new(PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000)), 0,
+                "video/quicktime, variant=(string)iso".to_owned()),
+            CapsRecord::new(PravegaTimestamp::from_nanoseconds(Some(1_600_000_010_000_000_000)), 5000,
+                "video/mpegts, systemstream=(boolean)true, packetsize=(int)188".to_owned()),
+        ];
+        let mut cursor = Cursor::new(Vec::new());
+        let mut caps_record_writer = CapsRecordWriter::new();
+        for record in records.iter() {
+            caps_record_writer.write(record, &mut cursor).unwrap();
+        }
+        let mut caps_history = CapsHistory::new(cursor);
+        caps_history.refresh().unwrap();
+
+        assert_eq!(caps_history.caps_at_offset(0), Some(&records[0]));
+        assert_eq!(caps_history.caps_at_offset(4999), Some(&records[0]));
+        assert_eq!(caps_history.caps_at_offset(5000), Some(&records[1]));
+        assert_eq!(caps_history.caps_at_offset(u64::MAX), Some(&records[1]));
+        assert_eq!(CapsHistory::new(Cursor::new(Vec::new())).caps_at_offset(0), None);
+
+        assert_eq!(caps_to_content_type(&records[0].caps), Some("video/mp4"));
+        assert_eq!(caps_to_content_type(&records[1].caps), Some("video/MP2T"));
+        assert_eq!(caps_to_content_type("video/x-raw, format=(string)I420"), None);
+    }
+}
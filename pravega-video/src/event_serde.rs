@@ -11,7 +11,8 @@
 // Module for serialization of events for writing to a Pravega byte stream.
 
 use std::convert::TryInto;
-use std::io::{Error, ErrorKind, Read, Write};
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::str::FromStr;
 use enumflags2::BitFlags;
 use crate::timestamp::PravegaTimestamp;
 
@@ -21,20 +22,145 @@ pub(crate) enum EventHeaderFlags {
     IncludeInIndex         = 0b00000001,
     RandomAccessIndicator  = 0b00000010,
     DiscontinuityIndicator = 0b00000100,
+    ChecksumPresent        = 0b00001000,
+    Continuation           = 0b00010000,
+    MoreFragments          = 0b00100000,
+    BufferMetadataPresent  = 0b01000000,
 }
 
-#[derive(Debug, PartialEq)]
+// Returns the minimum header extension length needed for the fields indicated by flags.
+fn required_extension_length(flags: BitFlags<EventHeaderFlags>) -> usize {
+    let mut length = 0;
+    if flags.contains(EventHeaderFlags::ChecksumPresent) {
+        length += 4;
+    }
+    if flags.contains(EventHeaderFlags::BufferMetadataPresent) {
+        length += BufferMetadata::SERIALIZED_LENGTH;
+    }
+    length
+}
+
+#[derive(BitFlags, Copy, Clone, Debug, PartialEq)]
+#[repr(u32)]
+enum BufferMetadataFlags {
+    Header    = 0b00000001,
+    Gap       = 0b00000010,
+    Droppable = 0b00000100,
+    Marker    = 0b00001000,
+    Batch     = 0b00010000,
+}
+
+/// Additional properties of a GStreamer buffer that are stored in the header extension.
+/// This allows buffers to be stored without a wrapper such as gdppay.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct BufferMetadata {
+    /// The decode timestamp, using the same epoch as the event timestamp.
+    pub dts: PravegaTimestamp,
+    /// The duration in nanoseconds.
+    pub duration: Option<u64>,
+    /// Corresponds to GST_BUFFER_FLAG_HEADER.
+    pub header: bool,
+    /// Corresponds to GST_BUFFER_FLAG_GAP.
+    pub gap: bool,
+    /// Corresponds to GST_BUFFER_FLAG_DROPPABLE.
+    pub droppable: bool,
+    /// Corresponds to GST_BUFFER_FLAG_MARKER.
+    pub marker: bool,
+}
+
+impl BufferMetadata {
+    const SERIALIZED_LENGTH: usize = 20;
+
+    // If batch is true, the buffer metadata will indicate that it is followed by a batch table.
+    fn write(&self, bytes: &mut [u8], batch: bool) {
+        let mut flags = BitFlags::<BufferMetadataFlags>::empty();
+        if self.header {
+            flags |= BufferMetadataFlags::Header;
+        }
+        if self.gap {
+            flags |= BufferMetadataFlags::Gap;
+        }
+        if self.droppable {
+            flags |= BufferMetadataFlags::Droppable;
+        }
+        if self.marker {
+            flags |= BufferMetadataFlags::Marker;
+        }
+        if batch {
+            flags |= BufferMetadataFlags::Batch;
+        }
+        bytes[0..8].copy_from_slice(&self.dts.nanoseconds().unwrap_or_default().to_be_bytes()[..]);
+        bytes[8..16].copy_from_slice(&self.duration.unwrap_or(u64::MAX).to_be_bytes()[..]);
+        bytes[16..20].copy_from_slice(&flags.bits().to_be_bytes()[..]);
+    }
+
+    // Returns the buffer metadata and true if it is followed by a batch table.
+    fn read(bytes: &[u8]) -> (Self, bool) {
+        let dts = u64::from_be_bytes(bytes[0..8].try_into().unwrap());
+        let duration = u64::from_be_bytes(bytes[8..16].try_into().unwrap());
+        // Unknown flags are ignored.
+        let flags = BitFlags::<BufferMetadataFlags>::from_bits_truncate(u32::from_be_bytes(bytes[16..20].try_into().unwrap()));
+        let buffer_metadata = Self {
+            dts: PravegaTimestamp::from_nanoseconds(if dts == 0 { None } else { Some(dts) }),
+            duration: if duration == u64::MAX { None } else { Some(duration) },
+            header: flags.contains(BufferMetadataFlags::Header),
+            gap: flags.contains(BufferMetadataFlags::Gap),
+            droppable: flags.contains(BufferMetadataFlags::Droppable),
+            marker: flags.contains(BufferMetadataFlags::Marker),
+        };
+        (buffer_metadata, flags.contains(BufferMetadataFlags::Batch))
+    }
+}
+
+// Returns the length of the batch table for a batch of the specified number of buffers.
+fn batch_table_length(buffer_count: usize) -> usize {
+    if buffer_count == 0 {
+        0
+    } else {
+        4 + 4 * buffer_count
+    }
+}
+
+// Reads the batch table from the header extension and validates it against the payload length.
+fn read_batch_table(bytes: &[u8], payload_length: usize) -> Result<Vec<usize>, Error> {
+    let invalid = |msg: String| Err(Error::new(ErrorKind::InvalidData, msg));
+    if bytes.len() < 4 {
+        return invalid(String::from("Header extension is too short for batch table"))
+    }
+    let buffer_count = u32::from_be_bytes(bytes[0..4].try_into().unwrap()) as usize;
+    if buffer_count == 0 || batch_table_length(buffer_count) > bytes.len() {
+        return invalid(format!("Invalid batch of {} buffers in header extension of {} bytes", buffer_count, bytes.len()))
+    }
+    let buffer_lengths: Vec<usize> = bytes[4..batch_table_length(buffer_count)].chunks_exact(4)
+        .map(|length| u32::from_be_bytes(length.try_into().unwrap()) as usize)
+        .collect();
+    let total_length: usize = buffer_lengths.iter().sum();
+    if total_length != payload_length {
+        return invalid(format!("Batch buffer lengths total {} but payload length is {}", total_length, payload_length))
+    }
+    Ok(buffer_lengths)
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub struct EventHeader {
     pub timestamp: PravegaTimestamp,
     pub include_in_index: bool,
     pub random_access: bool,
     pub discontinuity: bool,
+    /// True if this event contains a fragment that continues the payload of the previous event.
+    pub continuation: bool,
+    /// True if the payload continues in the next event.
+    pub more_fragments: bool,
+    /// Additional buffer properties. Only written by an EventWriter with buffer metadata enabled.
+    pub buffer_metadata: Option<BufferMetadata>,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct EventWithHeader<'a> {
     pub header: EventHeader,
     pub payload: &'a [u8],
+    /// If not empty, the payload is the concatenation of a batch of buffers with these lengths.
+    pub buffer_lengths: Vec<usize>,
 }
 
 /**
@@ -50,12 +176,12 @@ pub struct EventWithHeader<'a> {
    |                                                               |
    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
    |            event_length (32-bit BE unsigned int)              |
-   |    number of bytes from reserved to the end of the payload    |
+   |    number of bytes from version to the end of the payload     |
    |                                                               |
    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-   |                                                         |D|R|I|
-   |                    reserved (set to 0)                  |I|A|N|
-   |                                                         |S|N|D|
+   |               |                               | |B|M|C|C|D|R|I|
+   |    version    |        extension_length       |r|U|O|O|H|I|A|N|
+   |               |                               | |F|R|N|K|S|N|D|
    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
    |                                                               |
    |                                                               |
@@ -66,6 +192,10 @@ pub struct EventWithHeader<'a> {
    |                                                               |
    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
    |                                                               |
+   |          header extension (extension_length bytes)            |
+   |                                                               |
+   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+   |                                                               |
    |                    payload (variable length)                  |
    |                                                               |
    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
@@ -76,11 +206,32 @@ pub struct EventWithHeader<'a> {
       The type code must be 0 which corresponds to pravega_wire_protocol::wire_commands::EventCommand.TYPE_CODE.
       This makes this byte stream compatible with a Pravega event stream reader.
    event length:
-      This is number of bytes from reserved to the end of the payload.
+      This is number of bytes from version to the end of the payload.
       Encoded as a 32-bit big-endian unsigned int.
-   reserved:
-      All reserved bits must be 0.
-      These may be utilized in the future for other purposes.
+   version:
+      The version of the event header format. See "Format versions" below.
+   extension_length:
+      The number of bytes in the header extension, which follows the timestamp and precedes the payload.
+      Encoded as a 16-bit big-endian unsigned int.
+      This must be 0 in version 0.
+   reserved, res:
+      All reserved bits must be written as 0.
+      Readers must ignore reserved bits so that they may be utilized in the future for other purposes.
+   BUF - buffer metadata present:
+      If true (1), the header extension contains buffer metadata. Requires version 1.
+   MOR - more fragments:
+      If true (1), the payload continues in the next event.
+      Buffers larger than the maximum payload size are written as a chain of fragments.
+      All fragments except the last have MOR set to 1.
+   CON - continuation:
+      If true (1), this event continues the payload of the previous event.
+      All fragments except the first have CON set to 1.
+      Only the first fragment may have IND, RAN, or DIS set to 1.
+      A reader should join the payloads of a chain of fragments to obtain the original buffer.
+      If a chain is not terminated by an event with MOR set to 0, the writer terminated before writing
+      all fragments and the incomplete chain should be discarded.
+   CHK - checksum present:
+      If true (1), the header extension begins with a CRC32C checksum. Requires version 1.
    DIS - discontinuity indicator:
       True (1) if this event is or may be discontinuous from the previous event.
       This should usually be true for the first event written by a new process.
@@ -107,18 +258,87 @@ pub struct EventWithHeader<'a> {
       For video frames, the timestamp will reflect when the image was captured by the camera.
       If DTS can differ from PTS, this timestamp should be the PTS.
       This allows different streams to be correlated precisely.
+   header extension:
+      Optional fields whose presence is indicated by flags.
+      Fields are stored in the order listed below.
+      Readers must skip any bytes in the header extension that they do not understand.
+      checksum (present if CHK is 1):
+         CRC32C (Castagnoli) checksum, encoded as a 32-bit big-endian unsigned int.
+         It is calculated over all bytes from type code through the end of the payload,
+         excluding the checksum itself.
+      buffer metadata (present if BUF is 1):
+         DTS (decode timestamp), encoded as a 64-bit big-endian unsigned int.
+            This uses the same epoch as timestamp. 0 if unknown.
+         duration in nanoseconds, encoded as a 64-bit big-endian unsigned int.
+            0xFFFFFFFFFFFFFFFF if unknown.
+         buffer flags, encoded as a 32-bit big-endian unsigned int.
+            Bit 0 is HEADER, bit 1 is GAP, bit 2 is DROPPABLE, bit 3 is MARKER.
+            These have the same meaning as the GStreamer buffer flags.
+            Bit 4 is BATCH, which indicates that the buffer metadata is followed by a batch table.
+            All other bits are reserved.
+      batch table (present if BUF is 1 and the BATCH buffer flag is 1):
+         number of buffers, encoded as a 32-bit big-endian unsigned int, followed by
+         the length of each buffer, each encoded as a 32-bit big-endian unsigned int.
+         The payload is the concatenation of these buffers, which were written as a single event to reduce overhead.
+         The lengths must add up to the payload length.
+         The timestamp, flags, and buffer metadata describe the first buffer.
+         A reader that ignores the batch table will read the payload as a single buffer.
+         This is valid for MPEG transport streams.
    payload:
       Can be 0 or more MPEG TS packets, or any other payload.
-      When encoding an MPEG transport stream, this is currently a single 188-byte MPEG TS packet.
+      When encoding an MPEG transport stream, this is usually a single 188-byte MPEG TS packet,
+      or a batch of packets if the writer coalesces small buffers.
       Writes of the entire frame (type code through payload) must be atomic,
       which means it must be 8 MiB or smaller.
+
+   Format versions:
+      0: The original format. The version, extension_length, and reserved bits are all 0.
+      1: Adds extension_length and the header extension.
+         Events without a header extension can also be read by readers that only understand version 0.
+   New features that can be safely ignored by older readers (for example, checksums or additional timestamps)
+   are added by defining a new flag and storing any additional fields in the header extension.
+   The version is only incremented for changes that older readers cannot safely ignore.
+   EventReader returns an InvalidData error when it reads an event with an unsupported version.
 */
 pub struct EventWriter {
+    checksum: bool,
+    buffer_metadata: bool,
 }
 
 impl EventWriter {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            checksum: false,
+            buffer_metadata: false,
+        }
+    }
+
+    /// If checksum is true, a CRC32C checksum will be written in the header extension of each event.
+    pub fn with_checksum(checksum: bool) -> Self {
+        Self {
+            checksum,
+            buffer_metadata: false,
+        }
+    }
+
+    /// If buffer_metadata is true, the buffer_metadata field of each event header, if any,
+    /// will be written in the header extension.
+    /// Otherwise, it is ignored.
+    pub fn with_buffer_metadata(mut self, buffer_metadata: bool) -> Self {
+        self.buffer_metadata = buffer_metadata;
+        self
+    }
+
+    /// Returns the maximum payload size that can be written with the header extension used by this writer.
+    pub fn max_payload_size(&self) -> usize {
+        let mut flags = BitFlags::<EventHeaderFlags>::empty();
+        if self.checksum {
+            flags |= EventHeaderFlags::ChecksumPresent;
+        }
+        if self.buffer_metadata {
+            flags |= EventHeaderFlags::BufferMetadataPresent;
+        }
+        EventWithHeader::MAX_PAYLOAD_SIZE - required_extension_length(flags)
     }
 
     pub fn write<'a, W>(&mut self, event: &EventWithHeader<'a>, writer: &mut W) -> Result<(), Error>
@@ -135,51 +355,170 @@ impl EventWriter {
         if event.header.discontinuity {
             flags |= EventHeaderFlags::DiscontinuityIndicator;
         }
+        if self.checksum {
+            flags |= EventHeaderFlags::ChecksumPresent;
+        }
+        if event.header.continuation {
+            flags |= EventHeaderFlags::Continuation;
+        }
+        if event.header.more_fragments {
+            flags |= EventHeaderFlags::MoreFragments;
+        }
+        let buffer_metadata = if self.buffer_metadata { event.header.buffer_metadata } else { None };
+        // The batch table is stored after the buffer metadata, so a batch always has buffer metadata.
+        let batch = !event.buffer_lengths.is_empty();
+        let buffer_metadata = if batch { Some(buffer_metadata.unwrap_or_default()) } else { buffer_metadata };
+        if buffer_metadata.is_some() {
+            flags |= EventHeaderFlags::BufferMetadataPresent;
+        }
         let payload_length = event.payload.len();
-        if payload_length > EventWithHeader::MAX_PAYLOAD_SIZE {
+        if payload_length > self.max_payload_size() {
             return Err(Error::new(ErrorKind::InvalidInput, format!("Payload of {} bytes exceeds {} bytes",
-                payload_length, EventWithHeader::MAX_PAYLOAD_SIZE)));
+                payload_length, self.max_payload_size())));
+        }
+        if batch {
+            let total_length: usize = event.buffer_lengths.iter().sum();
+            if total_length != payload_length || event.buffer_lengths.len() > EventWithHeader::MAX_BATCH_LENGTH {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("Invalid batch of {} buffers totaling {} bytes with payload of {} bytes",
+                    event.buffer_lengths.len(), total_length, payload_length)));
+            }
+        }
+        let extension_length = required_extension_length(flags) + batch_table_length(event.buffer_lengths.len());
+        if 20 + extension_length + payload_length > EventWithHeader::MAX_ATOMIC_WRITE_SIZE {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Event with payload of {} bytes and header extension of {} bytes exceeds the maximum event size",
+                payload_length, extension_length)));
         }
-        let event_length: u32 = (payload_length + 12).try_into().unwrap();
-        let write_length = payload_length + 20;
+        let event_length: u32 = (payload_length + extension_length + 12).try_into().unwrap();
+        let payload_offset = 20 + extension_length;
+        let write_length = payload_offset + payload_length;
         let mut bytes_to_write: Vec<u8> = vec![0; write_length];
         bytes_to_write[4..8].copy_from_slice(&event_length.to_be_bytes()[..]);
+        bytes_to_write[8] = EventWithHeader::VERSION;
+        bytes_to_write[9..11].copy_from_slice(&(extension_length as u16).to_be_bytes()[..]);
         bytes_to_write[11..12].copy_from_slice(&flags.bits().to_be_bytes()[..]);
         bytes_to_write[12..20].copy_from_slice(&event.header.timestamp.nanoseconds().unwrap_or_default().to_be_bytes()[..]);
-        bytes_to_write[20..20+payload_length].copy_from_slice(&event.payload[..]);
-        writer.write_all(&bytes_to_write).unwrap();
+        bytes_to_write[payload_offset..write_length].copy_from_slice(&event.payload[..]);
+        if let Some(buffer_metadata) = buffer_metadata {
+            let field_offset = if self.checksum { 24 } else { 20 };
+            buffer_metadata.write(&mut bytes_to_write[field_offset..field_offset+BufferMetadata::SERIALIZED_LENGTH], batch);
+            if batch {
+                let mut field_offset = field_offset + BufferMetadata::SERIALIZED_LENGTH;
+                bytes_to_write[field_offset..field_offset+4].copy_from_slice(&(event.buffer_lengths.len() as u32).to_be_bytes()[..]);
+                for buffer_length in event.buffer_lengths.iter() {
+                    field_offset += 4;
+                    bytes_to_write[field_offset..field_offset+4].copy_from_slice(&(*buffer_length as u32).to_be_bytes()[..]);
+                }
+            }
+        }
+        if self.checksum {
+            let checksum = calculate_checksum(&bytes_to_write[..], 20);
+            bytes_to_write[20..24].copy_from_slice(&checksum.to_be_bytes()[..]);
+        }
+        writer.write_all(&bytes_to_write)?;
         Ok(())
     }
 }
 
+// Calculate the CRC32C checksum of an entire serialized event, excluding the 4-byte checksum at checksum_offset.
+fn calculate_checksum(bytes: &[u8], checksum_offset: usize) -> u32 {
+    let checksum = crc32c::crc32c(&bytes[..checksum_offset]);
+    crc32c::crc32c_append(checksum, &bytes[checksum_offset+4..])
+}
+
+/// The result of verifying the checksum of the last event read by EventReader.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ChecksumStatus {
+    /// The event was written without a checksum.
+    NotPresent,
+    Valid,
+    Invalid {
+        /// The checksum stored in the event header.
+        expected: u32,
+        /// The checksum calculated from the event that was read.
+        actual: u32,
+    },
+}
+
+/// Determines what a reader should do when an event fails checksum verification.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ChecksumMismatchPolicy {
+    /// Fail with an error.
+    Error,
+    /// Drop the event and mark the next event as a discontinuity.
+    Drop,
+    /// Log a warning and use the event as-is.
+    Warn,
+}
+
+impl FromStr for ChecksumMismatchPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(ChecksumMismatchPolicy::Error),
+            "drop" => Ok(ChecksumMismatchPolicy::Drop),
+            "warn" => Ok(ChecksumMismatchPolicy::Warn),
+            _ => Err(format!("Invalid checksum mismatch policy '{}'. It must be error, drop, or warn.", s)),
+        }
+    }
+}
+
 pub struct EventReader {
-    // This is a copy of the first 8 bytes of the serialized EventWithHeader.
-    // This currently contains only the event length but the unused bits may be used in the future.
-    event_length_bytes: [u8; 8],
+    // This is a copy of the first bytes of the serialized EventWithHeader that were read by read_required_buffer_length().
+    // The first 8 bytes currently contain only the event length but the unused bits may be used in the future.
+    header_bytes: [u8; EventWithHeader::HEADER_LENGTH],
+    // The number of bytes in header_bytes that were read.
+    header_bytes_read: usize,
+    // If true, the fixed header is validated with validate_header() before the rest of the event is read.
+    header_validation: bool,
     // The number of bytes that follow the event length.
     event_length: usize,
     // The minimum buffer size required to read the entire EventWithHeader.
     required_buffer_length: usize,
+    // The result of verifying the checksum of the last event read.
+    checksum_status: ChecksumStatus,
 }
 
 // A struct to deserialize an EventWithHeader that was written to a Pravega byte stream.
 impl EventReader {
     pub fn new() -> Self {
         Self {
-            event_length_bytes: [0; 8],
+            header_bytes: [0; EventWithHeader::HEADER_LENGTH],
+            header_bytes_read: 0,
+            header_validation: false,
             event_length: 0,
             required_buffer_length: 0,
+            checksum_status: ChecksumStatus::NotPresent,
         }
     }
 
+    /// If header_validation is true, read_required_buffer_length() will read the entire 20-byte fixed header
+    /// and return an InvalidData error if validate_header() rejects it.
+    /// This detects corruption without reading the rest of the event.
+    pub fn with_header_validation(mut self, header_validation: bool) -> Self {
+        self.header_validation = header_validation;
+        self
+    }
+
+    /// Returns the result of verifying the checksum of the last event read by read_event().
+    /// An event that fails verification is still returned by read_event() so that the caller can apply its policy.
+    pub fn checksum_status(&self) -> ChecksumStatus {
+        self.checksum_status
+    }
+
     // Reads exactly 8 bytes from the Pravega stream, which should contain the event length.
+    // If header validation is enabled, the entire 20-byte fixed header is read and validated instead.
     // Returns the minimum size of the buffer that can be passed to read_event() to read the whole event.
     pub fn read_required_buffer_length<R>(&mut self, rdr: &mut R) -> Result<usize, Error>
     where
         R: Read,
     {
-        rdr.read_exact(&mut self.event_length_bytes[0..8])?;
-        let event_length_bytes: [u8; 4] = self.event_length_bytes[4..8].try_into().unwrap();
+        self.header_bytes_read = if self.header_validation { EventWithHeader::HEADER_LENGTH } else { 8 };
+        rdr.read_exact(&mut self.header_bytes[0..self.header_bytes_read])?;
+        if self.header_validation {
+            EventReader::validate_header(&self.header_bytes)?;
+        }
+        let event_length_bytes: [u8; 4] = self.header_bytes[4..8].try_into().unwrap();
         self.event_length = u32::from_be_bytes(event_length_bytes) as usize;
         // Event length must be between 12 and MAX_ATOMIC_WRITE_SIZE - 8.
         if self.event_length < 12 || 8 + self.event_length > EventWithHeader::MAX_ATOMIC_WRITE_SIZE {
@@ -191,7 +530,7 @@ impl EventReader {
 
     // Reads the rest of event, including the rest of the EventHeader and the payload.
     // This must be called after read_required_buffer_length() has been called to determine the event length.
-    // The reader must be positioned at the byte immediatley after event_length.
+    // The reader must be positioned at the byte immediatley after the bytes read by read_required_buffer_length().
     pub fn read_event<'a, R>(&mut self, rdr: &mut R, buffer: &'a mut [u8]) -> Result<EventWithHeader<'a>, Error>
     where
         R: Read,
@@ -199,33 +538,291 @@ impl EventReader {
         if buffer.len() < self.required_buffer_length {
             return Err(Error::new(ErrorKind::InvalidInput, "Buffer too small"))
         }
-        //  Note that bytes 0..8 of buffer are unused. However, this keeps the byte ranges consistent with the writer.
-        rdr.read_exact(&mut buffer[8..self.required_buffer_length])?;
-        let flags = BitFlags::<EventHeaderFlags>::from_bits(buffer[11]).unwrap();
+        // The first bytes of buffer are copied from the bytes read by read_required_buffer_length().
+        // This keeps the byte ranges consistent with the writer and allows the checksum to be calculated.
+        self.checksum_status = ChecksumStatus::NotPresent;
+        let header_bytes_read = self.header_bytes_read;
+        buffer[0..header_bytes_read].copy_from_slice(&self.header_bytes[0..header_bytes_read]);
+        rdr.read_exact(&mut buffer[header_bytes_read..self.required_buffer_length])?;
+        let version = buffer[8];
+        let extension_length = match version {
+            0 => 0,
+            1 => u16::from_be_bytes(buffer[9..11].try_into().unwrap()) as usize,
+            _ => return Err(Error::new(ErrorKind::InvalidData, format!("Unsupported event header version {}", version))),
+        };
+        if 12 + extension_length > self.event_length {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Header extension length {} exceeds event length {}",
+                extension_length, self.event_length)))
+        }
+        // Unknown flags are ignored.
+        let flags = BitFlags::<EventHeaderFlags>::from_bits_truncate(buffer[11]);
         let include_in_index = flags.contains(EventHeaderFlags::IncludeInIndex);
         let random_access = flags.contains(EventHeaderFlags::RandomAccessIndicator);
         let discontinuity = flags.contains(EventHeaderFlags::DiscontinuityIndicator);
+        let continuation = flags.contains(EventHeaderFlags::Continuation);
+        let more_fragments = flags.contains(EventHeaderFlags::MoreFragments);
+        if extension_length < required_extension_length(flags) {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Header extension length {} is too short for flags {:?}",
+                extension_length, flags)))
+        }
+        // Fields in the header extension are stored in the order of their flags.
+        let mut field_offset = 20;
+        if flags.contains(EventHeaderFlags::ChecksumPresent) {
+            let expected = u32::from_be_bytes(buffer[20..24].try_into().unwrap());
+            let actual = calculate_checksum(&buffer[..self.required_buffer_length], 20);
+            self.checksum_status = if expected == actual {
+                ChecksumStatus::Valid
+            } else {
+                ChecksumStatus::Invalid { expected, actual }
+            };
+            field_offset += 4;
+        }
+        let payload_length = self.event_length - 12 - extension_length;
+        let payload_offset = 20 + extension_length;
+        let (buffer_metadata, buffer_lengths) = if flags.contains(EventHeaderFlags::BufferMetadataPresent) {
+            let (buffer_metadata, batch) = BufferMetadata::read(&buffer[field_offset..field_offset+BufferMetadata::SERIALIZED_LENGTH]);
+            field_offset += BufferMetadata::SERIALIZED_LENGTH;
+            let buffer_lengths = if batch {
+                read_batch_table(&buffer[field_offset..payload_offset], payload_length)?
+            } else {
+                Vec::new()
+            };
+            (Some(buffer_metadata), buffer_lengths)
+        } else {
+            (None, Vec::new())
+        };
         let timestamp = u64::from_be_bytes(buffer[12..20].try_into().unwrap());
         let timestamp = if timestamp == 0 { None } else { Some(timestamp) };
-        let payload_length = self.event_length - 12;
-        let payload = &buffer[20..20+payload_length];
+        let payload = &buffer[payload_offset..payload_offset+payload_length];
         Ok(EventWithHeader {
             header: EventHeader {
                 timestamp: PravegaTimestamp::from_nanoseconds(timestamp),
                 include_in_index,
                 random_access,
                 discontinuity,
+                continuation,
+                more_fragments,
+                buffer_metadata,
             },
             payload,
+            buffer_lengths,
         })
     }
+
+    /// Checks whether the 20-byte fixed header at the start of bytes is plausibly a valid event header.
+    /// This is stricter than read_event(): reserved bits and unknown flags must be 0, and the timestamp must be
+    /// 0 or earlier than 2200-01-01.
+    /// It is used to detect corruption and to find the next event after corruption.
+    pub fn validate_header(bytes: &[u8]) -> Result<(), Error> {
+        let invalid = |msg: String| Err(Error::new(ErrorKind::InvalidData, msg));
+        if bytes.len() < EventWithHeader::HEADER_LENGTH {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Event header is truncated"))
+        }
+        let type_code = i32::from_be_bytes(bytes[0..4].try_into().unwrap());
+        if type_code != 0 {
+            return invalid(format!("Invalid type code {}", type_code))
+        }
+        let event_length = u32::from_be_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        if event_length < 12 || 8 + event_length > EventWithHeader::MAX_ATOMIC_WRITE_SIZE {
+            return invalid(format!("Invalid event length {}", event_length))
+        }
+        let version = bytes[8];
+        let extension_length = u16::from_be_bytes(bytes[9..11].try_into().unwrap()) as usize;
+        let flags = bytes[11];
+        match version {
+            0 => {
+                if extension_length != 0 || flags & !0b00000111 != 0 {
+                    return invalid(format!("Reserved bits are set in version 0 event header: {:02x?}", &bytes[9..12]))
+                }
+            },
+            1 => {
+                if BitFlags::<EventHeaderFlags>::from_bits(flags).is_err() {
+                    return invalid(format!("Unknown flags {:#010b}", flags))
+                }
+                if 12 + extension_length > event_length {
+                    return invalid(format!("Header extension length {} exceeds event length {}", extension_length, event_length))
+                }
+                let flags = BitFlags::<EventHeaderFlags>::from_bits_truncate(flags);
+                if extension_length < required_extension_length(flags) {
+                    return invalid(format!("Header extension length {} is too short for flags {:?}", extension_length, flags))
+                }
+            },
+            _ => return invalid(format!("Unsupported event header version {}", version)),
+        }
+        let timestamp = u64::from_be_bytes(bytes[12..20].try_into().unwrap());
+        if timestamp > EventWithHeader::MAX_PLAUSIBLE_TIMESTAMP {
+            return invalid(format!("Implausible timestamp {}", timestamp))
+        }
+        Ok(())
+    }
+
+    /// Scans forward from start_offset for the first position that contains a valid event header.
+    /// On success, the reader will be positioned at the returned offset.
+    /// Returns an UnexpectedEof error if the end of the stream is reached before a valid header is found.
+    pub fn scan_for_header<R>(&mut self, rdr: &mut R, start_offset: u64) -> Result<u64, Error>
+    where
+        R: Read + Seek,
+    {
+        const CHUNK_SIZE: usize = 64 * 1024;
+        rdr.seek(SeekFrom::Start(start_offset))?;
+        // buffer[0] corresponds to buffer_offset in the stream.
+        let mut buffer: Vec<u8> = Vec::with_capacity(CHUNK_SIZE + EventWithHeader::HEADER_LENGTH);
+        let mut buffer_offset = start_offset;
+        loop {
+            let old_length = buffer.len();
+            buffer.resize(old_length + CHUNK_SIZE, 0);
+            let mut filled = old_length;
+            while filled < buffer.len() {
+                match rdr.read(&mut buffer[filled..]) {
+                    Ok(0) => break,
+                    Ok(n) => filled += n,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {},
+                    Err(e) => return Err(e),
+                }
+            }
+            let end_of_stream = filled < buffer.len();
+            buffer.truncate(filled);
+            let mut i = 0;
+            while i + EventWithHeader::HEADER_LENGTH <= buffer.len() {
+                if EventReader::validate_header(&buffer[i..]).is_ok() {
+                    let offset = buffer_offset + i as u64;
+                    rdr.seek(SeekFrom::Start(offset))?;
+                    return Ok(offset)
+                }
+                i += 1;
+            }
+            if end_of_stream {
+                return Err(Error::new(ErrorKind::UnexpectedEof,
+                    format!("No valid event header found after offset {}", start_offset)))
+            }
+            // Keep the trailing bytes that may be the start of a header.
+            buffer.drain(..i);
+            buffer_offset += i as u64;
+        }
+    }
+}
+
+/// The result of adding an event to a FragmentReassembler.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FragmentStatus {
+    /// The event was not fragmented. Its payload should be used as-is.
+    NotFragmented,
+    /// The event is a fragment and more fragments are required.
+    Incomplete,
+    /// The event was the last fragment. Call take() to get the reassembled buffer.
+    Complete,
+    /// The event continues a chain whose first fragment was not read, for instance after a seek.
+    /// It should be dropped.
+    Orphan,
+}
+
+/// Joins the payloads of a chain of fragments written for a buffer that was too large for a single event.
+pub struct FragmentReassembler {
+    // The header of the first fragment.
+    header: Option<EventHeader>,
+    payload: Vec<u8>,
+    fragment_count: usize,
+    max_size: usize,
+}
+
+impl FragmentReassembler {
+    /// The default maximum size of a reassembled payload.
+    /// This limits the memory used when reading a corrupt chain that never ends.
+    pub const DEFAULT_MAX_SIZE: usize = 256 * 1024 * 1024;
+
+    pub fn new() -> Self {
+        Self::with_max_size(Self::DEFAULT_MAX_SIZE)
+    }
+
+    /// Creates a FragmentReassembler that will not reassemble payloads larger than max_size bytes.
+    pub fn with_max_size(max_size: usize) -> Self {
+        Self {
+            header: None,
+            payload: Vec::new(),
+            fragment_count: 0,
+            max_size,
+        }
+    }
+
+    /// Returns true if the first fragment of a chain has been added but the last fragment has not.
+    pub fn is_incomplete(&self) -> bool {
+        self.header.is_some()
+    }
+
+    /// Returns the number of fragments in the current chain.
+    pub fn fragment_count(&self) -> usize {
+        self.fragment_count
+    }
+
+    /// Discards an incomplete chain.
+    /// Returns the number of payload bytes that were discarded.
+    pub fn reset(&mut self) -> usize {
+        let discarded = self.payload.len();
+        self.header = None;
+        self.payload.clear();
+        self.fragment_count = 0;
+        discarded
+    }
+
+    /// Adds an event.
+    /// If a chain is incomplete and the event is not a continuation, the chain was truncated.
+    /// Callers must check for this using is_incomplete() and call reset() before adding the event,
+    /// otherwise an InvalidInput error is returned.
+    /// If the reassembled payload would exceed the maximum size, an InvalidData error is returned
+    /// and the event is not added. Callers should then discard the chain using reset().
+    pub fn push(&mut self, event: &EventWithHeader) -> Result<FragmentStatus, Error> {
+        let header = &event.header;
+        if !header.continuation {
+            if self.is_incomplete() {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                    format!("Chain of {} fragments was truncated", self.fragment_count)))
+            }
+            if !header.more_fragments {
+                return Ok(FragmentStatus::NotFragmented)
+            }
+        } else if !self.is_incomplete() {
+            return Ok(FragmentStatus::Orphan)
+        }
+        if self.payload.len() + event.payload.len() > self.max_size {
+            return Err(Error::new(ErrorKind::InvalidData,
+                format!("Chain of {} fragments exceeds the maximum size of {} bytes", self.fragment_count + 1, self.max_size)))
+        }
+        if !header.continuation {
+            self.header = Some(*header);
+        }
+        self.payload.extend_from_slice(event.payload);
+        self.fragment_count += 1;
+        if header.more_fragments {
+            Ok(FragmentStatus::Incomplete)
+        } else {
+            Ok(FragmentStatus::Complete)
+        }
+    }
+
+    /// Returns the header of the first fragment and the reassembled payload.
+    /// The returned header will have more_fragments set to false.
+    /// This must be called only after push() returns Complete.
+    pub fn take(&mut self) -> (EventHeader, Vec<u8>) {
+        let mut header = self.header.take().expect("No complete chain");
+        header.more_fragments = false;
+        self.fragment_count = 0;
+        (header, std::mem::take(&mut self.payload))
+    }
 }
 
 impl<'a> EventWithHeader<'a> {
+    /// The version of the event header format written by EventWriter.
+    pub const VERSION: u8 = 1;
     // Maximum size of the entire frame from type code through payload.
     // Corresponds to pravega_client_rust::event_stream_writer::EventWriter.
     const MAX_ATOMIC_WRITE_SIZE: usize = 8 * 1024 * 1024;
     const MAX_PAYLOAD_SIZE: usize = EventWithHeader::MAX_ATOMIC_WRITE_SIZE - 20;
+    // Maximum number of buffers in a batch. This ensures that the header extension length fits in 16 bits.
+    const MAX_BATCH_LENGTH: usize = 4096;
+    // Size of the fixed part of the header, from type code through timestamp.
+    const HEADER_LENGTH: usize = 20;
+    // 2200-01-01 00:00:00 TAI. Timestamps after this are assumed to be the result of corruption.
+    const MAX_PLAUSIBLE_TIMESTAMP: u64 = 7_258_118_400_000_000_000;
 
     pub fn new(payload: &'a [u8], timestamp: PravegaTimestamp,
         include_in_index: bool, random_access: bool, discontinuity: bool) -> Self {
@@ -235,19 +832,78 @@ impl<'a> EventWithHeader<'a> {
                 include_in_index,
                 random_access,
                 discontinuity,
+                continuation: false,
+                more_fragments: false,
+                buffer_metadata: None,
             },
             payload: payload,
+            buffer_lengths: Vec::new(),
         }
     }
 
+    /// Returns an event that contains one fragment of a buffer that is too large for a single event.
+    /// Only the first fragment (continuation=false) should be indexed or marked as random access or a discontinuity.
+    pub fn new_fragment(payload: &'a [u8], timestamp: PravegaTimestamp,
+        include_in_index: bool, random_access: bool, discontinuity: bool,
+        continuation: bool, more_fragments: bool) -> Self {
+        Self {
+            header: EventHeader {
+                timestamp,
+                include_in_index,
+                random_access,
+                discontinuity,
+                continuation,
+                more_fragments,
+                buffer_metadata: None,
+            },
+            payload,
+            buffer_lengths: Vec::new(),
+        }
+    }
+
+    /// Returns an event that contains a batch of buffers with the specified lengths, concatenated in payload.
+    /// The timestamp and flags describe the first buffer.
+    pub fn new_batch(payload: &'a [u8], buffer_lengths: Vec<usize>, timestamp: PravegaTimestamp,
+        include_in_index: bool, random_access: bool, discontinuity: bool) -> Self {
+        let mut event = EventWithHeader::new(payload, timestamp, include_in_index, random_access, discontinuity);
+        event.buffer_lengths = buffer_lengths;
+        event
+    }
+
+    /// Returns the buffers in the payload.
+    /// If this is not a batch, this returns the entire payload as a single buffer.
+    pub fn buffers(&self) -> Vec<&'a [u8]> {
+        if self.buffer_lengths.is_empty() {
+            return vec![self.payload];
+        }
+        let payload = self.payload;
+        let mut buffers = Vec::with_capacity(self.buffer_lengths.len());
+        let mut offset = 0;
+        for buffer_length in self.buffer_lengths.iter() {
+            buffers.push(&payload[offset..offset+buffer_length]);
+            offset += buffer_length;
+        }
+        buffers
+    }
+
+    /// Returns the maximum number of buffers in a batch.
+    pub fn max_batch_length() -> usize {
+        EventWithHeader::MAX_BATCH_LENGTH
+    }
+
     pub fn max_payload_size() -> usize {
         EventWithHeader::MAX_PAYLOAD_SIZE
     }
+
+    /// Returns the maximum number of bytes that can be appended to a byte stream atomically.
+    pub fn max_atomic_write_size() -> usize {
+        EventWithHeader::MAX_ATOMIC_WRITE_SIZE
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::event_serde::{EventWithHeader, EventWriter, EventReader};
+    use crate::event_serde::{BufferMetadata, ChecksumStatus, EventWithHeader, EventWriter, EventReader, FragmentReassembler, FragmentStatus};
     use crate::timestamp::PravegaTimestamp;
     use tracing::{info, trace};
     use rand::{RngCore, SeedableRng};
@@ -289,4 +945,305 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_event_reader_versions() {
+        let payload = [1, 2, 3, 4];
+        let timestamp_bytes = 1_600_000_000_000_000_000u64.to_be_bytes();
+        // Version 0 event.
+        let mut v0 = vec![0, 0, 0, 0, 0, 0, 0, 16, 0, 0, 0, 0b110];
+        v0.extend_from_slice(&timestamp_bytes);
+        v0.extend_from_slice(&payload);
+        // Version 1 event with a 3-byte header extension and an unknown flag.
+        let mut v1 = vec![0, 0, 0, 0, 0, 0, 0, 19, 1, 0, 3, 0b10000110];
+        v1.extend_from_slice(&timestamp_bytes);
+        v1.extend_from_slice(&[0xff, 0xff, 0xff]);
+        v1.extend_from_slice(&payload);
+        for bytes in [v0, v1].iter() {
+            let mut cursor = Cursor::new(bytes);
+            let mut event_reader = EventReader::new();
+            let required_buffer_length = event_reader.read_required_buffer_length(&mut cursor).unwrap();
+            let mut read_buffer: Vec<u8> = vec![0; required_buffer_length];
+            let event = event_reader.read_event(&mut cursor, &mut read_buffer[..]).unwrap();
+            assert_eq!(event, EventWithHeader::new(
+                &payload[..],
+                PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000)),
+                false, true, true));
+        }
+        // Unsupported version.
+        let mut v2 = vec![0, 0, 0, 0, 0, 0, 0, 12, 2, 0, 0, 0];
+        v2.extend_from_slice(&timestamp_bytes);
+        let mut cursor = Cursor::new(v2);
+        let mut event_reader = EventReader::new();
+        let required_buffer_length = event_reader.read_required_buffer_length(&mut cursor).unwrap();
+        let mut read_buffer: Vec<u8> = vec![0; required_buffer_length];
+        let result = event_reader.read_event(&mut cursor, &mut read_buffer[..]).map_err(|e| e.kind());
+        assert_eq!(result, Err(ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn test_event_checksum() {
+        let payload: Vec<u8> = (0..200).map(|i| i as u8).collect();
+        let event = EventWithHeader::new(
+            &payload[..],
+            PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000)),
+            true, true, false);
+        let mut serialized_bytes_cursor = Cursor::new(Vec::new());
+        let mut event_writer = EventWriter::with_checksum(true);
+        assert_eq!(event_writer.max_payload_size(), EventWithHeader::max_payload_size() - 4);
+        event_writer.write(&event, &mut serialized_bytes_cursor).unwrap();
+        let serialized_bytes = serialized_bytes_cursor.into_inner();
+        assert_eq!(serialized_bytes.len(), 24 + payload.len());
+
+        // Read valid event.
+        let mut cursor = Cursor::new(&serialized_bytes);
+        let mut event_reader = EventReader::new();
+        let required_buffer_length = event_reader.read_required_buffer_length(&mut cursor).unwrap();
+        let mut read_buffer: Vec<u8> = vec![0; required_buffer_length];
+        let deserialized_event = event_reader.read_event(&mut cursor, &mut read_buffer[..]).unwrap();
+        assert_eq!(event, deserialized_event);
+        assert_eq!(event_reader.checksum_status(), ChecksumStatus::Valid);
+
+        // Corrupt each of the timestamp, checksum, and payload.
+        for corrupt_offset in [15, 21, 100].iter() {
+            let mut corrupted_bytes = serialized_bytes.clone();
+            corrupted_bytes[*corrupt_offset] ^= 0x01;
+            let mut cursor = Cursor::new(&corrupted_bytes);
+            let required_buffer_length = event_reader.read_required_buffer_length(&mut cursor).unwrap();
+            let mut read_buffer: Vec<u8> = vec![0; required_buffer_length];
+            event_reader.read_event(&mut cursor, &mut read_buffer[..]).unwrap();
+            assert!(matches!(event_reader.checksum_status(), ChecksumStatus::Invalid { .. }));
+        }
+
+        // Events without a checksum.
+        let mut serialized_bytes_cursor = Cursor::new(Vec::new());
+        EventWriter::new().write(&event, &mut serialized_bytes_cursor).unwrap();
+        serialized_bytes_cursor.set_position(0);
+        let required_buffer_length = event_reader.read_required_buffer_length(&mut serialized_bytes_cursor).unwrap();
+        let mut read_buffer: Vec<u8> = vec![0; required_buffer_length];
+        let deserialized_event = event_reader.read_event(&mut serialized_bytes_cursor, &mut read_buffer[..]).unwrap();
+        assert_eq!(event, deserialized_event);
+        assert_eq!(event_reader.checksum_status(), ChecksumStatus::NotPresent);
+    }
+
+    #[test]
+    fn test_event_buffer_metadata() {
+        let payload: Vec<u8> = (0..200).map(|i| i as u8).collect();
+        let mut event = EventWithHeader::new(
+            &payload[..],
+            PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000)),
+            true, false, false);
+        let buffer_metadata = BufferMetadata {
+            dts: PravegaTimestamp::from_nanoseconds(Some(1_599_999_999_966_666_667)),
+            duration: Some(33_333_333),
+            header: false,
+            gap: false,
+            droppable: true,
+            marker: true,
+        };
+        event.header.buffer_metadata = Some(buffer_metadata);
+        for checksum in [false, true].iter() {
+            let mut serialized_bytes_cursor = Cursor::new(Vec::new());
+            let mut event_writer = EventWriter::with_checksum(*checksum).with_buffer_metadata(true);
+            let extension_length = if *checksum { 24 } else { 20 };
+            assert_eq!(event_writer.max_payload_size(), EventWithHeader::max_payload_size() - extension_length);
+            event_writer.write(&event, &mut serialized_bytes_cursor).unwrap();
+            // An event without buffer metadata.
+            let mut event_without_metadata = EventWithHeader::new(&payload[..], event.header.timestamp, false, false, false);
+            event_writer.write(&event_without_metadata, &mut serialized_bytes_cursor).unwrap();
+            serialized_bytes_cursor.set_position(0);
+            let mut event_reader = EventReader::new();
+            let required_buffer_length = event_reader.read_required_buffer_length(&mut serialized_bytes_cursor).unwrap();
+            assert_eq!(required_buffer_length, 20 + extension_length + payload.len());
+            let mut read_buffer: Vec<u8> = vec![0; required_buffer_length];
+            let deserialized_event = event_reader.read_event(&mut serialized_bytes_cursor, &mut read_buffer[..]).unwrap();
+            assert_eq!(event, deserialized_event);
+            if *checksum {
+                assert_eq!(event_reader.checksum_status(), ChecksumStatus::Valid);
+            }
+            let required_buffer_length = event_reader.read_required_buffer_length(&mut serialized_bytes_cursor).unwrap();
+            let mut read_buffer: Vec<u8> = vec![0; required_buffer_length];
+            let deserialized_event = event_reader.read_event(&mut serialized_bytes_cursor, &mut read_buffer[..]).unwrap();
+            assert_eq!(event_without_metadata, deserialized_event);
+            // Buffer metadata is ignored by a writer without buffer metadata enabled.
+            event_without_metadata.header.buffer_metadata = Some(BufferMetadata::default());
+            let mut serialized_bytes_cursor = Cursor::new(Vec::new());
+            EventWriter::with_checksum(*checksum).write(&event_without_metadata, &mut serialized_bytes_cursor).unwrap();
+            assert_eq!(serialized_bytes_cursor.into_inner().len(), 20 + extension_length - 20 + payload.len());
+        }
+    }
+
+    #[test]
+    fn test_event_batch() {
+        let timestamp = PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000));
+        let payload: Vec<u8> = (0..188 * 3 + 10).map(|i| i as u8).collect();
+        let buffer_lengths = vec![188, 188, 188, 10];
+        let mut event = EventWithHeader::new_batch(&payload[..], buffer_lengths.clone(), timestamp, true, true, false);
+        // A batch always has buffer metadata.
+        event.header.buffer_metadata = Some(BufferMetadata::default());
+        for checksum in [false, true].iter() {
+            let mut serialized_bytes_cursor = Cursor::new(Vec::new());
+            let mut event_writer = EventWriter::with_checksum(*checksum);
+            event_writer.write(&event, &mut serialized_bytes_cursor).unwrap();
+            let extension_length = if *checksum { 4 } else { 0 } + 20 + 4 + 4 * buffer_lengths.len();
+            serialized_bytes_cursor.set_position(0);
+            let mut event_reader = EventReader::new();
+            let required_buffer_length = event_reader.read_required_buffer_length(&mut serialized_bytes_cursor).unwrap();
+            assert_eq!(required_buffer_length, 20 + extension_length + payload.len());
+            let mut read_buffer: Vec<u8> = vec![0; required_buffer_length];
+            let deserialized_event = event_reader.read_event(&mut serialized_bytes_cursor, &mut read_buffer[..]).unwrap();
+            assert_eq!(event, deserialized_event);
+            let buffers = deserialized_event.buffers();
+            assert_eq!(buffers.len(), 4);
+            assert_eq!(buffers[1], &payload[188..376]);
+            assert_eq!(buffers[3], &payload[564..574]);
+            if *checksum {
+                assert_eq!(event_reader.checksum_status(), ChecksumStatus::Valid);
+            }
+        }
+        // The buffer lengths must add up to the payload length.
+        let invalid_event = EventWithHeader::new_batch(&payload[..], vec![188, 188], timestamp, true, true, false);
+        let result = EventWriter::new().write(&invalid_event, &mut Cursor::new(Vec::new())).map_err(|e| e.kind());
+        assert_eq!(result, Err(ErrorKind::InvalidInput));
+        // An event that is not a batch has a single buffer.
+        let event = EventWithHeader::new(&payload[..], timestamp, true, true, false);
+        assert_eq!(event.buffers(), vec![&payload[..]]);
+    }
+
+    #[test]
+    fn test_event_reader_resync() {
+        let timestamp = PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000));
+        let payloads: Vec<Vec<u8>> = (0..3).map(|i| vec![i as u8; 100]).collect();
+        let mut serialized_bytes_cursor = Cursor::new(Vec::new());
+        let mut event_offsets = Vec::new();
+        let mut event_writer = EventWriter::with_checksum(true);
+        for payload in payloads.iter() {
+            event_offsets.push(serialized_bytes_cursor.position());
+            let event = EventWithHeader::new(&payload[..], timestamp, true, true, false);
+            event_writer.write(&event, &mut serialized_bytes_cursor).unwrap();
+        }
+        let mut serialized_bytes = serialized_bytes_cursor.into_inner();
+        // Corrupt the event length of the second event.
+        serialized_bytes[event_offsets[1] as usize + 5] = 0xff;
+        let mut cursor = Cursor::new(&serialized_bytes);
+        let mut event_reader = EventReader::new().with_header_validation(true);
+        let required_buffer_length = event_reader.read_required_buffer_length(&mut cursor).unwrap();
+        let mut read_buffer: Vec<u8> = vec![0; required_buffer_length];
+        let event = event_reader.read_event(&mut cursor, &mut read_buffer[..]).unwrap();
+        assert_eq!(event.payload, &payloads[0][..]);
+        assert_eq!(event_reader.checksum_status(), ChecksumStatus::Valid);
+        let result = event_reader.read_required_buffer_length(&mut cursor).map_err(|e| e.kind());
+        assert_eq!(result, Err(ErrorKind::InvalidData));
+        // Only the fixed header was read.
+        assert_eq!(cursor.position(), event_offsets[1] + EventWithHeader::HEADER_LENGTH as u64);
+        // Scanning forward should find the third event.
+        let offset = event_reader.scan_for_header(&mut cursor, event_offsets[1] + 1).unwrap();
+        assert_eq!(offset, event_offsets[2]);
+        assert_eq!(cursor.position(), event_offsets[2]);
+        let required_buffer_length = event_reader.read_required_buffer_length(&mut cursor).unwrap();
+        let mut read_buffer: Vec<u8> = vec![0; required_buffer_length];
+        let event = event_reader.read_event(&mut cursor, &mut read_buffer[..]).unwrap();
+        assert_eq!(event.payload, &payloads[2][..]);
+        assert_eq!(event_reader.checksum_status(), ChecksumStatus::Valid);
+        // There are no valid headers after the third event.
+        let result = event_reader.scan_for_header(&mut cursor, event_offsets[2] + 1).map_err(|e| e.kind());
+        assert_eq!(result, Err(ErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn test_fragment_reassembler() {
+        let timestamp = PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000));
+        let payload: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        // Write a standalone event, a chain of 3 fragments, a truncated chain, and a standalone event.
+        let mut serialized_bytes_cursor = Cursor::new(Vec::new());
+        let mut event_writer = EventWriter::new();
+        let events = [
+            EventWithHeader::new(&payload[0..10], timestamp, true, true, false),
+            EventWithHeader::new_fragment(&payload[0..100], timestamp, true, true, false, false, true),
+            EventWithHeader::new_fragment(&payload[100..200], timestamp, false, false, false, true, true),
+            EventWithHeader::new_fragment(&payload[200..300], timestamp, false, false, false, true, false),
+            EventWithHeader::new_fragment(&payload[0..100], timestamp, true, true, false, false, true),
+            EventWithHeader::new(&payload[0..20], timestamp, true, true, true),
+        ];
+        for event in events.iter() {
+            event_writer.write(event, &mut serialized_bytes_cursor).unwrap();
+        }
+        serialized_bytes_cursor.set_position(0);
+
+        let mut event_reader = EventReader::new();
+        let mut reassembler = FragmentReassembler::new();
+        let mut statuses = Vec::new();
+        let mut truncated = Vec::new();
+        let mut buffers = Vec::new();
+        for _ in 0..events.len() {
+            let required_buffer_length = event_reader.read_required_buffer_length(&mut serialized_bytes_cursor).unwrap();
+            let mut read_buffer: Vec<u8> = vec![0; required_buffer_length];
+            let event = event_reader.read_event(&mut serialized_bytes_cursor, &mut read_buffer[..]).unwrap();
+            if reassembler.is_incomplete() && !event.header.continuation {
+                truncated.push(reassembler.reset());
+            }
+            let status = reassembler.push(&event).unwrap();
+            statuses.push(status);
+            match status {
+                FragmentStatus::NotFragmented => buffers.push((event.header, event.payload.to_vec())),
+                FragmentStatus::Complete => buffers.push(reassembler.take()),
+                _ => {},
+            }
+        }
+        assert_eq!(statuses, vec![
+            FragmentStatus::NotFragmented,
+            FragmentStatus::Incomplete,
+            FragmentStatus::Incomplete,
+            FragmentStatus::Complete,
+            FragmentStatus::Incomplete,
+            FragmentStatus::NotFragmented,
+        ]);
+        assert_eq!(truncated, vec![100]);
+        assert_eq!(buffers.len(), 3);
+        assert_eq!(buffers[0].1, &payload[0..10]);
+        assert_eq!(buffers[1].0, EventWithHeader::new(&[], timestamp, true, true, false).header);
+        assert_eq!(buffers[1].1, payload);
+        assert_eq!(buffers[2].1, &payload[0..20]);
+        assert!(buffers[2].0.discontinuity);
+
+        // A continuation without a first fragment is an orphan.
+        let mut reassembler = FragmentReassembler::new();
+        assert_eq!(reassembler.push(&events[3]).unwrap(), FragmentStatus::Orphan);
+        // Adding a new chain to an incomplete chain without calling reset() is an error.
+        assert_eq!(reassembler.push(&events[1]).unwrap(), FragmentStatus::Incomplete);
+        assert_eq!(reassembler.push(&events[4]).map_err(|e| e.kind()), Err(ErrorKind::InvalidInput));
+
+        // A chain that exceeds the maximum size is an error. After reset(), the remaining fragments are orphans.
+        let mut reassembler = FragmentReassembler::with_max_size(150);
+        assert_eq!(reassembler.push(&events[1]).unwrap(), FragmentStatus::Incomplete);
+        assert_eq!(reassembler.push(&events[2]).map_err(|e| e.kind()), Err(ErrorKind::InvalidData));
+        assert_eq!(reassembler.fragment_count(), 1);
+        assert_eq!(reassembler.reset(), 100);
+        assert_eq!(reassembler.push(&events[3]).unwrap(), FragmentStatus::Orphan);
+        assert_eq!(reassembler.push(&events[5]).unwrap(), FragmentStatus::NotFragmented);
+    }
+
+    #[test]
+    fn test_event_reader_validate_header() {
+        let mut header = vec![0, 0, 0, 0, 0, 0, 0, 16, 1, 0, 4, 0b1011];
+        header.extend_from_slice(&1_600_000_000_000_000_000u64.to_be_bytes());
+        assert!(EventReader::validate_header(&header).is_ok());
+        assert_eq!(EventReader::validate_header(&header[..19]).map_err(|e| e.kind()), Err(ErrorKind::UnexpectedEof));
+        // Each of these corruptions must be detected.
+        let corruptions: [(usize, u8); 7] = [
+            (0, 1),             // type code
+            (4, 1),             // event length too large
+            (7, 8),             // event length too small
+            (8, 2),             // unsupported version
+            (10, 13),           // extension length exceeds event length
+            (11, 0b10001011),   // unknown flag
+            (12, 0xff),         // implausible timestamp
+        ];
+        for (offset, value) in corruptions.iter() {
+            let mut corrupted_header = header.clone();
+            corrupted_header[*offset] = *value;
+            let result = EventReader::validate_header(&corrupted_header).map_err(|e| e.kind());
+            assert_eq!(result, Err(ErrorKind::InvalidData), "offset={}, value={}", offset, value);
+        }
+    }
 }
@@ -10,7 +10,7 @@
 
 // Module for writing and reading an index in a Pravega stream.
 
-use crate::event_serde::EventHeaderFlags;
+use crate::event_serde::{EventHeader, EventHeaderFlags, EventReader};
 use crate::timestamp::PravegaTimestamp;
 use crate::utils::CurrentHead;
 use enumflags2::BitFlags;
@@ -33,6 +33,8 @@ pub struct IndexRecord {
 
 impl IndexRecord {
     pub const RECORD_SIZE: usize = 20;
+    /// The version of the index record format written by IndexRecordWriter.
+    pub const VERSION: u8 = 1;
 
     pub fn new(timestamp: PravegaTimestamp, offset: u64,
                random_access: bool, discontinuity: bool) -> Self {
@@ -53,9 +55,9 @@ impl IndexRecord {
     0                   1                   2                   3
     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-   |                                                         |D|R|R|
-   |                    reserved (set to 0)                  |I|A|E|
-   |                                                         |S|N|S|
+   |               |                                         |D|R|R|
+   |    version    |           reserved (set to 0)           |I|A|E|
+   |               |                                         |S|N|S|
    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
    |                                                               |
    |                                                               |
@@ -76,9 +78,18 @@ impl IndexRecord {
 
    One tick mark represents one bit position.
 
+   version:
+      The version of the index record format.
+      0: The original format, written before the version field was defined.
+      1: Identical to version 0 except that the version field is set.
+      Records with any other version cannot be read by this implementation and
+      IndexRecordReader will return an InvalidData error.
+      Because the index is searched using a fixed record size, all records in an index
+      must have the same size. A future version that changes the record size must use a new index stream.
+      A header record at the start of the index stream is not used because it would be removed when the index is truncated.
    reserved, RES:
-      All reserved bits must be 0.
-      These may be utilized in the future for other purposes.
+      All reserved bits must be written as 0.
+      Readers must ignore reserved bits so that they may be utilized in the future for other purposes.
    DIS - discontinuity indicator
    RAN - random access indicator
    timestamp:
@@ -128,6 +139,7 @@ impl IndexRecordWriter {
             return Err(Error::new(ErrorKind::InvalidInput, "Timestamp is none or 0"));
         }
         let mut bytes_to_write: Vec<u8> = vec![0; IndexRecord::RECORD_SIZE];
+        bytes_to_write[0] = IndexRecord::VERSION;
         bytes_to_write[3..4].copy_from_slice(&flags.bits().to_be_bytes()[..]);
         bytes_to_write[4..12].copy_from_slice(&timestamp_nanos.to_be_bytes()[..]);
         bytes_to_write[12..20].copy_from_slice(&record.offset.to_be_bytes()[..]);
@@ -151,7 +163,12 @@ impl IndexRecordReader {
     {
         let mut buffer: Vec<u8> = vec![0; IndexRecord::RECORD_SIZE];
         rdr.read_exact(&mut buffer[..])?;
-        let flags = BitFlags::<EventHeaderFlags>::from_bits(buffer[3]).unwrap();
+        let version = buffer[0];
+        if version > IndexRecord::VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Unsupported index record version {}", version)));
+        }
+        // Unknown flags are ignored.
+        let flags = BitFlags::<EventHeaderFlags>::from_bits_truncate(buffer[3]);
         let random_access = flags.contains(EventHeaderFlags::RandomAccessIndicator);
         let discontinuity = flags.contains(EventHeaderFlags::DiscontinuityIndicator);
         let timestamp = u64::from_be_bytes(buffer[4..12].try_into().unwrap());
@@ -182,7 +199,32 @@ pub enum SearchMethod {
     After,
 }
 
+/// Determines which index records are considered by the search methods of IndexSearcher.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SearchFilter {
+    /// Consider only index records with random_access=true.
+    /// Decoding can begin at these records.
+    RandomAccess,
+    /// Consider index records with random_access=true or discontinuity=true.
+    IncludeDiscontinuities,
+    /// Consider all index records, including forced delta unit records and the final record written at end-of-stream.
+    Any,
+}
+
+impl SearchFilter {
+    pub fn matches(&self, record: &IndexRecord) -> bool {
+        match self {
+            SearchFilter::RandomAccess => record.random_access,
+            SearchFilter::IncludeDiscontinuities => record.random_access || record.discontinuity,
+            SearchFilter::Any => true,
+        }
+    }
+}
+
 impl<R: Read + Seek + CurrentHead> IndexSearcher<R> {
+    // The number of index records read at a time when searching backwards.
+    const BACKWARDS_BLOCK_RECORDS: u64 = 256;
+
     pub fn new(reader: R) -> Self {
         Self {
             reader: BufReader::with_capacity(8*1024, reader),
@@ -196,17 +238,17 @@ impl<R: Read + Seek + CurrentHead> IndexSearcher<R> {
     /// If the desired size exceeds offset difference between the first and last index record in the index, returns the first index record.
     /// If the index has no records, returns an UnexpectedEof error.
     /// Otherwise, it uses the specified SearchMethod.
-    /// TODO: Add flag to not consider records with random_access=false.
+    /// If the located index record does not match the filter, the nearest matching record is returned instead.
     /// TODO: Make this method private.
-    pub fn search_size_and_return_index_offset(&mut self, size_bytes: u64, method: SearchMethod)
+    pub fn search_size_and_return_index_offset(&mut self, size_bytes: u64, method: SearchMethod, filter: SearchFilter)
             -> Result<(IndexRecord, u64), Error> {
 
         let result = (|| {
             let mut index_record_reader = IndexRecordReader::new();
 
-            let first_index_offset = self.reader.get_ref().current_head()?;
+            let head_index_offset = self.reader.get_ref().current_head()?;
             let tail_offset = self.reader.seek(SeekFrom::End(0))?;
-            if tail_offset < first_index_offset + IndexRecord::RECORD_SIZE as u64 {
+            if tail_offset < head_index_offset + IndexRecord::RECORD_SIZE as u64 {
                 return Err(Error::new(ErrorKind::UnexpectedEof, "Index has no records"));
             }
 
@@ -215,12 +257,12 @@ impl<R: Read + Seek + CurrentHead> IndexSearcher<R> {
             let tail_index_record = index_record_reader.read(&mut self.reader)?;
 
             // Read first record.
-            let mut first_index_offset = self.reader.seek(SeekFrom::Start(first_index_offset))?;
+            let mut first_index_offset = self.reader.seek(SeekFrom::Start(head_index_offset))?;
             let first_index_record = index_record_reader.read(&mut self.reader)?;
 
             // Return first record if desired size is larger or equal to it.
             if tail_index_record.offset - first_index_record.offset <= size_bytes {
-                return Ok((first_index_record, first_index_offset));
+                return self.find_matching_record(first_index_offset, head_index_offset, tail_offset, &method, filter);
             }
 
             // Use binary search algorithm
@@ -234,27 +276,19 @@ impl<R: Read + Seek + CurrentHead> IndexSearcher<R> {
                 } else if size_bytes < tail_index_record.offset - middle_index_record.offset {
                     first_index_offset = middle_index_offset + IndexRecord::RECORD_SIZE as u64;
                 } else {
-                    return Ok((middle_index_record, middle_index_offset));
+                    return self.find_matching_record(middle_index_offset, head_index_offset, tail_offset, &method, filter);
                 }
                 if first_index_offset > last_index_offset {
                     break;
                 }
             }
-            
-            return match method {
-                SearchMethod::Before => {
-                    self.reader.seek(SeekFrom::Start(last_index_offset))?;
-                    let last_index_record = index_record_reader.read(&mut self.reader)?;
-                    Ok((last_index_record, last_index_offset))
-                },
-                SearchMethod::After => {
-                    self.reader.seek(SeekFrom::Start(first_index_offset))?;
-                    let first_index_record = index_record_reader.read(&mut self.reader)?;
-                    Ok((first_index_record, first_index_offset))
-                },
+
+            match method {
+                SearchMethod::Before => self.find_matching_record(last_index_offset, head_index_offset, tail_offset, &method, filter),
+                SearchMethod::After => self.find_matching_record(first_index_offset, head_index_offset, tail_offset, &method, filter),
             }
         })();
-        debug!("IndexSearcher::search_size_and_return_index_offset({}, {:?}) = {:?}", size_bytes, method, result);
+        debug!("IndexSearcher::search_size_and_return_index_offset({}, {:?}, {:?}) = {:?}", size_bytes, method, filter, result);
         result
     }
 
@@ -265,35 +299,36 @@ impl<R: Read + Seek + CurrentHead> IndexSearcher<R> {
     /// If the desired timestamp exceeds the first and last timestamp in the index, returns the nearest index record.
     /// If the index has no records, returns an UnexpectedEof error.
     /// Otherwise, it uses the specified SearchMethod.
-    /// TODO: Add flag to not consider records with random_access=false.
+    /// If the located index record does not match the filter, the nearest matching record is returned instead.
+    /// If no index records match the filter, returns a NotFound error.
     /// TODO: Make this method private.
-    pub fn search_timestamp_and_return_index_offset(&mut self, timestamp: PravegaTimestamp, method: SearchMethod)
+    pub fn search_timestamp_and_return_index_offset(&mut self, timestamp: PravegaTimestamp, method: SearchMethod, filter: SearchFilter)
             -> Result<(IndexRecord, u64), Error> {
 
         let result = (|| {
             let mut index_record_reader = IndexRecordReader::new();
 
-            let first_index_offset = self.reader.get_ref().current_head()?;
+            let head_index_offset = self.reader.get_ref().current_head()?;
             let tail_offset = self.reader.seek(SeekFrom::End(0))?;
-            if tail_offset < first_index_offset + IndexRecord::RECORD_SIZE as u64 {
+            if tail_offset < head_index_offset + IndexRecord::RECORD_SIZE as u64 {
                 return Err(Error::new(ErrorKind::UnexpectedEof, "Index has no records"));
             }
 
             // Get last record.
             let mut last_index_offset = self.reader.seek(SeekFrom::Start(tail_offset - IndexRecord::RECORD_SIZE as u64))?;
             // TODO: Below may fail due to https://github.com/pravega/pravega-client-rust/issues/163.
-            let mut last_index_record = index_record_reader.read(&mut self.reader)?;
+            let last_index_record = index_record_reader.read(&mut self.reader)?;
             // Return last record if desired timestamp is after or equal to it.
             if last_index_record.timestamp <= timestamp {
-                return Ok((last_index_record, last_index_offset));
+                return self.find_matching_record(last_index_offset, head_index_offset, tail_offset, &method, filter);
             }
 
             // Read first record.
-            let mut first_index_offset = self.reader.seek(SeekFrom::Start(first_index_offset))?;
-            let mut first_index_record = index_record_reader.read(&mut self.reader)?;
+            let mut first_index_offset = self.reader.seek(SeekFrom::Start(head_index_offset))?;
+            let first_index_record = index_record_reader.read(&mut self.reader)?;
             // Return first record if desired timestamp is before or equal to it.
             if timestamp <= first_index_record.timestamp {
-                return Ok((first_index_record, first_index_offset));
+                return self.find_matching_record(first_index_offset, head_index_offset, tail_offset, &method, filter);
             }
 
             // Use binary search algorithm
@@ -307,58 +342,198 @@ impl<R: Read + Seek + CurrentHead> IndexSearcher<R> {
                 } else if timestamp > middle_index_record.timestamp {
                     first_index_offset = middle_index_offset + IndexRecord::RECORD_SIZE as u64;
                 } else {
-                    return Ok((middle_index_record, middle_index_offset));
+                    return self.find_matching_record(middle_index_offset, head_index_offset, tail_offset, &method, filter);
                 }
                 if first_index_offset > last_index_offset {
                     break;
                 }
             }
-            
-            return match method {
-                SearchMethod::Before => {
-                    self.reader.seek(SeekFrom::Start(last_index_offset))?;
-                    last_index_record = index_record_reader.read(&mut self.reader)?;
-                    Ok((last_index_record, last_index_offset))
-                },
-                SearchMethod::After => {
-                    self.reader.seek(SeekFrom::Start(first_index_offset))?;
-                    first_index_record = index_record_reader.read(&mut self.reader)?;
-                    Ok((first_index_record, first_index_offset))
-                },
+
+            match method {
+                SearchMethod::Before => self.find_matching_record(last_index_offset, head_index_offset, tail_offset, &method, filter),
+                SearchMethod::After => self.find_matching_record(first_index_offset, head_index_offset, tail_offset, &method, filter),
             }
         })();
-        debug!("IndexSearcher::search_timestamp_and_return_index_offset({}, {:?}) = {:?}", timestamp, method, result);
+        debug!("IndexSearcher::search_timestamp_and_return_index_offset({}, {:?}, {:?}) = {:?}", timestamp, method, filter, result);
         result
     }
 
+    /// Starting at the index record at index_offset, returns the nearest index record that matches the filter.
+    /// Records are first considered in the direction of the SearchMethod (backwards for Before, forwards for After).
+    /// If there is no matching record in that direction, records in the opposite direction are considered.
+    /// If no index records match the filter, returns a NotFound error.
+    fn find_matching_record(&mut self, index_offset: u64, head_index_offset: u64, tail_offset: u64,
+            method: &SearchMethod, filter: SearchFilter) -> Result<(IndexRecord, u64), Error> {
+        let record_size = IndexRecord::RECORD_SIZE as u64;
+        let mut index_record_reader = IndexRecordReader::new();
+        let backwards_first = matches!(method, SearchMethod::Before);
+        for (i, &backwards) in [backwards_first, !backwards_first].iter().enumerate() {
+            // On the second pass, do not reconsider the starting record.
+            let found = if backwards {
+                let end_offset = if i == 0 { index_offset + record_size } else { index_offset };
+                self.find_matching_record_backwards(end_offset, head_index_offset, filter)?
+            } else {
+                let mut offset = if i == 0 {
+                    Some(index_offset)
+                } else {
+                    Some(index_offset + record_size).filter(|&o| o + record_size <= tail_offset)
+                };
+                if let Some(start_offset) = offset {
+                    self.reader.seek(SeekFrom::Start(start_offset))?;
+                }
+                let mut found = None;
+                while let Some(current_offset) = offset {
+                    let index_record = index_record_reader.read(&mut self.reader)?;
+                    if filter.matches(&index_record) {
+                        found = Some((index_record, current_offset));
+                        break;
+                    }
+                    trace!("IndexSearcher::find_matching_record: skipping index_record={:?}", index_record);
+                    // The reader is already positioned at the next record.
+                    offset = Some(current_offset + record_size).filter(|&o| o + record_size <= tail_offset);
+                }
+                found
+            };
+            if let Some(found) = found {
+                return Ok(found);
+            }
+        }
+        Err(Error::new(ErrorKind::NotFound, format!("Index has no records matching filter {:?}", filter)))
+    }
+
+    /// Returns the last index record before end_offset that matches the filter, or None if there is none.
+    /// To avoid a seek for each record, records are read in blocks, starting with the block that ends at end_offset.
+    fn find_matching_record_backwards(&mut self, end_offset: u64, head_index_offset: u64, filter: SearchFilter)
+            -> Result<Option<(IndexRecord, u64)>, Error> {
+        let record_size = IndexRecord::RECORD_SIZE as u64;
+        let mut index_record_reader = IndexRecordReader::new();
+        let mut block = Vec::new();
+        let mut end_offset = end_offset;
+        while end_offset >= head_index_offset + record_size {
+            let block_records = u64::min(Self::BACKWARDS_BLOCK_RECORDS, (end_offset - head_index_offset) / record_size);
+            let block_offset = end_offset - block_records * record_size;
+            block.resize((block_records * record_size) as usize, 0);
+            self.reader.seek(SeekFrom::Start(block_offset))?;
+            self.reader.read_exact(&mut block[..])?;
+            for (i, record_bytes) in block.chunks_exact(IndexRecord::RECORD_SIZE).enumerate().rev() {
+                let index_record = index_record_reader.read(&mut &record_bytes[..])?;
+                if filter.matches(&index_record) {
+                    return Ok(Some((index_record, block_offset + i as u64 * record_size)));
+                }
+                trace!("IndexSearcher::find_matching_record_backwards: skipping index_record={:?}", index_record);
+            }
+            end_offset = block_offset;
+        }
+        Ok(None)
+    }
+
     /// If a non-exact match is found, return the index record immediately before the desired timestamp.
-    /// This is expected to be used to determine the offset at which to start reading.
-    /// TODO: This should only consider index records with random_access=true.
-    pub fn search_timestamp(&mut self, timestamp: PravegaTimestamp) -> Result<IndexRecord, Error> {
-        let result = self.search_timestamp_and_return_index_offset(timestamp, SearchMethod::Before);
-        debug!("IndexSearcher::search_timestamp({}) = {:?}", timestamp, result);
+    /// This is expected to be used with SearchFilter::RandomAccess to determine the offset at which to start reading.
+    pub fn search_timestamp(&mut self, timestamp: PravegaTimestamp, filter: SearchFilter) -> Result<IndexRecord, Error> {
+        let result = self.search_timestamp_and_return_index_offset(timestamp, SearchMethod::Before, filter);
+        debug!("IndexSearcher::search_timestamp({}, {:?}) = {:?}", timestamp, filter, result);
         result.map(|x| x.0)
     }
 
     /// If a non-exact match is found, return the index record immediately after the desired timestamp.
-    /// This will consider any index record, including those with random_access=false.
-    /// This is expected to be used to determine the offset at which to stop reading.
-    pub fn search_timestamp_after(&mut self, timestamp: PravegaTimestamp) -> Result<IndexRecord, Error> {
-        let result = self.search_timestamp_and_return_index_offset(timestamp, SearchMethod::After);
-        debug!("IndexSearcher::search_timestamp_after({}) = {:?}", timestamp, result);
+    /// This is expected to be used with SearchFilter::Any to determine the offset at which to stop reading.
+    pub fn search_timestamp_after(&mut self, timestamp: PravegaTimestamp, filter: SearchFilter) -> Result<IndexRecord, Error> {
+        let result = self.search_timestamp_and_return_index_offset(timestamp, SearchMethod::After, filter);
+        debug!("IndexSearcher::search_timestamp_after({}, {:?}) = {:?}", timestamp, filter, result);
         result.map(|x| x.0)
     }
 
-    /// This is expected to be used to determine the offset at which to start reading.
-    /// TODO: This should only consider index records with random_access=true.
-    pub fn get_first_record(&mut self) -> Result<IndexRecord, Error> {
-        self.search_timestamp(PravegaTimestamp::MIN)
+    /// Returns the first index record that matches the filter.
+    /// This is expected to be used with SearchFilter::RandomAccess to determine the offset at which to start reading.
+    pub fn get_first_record(&mut self, filter: SearchFilter) -> Result<IndexRecord, Error> {
+        self.search_timestamp_after(PravegaTimestamp::MIN, filter)
+    }
+
+    /// Returns the last index record that matches the filter.
+    /// With SearchFilter::RandomAccess, this is the last position at which reading can start.
+    /// With SearchFilter::Any, this is the end of the indexed data.
+    pub fn get_last_record(&mut self, filter: SearchFilter) -> Result<IndexRecord, Error> {
+        self.search_timestamp(PravegaTimestamp::MAX, filter)
+    }
+
+    /// Returns the first index record that matches the filter and has a data stream offset greater than the specified offset.
+    /// This is expected to be used with SearchFilter::RandomAccess to find where to resume reading after corrupt data.
+    /// If there is no such index record, returns a NotFound error.
+    pub fn search_offset_after(&mut self, offset: u64, filter: SearchFilter) -> Result<IndexRecord, Error> {
+        let result = (|| {
+            let record_size = IndexRecord::RECORD_SIZE as u64;
+            let mut index_record_reader = IndexRecordReader::new();
+            let head_index_offset = self.reader.get_ref().current_head()?;
+            let tail_offset = self.reader.seek(SeekFrom::End(0))?;
+            // Use binary search algorithm to find the first record with an offset greater than the desired offset.
+            // Records are numbered from first_index (inclusive) to last_index (exclusive).
+            let mut first_index = head_index_offset / record_size;
+            let mut last_index = tail_offset / record_size;
+            while first_index < last_index {
+                let middle_index = first_index + (last_index - first_index) / 2;
+                self.reader.seek(SeekFrom::Start(middle_index * record_size))?;
+                let middle_index_record = index_record_reader.read(&mut self.reader)?;
+                trace!("IndexSearcher::search_offset_after: index_record={:?}", middle_index_record);
+                if middle_index_record.offset > offset {
+                    last_index = middle_index;
+                } else {
+                    first_index = middle_index + 1;
+                }
+            }
+            // Read forward until a record matches the filter.
+            let mut index_offset = self.reader.seek(SeekFrom::Start(first_index * record_size))?;
+            while index_offset + record_size <= tail_offset {
+                let index_record = index_record_reader.read(&mut self.reader)?;
+                if filter.matches(&index_record) {
+                    return Ok(index_record);
+                }
+                index_offset += record_size;
+            }
+            Err(Error::new(ErrorKind::NotFound, format!("Index has no records after offset {} matching filter {:?}", offset, filter)))
+        })();
+        debug!("IndexSearcher::search_offset_after({}, {:?}) = {:?}", offset, filter, result);
+        result
     }
 
-    /// This is expected to be used to determine the offset at which to start reading.
-    /// TODO: This should only consider index records with random_access=true.
-    pub fn get_last_record(&mut self) -> Result<IndexRecord, Error> {
-        self.search_timestamp(PravegaTimestamp::MAX)
+    /// Returns the last index record that matches the filter and has a data stream offset less than the specified offset.
+    /// This is expected to be used with SearchFilter::RandomAccess to find the previous group of pictures during reverse playback.
+    /// If there is no such index record, returns a NotFound error.
+    pub fn search_offset_before(&mut self, offset: u64, filter: SearchFilter) -> Result<IndexRecord, Error> {
+        let result = (|| {
+            let record_size = IndexRecord::RECORD_SIZE as u64;
+            let mut index_record_reader = IndexRecordReader::new();
+            let head_index_offset = self.reader.get_ref().current_head()?;
+            let tail_offset = self.reader.seek(SeekFrom::End(0))?;
+            // Use binary search algorithm to find the first record with an offset greater than or equal to the desired offset.
+            // Records are numbered from first_index (inclusive) to last_index (exclusive).
+            let head_index = head_index_offset / record_size;
+            let mut first_index = head_index;
+            let mut last_index = tail_offset / record_size;
+            while first_index < last_index {
+                let middle_index = first_index + (last_index - first_index) / 2;
+                self.reader.seek(SeekFrom::Start(middle_index * record_size))?;
+                let middle_index_record = index_record_reader.read(&mut self.reader)?;
+                trace!("IndexSearcher::search_offset_before: index_record={:?}", middle_index_record);
+                if middle_index_record.offset < offset {
+                    first_index = middle_index + 1;
+                } else {
+                    last_index = middle_index;
+                }
+            }
+            // Read backward until a record matches the filter.
+            let mut index = first_index;
+            while index > head_index {
+                index -= 1;
+                self.reader.seek(SeekFrom::Start(index * record_size))?;
+                let index_record = index_record_reader.read(&mut self.reader)?;
+                if filter.matches(&index_record) {
+                    return Ok(index_record);
+                }
+            }
+            Err(Error::new(ErrorKind::NotFound, format!("Index has no records before offset {} matching filter {:?}", offset, filter)))
+        })();
+        debug!("IndexSearcher::search_offset_before({}, {:?}) = {:?}", offset, filter, result);
+        result
     }
 
     /// Unwraps this `IndexSearcher<R>`, returning the underlying reader.
@@ -384,12 +559,158 @@ impl<R: Read + Seek + CurrentHead> IndexSearcher<R> {
     }
 }
 
+/// Decides which events in a data stream should have index records.
+/// This applies the same policy as pravegasink so that an index can be reconstructed from the data stream alone.
+///   - Events without a timestamp are never indexed.
+///   - An event with the include-in-index flag is indexed because pravegasink wrote an index record for it.
+///   - For other events, which may have been written without the flag,
+///     a random access event is indexed if no index record has been created in the last index_min_nanos
+///     and any other event is indexed if no index record has been created in the last index_max_nanos.
+///   - An event with the discontinuity flag is treated like the first event written by a new pravegasink instance.
+///     The previous writer's final index record is created at the event's offset
+///     and the next index record will have the discontinuity flag.
+///   - Continuation fragments are never indexed.
+pub struct IndexBuilder {
+    index_min_nanos: u64,
+    index_max_nanos: u64,
+    first_valid_time: PravegaTimestamp,
+    last_index_time: PravegaTimestamp,
+    // The timestamp + duration of the last event with a timestamp, used for the final index record.
+    final_timestamp: PravegaTimestamp,
+    pending_discontinuity: bool,
+    records_created: u64,
+}
+
+impl IndexBuilder {
+    pub fn new(index_min_nanos: u64, index_max_nanos: u64) -> Self {
+        Self {
+            index_min_nanos,
+            index_max_nanos,
+            first_valid_time: PravegaTimestamp::NONE,
+            last_index_time: PravegaTimestamp::NONE,
+            final_timestamp: PravegaTimestamp::NONE,
+            pending_discontinuity: true,
+            records_created: 0,
+        }
+    }
+
+    /// Returns the number of index records returned by add_event() and finish().
+    pub fn records_created(&self) -> u64 {
+        self.records_created
+    }
+
+    /// Processes the event header read from the data stream at the given offset.
+    /// Returns the index records that should precede this event, in order.
+    pub fn add_event(&mut self, header: &EventHeader, offset: u64) -> Vec<IndexRecord> {
+        let mut records = Vec::new();
+        if header.continuation {
+            return records;
+        }
+        if header.discontinuity {
+            if let Some(record) = self.final_record(offset) {
+                records.push(record);
+            }
+            self.first_valid_time = PravegaTimestamp::NONE;
+            self.last_index_time = PravegaTimestamp::NONE;
+            self.pending_discontinuity = true;
+        }
+        let timestamp = match header.timestamp.nanoseconds() {
+            Some(timestamp) => timestamp,
+            None => return records,
+        };
+        if self.first_valid_time.is_none() {
+            self.first_valid_time = header.timestamp;
+        }
+        // As in pravegasink, the final timestamp must be strictly greater than the timestamp of the last event.
+        // The duration is only known if the event has buffer metadata.
+        let duration = u64::max(1, header.buffer_metadata.and_then(|m| m.duration).unwrap_or_default());
+        self.final_timestamp = PravegaTimestamp::from_nanoseconds(Some(timestamp + duration));
+        let include_in_index = header.include_in_index || match self.last_index_time.nanoseconds() {
+            Some(last_index_time) => {
+                if header.random_access {
+                    timestamp >= last_index_time + self.index_min_nanos
+                } else {
+                    timestamp > last_index_time + self.index_max_nanos
+                }
+            },
+            None => {
+                header.random_access
+                    || timestamp > self.first_valid_time.nanoseconds().unwrap() + self.index_max_nanos
+            },
+        };
+        if include_in_index {
+            let record = IndexRecord::new(header.timestamp, offset, header.random_access, self.pending_discontinuity);
+            trace!("IndexBuilder::add_event: record={:?}", record);
+            records.push(record);
+            self.records_created += 1;
+            self.last_index_time = header.timestamp;
+            self.pending_discontinuity = false;
+        }
+        records
+    }
+
+    /// Returns the final index record, which marks the end of the data at end_offset.
+    /// Returns None if no event had a timestamp.
+    pub fn finish(&mut self, end_offset: u64) -> Option<IndexRecord> {
+        self.final_record(end_offset)
+    }
+
+    fn final_record(&mut self, offset: u64) -> Option<IndexRecord> {
+        if self.final_timestamp.is_none() {
+            return None;
+        }
+        let record = IndexRecord::new(self.final_timestamp, offset, false, false);
+        self.final_timestamp = PravegaTimestamp::NONE;
+        self.records_created += 1;
+        Some(record)
+    }
+}
+
+/// Reconstructs the index of a data stream by reading the events between start_offset and end_offset.
+/// The reader must be positioned at start_offset, which must be the beginning of an event.
+/// Each index record is passed to write_record, in order.
+/// Returns the number of events read.
+pub fn rebuild_index<R, F>(reader: &mut R, start_offset: u64, end_offset: u64, index_builder: &mut IndexBuilder,
+                           mut write_record: F) -> Result<u64, Error>
+where
+    R: Read,
+    F: FnMut(&IndexRecord) -> Result<(), Error>,
+{
+    let mut event_reader = EventReader::new();
+    let mut buffer = Vec::new();
+    let mut offset = start_offset;
+    let mut events_read = 0;
+    while offset < end_offset {
+        let required_buffer_length = event_reader.read_required_buffer_length(reader).map_err(|e| {
+            Error::new(e.kind(), format!("Unable to read event at offset {}: {}", offset, e))
+        })?;
+        if buffer.len() < required_buffer_length {
+            buffer.resize(required_buffer_length, 0);
+        }
+        let event = event_reader.read_event(reader, &mut buffer[..]).map_err(|e| {
+            Error::new(e.kind(), format!("Unable to read event at offset {}: {}", offset, e))
+        })?;
+        for record in index_builder.add_event(&event.header, offset) {
+            write_record(&record)?;
+        }
+        offset += required_buffer_length as u64;
+        events_read += 1;
+    }
+    if let Some(record) = index_builder.finish(offset) {
+        write_record(&record)?;
+    }
+    debug!("rebuild_index: events_read={}, records_created={}", events_read, index_builder.records_created());
+    Ok(events_read)
+}
+
 #[cfg(test)]
 mod test {
-    use crate::index::{IndexRecord, IndexRecordWriter, IndexRecordReader, IndexSearcher, SearchMethod};
+    use crate::event_serde::{EventWithHeader, EventWriter};
+    use crate::index::{IndexBuilder, IndexRecord, IndexRecordWriter, IndexRecordReader, IndexSearcher, SearchFilter, SearchMethod,
+        rebuild_index};
     use crate::timestamp::PravegaTimestamp;
     use tracing::info;
-    use std::io::Cursor;
+    use std::io::{Cursor, Seek};
 
     #[test]
     fn test_index_writer_reader() {
@@ -409,6 +730,31 @@ mod test {
         assert_eq!(index_record, deserialized_index_record);
     }
 
+    #[test]
+    fn test_index_reader_versions() {
+        let timestamp_bytes = 1_600_000_000_000_000_000u64.to_be_bytes();
+        let offset_bytes = 300u64.to_be_bytes();
+        let expected = IndexRecord::new(
+            PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000)),
+            300, true, true);
+        // Version 0 and version 1 with an unknown flag.
+        for header in [[0, 0, 0, 0b110], [1, 0, 0, 0b10000110]].iter() {
+            let mut bytes = header.to_vec();
+            bytes.extend_from_slice(&timestamp_bytes);
+            bytes.extend_from_slice(&offset_bytes);
+            let mut index_record_reader = IndexRecordReader::new();
+            let index_record = index_record_reader.read(&mut Cursor::new(bytes)).unwrap();
+            assert_eq!(index_record, expected);
+        }
+        // Unsupported version.
+        let mut bytes = vec![2, 0, 0, 0b110];
+        bytes.extend_from_slice(&timestamp_bytes);
+        bytes.extend_from_slice(&offset_bytes);
+        let mut index_record_reader = IndexRecordReader::new();
+        let result = index_record_reader.read(&mut Cursor::new(bytes)).map_err(|e| e.kind());
+        assert_eq!(result, Err(std::io::ErrorKind::InvalidData));
+    }
+
     #[test]
     fn test_index_searcher() {
         // env_logger::init();
@@ -439,18 +785,18 @@ mod test {
         let mut index_searcher = IndexSearcher::new(memory_index_cursor);
 
         // get_first_record
-        let found_first_record = index_searcher.get_first_record().unwrap();
+        let found_first_record = index_searcher.get_first_record(SearchFilter::Any).unwrap();
         info!("found_first_record={:?}", found_first_record);
         assert_eq!(found_first_record, first_record.clone());
 
         // get last record
-        let found_last_record = index_searcher.get_last_record().unwrap();
+        let found_last_record = index_searcher.get_last_record(SearchFilter::Any).unwrap();
         info!("found_last_record={:?}", found_last_record);
         assert_eq!(found_last_record, last_record);
 
         // Search for timestamp beyond the last record.
         let found_record_beyond_last = index_searcher.search_timestamp(
-            PravegaTimestamp::from_nanoseconds(Some(last_record.timestamp.nanoseconds().unwrap() + 1))).unwrap();
+            PravegaTimestamp::from_nanoseconds(Some(last_record.timestamp.nanoseconds().unwrap() + 1)), SearchFilter::Any).unwrap();
         info!("found_record_beyond_last={:?}", found_record_beyond_last);
         assert_eq!(found_record_beyond_last, last_record);
 
@@ -461,7 +807,7 @@ mod test {
                 let search_timestamp =
                     PravegaTimestamp::from_nanoseconds(Some(rec.timestamp.nanoseconds().unwrap() - search_timestamp_offset));
                 let found_record = index_searcher.search_timestamp_and_return_index_offset(
-                    search_timestamp, SearchMethod::After).unwrap();
+                    search_timestamp, SearchMethod::After, SearchFilter::Any).unwrap();
                 info!("search_timestamp={}, found_record={:?}", search_timestamp, found_record);
                 assert_eq!(found_record.0, *rec);
                 assert_eq!(found_record.1, (i * IndexRecord::RECORD_SIZE) as u64);
@@ -472,11 +818,199 @@ mod test {
                 let search_timestamp =
                     PravegaTimestamp::from_nanoseconds(Some(rec.timestamp.nanoseconds().unwrap() + search_timestamp_offset));
                 let found_record = index_searcher.search_timestamp_and_return_index_offset(
-                    search_timestamp, SearchMethod::Before).unwrap();
+                    search_timestamp, SearchMethod::Before, SearchFilter::Any).unwrap();
                 info!("search_timestamp={}, found_record={:?}", search_timestamp, found_record);
                 assert_eq!(found_record.0, *rec);
                 assert_eq!(found_record.1, (i * IndexRecord::RECORD_SIZE) as u64);
             }
         }
     }
+
+    #[test]
+    fn test_index_searcher_filter() {
+        // Create index in memory.
+        // Every 4th record is random access, starting with the 2nd record.
+        // The 7th record is a forced delta unit with a discontinuity.
+        // The last record is the final record written at end-of-stream.
+        let num_recs = 20;
+        let mut index_records: Vec<IndexRecord> = Vec::new();
+        let mut memory_index_cursor = Cursor::new(vec![0 as u8; num_recs * IndexRecord::RECORD_SIZE]);
+        let mut index_record_writer = IndexRecordWriter::new();
+        for i in 0..num_recs {
+            let rec = IndexRecord::new(
+                PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000 + 1000 * i as u64)),
+                100 * i as u64,
+                i % 4 == 1 && i < num_recs - 1,
+                i == 6);
+            index_records.push(rec);
+            index_record_writer.write(&rec, &mut memory_index_cursor).unwrap();
+        }
+        memory_index_cursor.set_position(0);
+        let mut index_searcher = IndexSearcher::new(memory_index_cursor);
+        let timestamp = |i: usize, delta: i64| PravegaTimestamp::from_nanoseconds(
+            Some((index_records[i].timestamp.nanoseconds().unwrap() as i64 + delta) as u64));
+
+        assert_eq!(index_searcher.get_first_record(SearchFilter::Any).unwrap(), index_records[0]);
+        assert_eq!(index_searcher.get_first_record(SearchFilter::RandomAccess).unwrap(), index_records[1]);
+        assert_eq!(index_searcher.get_last_record(SearchFilter::Any).unwrap(), index_records[19]);
+        assert_eq!(index_searcher.get_last_record(SearchFilter::RandomAccess).unwrap(), index_records[17]);
+
+        // Exact match on a delta unit record.
+        assert_eq!(index_searcher.search_timestamp(timestamp(7, 0), SearchFilter::Any).unwrap(), index_records[7]);
+        assert_eq!(index_searcher.search_timestamp(timestamp(7, 0), SearchFilter::RandomAccess).unwrap(), index_records[5]);
+        assert_eq!(index_searcher.search_timestamp(timestamp(7, 0), SearchFilter::IncludeDiscontinuities).unwrap(), index_records[6]);
+        assert_eq!(index_searcher.search_timestamp_after(timestamp(7, 0), SearchFilter::RandomAccess).unwrap(), index_records[9]);
+
+        // Non-exact matches.
+        assert_eq!(index_searcher.search_timestamp(timestamp(12, 500), SearchFilter::RandomAccess).unwrap(), index_records[9]);
+        assert_eq!(index_searcher.search_timestamp_after(timestamp(12, 500), SearchFilter::RandomAccess).unwrap(), index_records[13]);
+        assert_eq!(index_searcher.search_timestamp_after(timestamp(11, 500), SearchFilter::Any).unwrap(), index_records[12]);
+
+        // Before the first random access record, the nearest record after it is returned.
+        let found_record = index_searcher.search_timestamp_and_return_index_offset(
+            timestamp(0, 0), SearchMethod::Before, SearchFilter::RandomAccess).unwrap();
+        assert_eq!(found_record, (index_records[1], IndexRecord::RECORD_SIZE as u64));
+        // After the last random access record, the nearest record before it is returned.
+        let found_record = index_searcher.search_timestamp_and_return_index_offset(
+            timestamp(19, 1), SearchMethod::After, SearchFilter::RandomAccess).unwrap();
+        assert_eq!(found_record, (index_records[17], 17 * IndexRecord::RECORD_SIZE as u64));
+
+        // Size search.
+        let found_record = index_searcher.search_size_and_return_index_offset(
+            1100, SearchMethod::Before, SearchFilter::RandomAccess).unwrap();
+        assert_eq!(found_record.0, index_records[5]);
+        let found_record = index_searcher.search_size_and_return_index_offset(
+            1100, SearchMethod::Before, SearchFilter::Any).unwrap();
+        assert_eq!(found_record.0, index_records[8]);
+
+        // Offset search.
+        assert_eq!(index_searcher.search_offset_after(0, SearchFilter::Any).unwrap(), index_records[1]);
+        assert_eq!(index_searcher.search_offset_after(550, SearchFilter::Any).unwrap(), index_records[6]);
+        assert_eq!(index_searcher.search_offset_after(550, SearchFilter::RandomAccess).unwrap(), index_records[9]);
+        assert_eq!(index_searcher.search_offset_after(1700, SearchFilter::RandomAccess).unwrap_err().kind(),
+            std::io::ErrorKind::NotFound);
+        assert_eq!(index_searcher.search_offset_after(1900, SearchFilter::Any).unwrap_err().kind(),
+            std::io::ErrorKind::NotFound);
+        assert_eq!(index_searcher.search_offset_before(550, SearchFilter::Any).unwrap(), index_records[5]);
+        assert_eq!(index_searcher.search_offset_before(500, SearchFilter::RandomAccess).unwrap(), index_records[1]);
+        assert_eq!(index_searcher.search_offset_before(2000, SearchFilter::RandomAccess).unwrap(), index_records[17]);
+        assert_eq!(index_searcher.search_offset_before(100, SearchFilter::RandomAccess).unwrap_err().kind(),
+            std::io::ErrorKind::NotFound);
+        assert_eq!(index_searcher.search_offset_before(0, SearchFilter::Any).unwrap_err().kind(),
+            std::io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_index_searcher_backwards_blocks() {
+        // Only record 3 is random access, so searching backwards from the end reads several blocks.
+        let num_recs = 1000;
+        let mut index_records: Vec<IndexRecord> = Vec::new();
+        let mut memory_index_cursor = Cursor::new(Vec::new());
+        let mut index_record_writer = IndexRecordWriter::new();
+        for i in 0..num_recs {
+            let rec = IndexRecord::new(
+                PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000 + 1000 * i as u64)),
+                100 * i as u64, i == 3, false);
+            index_records.push(rec);
+            index_record_writer.write(&rec, &mut memory_index_cursor).unwrap();
+        }
+        memory_index_cursor.set_position(0);
+        let mut index_searcher = IndexSearcher::new(memory_index_cursor);
+        assert_eq!(index_searcher.get_last_record(SearchFilter::RandomAccess).unwrap(), index_records[3]);
+        let found_record = index_searcher.search_timestamp_and_return_index_offset(
+            index_records[900].timestamp, SearchMethod::Before, SearchFilter::RandomAccess).unwrap();
+        assert_eq!(found_record, (index_records[3], 3 * IndexRecord::RECORD_SIZE as u64));
+        assert_eq!(index_searcher.search_timestamp(index_records[2].timestamp, SearchFilter::Any).unwrap(), index_records[2]);
+    }
+
+    #[test]
+    fn test_index_searcher_filter_no_match() {
+        let mut memory_index_cursor = Cursor::new(vec![0 as u8; 3 * IndexRecord::RECORD_SIZE]);
+        let mut index_record_writer = IndexRecordWriter::new();
+        for i in 0..3 {
+            let rec = IndexRecord::new(
+                PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000 + 1000 * i as u64)),
+                100 * i as u64, false, false);
+            index_record_writer.write(&rec, &mut memory_index_cursor).unwrap();
+        }
+        memory_index_cursor.set_position(0);
+        let mut index_searcher = IndexSearcher::new(memory_index_cursor);
+        let err = index_searcher.get_first_record(SearchFilter::RandomAccess).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+        assert!(index_searcher.get_first_record(SearchFilter::Any).is_ok());
+    }
+
+    #[test]
+    fn test_rebuild_index() {
+        let t0 = 1_600_000_000_000_000_000;
+        let ms = 1_000_000;
+        let payload = vec![0; 100];
+        let mut data = Cursor::new(Vec::new());
+        let mut event_writer = EventWriter::new();
+        let mut offsets = Vec::new();
+        let mut write = |data: &mut Cursor<Vec<u8>>, event: EventWithHeader| {
+            offsets.push(data.stream_position().unwrap());
+            event_writer.write(&event, data).unwrap();
+        };
+        let ts = |t: u64| PravegaTimestamp::from_nanoseconds(Some(t0 + t * ms));
+        // Index record at first key frame with discontinuity.
+        write(&mut data, EventWithHeader::new(&payload, ts(0), true, true, true));
+        // Key frame within index_min_nanos is not indexed.
+        write(&mut data, EventWithHeader::new(&payload, ts(200), false, true, false));
+        write(&mut data, EventWithHeader::new(&payload, ts(600), false, false, false));
+        // Key frame after index_min_nanos is indexed.
+        write(&mut data, EventWithHeader::new(&payload, ts(1000), true, true, false));
+        // An event with the include-in-index flag is indexed even within index_min_nanos.
+        write(&mut data, EventWithHeader::new(&payload, ts(1100), true, true, false));
+        // A key frame written without the include-in-index flag is indexed after index_min_nanos.
+        write(&mut data, EventWithHeader::new(&payload, ts(1700), false, true, false));
+        // Only the first fragment is indexed.
+        write(&mut data, EventWithHeader::new_fragment(&payload, ts(2000), true, true, false, false, true));
+        write(&mut data, EventWithHeader::new_fragment(&payload, ts(2000), false, false, false, true, false));
+        // Delta unit after index_max_nanos is indexed.
+        write(&mut data, EventWithHeader::new(&payload, ts(13000), true, false, false));
+        // A discontinuity ends the previous session but a delta unit is not indexed.
+        write(&mut data, EventWithHeader::new(&payload, ts(14000), false, false, true));
+        write(&mut data, EventWithHeader::new(&payload, ts(14100), true, true, false));
+        // An event without a timestamp is never indexed.
+        write(&mut data, EventWithHeader::new(&payload, PravegaTimestamp::NONE, false, true, false));
+        let end_offset = data.stream_position().unwrap();
+
+        let expected = vec![
+            IndexRecord::new(ts(0), offsets[0], true, true),
+            IndexRecord::new(ts(1000), offsets[3], true, false),
+            IndexRecord::new(ts(1100), offsets[4], true, false),
+            IndexRecord::new(ts(1700), offsets[5], true, false),
+            IndexRecord::new(ts(2000), offsets[6], true, false),
+            IndexRecord::new(ts(13000), offsets[8], false, false),
+            IndexRecord::new(ts(13000) + PravegaTimestamp::from_nanoseconds(Some(1)), offsets[9], false, false),
+            IndexRecord::new(ts(14100), offsets[10], true, true),
+            IndexRecord::new(ts(14100) + PravegaTimestamp::from_nanoseconds(Some(1)), end_offset, false, false),
+        ];
+        data.set_position(0);
+        let mut index_builder = IndexBuilder::new(500 * ms, 10_000 * ms);
+        let mut records = Vec::new();
+        let events_read = rebuild_index(&mut data, 0, end_offset, &mut index_builder, |record| {
+            records.push(*record);
+            Ok(())
+        }).unwrap();
+        assert_eq!(events_read, 12);
+        assert_eq!(records, expected);
+        assert_eq!(index_builder.records_created(), expected.len() as u64);
+
+        // Records can be written to a new index and searched.
+        let mut index_cursor = Cursor::new(Vec::new());
+        let mut index_record_writer = IndexRecordWriter::new();
+        for record in records.iter() {
+            index_record_writer.write(record, &mut index_cursor).unwrap();
+        }
+        let mut index_searcher = IndexSearcher::new(index_cursor);
+        assert_eq!(index_searcher.search_timestamp(ts(1500), SearchFilter::RandomAccess).unwrap(), expected[2]);
+
+        // Rebuilding from a truncated event fails.
+        data.set_position(0);
+        let err = rebuild_index(&mut data, 0, end_offset + 1, &mut IndexBuilder::new(500 * ms, 10_000 * ms),
+            |_| Ok(())).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
 }
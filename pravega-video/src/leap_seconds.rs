@@ -0,0 +1,488 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Module for converting between UTC and TAI using a leap second table.
+
+use anyhow::{anyhow, bail, Context};
+use once_cell::sync::Lazy;
+use std::convert::TryFrom;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use tracing::{info, warn};
+
+/// If this environment variable is set, the leap second table will be loaded from this file
+/// instead of using the built-in table.
+/// The file must be in the format of the IERS leap-seconds.list file.
+/// See [https://www.ietf.org/timezones/data/leap-seconds.list].
+pub const LEAP_SECONDS_FILE_ENV_VAR: &str = "PRAVEGA_VIDEO_LEAP_SECONDS_FILE";
+
+// Difference between NTP and Unix epochs.
+const UNIX_TO_NTP_SECONDS: u64 = (70 * 365 + 17) * 24 * 60 * 60;
+
+const NANOS_PER_SECOND: i128 = 1_000_000_000;
+
+/// A single entry in the leap second table.
+/// Beginning at the Unix time `unix_seconds`, TAI is ahead of UTC by `tai_minus_utc` seconds.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct LeapSecond {
+    pub unix_seconds: u64,
+    pub tai_minus_utc: i64,
+}
+
+/// The built-in leap second table.
+/// This must be updated when the IERS announces a new leap second.
+/// Alternatively, a newer table can be loaded at runtime with [load_file] or by setting the
+/// environment variable PRAVEGA_VIDEO_LEAP_SECONDS_FILE.
+const BUILTIN_LEAP_SECONDS: [LeapSecond; 28] = [
+    LeapSecond { unix_seconds: 63072000, tai_minus_utc: 10 },   // 1972-01-01
+    LeapSecond { unix_seconds: 78796800, tai_minus_utc: 11 },   // 1972-07-01
+    LeapSecond { unix_seconds: 94694400, tai_minus_utc: 12 },   // 1973-01-01
+    LeapSecond { unix_seconds: 126230400, tai_minus_utc: 13 },  // 1974-01-01
+    LeapSecond { unix_seconds: 157766400, tai_minus_utc: 14 },  // 1975-01-01
+    LeapSecond { unix_seconds: 189302400, tai_minus_utc: 15 },  // 1976-01-01
+    LeapSecond { unix_seconds: 220924800, tai_minus_utc: 16 },  // 1977-01-01
+    LeapSecond { unix_seconds: 252460800, tai_minus_utc: 17 },  // 1978-01-01
+    LeapSecond { unix_seconds: 283996800, tai_minus_utc: 18 },  // 1979-01-01
+    LeapSecond { unix_seconds: 315532800, tai_minus_utc: 19 },  // 1980-01-01
+    LeapSecond { unix_seconds: 362793600, tai_minus_utc: 20 },  // 1981-07-01
+    LeapSecond { unix_seconds: 394329600, tai_minus_utc: 21 },  // 1982-07-01
+    LeapSecond { unix_seconds: 425865600, tai_minus_utc: 22 },  // 1983-07-01
+    LeapSecond { unix_seconds: 489024000, tai_minus_utc: 23 },  // 1985-07-01
+    LeapSecond { unix_seconds: 567993600, tai_minus_utc: 24 },  // 1988-01-01
+    LeapSecond { unix_seconds: 631152000, tai_minus_utc: 25 },  // 1990-01-01
+    LeapSecond { unix_seconds: 662688000, tai_minus_utc: 26 },  // 1991-01-01
+    LeapSecond { unix_seconds: 709948800, tai_minus_utc: 27 },  // 1992-07-01
+    LeapSecond { unix_seconds: 741484800, tai_minus_utc: 28 },  // 1993-07-01
+    LeapSecond { unix_seconds: 773020800, tai_minus_utc: 29 },  // 1994-07-01
+    LeapSecond { unix_seconds: 820454400, tai_minus_utc: 30 },  // 1996-01-01
+    LeapSecond { unix_seconds: 867715200, tai_minus_utc: 31 },  // 1997-07-01
+    LeapSecond { unix_seconds: 915148800, tai_minus_utc: 32 },  // 1999-01-01
+    LeapSecond { unix_seconds: 1136073600, tai_minus_utc: 33 }, // 2006-01-01
+    LeapSecond { unix_seconds: 1230768000, tai_minus_utc: 34 }, // 2009-01-01
+    LeapSecond { unix_seconds: 1341100800, tai_minus_utc: 35 }, // 2012-07-01
+    LeapSecond { unix_seconds: 1435708800, tai_minus_utc: 36 }, // 2015-07-01
+    LeapSecond { unix_seconds: 1483228800, tai_minus_utc: 37 }, // 2017-01-01
+];
+
+/// The expiration time of the built-in leap second table (2022-06-28), from the IERS leap-seconds.list file
+/// updated through IERS Bulletin C 62.
+/// This must be updated along with [BUILTIN_LEAP_SECONDS].
+const BUILTIN_EXPIRES_UNIX_SECONDS: u64 = 1656374400;
+
+/**
+   A table of UTC to TAI offsets.
+
+   Prior to the first entry in the table, UTC and TAI are considered equal.
+   This keeps the TAI epoch 1970-01-01 00:00 TAI aligned with the Unix epoch.
+   The first entry (normally 1972-01-01) is treated as the baseline and not as a leap second.
+
+   Each subsequent increase of the offset is a positive leap second that is inserted
+   immediately before `unix_seconds` and is labeled 23:59:60 UTC.
+   Unix time cannot represent a leap second. By POSIX convention, 23:59:60 has the same Unix time
+   as the following 00:00:00. Therefore, a Unix time within the second following a leap second is ambiguous.
+   Calendar representations (chrono, ISO 8601) are not ambiguous because they can label the leap second 23:59:60.
+*/
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LeapSecondTable {
+    entries: Vec<LeapSecond>,
+    /// The Unix time after which this table should not be trusted.
+    expires_unix_seconds: Option<u64>,
+}
+
+impl LeapSecondTable {
+    /// Create a table from entries, which must have strictly increasing times.
+    pub fn new(entries: Vec<LeapSecond>, expires_unix_seconds: Option<u64>) -> anyhow::Result<Self> {
+        for pair in entries.windows(2) {
+            if pair[1].unix_seconds <= pair[0].unix_seconds {
+                bail!("Leap second table entries must be in increasing order; {:?} is not before {:?}", pair[0], pair[1]);
+            }
+        }
+        Ok(Self { entries, expires_unix_seconds })
+    }
+
+    pub fn builtin() -> Self {
+        Self {
+            entries: BUILTIN_LEAP_SECONDS.to_vec(),
+            expires_unix_seconds: Some(BUILTIN_EXPIRES_UNIX_SECONDS),
+        }
+    }
+
+    /// Parse a table in the format of the IERS leap-seconds.list file.
+    /// Each data line contains the NTP time (seconds since 1900-01-01 00:00:00 UTC) and the new TAI-UTC offset.
+    /// The line beginning with "#@" contains the expiration time of the file as an NTP time.
+    /// All other lines beginning with "#" are ignored.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut entries = Vec::new();
+        let mut expires_unix_seconds = None;
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if let Some(expires) = line.strip_prefix("#@") {
+                let expires_ntp_seconds: u64 = expires.trim().parse()
+                    .with_context(|| format!("Invalid expiration time on line {}", line_number + 1))?;
+                expires_unix_seconds = expires_ntp_seconds.checked_sub(UNIX_TO_NTP_SECONDS);
+                continue;
+            }
+            let data = line.split('#').next().unwrap_or_default().trim();
+            if data.is_empty() {
+                continue;
+            }
+            let mut fields = data.split_whitespace();
+            let (ntp_seconds, tai_minus_utc) = match (fields.next(), fields.next()) {
+                (Some(ntp_seconds), Some(tai_minus_utc)) => (ntp_seconds, tai_minus_utc),
+                _ => bail!("Expected NTP time and TAI-UTC offset on line {}", line_number + 1),
+            };
+            let ntp_seconds: u64 = ntp_seconds.parse()
+                .with_context(|| format!("Invalid NTP time on line {}", line_number + 1))?;
+            let tai_minus_utc: i64 = tai_minus_utc.parse()
+                .with_context(|| format!("Invalid TAI-UTC offset on line {}", line_number + 1))?;
+            let unix_seconds = ntp_seconds.checked_sub(UNIX_TO_NTP_SECONDS)
+                .ok_or_else(|| anyhow!("NTP time on line {} is before the Unix epoch", line_number + 1))?;
+            entries.push(LeapSecond { unix_seconds, tai_minus_utc });
+        }
+        if entries.is_empty() {
+            bail!("Leap second table has no entries");
+        }
+        Self::new(entries, expires_unix_seconds)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Unable to read leap second file {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Unable to parse leap second file {}", path.display()))
+    }
+
+    pub fn entries(&self) -> &[LeapSecond] {
+        &self.entries
+    }
+
+    pub fn expires_unix_seconds(&self) -> Option<u64> {
+        self.expires_unix_seconds
+    }
+
+    /// Returns true if the table has an expiration time and the given Unix time is after it.
+    /// A leap second may have been announced after the table was published.
+    pub fn is_expired(&self, unix_seconds: u64) -> bool {
+        match self.expires_unix_seconds {
+            Some(expires_unix_seconds) => unix_seconds > expires_unix_seconds,
+            None => false,
+        }
+    }
+
+    /// Returns the TAI-UTC offset in seconds in effect at the given Unix time.
+    /// If the Unix time is within the second following a leap second, the offset after the leap second is returned.
+    pub fn tai_minus_utc(&self, unix_seconds: u64) -> i64 {
+        let index = self.entries.partition_point(|e| e.unix_seconds <= unix_seconds);
+        self.offset_before(index)
+    }
+
+    /// Convert a UTC time in calendar representation to nanoseconds since the TAI epoch.
+    /// As in chrono, a leap second is represented by nanoseconds of 1,000,000,000 or more in the second before the
+    /// leap second was inserted. For example, 2016-12-31T23:59:60.5Z is unix_seconds=1483228799, nanoseconds=1,500,000,000.
+    /// This representation is not ambiguous.
+    pub fn utc_to_tai(&self, unix_seconds: u64, nanoseconds: u32) -> anyhow::Result<u64> {
+        let nanoseconds = nanoseconds as i128;
+        if nanoseconds >= NANOS_PER_SECOND {
+            // The caller is requesting a time within a leap second.
+            let index = self.entries.partition_point(|e| e.unix_seconds <= unix_seconds + 1);
+            let is_leap_second = index >= 2
+                && self.entries[index - 1].unix_seconds == unix_seconds + 1
+                && nanoseconds < (1 + self.step(index - 1)) as i128 * NANOS_PER_SECOND;
+            if !is_leap_second {
+                bail!("UTC time {}.{:09} is not within a leap second", unix_seconds, nanoseconds);
+            }
+            let offset = self.offset_before(index - 1);
+            return Self::to_u64(unix_seconds as i128 * NANOS_PER_SECOND + nanoseconds + offset as i128 * NANOS_PER_SECOND);
+        }
+        if let Some(entry) = self.deleted_second(unix_seconds) {
+            bail!("UTC time {} does not exist because it was removed by the negative leap second at {:?}", unix_seconds, entry);
+        }
+        let offset = self.tai_minus_utc(unix_seconds);
+        Self::to_u64(unix_seconds as i128 * NANOS_PER_SECOND + nanoseconds + offset as i128 * NANOS_PER_SECOND)
+    }
+
+    /// Convert nanoseconds since the Unix epoch 1970-01-01 00:00:00 UTC (not including leap seconds)
+    /// to nanoseconds since the TAI epoch.
+    /// Returns an error if the Unix time is within the second following a leap second,
+    /// since it can refer to either the leap second or the second after it.
+    pub fn unix_to_tai(&self, unix_nanoseconds: u64) -> anyhow::Result<u64> {
+        let unix_seconds = unix_nanoseconds / NANOS_PER_SECOND as u64;
+        let nanoseconds = (unix_nanoseconds % NANOS_PER_SECOND as u64) as u32;
+        if let Some(entry) = self.ambiguous_second(unix_seconds) {
+            bail!("Unix time {} ns is ambiguous because it may be within the leap second inserted before {:?}",
+                unix_nanoseconds, entry);
+        }
+        self.utc_to_tai(unix_seconds, nanoseconds)
+    }
+
+    /// Like unix_to_tai() but a Unix time within the second following a leap second is resolved to
+    /// the earlier TAI time, which is within the leap second.
+    pub fn unix_to_tai_earlier(&self, unix_nanoseconds: u64) -> anyhow::Result<u64> {
+        let unix_seconds = unix_nanoseconds / NANOS_PER_SECOND as u64;
+        if self.ambiguous_second(unix_seconds).is_some() {
+            let index = self.entries.partition_point(|e| e.unix_seconds <= unix_seconds);
+            let offset = self.offset_before(index - 1);
+            return Self::to_u64(unix_nanoseconds as i128 + offset as i128 * NANOS_PER_SECOND);
+        }
+        self.unix_to_tai(unix_nanoseconds)
+    }
+
+    /// Convert nanoseconds since the TAI epoch to a UTC time in calendar representation.
+    /// Returns a tuple containing the Unix seconds and nanoseconds.
+    /// As in chrono, if the time is within a leap second, nanoseconds will be 1,000,000,000 or more.
+    pub fn tai_to_utc(&self, tai_nanoseconds: u64) -> (u64, u32) {
+        let tai_nanoseconds = tai_nanoseconds as i128;
+        let index = self.entries.partition_point(|e| {
+            (e.unix_seconds as i128 + e.tai_minus_utc as i128) * NANOS_PER_SECOND <= tai_nanoseconds
+        });
+        let unix_nanoseconds = tai_nanoseconds - self.offset_before(index) as i128 * NANOS_PER_SECOND;
+        if index < self.entries.len() {
+            let next_unix_nanoseconds = self.entries[index].unix_seconds as i128 * NANOS_PER_SECOND;
+            if unix_nanoseconds >= next_unix_nanoseconds {
+                let next_unix_seconds = self.entries[index].unix_seconds;
+                if index == 0 {
+                    // This is in the gap between the TAI epoch baseline and the first entry.
+                    // It does not correspond to any UTC time so use the first time with the new offset.
+                    return (next_unix_seconds, 0);
+                }
+                // This is within an inserted leap second.
+                let within = i128::min(unix_nanoseconds - next_unix_nanoseconds, NANOS_PER_SECOND - 1);
+                return (next_unix_seconds - 1, (NANOS_PER_SECOND + within) as u32);
+            }
+        }
+        let unix_nanoseconds = i128::max(0, unix_nanoseconds);
+        ((unix_nanoseconds / NANOS_PER_SECOND) as u64, (unix_nanoseconds % NANOS_PER_SECOND) as u32)
+    }
+
+    /// Returns true if the TAI time is within an inserted leap second.
+    pub fn is_leap_second(&self, tai_nanoseconds: u64) -> bool {
+        self.tai_to_utc(tai_nanoseconds).1 as i128 >= NANOS_PER_SECOND
+    }
+
+    // Returns the offset in effect before the entry with the specified index.
+    fn offset_before(&self, index: usize) -> i64 {
+        if index == 0 { 0 } else { self.entries[index - 1].tai_minus_utc }
+    }
+
+    // Returns the change in offset at the specified entry.
+    // The change from the baseline to the first entry is not a leap second so it is reported as 0.
+    fn step(&self, index: usize) -> i64 {
+        if index == 0 { 0 } else { self.entries[index].tai_minus_utc - self.entries[index - 1].tai_minus_utc }
+    }
+
+    // If the Unix second can refer to either a leap second or the following second, return the leap second entry.
+    fn ambiguous_second(&self, unix_seconds: u64) -> Option<LeapSecond> {
+        let index = self.entries.partition_point(|e| e.unix_seconds <= unix_seconds);
+        if index == 0 {
+            return None;
+        }
+        let step = self.step(index - 1);
+        let entry = self.entries[index - 1];
+        if step > 0 && unix_seconds < entry.unix_seconds + step as u64 {
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    // If the Unix second was removed by a negative leap second, return the leap second entry.
+    fn deleted_second(&self, unix_seconds: u64) -> Option<LeapSecond> {
+        let index = self.entries.partition_point(|e| e.unix_seconds <= unix_seconds);
+        if index >= self.entries.len() {
+            return None;
+        }
+        let step = self.step(index);
+        let entry = self.entries[index];
+        if step < 0 && unix_seconds as i128 >= entry.unix_seconds as i128 + step as i128 {
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    fn to_u64(nanoseconds: i128) -> anyhow::Result<u64> {
+        u64::try_from(nanoseconds).map_err(|_| anyhow!("Time {} ns cannot be represented as a TAI timestamp", nanoseconds))
+    }
+}
+
+impl Default for LeapSecondTable {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+static LEAP_SECOND_TABLE: Lazy<RwLock<Arc<LeapSecondTable>>> = Lazy::new(|| {
+    let table = match std::env::var(LEAP_SECONDS_FILE_ENV_VAR) {
+        Ok(path) if !path.is_empty() => {
+            match LeapSecondTable::from_file(&path) {
+                Ok(table) => {
+                    info!("Loaded leap second table from {}", path);
+                    table
+                },
+                Err(e) => {
+                    warn!("Using built-in leap second table: {:#}", e);
+                    LeapSecondTable::builtin()
+                },
+            }
+        },
+        _ => LeapSecondTable::builtin(),
+    };
+    warn_if_expired(&table);
+    RwLock::new(Arc::new(table))
+});
+
+fn warn_if_expired(table: &LeapSecondTable) {
+    let now_unix_seconds = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    if table.is_expired(now_unix_seconds) {
+        warn!("The leap second table expired at Unix time {}. Timestamps after a newly announced leap second will be \
+            off by one second. Set {} to the path of a current leap-seconds.list file.",
+            table.expires_unix_seconds().unwrap_or_default(), LEAP_SECONDS_FILE_ENV_VAR);
+    }
+}
+
+/// Returns the leap second table used by all timestamp conversions.
+pub fn current_table() -> Arc<LeapSecondTable> {
+    LEAP_SECOND_TABLE.read().unwrap().clone()
+}
+
+/// Replace the leap second table used by all timestamp conversions.
+pub fn set_table(table: LeapSecondTable) {
+    warn_if_expired(&table);
+    *LEAP_SECOND_TABLE.write().unwrap() = Arc::new(table);
+}
+
+/// Load a leap second table from a file in the format of the IERS leap-seconds.list file
+/// and use it for all timestamp conversions.
+pub fn load_file<P: AsRef<Path>>(path: P) -> anyhow::Result<()> {
+    let table = LeapSecondTable::from_file(path)?;
+    set_table(table);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Abbreviated version of https://www.ietf.org/timezones/data/leap-seconds.list.
+    const LEAP_SECONDS_LIST: &str = "\
+#	Updated through IERS Bulletin C60
+#$	 3913697179
+#@	3960057600
+#
+2272060800	10	# 1 Jan 1972
+2287785600	11	# 1 Jul 1972
+3644697600	36	# 1 Jul 2015
+3692217600	37	# 1 Jan 2017
+#h	16edd0f0 3f7fc3eb 8e92ab11 13cf1e95 4ea36e2f
+";
+
+    const LEAP_2016: u64 = 1483228800;
+
+    #[test]
+    fn test_parse() {
+        let table = LeapSecondTable::parse(LEAP_SECONDS_LIST).unwrap();
+        assert_eq!(table.entries().len(), 4);
+        assert_eq!(table.entries()[0], BUILTIN_LEAP_SECONDS[0]);
+        assert_eq!(table.entries()[1], BUILTIN_LEAP_SECONDS[1]);
+        assert_eq!(table.entries()[2], BUILTIN_LEAP_SECONDS[26]);
+        assert_eq!(table.entries()[3], BUILTIN_LEAP_SECONDS[27]);
+        assert_eq!(table.expires_unix_seconds(), Some(3960057600 - UNIX_TO_NTP_SECONDS));
+        assert!(LeapSecondTable::parse("# no entries\n").is_err());
+        assert!(LeapSecondTable::parse("2287785600 11\n2272060800 10\n").is_err());
+        assert!(LeapSecondTable::parse("2272060800\n").is_err());
+    }
+
+    #[test]
+    fn test_expires() {
+        let table = LeapSecondTable::builtin();
+        assert_eq!(table.expires_unix_seconds(), Some(BUILTIN_EXPIRES_UNIX_SECONDS));
+        assert!(!table.is_expired(BUILTIN_EXPIRES_UNIX_SECONDS));
+        assert!(table.is_expired(BUILTIN_EXPIRES_UNIX_SECONDS + 1));
+        assert!(!LeapSecondTable::new(BUILTIN_LEAP_SECONDS.to_vec(), None).unwrap().is_expired(u64::MAX));
+    }
+
+    #[test]
+    fn test_tai_minus_utc() {
+        let table = LeapSecondTable::builtin();
+        assert_eq!(table.tai_minus_utc(0), 0);
+        assert_eq!(table.tai_minus_utc(63072000 - 1), 0);
+        assert_eq!(table.tai_minus_utc(63072000), 10);
+        // 2012-06-30T23:59:59Z and 2012-07-01T00:00:00Z
+        assert_eq!(table.tai_minus_utc(1341100800 - 1), 34);
+        assert_eq!(table.tai_minus_utc(1341100800), 35);
+        // 2014-06-01T00:00:00Z
+        assert_eq!(table.tai_minus_utc(1401580800), 35);
+        assert_eq!(table.tai_minus_utc(LEAP_2016), 37);
+        assert_eq!(table.tai_minus_utc(u64::MAX / 1_000_000_000), 37);
+    }
+
+    #[test]
+    fn test_leap_second_2016() {
+        let table = LeapSecondTable::builtin();
+        // 2016-12-31T23:59:59.5Z
+        let t1 = table.unix_to_tai((LEAP_2016 - 1) * 1_000_000_000 + 500_000_000).unwrap();
+        assert_eq!(t1, (LEAP_2016 - 1 + 36) * 1_000_000_000 + 500_000_000);
+        assert_eq!(table.tai_to_utc(t1), (LEAP_2016 - 1, 500_000_000));
+        // 2016-12-31T23:59:60.5Z cannot be represented in Unix time.
+        let t2 = table.utc_to_tai(LEAP_2016 - 1, 1_500_000_000).unwrap();
+        assert_eq!(t2, t1 + 1_000_000_000);
+        assert_eq!(table.tai_to_utc(t2), (LEAP_2016 - 1, 1_500_000_000));
+        assert!(table.is_leap_second(t2));
+        // 2017-01-01T00:00:00.5Z
+        let t3 = table.utc_to_tai(LEAP_2016, 500_000_000).unwrap();
+        assert_eq!(t3, t2 + 1_000_000_000);
+        assert_eq!(table.tai_to_utc(t3), (LEAP_2016, 500_000_000));
+        assert!(!table.is_leap_second(t3));
+        // The Unix time of both 23:59:60.5 and 00:00:00.5 is ambiguous.
+        assert!(table.unix_to_tai(LEAP_2016 * 1_000_000_000 + 500_000_000).is_err());
+        assert!(table.unix_to_tai((LEAP_2016 + 1) * 1_000_000_000).is_ok());
+        // 2017-01-01T00:00:00.5Z is not within a leap second.
+        assert!(table.utc_to_tai(LEAP_2016, 1_500_000_000).is_err());
+        // 2014-06-30T23:59:60Z is not a leap second.
+        assert!(table.utc_to_tai(1404172799, 1_000_000_000).is_err());
+    }
+
+    #[test]
+    fn test_baseline() {
+        let table = LeapSecondTable::builtin();
+        assert_eq!(table.unix_to_tai(0).unwrap(), 0);
+        assert_eq!(table.tai_to_utc(0), (0, 0));
+        assert_eq!(table.unix_to_tai(63072000 * 1_000_000_000).unwrap(), (63072000 + 10) * 1_000_000_000);
+        // TAI times between the baseline and the first entry map to the first entry.
+        assert_eq!(table.tai_to_utc((63072000 + 5) * 1_000_000_000), (63072000, 0));
+        assert!(!table.is_leap_second((63072000 + 5) * 1_000_000_000));
+    }
+
+    #[test]
+    fn test_negative_leap_second() {
+        let table = LeapSecondTable::new(vec![
+            LeapSecond { unix_seconds: 1000, tai_minus_utc: 10 },
+            LeapSecond { unix_seconds: 2000, tai_minus_utc: 9 },
+        ], None).unwrap();
+        assert_eq!(table.unix_to_tai(1998 * 1_000_000_000).unwrap(), 2008 * 1_000_000_000);
+        assert!(table.unix_to_tai(1999 * 1_000_000_000).is_err());
+        assert_eq!(table.unix_to_tai(2000 * 1_000_000_000).unwrap(), 2009 * 1_000_000_000);
+        assert_eq!(table.tai_to_utc(2008 * 1_000_000_000), (1998, 0));
+        assert_eq!(table.tai_to_utc(2009 * 1_000_000_000), (2000, 0));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let table = LeapSecondTable::builtin();
+        for entry in BUILTIN_LEAP_SECONDS.iter().skip(1) {
+            for unix_seconds in [entry.unix_seconds - 2, entry.unix_seconds - 1, entry.unix_seconds + 1, entry.unix_seconds + 86400].iter() {
+                let unix_nanoseconds = unix_seconds * 1_000_000_000 + 123;
+                let tai = table.unix_to_tai(unix_nanoseconds).unwrap();
+                assert_eq!(table.tai_to_utc(tai), (*unix_seconds, 123));
+            }
+        }
+    }
+}
@@ -8,8 +8,18 @@
 // http://www.apache.org/licenses/LICENSE-2.0
 //
 
+pub mod archive;
+pub mod caps;
 pub mod event_serde;
 pub mod index;
+pub mod leap_seconds;
+pub mod marker;
+pub mod record_history;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod spool;
+pub mod storage;
 pub mod timestamp;
 pub mod tracing;
 pub mod utils;
+pub mod verify;
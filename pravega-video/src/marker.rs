@@ -0,0 +1,234 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Module for writing and reading markers (bookmarks) of a media stream in a Pravega stream.
+
+use crate::record_history::{HistoryRecord, RecordHistory};
+use crate::timestamp::PravegaTimestamp;
+use crate::utils::CurrentHead;
+use std::convert::TryInto;
+use std::io::{Error, ErrorKind, Read, Write, Seek};
+
+pub fn get_marker_stream_name(stream_name: &str) -> String {
+    format!("{}-markers", stream_name)
+}
+
+/// Tags a moment in a media stream, such as "door forced" or an operator note.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MarkerRecord {
+    /// The timestamp of the tagged moment.
+    pub timestamp: PravegaTimestamp,
+    /// A short description of the marker.
+    pub label: String,
+    /// Application-defined data, usually JSON. This may be empty.
+    pub payload: String,
+}
+
+/**
+   A marker stream contains a marker record for each marker added to the associated data stream.
+
+   Marker records have the following variable-length encoding:
+
+   version (8-bit unsigned int):
+      Currently 1.
+   reserved (24 bits):
+      Must be written as 0.
+   label_length (32-bit BE unsigned int):
+      The number of bytes in label.
+   payload_length (32-bit BE unsigned int):
+      The number of bytes in payload.
+   reserved (32 bits):
+      Must be written as 0.
+   timestamp (64-bit BE unsigned int):
+      See [crate::event_serde::EventWriter] for a description of timestamp.
+   label (label_length bytes):
+      The label, encoded as UTF-8.
+   payload (payload_length bytes):
+      The payload, encoded as UTF-8.
+
+   Records are written in the order that they were added, which is usually, but not necessarily, in order of timestamp.
+   Like the caps stream, the marker stream is not truncated when the data stream is truncated.
+*/
+impl MarkerRecord {
+    pub const VERSION: u8 = 1;
+    const HEADER_SIZE: usize = 24;
+    // Labels and payloads longer than this are assumed to be the result of corruption.
+    const MAX_LABEL_LENGTH: usize = 64 * 1024;
+    const MAX_PAYLOAD_LENGTH: usize = 1024 * 1024;
+
+    pub fn new(timestamp: PravegaTimestamp, label: String, payload: String) -> Self {
+        Self {
+            timestamp,
+            label,
+            payload,
+        }
+    }
+}
+
+pub struct MarkerRecordWriter {
+}
+
+impl MarkerRecordWriter {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn write<W>(&mut self, record: &MarkerRecord, writer: &mut W) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        let label_bytes = record.label.as_bytes();
+        let payload_bytes = record.payload.as_bytes();
+        if label_bytes.len() > MarkerRecord::MAX_LABEL_LENGTH {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Marker label of {} bytes exceeds {} bytes",
+                label_bytes.len(), MarkerRecord::MAX_LABEL_LENGTH)));
+        }
+        if payload_bytes.len() > MarkerRecord::MAX_PAYLOAD_LENGTH {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Marker payload of {} bytes exceeds {} bytes",
+                payload_bytes.len(), MarkerRecord::MAX_PAYLOAD_LENGTH)));
+        }
+        let label_end = MarkerRecord::HEADER_SIZE + label_bytes.len();
+        let mut bytes_to_write = vec![0; label_end + payload_bytes.len()];
+        bytes_to_write[0] = MarkerRecord::VERSION;
+        bytes_to_write[4..8].copy_from_slice(&(label_bytes.len() as u32).to_be_bytes()[..]);
+        bytes_to_write[8..12].copy_from_slice(&(payload_bytes.len() as u32).to_be_bytes()[..]);
+        bytes_to_write[16..24].copy_from_slice(&record.timestamp.nanoseconds().unwrap_or_default().to_be_bytes()[..]);
+        bytes_to_write[MarkerRecord::HEADER_SIZE..label_end].copy_from_slice(label_bytes);
+        bytes_to_write[label_end..].copy_from_slice(payload_bytes);
+        // Marker records must be written atomically so that a reader never reads a partial record.
+        writer.write_all(&bytes_to_write)?;
+        Ok(())
+    }
+}
+
+pub struct MarkerRecordReader {
+}
+
+impl MarkerRecordReader {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Reads a marker record. Returns the record and its length in bytes.
+    pub fn read<R>(&mut self, rdr: &mut R) -> Result<(MarkerRecord, u64), Error>
+    where
+        R: Read,
+    {
+        let mut header = [0; MarkerRecord::HEADER_SIZE];
+        rdr.read_exact(&mut header)?;
+        let version = header[0];
+        if version != MarkerRecord::VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Unsupported marker record version {}", version)));
+        }
+        let label_length = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+        if label_length > MarkerRecord::MAX_LABEL_LENGTH {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Invalid marker label length {}", label_length)));
+        }
+        let payload_length = u32::from_be_bytes(header[8..12].try_into().unwrap()) as usize;
+        if payload_length > MarkerRecord::MAX_PAYLOAD_LENGTH {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Invalid marker payload length {}", payload_length)));
+        }
+        let timestamp = u64::from_be_bytes(header[16..24].try_into().unwrap());
+        let mut label_bytes = vec![0; label_length];
+        rdr.read_exact(&mut label_bytes)?;
+        let label = String::from_utf8(label_bytes).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let mut payload_bytes = vec![0; payload_length];
+        rdr.read_exact(&mut payload_bytes)?;
+        let payload = String::from_utf8(payload_bytes).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let record = MarkerRecord {
+            timestamp: PravegaTimestamp::from_nanoseconds(if timestamp == 0 { None } else { Some(timestamp) }),
+            label,
+            payload,
+        };
+        Ok((record, (MarkerRecord::HEADER_SIZE + label_length + payload_length) as u64))
+    }
+}
+
+impl HistoryRecord for MarkerRecord {
+    fn read_record<R: Read>(rdr: &mut R) -> Result<(Self, u64), Error> {
+        MarkerRecordReader::new().read(rdr)
+    }
+}
+
+/// Maintains the list of marker records in a marker stream.
+/// The marker stream can be stored in any object that implements Read and Seek, including a Pravega stream.
+pub type MarkerHistory<R> = RecordHistory<R, MarkerRecord>;
+
+impl<R: Read + Seek + CurrentHead> RecordHistory<R, MarkerRecord> {
+    /// Returns the marker records with a timestamp greater than or equal to begin and less than end,
+    /// in order of timestamp.
+    /// If begin or end is PravegaTimestamp::NONE, the range is unbounded on that side.
+    /// Marker records without a timestamp are never returned.
+    pub fn markers_in_range(&self, begin: PravegaTimestamp, end: PravegaTimestamp) -> Vec<&MarkerRecord> {
+        let mut markers: Vec<&MarkerRecord> = self.records().iter().filter(|record| {
+            match record.timestamp.nanoseconds() {
+                Some(timestamp) => {
+                    begin.nanoseconds().map_or(true, |begin| begin <= timestamp)
+                    && end.nanoseconds().map_or(true, |end| timestamp < end)
+                },
+                None => false,
+            }
+        }).collect();
+        markers.sort_by_key(|record| record.timestamp.nanoseconds());
+        markers
+    }
+
+    /// Returns the marker record with the specified label that has the greatest timestamp.
+    pub fn last_with_label(&self, label: &str) -> Option<&MarkerRecord> {
+        self.markers_in_range(PravegaTimestamp::NONE, PravegaTimestamp::NONE)
+            .into_iter()
+            .rev()
+            .find(|record| record.label == label)
+    }
+}
+
+/// Reads a marker stream and returns the marker records in the time range, in order of timestamp.
+/// See [MarkerHistory::markers_in_range].
+pub fn list_markers<R: Read + Seek + CurrentHead>(reader: R, begin: PravegaTimestamp, end: PravegaTimestamp)
+        -> Result<Vec<MarkerRecord>, Error> {
+    let mut marker_history = MarkerHistory::new(reader);
+    marker_history.refresh()?;
+    Ok(marker_history.markers_in_range(begin, end).into_iter().cloned().collect())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::marker::{MarkerHistory, MarkerRecord, MarkerRecordWriter, list_markers};
+    use crate::timestamp::PravegaTimestamp;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_markers_in_range() {
+        let timestamp = |seconds: u64| PravegaTimestamp::from_nanoseconds(Some(seconds * 1_000_000_000));
+        let records = vec![
+            MarkerRecord::new(timestamp(1_600_000_010), "door forced".to_owned(), r#"{"door":3}"#.to_owned()),
+            MarkerRecord::new(timestamp(1_600_000_000), "operator note".to_owned(), "".to_owned()),
+            MarkerRecord::new(timestamp(1_600_000_020), "door forced".to_owned(), r#"{"door":4}"#.to_owned()),
+        ];
+        let mut cursor = Cursor::new(Vec::new());
+        let mut marker_record_writer = MarkerRecordWriter::new();
+        for record in records.iter() {
+            marker_record_writer.write(record, &mut cursor).unwrap();
+        }
+        let mut marker_history = MarkerHistory::new(cursor);
+        marker_history.refresh().unwrap();
+
+        assert_eq!(marker_history.markers_in_range(PravegaTimestamp::NONE, PravegaTimestamp::NONE),
+            vec![&records[1], &records[0], &records[2]]);
+        assert_eq!(marker_history.markers_in_range(timestamp(1_600_000_010), timestamp(1_600_000_020)), vec![&records[0]]);
+        assert_eq!(marker_history.markers_in_range(timestamp(1_600_000_030), PravegaTimestamp::NONE), Vec::<&MarkerRecord>::new());
+        assert_eq!(marker_history.last_with_label("door forced"), Some(&records[2]));
+        assert_eq!(marker_history.last_with_label("missing"), None);
+
+        let cursor = marker_history.into_inner();
+        assert_eq!(list_markers(cursor, timestamp(1_600_000_005), PravegaTimestamp::NONE).unwrap(),
+            vec![records[0].clone(), records[2].clone()]);
+    }
+}
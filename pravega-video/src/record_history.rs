@@ -0,0 +1,108 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Module for reading the variable-length records of a companion stream, such as the caps or marker stream.
+
+use crate::utils::CurrentHead;
+use std::fmt::Debug;
+use std::io::{Error, Read, Seek, SeekFrom};
+use tracing::{debug, trace};
+
+/// A record type that can be read from a companion stream by [RecordHistory].
+pub trait HistoryRecord: Debug + Sized {
+    /// Reads a record. Returns the record and its length in bytes.
+    fn read_record<R: Read>(rdr: &mut R) -> Result<(Self, u64), Error>;
+}
+
+/// Maintains the list of records in a companion stream, in the order that they were written.
+/// The stream can be stored in any object that implements Read and Seek, including a Pravega stream.
+pub struct RecordHistory<R: Read + Seek + CurrentHead, T: HistoryRecord> {
+    reader: R,
+    records: Vec<T>,
+    // The offset in the stream of the next record to read.
+    position: Option<u64>,
+}
+
+impl<R: Read + Seek + CurrentHead, T: HistoryRecord> RecordHistory<R, T> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            records: Vec::new(),
+            position: None,
+        }
+    }
+
+    /// Reads any records that have been appended since the last call.
+    /// This will not block waiting for new records.
+    pub fn refresh(&mut self) -> Result<(), Error> {
+        let head_offset = self.reader.current_head()?;
+        let tail_offset = self.reader.seek(SeekFrom::End(0))?;
+        let mut position = u64::max(self.position.unwrap_or_default(), head_offset);
+        if position < tail_offset {
+            self.reader.seek(SeekFrom::Start(position))?;
+            let mut reader = (&mut self.reader).take(tail_offset - position);
+            while position < tail_offset {
+                let (record, length) = T::read_record(&mut reader)?;
+                trace!("RecordHistory::refresh: record={:?}", record);
+                self.records.push(record);
+                position += length;
+            }
+        }
+        self.position = Some(position);
+        debug!("RecordHistory::refresh: records={}, position={}", self.records.len(), position);
+        Ok(())
+    }
+
+    /// Returns all records that have been read, in the order that they were written.
+    pub fn records(&self) -> &[T] {
+        &self.records
+    }
+
+    /// Unwraps this `RecordHistory<R, T>`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::caps::{CapsRecord, CapsRecordWriter};
+    use crate::record_history::RecordHistory;
+    use crate::timestamp::PravegaTimestamp;
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    #[test]
+    fn test_record_history_refresh() {
+        let records = vec![
+            CapsRecord::new(PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000)), 0,
+                "video/quicktime, variant=(string)iso".to_owned()),
+            CapsRecord::new(PravegaTimestamp::from_nanoseconds(Some(1_600_000_010_000_000_000)), 5000,
+                "video/mpegts, systemstream=(boolean)true, packetsize=(int)188".to_owned()),
+        ];
+        let mut cursor = Cursor::new(Vec::new());
+        let mut caps_record_writer = CapsRecordWriter::new();
+        caps_record_writer.write(&records[0], &mut cursor).unwrap();
+        let mut history: RecordHistory<_, CapsRecord> = RecordHistory::new(cursor);
+        history.refresh().unwrap();
+        assert_eq!(history.records(), &records[0..1]);
+
+        // Append a record and refresh. Only the new record should be read.
+        history.reader.seek(SeekFrom::End(0)).unwrap();
+        caps_record_writer.write(&records[1], &mut history.reader).unwrap();
+        history.refresh().unwrap();
+        history.refresh().unwrap();
+        assert_eq!(history.records(), &records[..]);
+
+        // A new history reads all records.
+        let mut history: RecordHistory<_, CapsRecord> = RecordHistory::new(history.into_inner());
+        history.refresh().unwrap();
+        assert_eq!(history.records(), &records[..]);
+    }
+}
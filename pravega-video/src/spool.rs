@@ -0,0 +1,286 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Module for spooling media streams to a local directory while the primary storage is unavailable.
+
+use crate::caps::{CapsRecordReader, CapsRecordWriter, get_caps_stream_name};
+use crate::index::{IndexRecord, IndexRecordReader, IndexRecordWriter, get_index_stream_name};
+use crate::storage::{ByteStreamReader, ByteStreamWriter, FileStorage, StorageBackend};
+use crate::timestamp::PravegaTimestamp;
+use crate::utils::CurrentHead;
+use std::convert::TryInto;
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use tracing::{debug, info};
+
+/// The data, index, and caps writers of a media stream.
+pub struct MediaStreamWriters {
+    pub data: Box<dyn ByteStreamWriter>,
+    pub index: Box<dyn ByteStreamWriter>,
+    pub caps: Box<dyn ByteStreamWriter>,
+}
+
+/// The result of replaying a spool.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ReplayStats {
+    pub data_bytes: u64,
+    pub events: u64,
+    pub index_records: u64,
+    pub caps_records: u64,
+}
+
+/**
+   Holds the data, index, and caps streams of a media stream in a local directory while the primary storage
+   (usually Pravega) is unavailable.
+
+   The spooled streams are stored with [FileStorage] in the directory dir/scope/stream.
+   Offsets in the spooled index and caps records refer to the spooled data stream.
+   When the primary storage becomes available, replay() appends the spooled events to the primary data stream,
+   one event per write, and then appends the index and caps records with their offsets translated.
+   The spooled streams are then truncated.
+
+   Spooled data that has not been replayed survives a restart of the writer.
+   If the writer terminates during a replay, the replay will be repeated and the primary streams may
+   contain a duplicate copy of some spooled events.
+*/
+pub struct Spool {
+    storage: FileStorage,
+    scope_name: String,
+    stream_name: String,
+    index_stream_name: String,
+    caps_stream_name: String,
+    max_bytes: u64,
+}
+
+impl Spool {
+    pub fn new(dir: &Path, scope_name: &str, stream_name: &str, max_bytes: u64) -> Result<Spool, Error> {
+        let storage = FileStorage::new(dir);
+        let index_stream_name = get_index_stream_name(stream_name);
+        let caps_stream_name = get_caps_stream_name(stream_name);
+        storage.create_scope(scope_name)?;
+        storage.create_stream(scope_name, stream_name, None)?;
+        storage.create_stream(scope_name, &index_stream_name, None)?;
+        storage.create_stream(scope_name, &caps_stream_name, None)?;
+        Ok(Spool {
+            storage,
+            scope_name: scope_name.to_owned(),
+            stream_name: stream_name.to_owned(),
+            index_stream_name,
+            caps_stream_name,
+            max_bytes,
+        })
+    }
+
+    pub fn max_bytes(&self) -> u64 {
+        self.max_bytes
+    }
+
+    /// Returns writers that append to the spooled streams.
+    /// There must be at most one set of writers at a time.
+    pub fn create_writers(&self) -> Result<MediaStreamWriters, Error> {
+        Ok(MediaStreamWriters {
+            data: self.storage.create_writer(&self.scope_name, &self.stream_name)?,
+            index: self.storage.create_writer(&self.scope_name, &self.index_stream_name)?,
+            caps: self.storage.create_writer(&self.scope_name, &self.caps_stream_name)?,
+        })
+    }
+
+    /// Returns the number of spooled data bytes that have not been replayed.
+    pub fn size_bytes(&self) -> Result<u64, Error> {
+        let (head, tail) = head_and_tail(&mut self.storage.create_reader(&self.scope_name, &self.stream_name)?)?;
+        Ok(tail - head)
+    }
+
+    pub fn is_empty(&self) -> Result<bool, Error> {
+        Ok(self.size_bytes()? == 0)
+    }
+
+    /// Returns true if the spooled data has reached the maximum size.
+    pub fn is_full(&self) -> Result<bool, Error> {
+        Ok(self.size_bytes()? >= self.max_bytes)
+    }
+
+    /// Returns the timestamp of the first index record that has not been replayed.
+    /// This can be used to determine the age of the spooled data.
+    pub fn oldest_timestamp(&self) -> Result<PravegaTimestamp, Error> {
+        let mut reader = self.storage.create_reader(&self.scope_name, &self.index_stream_name)?;
+        let (head, tail) = head_and_tail(&mut reader)?;
+        if tail - head < IndexRecord::RECORD_SIZE as u64 {
+            return Ok(PravegaTimestamp::NONE);
+        }
+        reader.seek(SeekFrom::Start(head))?;
+        Ok(IndexRecordReader::new().read(&mut reader)?.timestamp)
+    }
+
+    /// Appends all spooled events and records to the primary streams and then truncates the spooled streams.
+    /// Writers of the spooled streams must be closed before calling this.
+    pub fn replay(&self, primary: &mut MediaStreamWriters) -> Result<ReplayStats, Error> {
+        let mut stats = ReplayStats::default();
+        let mut data_reader = self.storage.create_reader(&self.scope_name, &self.stream_name)?;
+        let (data_head, data_tail) = head_and_tail(&mut data_reader)?;
+        let mut index_reader = self.storage.create_reader(&self.scope_name, &self.index_stream_name)?;
+        let (index_head, index_tail) = head_and_tail(&mut index_reader)?;
+        let mut caps_reader = self.storage.create_reader(&self.scope_name, &self.caps_stream_name)?;
+        let (caps_head, caps_tail) = head_and_tail(&mut caps_reader)?;
+        if data_head == data_tail && index_head == index_tail && caps_head == caps_tail {
+            return Ok(stats);
+        }
+        let primary_offset = primary.data.seek_to_tail()?;
+        info!("Spool::replay: Replaying spooled data from offset {} to {} to primary offset {}",
+            data_head, data_tail, primary_offset);
+        let translate = |offset: u64| primary_offset + offset.saturating_sub(data_head);
+
+        // Caps records are written first so that readers will find them when they read the events.
+        caps_reader.seek(SeekFrom::Start(caps_head))?;
+        let mut caps_position = caps_head;
+        while caps_position < caps_tail {
+            let (mut record, length) = CapsRecordReader::new().read(&mut caps_reader)?;
+            caps_position += length;
+            record.offset = translate(record.offset);
+            CapsRecordWriter::new().write(&record, &mut primary.caps)?;
+            stats.caps_records += 1;
+        }
+        primary.caps.flush()?;
+
+        // Copy events one at a time so that each event is appended atomically.
+        data_reader.seek(SeekFrom::Start(data_head))?;
+        let mut data_position = data_head;
+        let mut event_bytes = Vec::new();
+        while data_position < data_tail {
+            let mut frame_header = [0; 8];
+            data_reader.read_exact(&mut frame_header)?;
+            let event_length = u32::from_be_bytes(frame_header[4..8].try_into().unwrap()) as usize;
+            let frame_length = frame_header.len() + event_length;
+            if data_position + frame_length as u64 > data_tail {
+                return Err(Error::new(ErrorKind::InvalidData, format!(
+                    "Spooled event at offset {} with length {} extends beyond tail {}", data_position, frame_length, data_tail)));
+            }
+            event_bytes.clear();
+            event_bytes.extend_from_slice(&frame_header);
+            event_bytes.resize(frame_length, 0);
+            data_reader.read_exact(&mut event_bytes[frame_header.len()..])?;
+            primary.data.write_all(&event_bytes)?;
+            data_position += frame_length as u64;
+            stats.events += 1;
+        }
+        primary.data.flush()?;
+        stats.data_bytes = data_tail - data_head;
+
+        // Index records are written after the events so that they only refer to data that has been written.
+        index_reader.seek(SeekFrom::Start(index_head))?;
+        let mut index_position = index_head;
+        while index_position + (IndexRecord::RECORD_SIZE as u64) <= index_tail {
+            let mut record = IndexRecordReader::new().read(&mut index_reader)?;
+            index_position += IndexRecord::RECORD_SIZE as u64;
+            if record.offset < data_head {
+                debug!("Spool::replay: Skipping index record for truncated offset {:?}", record);
+                continue;
+            }
+            record.offset = translate(record.offset);
+            IndexRecordWriter::new().write(&record, &mut primary.index)?;
+            stats.index_records += 1;
+        }
+        primary.index.flush()?;
+
+        let mut spool_writers = self.create_writers()?;
+        spool_writers.data.truncate_data_before(data_tail)?;
+        spool_writers.index.truncate_data_before(index_tail)?;
+        spool_writers.caps.truncate_data_before(caps_tail)?;
+        info!("Spool::replay: Replayed {:?}", stats);
+        Ok(stats)
+    }
+}
+
+fn head_and_tail(reader: &mut Box<dyn ByteStreamReader>) -> Result<(u64, u64), Error> {
+    let head = reader.current_head()?;
+    let tail = reader.seek(SeekFrom::End(0))?;
+    Ok((head, tail))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::caps::CapsRecord;
+    use crate::event_serde::{EventReader, EventWithHeader, EventWriter};
+    use crate::storage::MemoryStorage;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn test_spool_replay() {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let dir = std::env::temp_dir().join(format!("pravega-video-test-spool-{}", nanos));
+        let (scope, stream) = ("scope1", "stream1");
+        let spool = Spool::new(&dir, scope, stream, 1024 * 1024).unwrap();
+        assert!(spool.is_empty().unwrap());
+        assert_eq!(spool.oldest_timestamp().unwrap(), PravegaTimestamp::NONE);
+
+        // Spool 3 events. The first and third are indexed.
+        let timestamps = [1_000, 2_000, 3_000];
+        {
+            let mut writers = spool.create_writers().unwrap();
+            let caps_record = CapsRecord::new(PravegaTimestamp::from_nanoseconds(Some(1_000)), 0, "video/x-h264".to_owned());
+            CapsRecordWriter::new().write(&caps_record, &mut writers.caps).unwrap();
+            for (i, &timestamp) in timestamps.iter().enumerate() {
+                let timestamp = PravegaTimestamp::from_nanoseconds(Some(timestamp));
+                let indexed = i != 1;
+                if indexed {
+                    let offset = writers.data.current_offset();
+                    let index_record = IndexRecord::new(timestamp, offset, true, i == 0);
+                    IndexRecordWriter::new().write(&index_record, &mut writers.index).unwrap();
+                }
+                let payload = vec![i as u8; 100];
+                let event = EventWithHeader::new(&payload, timestamp, indexed, indexed, i == 0);
+                EventWriter::new().write(&event, &mut writers.data).unwrap();
+            }
+        }
+        assert!(!spool.is_empty().unwrap());
+        assert!(!spool.is_full().unwrap());
+        assert_eq!(spool.oldest_timestamp().unwrap(), PravegaTimestamp::from_nanoseconds(Some(1_000)));
+
+        // Replay to a primary stream that already has some data.
+        let primary_storage = MemoryStorage::new();
+        primary_storage.create_scope(scope).unwrap();
+        for stream_name in &[stream.to_owned(), get_index_stream_name(stream), get_caps_stream_name(stream)] {
+            primary_storage.create_stream(scope, stream_name, None).unwrap();
+        }
+        let mut primary = MediaStreamWriters {
+            data: primary_storage.create_writer(scope, stream).unwrap(),
+            index: primary_storage.create_writer(scope, &get_index_stream_name(stream)).unwrap(),
+            caps: primary_storage.create_writer(scope, &get_caps_stream_name(stream)).unwrap(),
+        };
+        primary.data.write_all(&[0; 50]).unwrap();
+        let stats = spool.replay(&mut primary).unwrap();
+        assert_eq!(stats.events, 3);
+        assert_eq!(stats.index_records, 2);
+        assert_eq!(stats.caps_records, 1);
+        assert!(spool.is_empty().unwrap());
+        assert_eq!(spool.oldest_timestamp().unwrap(), PravegaTimestamp::NONE);
+
+        // Index and caps records must refer to the replayed events.
+        let mut index_reader = primary_storage.create_reader(scope, &get_index_stream_name(stream)).unwrap();
+        let mut data_reader = primary_storage.create_reader(scope, stream).unwrap();
+        let mut event_reader = EventReader::new();
+        let mut buffer = vec![0; 1024];
+        for &expected_timestamp in &[1_000, 3_000] {
+            let index_record = IndexRecordReader::new().read(&mut index_reader).unwrap();
+            data_reader.seek(SeekFrom::Start(index_record.offset)).unwrap();
+            event_reader.read_required_buffer_length(&mut data_reader).unwrap();
+            let event = event_reader.read_event(&mut data_reader, &mut buffer).unwrap();
+            assert_eq!(event.header.timestamp, PravegaTimestamp::from_nanoseconds(Some(expected_timestamp)));
+        }
+        let mut caps_reader = primary_storage.create_reader(scope, &get_caps_stream_name(stream)).unwrap();
+        let (caps_record, _) = CapsRecordReader::new().read(&mut caps_reader).unwrap();
+        assert_eq!(caps_record.offset, 50);
+
+        // Replaying an empty spool does nothing.
+        assert_eq!(spool.replay(&mut primary).unwrap(), ReplayStats::default());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,871 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Module for storing byte streams in Pravega or in a local directory.
+
+use crate::utils::CurrentHead;
+use once_cell::sync::Lazy;
+use pravega_client::byte::{ByteReader, ByteWriter};
+use pravega_client::client_factory::ClientFactory;
+use pravega_client_shared::{Scope, Stream, StreamConfiguration, ScopedStream, Scaling, ScaleType};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, info};
+
+/// A reader of a byte stream.
+/// Seek to SeekFrom::End(0) to get the tail offset.
+/// Reads beyond the tail will block until data is written or the stream is sealed.
+pub trait ByteStreamReader: Read + Seek + CurrentHead + Send {}
+
+impl<T: Read + Seek + CurrentHead + Send> ByteStreamReader for T {}
+
+impl CurrentHead for Box<dyn ByteStreamReader> {
+    fn current_head(&self) -> std::io::Result<u64> {
+        self.as_ref().current_head()
+    }
+}
+
+/// A writer that appends to a byte stream.
+/// Each call to write() is atomic for readers, provided that it is at most 8 MiB.
+pub trait ByteStreamWriter: Write + Send {
+    /// Returns the offset at which the next byte will be written.
+    fn current_offset(&self) -> u64;
+
+    /// Positions the writer at the end of the stream. Returns the tail offset.
+    fn seek_to_tail(&mut self) -> Result<u64, Error>;
+
+    /// Deletes all data prior to offset.
+    /// Subsequent reads of the deleted data will fail. Offsets of the remaining data do not change.
+    fn truncate_data_before(&mut self, offset: u64) -> Result<(), Error>;
+
+    /// Seals the stream. No more data can be written.
+    fn seal(&mut self) -> Result<(), Error>;
+}
+
+/// A storage system that contains byte streams, organized by scope.
+pub trait StorageBackend: Send + Sync {
+    fn create_scope(&self, scope_name: &str) -> Result<(), Error>;
+
+    /// Creates a stream if it does not exist.
+    /// Tags are used by Pravega to identify video streams. They may be ignored by other implementations.
+    fn create_stream(&self, scope_name: &str, stream_name: &str, tags: Option<Vec<String>>) -> Result<(), Error>;
+
+    /// Returns true if the stream exists.
+    fn stream_exists(&self, scope_name: &str, stream_name: &str) -> Result<bool, Error>;
+
+    fn create_reader(&self, scope_name: &str, stream_name: &str) -> Result<Box<dyn ByteStreamReader>, Error>;
+
+    fn create_writer(&self, scope_name: &str, stream_name: &str) -> Result<Box<dyn ByteStreamWriter>, Error>;
+}
+
+/// Returns the directory of a storage URI such as "file:///var/lib/video".
+/// Returns None if the URI does not use the file scheme.
+pub fn parse_file_storage_uri(uri: &str) -> Option<PathBuf> {
+    uri.strip_prefix("file://").map(PathBuf::from)
+}
+
+/// Returns the storage for a URI other than Pravega.
+///   - file:///var/lib/video stores streams in a local directory. See [FileStorage].
+///   - memory://name stores streams in the memory of this process. See [MemoryStorage].
+pub fn create_storage_from_uri(uri: &str) -> Result<Box<dyn StorageBackend>, Error> {
+    if let Some(root) = parse_file_storage_uri(uri) {
+        Ok(Box::new(FileStorage::new(root)))
+    } else if let Some(name) = uri.strip_prefix("memory://") {
+        Ok(Box::new(MemoryStorage::named(name)))
+    } else {
+        Err(Error::new(ErrorKind::InvalidInput, format!("Unsupported storage URI {}", uri)))
+    }
+}
+
+/// Stores byte streams in Pravega.
+pub struct PravegaStorage {
+    client_factory: ClientFactory,
+}
+
+impl PravegaStorage {
+    pub fn new(client_factory: ClientFactory) -> Self {
+        Self { client_factory }
+    }
+
+    pub fn client_factory(&self) -> &ClientFactory {
+        &self.client_factory
+    }
+
+    fn scoped_stream(scope_name: &str, stream_name: &str) -> ScopedStream {
+        ScopedStream {
+            scope: Scope::from(scope_name.to_owned()),
+            stream: Stream::from(stream_name.to_owned()),
+        }
+    }
+}
+
+impl StorageBackend for PravegaStorage {
+    fn create_scope(&self, scope_name: &str) -> Result<(), Error> {
+        let controller_client = self.client_factory.controller_client();
+        let scope = Scope::from(scope_name.to_owned());
+        self.client_factory.runtime().block_on(controller_client.create_scope(&scope)).map_err(|error| {
+            Error::new(ErrorKind::Other, format!("Failed to create Pravega scope {}: {:?}", scope_name, error))
+        })?;
+        Ok(())
+    }
+
+    fn create_stream(&self, scope_name: &str, stream_name: &str, tags: Option<Vec<String>>) -> Result<(), Error> {
+        let controller_client = self.client_factory.controller_client();
+        let stream_config = StreamConfiguration {
+            scoped_stream: PravegaStorage::scoped_stream(scope_name, stream_name),
+            scaling: Scaling {
+                scale_type: ScaleType::FixedNumSegments,
+                min_num_segments: 1,
+                ..Default::default()
+            },
+            retention: Default::default(),
+            tags,
+        };
+        self.client_factory.runtime().block_on(controller_client.create_stream(&stream_config)).map_err(|error| {
+            Error::new(ErrorKind::Other, format!("Failed to create Pravega stream {}/{}: {:?}", scope_name, stream_name, error))
+        })?;
+        Ok(())
+    }
+
+    fn stream_exists(&self, scope_name: &str, stream_name: &str) -> Result<bool, Error> {
+        let controller_client = self.client_factory.controller_client();
+        let scoped_stream = PravegaStorage::scoped_stream(scope_name, stream_name);
+        self.client_factory.runtime().block_on(controller_client.check_stream_exists(&scoped_stream)).map_err(|error| {
+            Error::new(ErrorKind::Other, format!("Failed to check Pravega stream {}/{}: {:?}", scope_name, stream_name, error))
+        })
+    }
+
+    fn create_reader(&self, scope_name: &str, stream_name: &str) -> Result<Box<dyn ByteStreamReader>, Error> {
+        let reader: ByteReader = self.client_factory.create_byte_reader(PravegaStorage::scoped_stream(scope_name, stream_name));
+        Ok(Box::new(reader))
+    }
+
+    fn create_writer(&self, scope_name: &str, stream_name: &str) -> Result<Box<dyn ByteStreamWriter>, Error> {
+        let writer = self.client_factory.create_byte_writer(PravegaStorage::scoped_stream(scope_name, stream_name));
+        Ok(Box::new(PravegaByteStreamWriter {
+            writer,
+            client_factory: self.client_factory.clone(),
+        }))
+    }
+}
+
+pub struct PravegaByteStreamWriter {
+    writer: ByteWriter,
+    client_factory: ClientFactory,
+}
+
+impl Write for PravegaByteStreamWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl ByteStreamWriter for PravegaByteStreamWriter {
+    fn current_offset(&self) -> u64 {
+        self.writer.current_write_offset() as u64
+    }
+
+    fn seek_to_tail(&mut self) -> Result<u64, Error> {
+        self.writer.seek_to_tail();
+        Ok(self.current_offset())
+    }
+
+    fn truncate_data_before(&mut self, offset: u64) -> Result<(), Error> {
+        self.client_factory.runtime().block_on(self.writer.truncate_data_before(offset as i64)).map_err(|error| {
+            Error::new(ErrorKind::Other, format!("Failed to truncate Pravega stream: {:?}", error))
+        })
+    }
+
+    fn seal(&mut self) -> Result<(), Error> {
+        self.client_factory.runtime().block_on(self.writer.seal()).map_err(|error| {
+            Error::new(ErrorKind::Other, format!("Failed to seal Pravega stream: {:?}", error))
+        })
+    }
+}
+
+/**
+   Stores byte streams in a local directory, using the same offsets as a Pravega byte stream.
+   This allows recording without a Pravega cluster.
+
+   Each stream is a directory named root/scope/stream that contains:
+
+   - Chunk files named with the 20-digit offset of their first byte, such as 00000000000000000000.chunk.
+     A new chunk is started when the current chunk reaches the chunk size. Writes are never split across chunks.
+   - An optional file named "head" that contains the offset of the first byte that has not been truncated.
+     Chunks that contain only truncated bytes are deleted.
+   - An optional empty file named "sealed" that indicates that the stream has been sealed.
+
+   There must be at most one writer per stream. Readers may be in other processes.
+*/
+pub struct FileStorage {
+    root: PathBuf,
+    chunk_size: u64,
+}
+
+impl FileStorage {
+    pub const DEFAULT_CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+            chunk_size: FileStorage::DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Sets the size at which a new chunk file will be started.
+    pub fn with_chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    fn stream_dir(&self, scope_name: &str, stream_name: &str) -> Result<PathBuf, Error> {
+        let dir = self.root.join(scope_name).join(stream_name);
+        if !dir.is_dir() {
+            return Err(Error::new(ErrorKind::NotFound, format!("Stream {}/{} does not exist in {}",
+                scope_name, stream_name, self.root.display())));
+        }
+        Ok(dir)
+    }
+}
+
+impl StorageBackend for FileStorage {
+    fn create_scope(&self, scope_name: &str) -> Result<(), Error> {
+        fs::create_dir_all(self.root.join(scope_name))
+    }
+
+    fn create_stream(&self, scope_name: &str, stream_name: &str, _tags: Option<Vec<String>>) -> Result<(), Error> {
+        let scope_dir = self.root.join(scope_name);
+        if !scope_dir.is_dir() {
+            return Err(Error::new(ErrorKind::NotFound, format!("Scope {} does not exist in {}",
+                scope_name, self.root.display())));
+        }
+        fs::create_dir_all(scope_dir.join(stream_name))
+    }
+
+    fn stream_exists(&self, scope_name: &str, stream_name: &str) -> Result<bool, Error> {
+        Ok(self.root.join(scope_name).join(stream_name).is_dir())
+    }
+
+    fn create_reader(&self, scope_name: &str, stream_name: &str) -> Result<Box<dyn ByteStreamReader>, Error> {
+        Ok(Box::new(FileByteStreamReader::new(self.stream_dir(scope_name, stream_name)?)))
+    }
+
+    fn create_writer(&self, scope_name: &str, stream_name: &str) -> Result<Box<dyn ByteStreamWriter>, Error> {
+        Ok(Box::new(FileByteStreamWriter::new(self.stream_dir(scope_name, stream_name)?, self.chunk_size)?))
+    }
+}
+
+const HEAD_FILE_NAME: &str = "head";
+const SEALED_FILE_NAME: &str = "sealed";
+const CHUNK_EXTENSION: &str = "chunk";
+
+fn chunk_path(dir: &Path, offset: u64) -> PathBuf {
+    dir.join(format!("{:020}.{}", offset, CHUNK_EXTENSION))
+}
+
+/// Returns the starting offsets of all chunks, in increasing order.
+fn list_chunks(dir: &Path) -> Result<Vec<u64>, Error> {
+    let mut chunks = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) == Some(CHUNK_EXTENSION) {
+            if let Some(offset) = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse::<u64>().ok()) {
+                chunks.push(offset);
+            }
+        }
+    }
+    chunks.sort_unstable();
+    Ok(chunks)
+}
+
+fn read_head(dir: &Path) -> Result<u64, Error> {
+    match fs::read_to_string(dir.join(HEAD_FILE_NAME)) {
+        Ok(s) => s.trim().parse::<u64>().map_err(|e| Error::new(ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns the offset of the end of the last chunk.
+fn read_tail(dir: &Path, chunks: &[u64]) -> Result<u64, Error> {
+    match chunks.last() {
+        Some(&offset) => Ok(offset + fs::metadata(chunk_path(dir, offset))?.len()),
+        None => Ok(0),
+    }
+}
+
+pub struct FileByteStreamReader {
+    dir: PathBuf,
+    position: u64,
+    // The open chunk and the offset of its first byte.
+    chunk: Option<(u64, File)>,
+}
+
+impl FileByteStreamReader {
+    // The interval to poll for new data when reading at the tail.
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            position: 0,
+            chunk: None,
+        }
+    }
+
+    fn is_sealed(&self) -> bool {
+        self.dir.join(SEALED_FILE_NAME).exists()
+    }
+}
+
+impl Read for FileByteStreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some((chunk_offset, file)) = self.chunk.as_mut() {
+                if self.position >= *chunk_offset {
+                    file.seek(SeekFrom::Start(self.position - *chunk_offset))?;
+                    let n = file.read(buf)?;
+                    if n > 0 {
+                        self.position += n as u64;
+                        return Ok(n);
+                    }
+                }
+            }
+            // Find the chunk that contains the position.
+            let head = read_head(&self.dir)?;
+            if self.position < head {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("Offset {} has been truncated; head is {}",
+                    self.position, head)));
+            }
+            let chunks = list_chunks(&self.dir)?;
+            let tail = read_tail(&self.dir, &chunks)?;
+            if self.position < tail {
+                let chunk_offset = *chunks.iter().rev().find(|&&offset| offset <= self.position).ok_or_else(|| {
+                    Error::new(ErrorKind::NotFound, format!("No chunk contains offset {}", self.position))
+                })?;
+                let is_open = matches!(self.chunk, Some((offset, _)) if offset == chunk_offset);
+                if !is_open {
+                    self.chunk = Some((chunk_offset, File::open(chunk_path(&self.dir, chunk_offset))?));
+                    continue;
+                }
+                // The open chunk ends before the position. Only the last chunk can grow, so if there is a later chunk,
+                // the position is in a gap between chunks. This happens in an archive if data was truncated without being archived.
+                if let Some(next_offset) = chunks.iter().find(|&&offset| offset > chunk_offset) {
+                    return Err(Error::new(ErrorKind::InvalidData, format!("Offset {} is in a gap before the chunk at offset {}",
+                        self.position, next_offset)));
+                }
+            } else if self.is_sealed() {
+                return Ok(0);
+            }
+            thread::sleep(FileByteStreamReader::POLL_INTERVAL);
+        }
+    }
+}
+
+impl Seek for FileByteStreamReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => {
+                if delta >= 0 {
+                    self.position.checked_add(delta as u64)
+                } else {
+                    self.position.checked_sub(delta.unsigned_abs())
+                }
+            },
+            SeekFrom::End(delta) => {
+                let tail = read_tail(&self.dir, &list_chunks(&self.dir)?)?;
+                if delta >= 0 {
+                    tail.checked_add(delta as u64)
+                } else {
+                    tail.checked_sub(delta.unsigned_abs())
+                }
+            },
+        };
+        self.position = new_position.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Invalid seek to a negative or overflowing position"))?;
+        Ok(self.position)
+    }
+}
+
+impl CurrentHead for FileByteStreamReader {
+    fn current_head(&self) -> std::io::Result<u64> {
+        read_head(&self.dir)
+    }
+}
+
+pub struct FileByteStreamWriter {
+    dir: PathBuf,
+    // The open chunk, the offset of its first byte, and its length.
+    file: File,
+    chunk_offset: u64,
+    chunk_length: u64,
+    chunk_size: u64,
+}
+
+impl FileByteStreamWriter {
+    pub fn new(dir: PathBuf, chunk_size: u64) -> Result<Self, Error> {
+        let chunks = list_chunks(&dir)?;
+        let chunk_offset = match chunks.last() {
+            Some(&offset) => offset,
+            None => read_head(&dir)?,
+        };
+        let file = OpenOptions::new().create(true).append(true).open(chunk_path(&dir, chunk_offset))?;
+        let chunk_length = file.metadata()?.len();
+        Ok(Self {
+            dir,
+            file,
+            chunk_offset,
+            chunk_length,
+            chunk_size,
+        })
+    }
+
+    fn check_not_sealed(&self) -> Result<(), Error> {
+        if self.dir.join(SEALED_FILE_NAME).exists() {
+            return Err(Error::new(ErrorKind::PermissionDenied, format!("Stream {} is sealed", self.dir.display())));
+        }
+        Ok(())
+    }
+}
+
+impl Write for FileByteStreamWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.check_not_sealed()?;
+        if self.chunk_length >= self.chunk_size {
+            self.flush()?;
+            let chunk_offset = self.chunk_offset + self.chunk_length;
+            debug!("FileByteStreamWriter::write: Starting new chunk at offset {}", chunk_offset);
+            self.file = OpenOptions::new().create(true).append(true).open(chunk_path(&self.dir, chunk_offset))?;
+            self.chunk_offset = chunk_offset;
+            self.chunk_length = 0;
+        }
+        // The entire buffer is written with a single call so that readers never see a partial write.
+        // If the file system accepts only part of the buffer, the partial write is removed.
+        let written = self.file.write(buf)?;
+        if written != buf.len() {
+            self.file.set_len(self.chunk_length)?;
+            return Err(Error::new(ErrorKind::WriteZero, format!("Short write of {} of {} bytes to {}",
+                written, buf.len(), self.dir.display())));
+        }
+        self.chunk_length += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    /// Flushes and syncs the written data to disk so that it survives a crash.
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        self.file.sync_data()
+    }
+}
+
+impl ByteStreamWriter for FileByteStreamWriter {
+    fn current_offset(&self) -> u64 {
+        self.chunk_offset + self.chunk_length
+    }
+
+    fn seek_to_tail(&mut self) -> Result<u64, Error> {
+        // There is only one writer so the writer is always at the tail.
+        Ok(self.current_offset())
+    }
+
+    fn truncate_data_before(&mut self, offset: u64) -> Result<(), Error> {
+        let offset = u64::min(offset, self.current_offset());
+        if offset <= read_head(&self.dir)? {
+            return Ok(());
+        }
+        // Write the new head atomically.
+        let temp_path = self.dir.join(format!("{}.tmp", HEAD_FILE_NAME));
+        fs::write(&temp_path, offset.to_string())?;
+        fs::rename(&temp_path, self.dir.join(HEAD_FILE_NAME))?;
+        // Delete chunks that end at or before the head. The last chunk is never deleted.
+        let chunks = list_chunks(&self.dir)?;
+        for pair in chunks.windows(2) {
+            if pair[1] <= offset {
+                fs::remove_file(chunk_path(&self.dir, pair[0]))?;
+            }
+        }
+        info!("FileByteStreamWriter::truncate_data_before: Truncated {} at offset {}", self.dir.display(), offset);
+        Ok(())
+    }
+
+    fn seal(&mut self) -> Result<(), Error> {
+        self.file.sync_all()?;
+        File::create(self.dir.join(SEALED_FILE_NAME))?;
+        Ok(())
+    }
+}
+
+/// Stores byte streams in the memory of this process.
+/// This is intended for testing elements without a Pravega cluster.
+/// Clones share the same streams.
+/// Reads beyond the tail block until data is written or the stream is sealed.
+/// An outage can be simulated with set_available().
+#[derive(Clone, Default)]
+pub struct MemoryStorage {
+    inner: Arc<Mutex<MemoryStorageInner>>,
+    unavailable: Arc<AtomicBool>,
+}
+
+#[derive(Default)]
+struct MemoryStorageInner {
+    scopes: HashSet<String>,
+    streams: HashMap<(String, String), Arc<MemoryStream>>,
+}
+
+static NAMED_MEMORY_STORAGE: Lazy<Mutex<HashMap<String, MemoryStorage>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the storage with the given name, creating it if needed.
+    /// All callers in this process that use the same name will share the same streams.
+    pub fn named(name: &str) -> Self {
+        let mut storages = NAMED_MEMORY_STORAGE.lock().unwrap();
+        storages.entry(name.to_owned()).or_default().clone()
+    }
+
+    /// While unavailable, creating scopes, streams, and writers and writing to existing writers will fail.
+    /// Reads are not affected.
+    pub fn set_available(&self, available: bool) {
+        self.unavailable.store(!available, Ordering::SeqCst);
+    }
+
+    fn get_stream(&self, scope_name: &str, stream_name: &str) -> Result<Arc<MemoryStream>, Error> {
+        let inner = self.inner.lock().unwrap();
+        inner.streams.get(&(scope_name.to_owned(), stream_name.to_owned())).cloned().ok_or_else(|| {
+            Error::new(ErrorKind::NotFound, format!("Stream {}/{} does not exist", scope_name, stream_name))
+        })
+    }
+}
+
+impl StorageBackend for MemoryStorage {
+    fn create_scope(&self, scope_name: &str) -> Result<(), Error> {
+        check_available(&self.unavailable)?;
+        self.inner.lock().unwrap().scopes.insert(scope_name.to_owned());
+        Ok(())
+    }
+
+    fn create_stream(&self, scope_name: &str, stream_name: &str, _tags: Option<Vec<String>>) -> Result<(), Error> {
+        check_available(&self.unavailable)?;
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.scopes.contains(scope_name) {
+            return Err(Error::new(ErrorKind::NotFound, format!("Scope {} does not exist", scope_name)));
+        }
+        inner.streams.entry((scope_name.to_owned(), stream_name.to_owned())).or_default();
+        Ok(())
+    }
+
+    fn stream_exists(&self, scope_name: &str, stream_name: &str) -> Result<bool, Error> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.streams.contains_key(&(scope_name.to_owned(), stream_name.to_owned())))
+    }
+
+    fn create_reader(&self, scope_name: &str, stream_name: &str) -> Result<Box<dyn ByteStreamReader>, Error> {
+        Ok(Box::new(MemoryByteStreamReader {
+            stream: self.get_stream(scope_name, stream_name)?,
+            position: 0,
+        }))
+    }
+
+    fn create_writer(&self, scope_name: &str, stream_name: &str) -> Result<Box<dyn ByteStreamWriter>, Error> {
+        check_available(&self.unavailable)?;
+        Ok(Box::new(MemoryByteStreamWriter {
+            stream: self.get_stream(scope_name, stream_name)?,
+            unavailable: self.unavailable.clone(),
+        }))
+    }
+}
+
+fn check_available(unavailable: &AtomicBool) -> Result<(), Error> {
+    if unavailable.load(Ordering::SeqCst) {
+        return Err(Error::new(ErrorKind::ConnectionRefused, "Memory storage is unavailable"));
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct MemoryStream {
+    data: Mutex<MemoryStreamData>,
+    // Notified when data is written or the stream is sealed.
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct MemoryStreamData {
+    // The offset of bytes[0].
+    head: u64,
+    bytes: Vec<u8>,
+    sealed: bool,
+}
+
+impl MemoryStreamData {
+    fn tail(&self) -> u64 {
+        self.head + self.bytes.len() as u64
+    }
+}
+
+pub struct MemoryByteStreamReader {
+    stream: Arc<MemoryStream>,
+    position: u64,
+}
+
+impl Read for MemoryByteStreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut data = self.stream.data.lock().unwrap();
+        loop {
+            if self.position < data.head {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("Offset {} has been truncated; head is {}",
+                    self.position, data.head)));
+            }
+            if self.position < data.tail() {
+                let start = (self.position - data.head) as usize;
+                let n = usize::min(buf.len(), data.bytes.len() - start);
+                buf[..n].copy_from_slice(&data.bytes[start..start + n]);
+                self.position += n as u64;
+                return Ok(n);
+            }
+            if data.sealed {
+                return Ok(0);
+            }
+            data = self.stream.changed.wait(data).unwrap();
+        }
+    }
+}
+
+impl Seek for MemoryByteStreamReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(offset) => (offset, 0),
+            SeekFrom::Current(delta) => (self.position, delta),
+            SeekFrom::End(delta) => (self.stream.data.lock().unwrap().tail(), delta),
+        };
+        let new_position = if delta >= 0 {
+            base.checked_add(delta as u64)
+        } else {
+            base.checked_sub(delta.unsigned_abs())
+        };
+        self.position = new_position.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Invalid seek to a negative or overflowing position"))?;
+        Ok(self.position)
+    }
+}
+
+impl CurrentHead for MemoryByteStreamReader {
+    fn current_head(&self) -> std::io::Result<u64> {
+        Ok(self.stream.data.lock().unwrap().head)
+    }
+}
+
+pub struct MemoryByteStreamWriter {
+    stream: Arc<MemoryStream>,
+    unavailable: Arc<AtomicBool>,
+}
+
+impl Write for MemoryByteStreamWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        check_available(&self.unavailable)?;
+        let mut data = self.stream.data.lock().unwrap();
+        if data.sealed {
+            return Err(Error::new(ErrorKind::PermissionDenied, "Stream is sealed"));
+        }
+        data.bytes.extend_from_slice(buf);
+        self.stream.changed.notify_all();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        check_available(&self.unavailable)
+    }
+}
+
+impl ByteStreamWriter for MemoryByteStreamWriter {
+    fn current_offset(&self) -> u64 {
+        self.stream.data.lock().unwrap().tail()
+    }
+
+    fn seek_to_tail(&mut self) -> Result<u64, Error> {
+        check_available(&self.unavailable)?;
+        Ok(self.current_offset())
+    }
+
+    fn truncate_data_before(&mut self, offset: u64) -> Result<(), Error> {
+        let mut data = self.stream.data.lock().unwrap();
+        let offset = u64::min(offset, data.tail());
+        if offset > data.head {
+            let length = (offset - data.head) as usize;
+            data.bytes.drain(..length);
+            data.head = offset;
+        }
+        Ok(())
+    }
+
+    fn seal(&mut self) -> Result<(), Error> {
+        self.stream.data.lock().unwrap().sealed = true;
+        self.stream.changed.notify_all();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::storage::{FileStorage, MemoryStorage, StorageBackend, chunk_path, create_storage_from_uri, parse_file_storage_uri};
+    use crate::utils::CurrentHead;
+    use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+    use std::path::PathBuf;
+    use std::thread;
+
+    #[test]
+    fn test_file_storage() {
+        assert_eq!(parse_file_storage_uri("file:///var/lib/video"), Some(PathBuf::from("/var/lib/video")));
+        assert_eq!(parse_file_storage_uri("tcp://127.0.0.1:9090"), None);
+
+        let root = std::env::temp_dir().join(format!("pravega-video-test-file-storage-{}", std::process::id()));
+        let chunk_size = 1000;
+        let storage = FileStorage::new(&root).with_chunk_size(chunk_size);
+        storage.create_scope("scope1").unwrap();
+        assert!(!storage.stream_exists("scope1", "stream1").unwrap());
+        storage.create_stream("scope1", "stream1", None).unwrap();
+        assert!(storage.stream_exists("scope1", "stream1").unwrap());
+        assert_eq!(storage.create_stream("scope2", "stream1", None).unwrap_err().kind(), ErrorKind::NotFound);
+
+        let mut writer = storage.create_writer("scope1", "stream1").unwrap();
+        assert_eq!(writer.seek_to_tail().unwrap(), 0);
+        // Write enough to create a second chunk.
+        let block = vec![7; (chunk_size / 2) as usize];
+        writer.write_all(&block).unwrap();
+        writer.write_all(&block).unwrap();
+        writer.write_all(&[1, 2, 3]).unwrap();
+        writer.flush().unwrap();
+        let tail = chunk_size + 3;
+        assert_eq!(writer.current_offset(), tail);
+
+        let mut reader = storage.create_reader("scope1", "stream1").unwrap();
+        assert_eq!(reader.current_head().unwrap(), 0);
+        assert_eq!(reader.seek(SeekFrom::End(0)).unwrap(), tail);
+        reader.seek(SeekFrom::Start(chunk_size - 2)).unwrap();
+        let mut buf = [0; 5];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [7, 7, 1, 2, 3]);
+
+        // A new writer appends to the existing stream.
+        let mut writer = storage.create_writer("scope1", "stream1").unwrap();
+        assert_eq!(writer.seek_to_tail().unwrap(), tail);
+        writer.write_all(&[4]).unwrap();
+        writer.flush().unwrap();
+        let mut buf = [0; 1];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [4]);
+
+        // Truncate the first chunk.
+        writer.truncate_data_before(chunk_size + 1).unwrap();
+        assert_eq!(reader.current_head().unwrap(), chunk_size + 1);
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap_err().kind(), ErrorKind::InvalidInput);
+        reader.seek(SeekFrom::Start(chunk_size + 1)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [2]);
+
+        // Reads at the tail of a sealed stream return 0.
+        writer.seal().unwrap();
+        assert!(writer.write_all(&[5]).is_err());
+        reader.seek(SeekFrom::End(0)).unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+
+        // Reads in a gap between chunks fail instead of waiting for data.
+        storage.create_stream("scope1", "stream2", None).unwrap();
+        let mut writer = storage.create_writer("scope1", "stream2").unwrap();
+        writer.write_all(&block).unwrap();
+        writer.write_all(&block).unwrap();
+        writer.write_all(&[1, 2, 3]).unwrap();
+        writer.flush().unwrap();
+        let dir = root.join("scope1").join("stream2");
+        std::fs::rename(chunk_path(&dir, chunk_size), chunk_path(&dir, chunk_size + 10)).unwrap();
+        let mut reader = storage.create_reader("scope1", "stream2").unwrap();
+        reader.seek(SeekFrom::Start(chunk_size - 1)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap_err().kind(), ErrorKind::InvalidData);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_memory_storage() {
+        assert!(create_storage_from_uri("memory://test_memory_storage").is_ok());
+        assert_eq!(create_storage_from_uri("tcp://127.0.0.1:9090").err().unwrap().kind(), ErrorKind::InvalidInput);
+
+        let storage = MemoryStorage::named("test_memory_storage");
+        storage.create_scope("scope1").unwrap();
+        assert_eq!(storage.create_stream("scope2", "stream1", None).unwrap_err().kind(), ErrorKind::NotFound);
+        // Streams are shared by storage with the same name.
+        MemoryStorage::named("test_memory_storage").create_stream("scope1", "stream1", None).unwrap();
+        assert!(storage.stream_exists("scope1", "stream1").unwrap());
+        assert!(!storage.stream_exists("scope1", "stream2").unwrap());
+        let mut writer = storage.create_writer("scope1", "stream1").unwrap();
+        let mut reader = storage.create_reader("scope1", "stream1").unwrap();
+        writer.write_all(&[1, 2, 3]).unwrap();
+        assert_eq!(writer.current_offset(), 3);
+        assert_eq!(reader.seek(SeekFrom::End(0)).unwrap(), 3);
+
+        // A read at the tail blocks until data is written.
+        let reader_thread = thread::spawn(move || {
+            let mut buf = [0; 2];
+            reader.read_exact(&mut buf).unwrap();
+            (reader, buf)
+        });
+        writer.write_all(&[4]).unwrap();
+        writer.write_all(&[5]).unwrap();
+        let (mut reader, buf) = reader_thread.join().unwrap();
+        assert_eq!(buf, [4, 5]);
+
+        // Truncation.
+        writer.truncate_data_before(2).unwrap();
+        assert_eq!(reader.current_head().unwrap(), 2);
+        reader.seek(SeekFrom::Start(1)).unwrap();
+        let mut buf = [0; 1];
+        assert_eq!(reader.read(&mut buf).unwrap_err().kind(), ErrorKind::InvalidInput);
+        reader.seek(SeekFrom::Start(2)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [3]);
+
+        // A read at the tail of a sealed stream returns 0.
+        reader.seek(SeekFrom::End(0)).unwrap();
+        let reader_thread = thread::spawn(move || {
+            let mut buf = [0; 1];
+            reader.read(&mut buf).unwrap()
+        });
+        writer.seal().unwrap();
+        assert_eq!(reader_thread.join().unwrap(), 0);
+        assert!(writer.write_all(&[6]).is_err());
+
+        // Simulated outage.
+        storage.set_available(false);
+        assert_eq!(storage.create_writer("scope1", "stream1").err().unwrap().kind(), ErrorKind::ConnectionRefused);
+        assert_eq!(storage.create_stream("scope1", "stream2", None).err().unwrap().kind(), ErrorKind::ConnectionRefused);
+        storage.set_available(true);
+        assert!(storage.create_writer("scope1", "stream1").is_ok());
+    }
+}
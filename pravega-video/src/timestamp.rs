@@ -8,13 +8,15 @@
 // http://www.apache.org/licenses/LICENSE-2.0
 //
 
-use anyhow;
+use anyhow::anyhow;
 use std::convert::{TryInto, TryFrom};
 use std::fmt;
 use std::ops::{Add, Mul, Sub, Div};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::leap_seconds;
 
 /// This stores the number of nanoseconds since the TAI epoch 1970-01-01 00:00 TAI (International Atomic Time).
+/// Conversions to and from UTC use the leap second table in the [leap_seconds] module.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Default)]
 pub struct PravegaTimestamp(pub Option<u64>);
 
@@ -28,16 +30,6 @@ impl PravegaTimestamp {
     // See [https://stackoverflow.com/a/29138806/5890553].
     pub const UNIX_TO_NTP_SECONDS: u64 = (70 * 365 + 17) * 24 * 60 * 60;
 
-    // UTC to TAI offset.
-    // Below is valid for dates between 2017-01-01 and the next leap second.
-    // Beyond this range, we must use a table that incorporates the leap second schedule.
-    // See [https://en.wikipedia.org/wiki/International_Atomic_Time].
-    const UTC_TO_TAI_SECONDS: u64 = 37;
-
-    // No leap seconds occurred within this many seconds of the TAI epoch.
-    // The first leap second occurred on 1972-01-01.
-    const ZERO_LEAP_SECONDS_UNTIL_SECONDS: u64 = (1972 - 1970) * 365 * 24 * 60 * 60;
-
     // Create a PravegaTimestamp from the number of nanoseconds since the TAI epoch 1970-01-01 00:00:00 TAI.
     pub fn from_nanoseconds(nanoseconds: Option<u64>) -> PravegaTimestamp {
         PravegaTimestamp(nanoseconds)
@@ -45,33 +37,59 @@ impl PravegaTimestamp {
 
     /// Create a PravegaTimestamp from the number of nanoseconds since the NTP epoch 1900-01-01 00:00:00 UTC,
     /// minus leap seconds.
-    /// A time that cannot be represented will return a None timestamp.
-    /// TODO: Return an error if time cannot be represented.
+    /// A time that cannot be represented or is ambiguous will return a None timestamp.
     pub fn from_ntp_nanoseconds(nanoseconds: Option<u64>) -> PravegaTimestamp {
         match nanoseconds {
-            Some(nanoseconds) => {
-                if nanoseconds >= PravegaTimestamp::UNIX_TO_NTP_SECONDS * 1_000_000_000 {
-                    PravegaTimestamp::from_unix_nanoseconds(Some(nanoseconds - PravegaTimestamp::UNIX_TO_NTP_SECONDS * 1_000_000_000))
-                } else {
-                    PravegaTimestamp(None)
-                }
-            },
+            Some(nanoseconds) => PravegaTimestamp::try_from_ntp_nanoseconds(nanoseconds).unwrap_or(PravegaTimestamp::NONE),
             None => PravegaTimestamp(None),
         }
     }
 
+    /// Create a PravegaTimestamp from the number of nanoseconds since the NTP epoch 1900-01-01 00:00:00 UTC,
+    /// minus leap seconds.
+    /// Returns an error if the time is before the Unix epoch or is ambiguous because it is within a leap second.
+    pub fn try_from_ntp_nanoseconds(nanoseconds: u64) -> anyhow::Result<PravegaTimestamp> {
+        let unix_nanoseconds = nanoseconds.checked_sub(PravegaTimestamp::UNIX_TO_NTP_SECONDS * 1_000_000_000)
+            .ok_or_else(|| anyhow!("NTP time {} ns is before the Unix epoch", nanoseconds))?;
+        PravegaTimestamp::try_from_unix_nanoseconds(unix_nanoseconds)
+    }
+
     /// Create a PravegaTimestamp from the number of nanoseconds since the Unix epoch 1970-01-01 00:00:00 UTC,
     /// minus leap seconds.
-    /// TODO: Return an error if time cannot be represented.
+    /// A time that cannot be represented or is ambiguous will return a None timestamp.
     pub fn from_unix_nanoseconds(nanoseconds: Option<u64>) -> PravegaTimestamp {
         match nanoseconds {
-            Some(nanoseconds) => PravegaTimestamp(Some(nanoseconds + PravegaTimestamp::UTC_TO_TAI_SECONDS * 1_000_000_000)),
+            Some(nanoseconds) => PravegaTimestamp::try_from_unix_nanoseconds(nanoseconds).unwrap_or(PravegaTimestamp::NONE),
             None => PravegaTimestamp(None),
         }
     }
 
+    /// Create a PravegaTimestamp from the number of nanoseconds since the Unix epoch 1970-01-01 00:00:00 UTC,
+    /// minus leap seconds.
+    /// Returns an error if the time is ambiguous because it is within the second following a leap second.
+    pub fn try_from_unix_nanoseconds(nanoseconds: u64) -> anyhow::Result<PravegaTimestamp> {
+        let tai_nanoseconds = leap_seconds::current_table().unix_to_tai(nanoseconds)?;
+        Ok(PravegaTimestamp(Some(tai_nanoseconds)))
+    }
+
+    /// Create a PravegaTimestamp from the number of nanoseconds since the Unix epoch 1970-01-01 00:00:00 UTC,
+    /// minus leap seconds.
+    /// A time within the second following a leap second is ambiguous and is resolved to the earlier TAI time,
+    /// which is within the leap second. Use is_leap_second() on the result to detect this.
+    pub fn try_from_unix_nanoseconds_earlier(nanoseconds: u64) -> anyhow::Result<PravegaTimestamp> {
+        let tai_nanoseconds = leap_seconds::current_table().unix_to_tai_earlier(nanoseconds)?;
+        Ok(PravegaTimestamp(Some(tai_nanoseconds)))
+    }
+
+    /// Returns the current time.
+    /// During the second following a leap second, the system clock is ambiguous and the earlier TAI time is returned.
     pub fn now() -> PravegaTimestamp {
-        PravegaTimestamp::from(SystemTime::now())
+        let nanoseconds = SystemTime::now().duration_since(UNIX_EPOCH).ok()
+            .and_then(|duration| u64::try_from(duration.as_nanos()).ok());
+        match nanoseconds {
+            Some(nanoseconds) => PravegaTimestamp::try_from_unix_nanoseconds_earlier(nanoseconds).unwrap_or(PravegaTimestamp::NONE),
+            None => PravegaTimestamp::NONE,
+        }
     }
 
     pub const fn none() -> Self {
@@ -91,29 +109,39 @@ impl PravegaTimestamp {
         self.0
     }
 
+    /// Return the number of nanoseconds since the Unix epoch 1970-01-01 00:00:00 UTC, minus leap seconds.
+    /// A time within a leap second will return the same value as the second following it.
     pub fn to_unix_nanoseconds(&self) -> Option<u64> {
+        self.nanoseconds().map(|nanoseconds| {
+            let (seconds, subsec_nanoseconds) = leap_seconds::current_table().tai_to_utc(nanoseconds);
+            seconds * 1_000_000_000 + subsec_nanoseconds as u64
+        })
+    }
+
+    /// Returns true if this timestamp is within a leap second.
+    pub fn is_leap_second(&self) -> bool {
         match self.nanoseconds() {
-            Some(nanoseconds) => {
-                if nanoseconds < PravegaTimestamp::ZERO_LEAP_SECONDS_UNTIL_SECONDS * 1_000_000_000 {
-                    Some(nanoseconds)
-                } else {
-                    Some(nanoseconds - PravegaTimestamp::UTC_TO_TAI_SECONDS * 1_000_000_000)
-                }
-            },
-            None => None,
+            Some(nanoseconds) => leap_seconds::current_table().is_leap_second(nanoseconds),
+            None => false,
         }
     }
 
+    /// Convert to a UTC date and time.
+    /// Unlike Unix time, this can represent a leap second such as 2016-12-31T23:59:60Z.
+    pub fn to_utc_datetime(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.nanoseconds().and_then(|nanoseconds| {
+            let (seconds, subsec_nanoseconds) = leap_seconds::current_table().tai_to_utc(nanoseconds);
+            let seconds = i64::try_from(seconds).ok()?;
+            let naive = chrono::NaiveDateTime::from_timestamp_opt(seconds, subsec_nanoseconds)?;
+            Some(chrono::DateTime::from_utc(naive, chrono::Utc))
+        })
+    }
+
     pub fn to_iso_8601(&self) -> Option<String> {
-        match self.nanoseconds() {
-            Some(_) => {
-                let system_time: SystemTime = (*self).into();
-                let datetime: chrono::DateTime<chrono::offset::Utc> = system_time.into();
-                let formatted_time = datetime.format("%Y-%m-%dT%T.%9fZ");
-                Some(format!("{}", formatted_time))
-                },
-            None => None,
-        }
+        self.to_utc_datetime().map(|datetime| {
+            let formatted_time = datetime.format("%Y-%m-%dT%T.%9fZ");
+            format!("{}", formatted_time)
+        })
     }
 
     /// Convert to format h:mm:ss.fffffffff
@@ -140,33 +168,48 @@ impl PravegaTimestamp {
     }
 }
 
-// TODO: Implement TryFrom.
-impl From<PravegaTimestamp> for SystemTime {
-    fn from(t: PravegaTimestamp) -> SystemTime {
+/// SystemTime cannot represent a leap second so this will return an error for a time within a leap second.
+impl TryFrom<PravegaTimestamp> for SystemTime {
+    type Error = anyhow::Error;
+
+    fn try_from(t: PravegaTimestamp) -> Result<Self, Self::Error> {
+        if t.is_leap_second() {
+            return Err(anyhow!("Timestamp {} is within a leap second and cannot be represented as SystemTime", t));
+        }
         match t.to_unix_nanoseconds() {
-            Some(nanoseconds) => UNIX_EPOCH + Duration::from_nanos(nanoseconds),
-            None => UNIX_EPOCH,
+            Some(nanoseconds) => Ok(UNIX_EPOCH + Duration::from_nanos(nanoseconds)),
+            None => Err(anyhow!("Timestamp is None")),
         }
     }
 }
 
-// TODO: Implement TryFrom.
-impl From<SystemTime> for PravegaTimestamp {
-    fn from(t: SystemTime) -> PravegaTimestamp {
-        let nanoseconds: Option<u64> = match t.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_nanos().try_into() {
-            Ok(nanoseconds) => Some(nanoseconds),
-            Err(_) => None,
-        };
-        PravegaTimestamp::from_unix_nanoseconds(nanoseconds)
+impl TryFrom<SystemTime> for PravegaTimestamp {
+    type Error = anyhow::Error;
+
+    fn try_from(t: SystemTime) -> Result<Self, Self::Error> {
+        let nanoseconds: u64 = t.duration_since(SystemTime::UNIX_EPOCH)?.as_nanos().try_into()?;
+        PravegaTimestamp::try_from_unix_nanoseconds(nanoseconds)
+    }
+}
+
+/// chrono::DateTime can represent a leap second so this conversion is never ambiguous.
+impl TryFrom<chrono::DateTime<chrono::Utc>> for PravegaTimestamp {
+    type Error = anyhow::Error;
+
+    fn try_from(t: chrono::DateTime<chrono::Utc>) -> Result<Self, Self::Error> {
+        let seconds = u64::try_from(t.timestamp())?;
+        let tai_nanoseconds = leap_seconds::current_table().utc_to_tai(seconds, t.timestamp_subsec_nanos())?;
+        Ok(PravegaTimestamp(Some(tai_nanoseconds)))
     }
 }
 
-// TODO: Implement TryFrom.
-impl From<Option<chrono::DateTime<chrono::Utc>>> for PravegaTimestamp {
-    fn from(t: Option<chrono::DateTime<chrono::Utc>>) -> PravegaTimestamp {
+impl TryFrom<Option<chrono::DateTime<chrono::Utc>>> for PravegaTimestamp {
+    type Error = anyhow::Error;
+
+    fn try_from(t: Option<chrono::DateTime<chrono::Utc>>) -> Result<Self, Self::Error> {
         match t {
-            Some(t) => PravegaTimestamp::from_unix_nanoseconds(u64::try_from(t.timestamp_nanos()).ok()),
-            None => PravegaTimestamp::NONE,
+            Some(t) => PravegaTimestamp::try_from(t),
+            None => Ok(PravegaTimestamp::NONE),
         }
     }
 }
@@ -178,8 +221,7 @@ impl TryFrom<Option<&str>> for PravegaTimestamp {
         match t {
             Some(t) => {
                 let dt = chrono::DateTime::parse_from_rfc3339(t)?;
-                let nanos = u64::try_from(dt.timestamp_nanos())?;
-                Ok(PravegaTimestamp::from_unix_nanoseconds(Some(nanos)))
+                PravegaTimestamp::try_from(dt.with_timezone(&chrono::Utc))
             },
             None => Ok(PravegaTimestamp::NONE),
         }
@@ -197,9 +239,7 @@ impl TryFrom<Option<String>> for PravegaTimestamp {
 impl TryFrom<&String> for PravegaTimestamp {
     type Error = anyhow::Error;
     fn try_from(t:&String) -> Result<Self, Self::Error> {
-        let dt = chrono::DateTime::parse_from_rfc3339(t)?;
-        let nanos = u64::try_from(dt.timestamp_nanos())?;
-        Ok(PravegaTimestamp::from_unix_nanoseconds(Some(nanos)))
+        PravegaTimestamp::try_from(Some(t.as_str()))
     }
 }
 
@@ -215,13 +255,8 @@ impl TryFrom<String> for PravegaTimestamp {
 /// For example: 2001-02-03T04:00:04.200000000Z
 impl fmt::Display for PravegaTimestamp {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        match self.nanoseconds() {
-            Some(_) => {
-                let system_time: SystemTime = (*self).into();
-                let datetime: chrono::DateTime<chrono::offset::Utc> = system_time.into();
-                let formatted_time = datetime.format("%Y-%m-%dT%T.%9fZ");
-                f.write_fmt(format_args!("{}", formatted_time))
-                },
+        match self.to_iso_8601() {
+            Some(formatted_time) => f.write_str(&formatted_time),
             None => f.write_str("None"),
         }
     }
@@ -231,14 +266,14 @@ impl fmt::Display for PravegaTimestamp {
 /// For example: 2001-02-03T04:00:04.100000000Z (981172841100000000 ns, 272548:00:41.100000000)
 impl fmt::Debug for PravegaTimestamp {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        match self.nanoseconds() {
-            Some(nanoseconds) => {
-                let system_time: SystemTime = (*self).into();
-                let datetime: chrono::DateTime<chrono::offset::Utc> = system_time.into();
-                let formatted_time = datetime.format("%Y-%m-%dT%T.%9fZ");
+        match (self.nanoseconds(), self.to_iso_8601()) {
+            (Some(nanoseconds), Some(formatted_time)) => {
                 f.write_fmt(format_args!("{} ({} ns, {})", formatted_time, nanoseconds, self.to_hms().unwrap_or_default()))
                 },
-            None => f.write_str("None"),
+            (Some(nanoseconds), None) => {
+                f.write_fmt(format_args!("{} ns ({})", nanoseconds, self.to_hms().unwrap_or_default()))
+                },
+            (None, _) => f.write_str("None"),
         }
     }
 }
@@ -570,4 +605,39 @@ mod test {
         let s2 = pt1.to_iso_8601().unwrap();
         assert_eq!(s1, s2);
     }
+
+    #[test]
+    fn test_pravega_timestamp_leap_seconds() {
+        // The leap second 2016-12-31T23:59:60Z separates these timestamps.
+        let pt1 = PravegaTimestamp::try_from(Some("2016-12-31T23:59:59.500000000Z")).unwrap();
+        let pt2 = PravegaTimestamp::try_from(Some("2016-12-31T23:59:60.500000000Z")).unwrap();
+        let pt3 = PravegaTimestamp::try_from(Some("2017-01-01T00:00:00.500000000Z")).unwrap();
+        assert_eq!(pt2 - pt1, SECOND);
+        assert_eq!(pt3 - pt2, SECOND);
+        assert!(!pt1.is_leap_second());
+        assert!(pt2.is_leap_second());
+        assert!(!pt3.is_leap_second());
+        assert_eq!(pt1.to_iso_8601().unwrap(), "2016-12-31T23:59:59.500000000Z");
+        assert_eq!(pt2.to_iso_8601().unwrap(), "2016-12-31T23:59:60.500000000Z");
+        assert_eq!(pt3.to_iso_8601().unwrap(), "2017-01-01T00:00:00.500000000Z");
+        assert!(SystemTime::try_from(pt1).is_ok());
+        assert!(SystemTime::try_from(pt2).is_err());
+        assert_eq!(pt2.to_unix_nanoseconds(), pt3.to_unix_nanoseconds());
+
+        // TAI-UTC was 35 seconds in 2014 and 37 seconds in 2018.
+        let pt4 = PravegaTimestamp::try_from(Some("2014-06-01T00:00:00.000000000Z")).unwrap();
+        assert_eq!(pt4.nanoseconds().unwrap(), (1401580800 + 35) * 1_000_000_000);
+        let pt5 = PravegaTimestamp::try_from(Some("2018-06-01T00:00:00.000000000Z")).unwrap();
+        assert_eq!(pt5.nanoseconds().unwrap(), (1527811200 + 37) * 1_000_000_000);
+        assert_eq!(pt5.to_unix_nanoseconds().unwrap(), 1527811200 * 1_000_000_000);
+        assert_eq!(PravegaTimestamp::from_unix_nanoseconds(Some(1401580800 * 1_000_000_000)), pt4);
+
+        // Unix time during the second after a leap second is ambiguous.
+        assert!(PravegaTimestamp::try_from_unix_nanoseconds(1483228800 * 1_000_000_000 + 500_000_000).is_err());
+        assert_eq!(PravegaTimestamp::from_unix_nanoseconds(Some(1483228800 * 1_000_000_000 + 500_000_000)), PravegaTimestamp::NONE);
+        assert!(PravegaTimestamp::try_from(Some("2017-12-31T23:59:60.000000000Z")).is_err());
+        // The ambiguous time can be resolved to the earlier time within the leap second.
+        assert_eq!(PravegaTimestamp::try_from_unix_nanoseconds_earlier(1483228800 * 1_000_000_000 + 500_000_000).unwrap(), pt2);
+        assert_eq!(PravegaTimestamp::try_from_unix_nanoseconds_earlier(1483228801 * 1_000_000_000 + 500_000_000).unwrap(), pt3 + SECOND);
+    }
 }
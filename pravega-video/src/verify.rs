@@ -0,0 +1,443 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Module for verifying the consistency of a data stream and its index.
+
+use crate::event_serde::{ChecksumStatus, EventReader};
+use crate::index::{IndexRecord, IndexRecordReader};
+use crate::timestamp::PravegaTimestamp;
+use crate::utils::CurrentHead;
+use std::io::{BufReader, Error, ErrorKind, Read, Seek, SeekFrom};
+use tracing::{debug, trace};
+
+/// The types of problems that can be found by verify_streams().
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ViolationKind {
+    /// An event in the data stream could not be decoded.
+    UndecodableEvent,
+    /// The checksum of an event in the data stream did not match its content.
+    ChecksumMismatch,
+    /// A record in the index stream could not be decoded.
+    UndecodableIndexRecord,
+    /// An index record has an offset that is not the beginning of an event,
+    /// or is the beginning of a continuation fragment.
+    IndexOffsetNotOnEventBoundary,
+    /// An index record has an offset that has been truncated from the data stream.
+    IndexOffsetTruncated,
+    /// An index record has an offset beyond the end of the data stream.
+    IndexOffsetBeyondTail,
+    /// An index record has a lower offset than the previous index record.
+    NonMonotonicIndexOffset,
+    /// An index record has an earlier timestamp than the previous index record and is not a discontinuity.
+    NonMonotonicIndexTimestamp,
+    /// An event has an earlier timestamp than the previous event and is not a discontinuity.
+    NonMonotonicEventTimestamp,
+    /// An event between the first and last index records of a span has a timestamp outside of their timestamps.
+    /// A span is a sequence of index records that ends before the next record with a discontinuity.
+    EventOutsideIndexBounds,
+    /// The last index record does not point to the end of the data stream.
+    /// This is expected while a writer is active.
+    MissingFinalRecord,
+}
+
+impl ViolationKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ViolationKind::UndecodableEvent => "undecodable-event",
+            ViolationKind::ChecksumMismatch => "checksum-mismatch",
+            ViolationKind::UndecodableIndexRecord => "undecodable-index-record",
+            ViolationKind::IndexOffsetNotOnEventBoundary => "index-offset-not-on-event-boundary",
+            ViolationKind::IndexOffsetTruncated => "index-offset-truncated",
+            ViolationKind::IndexOffsetBeyondTail => "index-offset-beyond-tail",
+            ViolationKind::NonMonotonicIndexOffset => "non-monotonic-index-offset",
+            ViolationKind::NonMonotonicIndexTimestamp => "non-monotonic-index-timestamp",
+            ViolationKind::NonMonotonicEventTimestamp => "non-monotonic-event-timestamp",
+            ViolationKind::EventOutsideIndexBounds => "event-outside-index-bounds",
+            ViolationKind::MissingFinalRecord => "missing-final-record",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    /// The offset in the data stream of the event, if applicable.
+    pub data_offset: Option<u64>,
+    /// The offset in the index stream of the index record, if applicable.
+    pub index_offset: Option<u64>,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub data_head_offset: u64,
+    pub data_tail_offset: u64,
+    pub index_head_offset: u64,
+    pub index_tail_offset: u64,
+    /// The number of events read from the data stream, including fragments.
+    pub event_count: u64,
+    pub index_record_count: u64,
+    /// The first and last timestamps of the events in the data stream.
+    pub first_timestamp: PravegaTimestamp,
+    pub last_timestamp: PravegaTimestamp,
+    /// The total number of violations found. Only the first MAX_REPORTED_VIOLATIONS are included in violations.
+    pub violation_count: u64,
+    pub violations: Vec<Violation>,
+}
+
+impl VerifyReport {
+    pub const MAX_REPORTED_VIOLATIONS: usize = 1000;
+
+    pub fn is_ok(&self) -> bool {
+        self.violation_count == 0
+    }
+
+    fn add(&mut self, kind: ViolationKind, data_offset: Option<u64>, index_offset: Option<u64>, message: String) {
+        trace!("VerifyReport::add: kind={:?}, data_offset={:?}, index_offset={:?}, message={}",
+            kind, data_offset, index_offset, message);
+        self.violation_count += 1;
+        if self.violations.len() < VerifyReport::MAX_REPORTED_VIOLATIONS {
+            self.violations.push(Violation { kind, data_offset, index_offset, message });
+        }
+    }
+}
+
+/// Reads an entire data stream and its index, and reports any violations of the constraints defined in index.rs.
+/// Only the data and index records that exist when this is called are verified.
+/// Returns an error only if a stream cannot be read. Problems with the content of the streams are reported as violations.
+pub fn verify_streams<D, I>(data_reader: D, index_reader: I) -> Result<VerifyReport, Error>
+where
+    D: Read + Seek + CurrentHead,
+    I: Read + Seek + CurrentHead,
+{
+    let mut report = VerifyReport::default();
+    let index_records = read_index(index_reader, &mut report)?;
+
+    let data_head_offset = data_reader.current_head()?;
+    let mut data_reader = BufReader::new(data_reader);
+    let data_tail_offset = data_reader.seek(SeekFrom::End(0))?;
+    report.data_head_offset = data_head_offset;
+    report.data_tail_offset = data_tail_offset;
+    debug!("verify_streams: data_head_offset={}, data_tail_offset={}, index_records={}",
+        data_head_offset, data_tail_offset, index_records.len());
+
+    // Check the sequence of index records.
+    let mut prev: Option<&(u64, IndexRecord)> = None;
+    for item in index_records.iter() {
+        let (index_offset, record) = item;
+        if record.offset < data_head_offset {
+            report.add(ViolationKind::IndexOffsetTruncated, Some(record.offset), Some(*index_offset),
+                format!("Index record points to offset {} but the data stream begins at {}", record.offset, data_head_offset));
+        } else if record.offset > data_tail_offset {
+            report.add(ViolationKind::IndexOffsetBeyondTail, Some(record.offset), Some(*index_offset),
+                format!("Index record points to offset {} but the data stream ends at {}", record.offset, data_tail_offset));
+        }
+        if let Some((_, prev_record)) = prev {
+            if record.offset < prev_record.offset {
+                report.add(ViolationKind::NonMonotonicIndexOffset, Some(record.offset), Some(*index_offset),
+                    format!("Index record offset {} is less than the previous offset {}", record.offset, prev_record.offset));
+            }
+            if record.timestamp < prev_record.timestamp && !record.discontinuity {
+                report.add(ViolationKind::NonMonotonicIndexTimestamp, Some(record.offset), Some(*index_offset),
+                    format!("Index record timestamp {} is earlier than the previous timestamp {}",
+                        record.timestamp, prev_record.timestamp));
+            }
+        }
+        prev = Some(item);
+    }
+
+    // Timestamps of events between the first and last index records of a span must be within the timestamps
+    // of these records. Timestamps may jump at a discontinuity so each span is checked separately.
+    let mut spans: Vec<(&IndexRecord, &IndexRecord)> = Vec::new();
+    let mut span_first: Option<&IndexRecord> = None;
+    for (i, (_, record)) in index_records.iter().enumerate() {
+        if span_first.is_none() || record.discontinuity {
+            span_first = Some(record);
+        }
+        let next_is_discontinuity = index_records.get(i + 1).map_or(true, |(_, next)| next.discontinuity);
+        if let Some(first) = span_first.filter(|first| next_is_discontinuity && first.offset < record.offset) {
+            spans.push((first, record));
+        }
+    }
+    spans.sort_by_key(|(first, _)| first.offset);
+    let mut spans = spans.into_iter().peekable();
+
+    // Index offsets that should be at event boundaries, in order of offset.
+    let mut pending_offsets: Vec<(u64, u64)> = index_records.iter()
+        .filter(|(_, record)| record.offset >= data_head_offset && record.offset < data_tail_offset)
+        .map(|(index_offset, record)| (record.offset, *index_offset))
+        .collect();
+    pending_offsets.sort_unstable();
+    let mut pending_offsets = pending_offsets.into_iter().peekable();
+
+    // Read all events in the data stream.
+    data_reader.seek(SeekFrom::Start(data_head_offset))?;
+    let mut event_reader = EventReader::new();
+    let mut buffer = Vec::new();
+    let mut offset = data_head_offset;
+    let mut last_event_timestamp = PravegaTimestamp::NONE;
+    while offset < data_tail_offset {
+        let result = (|| {
+            if offset + 8 > data_tail_offset {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "Event length extends beyond the end of the data stream"));
+            }
+            let required_buffer_length = event_reader.read_required_buffer_length(&mut data_reader)?;
+            if offset + required_buffer_length as u64 > data_tail_offset {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "Event extends beyond the end of the data stream"));
+            }
+            if buffer.len() < required_buffer_length {
+                buffer.resize(required_buffer_length, 0);
+            }
+            let event = event_reader.read_event(&mut data_reader, &mut buffer[..])?;
+            let header = event.header;
+            EventReader::validate_header(&buffer[..])?;
+            Ok((header, required_buffer_length))
+        })();
+        let (header, event_length) = match result {
+            Ok(result) => result,
+            Err(err) => {
+                report.add(ViolationKind::UndecodableEvent, Some(offset), None,
+                    format!("Unable to decode event: {}", err));
+                // Find the next valid event.
+                match event_reader.scan_for_header(&mut data_reader, offset + 1) {
+                    Ok(next_offset) if next_offset < data_tail_offset => {
+                        debug!("verify_streams: Skipped corrupt bytes from offset {} to {}", offset, next_offset);
+                        offset = next_offset;
+                        continue;
+                    },
+                    Ok(_) => break,
+                    Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                    Err(err) => return Err(err),
+                }
+            },
+        };
+        report.event_count += 1;
+        if let ChecksumStatus::Invalid { expected, actual } = event_reader.checksum_status() {
+            report.add(ViolationKind::ChecksumMismatch, Some(offset), None,
+                format!("Event checksum {:08x} does not match calculated checksum {:08x}", expected, actual));
+        }
+
+        // Check index records that point to this event or to bytes that were skipped.
+        while let Some((record_offset, index_offset)) = pending_offsets.next_if(|(record_offset, _)| *record_offset <= offset) {
+            if record_offset < offset {
+                report.add(ViolationKind::IndexOffsetNotOnEventBoundary, Some(record_offset), Some(index_offset),
+                    format!("Index record points to offset {} which is not the beginning of an event", record_offset));
+            } else if header.continuation {
+                report.add(ViolationKind::IndexOffsetNotOnEventBoundary, Some(record_offset), Some(index_offset),
+                    format!("Index record points to offset {} which is a continuation fragment", record_offset));
+            }
+        }
+
+        if header.timestamp.is_some() && !header.continuation {
+            if last_event_timestamp.is_some() && header.timestamp < last_event_timestamp && !header.discontinuity {
+                report.add(ViolationKind::NonMonotonicEventTimestamp, Some(offset), None,
+                    format!("Event timestamp {} is earlier than the previous timestamp {}",
+                        header.timestamp, last_event_timestamp));
+            }
+            while spans.next_if(|(_, last)| last.offset <= offset).is_some() {}
+            if let Some((first, last)) = spans.peek() {
+                if offset >= first.offset
+                        && (header.timestamp < first.timestamp || header.timestamp >= last.timestamp) {
+                    report.add(ViolationKind::EventOutsideIndexBounds, Some(offset), None,
+                        format!("Event timestamp {} is not between the index timestamps {} and {} of its span",
+                            header.timestamp, first.timestamp, last.timestamp));
+                }
+            }
+            if report.first_timestamp.is_none() {
+                report.first_timestamp = header.timestamp;
+            }
+            report.last_timestamp = header.timestamp;
+            last_event_timestamp = header.timestamp;
+        }
+        offset += event_length as u64;
+    }
+
+    // Any remaining index records point into the corrupt bytes at the end of the data stream.
+    for (record_offset, index_offset) in pending_offsets {
+        report.add(ViolationKind::IndexOffsetNotOnEventBoundary, Some(record_offset), Some(index_offset),
+            format!("Index record points to offset {} which is not the beginning of an event", record_offset));
+    }
+
+    // The last index record should point to the end of the data stream.
+    if data_tail_offset > data_head_offset {
+        match index_records.last() {
+            Some((index_offset, record)) if record.offset != data_tail_offset => {
+                report.add(ViolationKind::MissingFinalRecord, Some(record.offset), Some(*index_offset),
+                    format!("Last index record points to offset {} but the data stream ends at {}",
+                        record.offset, data_tail_offset));
+            },
+            None => {
+                report.add(ViolationKind::MissingFinalRecord, None, None, "Index is empty".to_owned());
+            },
+            _ => {},
+        }
+    }
+
+    debug!("verify_streams: event_count={}, violation_count={}", report.event_count, report.violation_count);
+    Ok(report)
+}
+
+/// Reads all records in the index stream. Returns the offset in the index stream and the record.
+fn read_index<I>(index_reader: I, report: &mut VerifyReport) -> Result<Vec<(u64, IndexRecord)>, Error>
+where
+    I: Read + Seek + CurrentHead,
+{
+    let index_head_offset = index_reader.current_head()?;
+    let mut index_reader = BufReader::new(index_reader);
+    let index_tail_offset = index_reader.seek(SeekFrom::End(0))?;
+    report.index_head_offset = index_head_offset;
+    report.index_tail_offset = index_tail_offset;
+    let record_size = IndexRecord::RECORD_SIZE as u64;
+    // IndexSearcher requires that records begin at a multiple of the record size.
+    if index_head_offset % record_size != 0 {
+        report.add(ViolationKind::UndecodableIndexRecord, None, Some(index_head_offset),
+            format!("Index stream begins at offset {} which is not a multiple of the record size", index_head_offset));
+    }
+    if index_tail_offset % record_size != 0 {
+        report.add(ViolationKind::UndecodableIndexRecord, None, Some(index_tail_offset - index_tail_offset % record_size),
+            format!("Index stream ends at offset {} which is not a multiple of the record size", index_tail_offset));
+    }
+    index_reader.seek(SeekFrom::Start(index_head_offset))?;
+    let mut index_record_reader = IndexRecordReader::new();
+    let mut records = Vec::new();
+    let mut index_offset = index_head_offset;
+    while index_offset + record_size <= index_tail_offset {
+        match index_record_reader.read(&mut index_reader) {
+            Ok(record) => {
+                if record.timestamp.is_none() {
+                    report.add(ViolationKind::UndecodableIndexRecord, Some(record.offset), Some(index_offset),
+                        "Index record has a timestamp of 0".to_owned());
+                }
+                records.push((index_offset, record));
+            },
+            // The full record was read so the next record can still be read.
+            Err(err) if err.kind() == ErrorKind::InvalidData => {
+                report.add(ViolationKind::UndecodableIndexRecord, None, Some(index_offset),
+                    format!("Unable to decode index record: {}", err));
+            },
+            Err(err) => return Err(err),
+        }
+        index_offset += record_size;
+    }
+    report.index_record_count = records.len() as u64;
+    Ok(records)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::event_serde::{EventWithHeader, EventWriter};
+    use crate::index::{IndexRecord, IndexRecordWriter};
+    use crate::timestamp::PravegaTimestamp;
+    use crate::verify::{ViolationKind, verify_streams};
+    use std::io::{Cursor, Seek, Write};
+
+    fn kinds(violations: &[crate::verify::Violation]) -> Vec<ViolationKind> {
+        violations.iter().map(|v| v.kind).collect()
+    }
+
+    #[test]
+    fn test_verify_streams() {
+        let ts = |t: u64| PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000 + t));
+        let payload = vec![0; 100];
+        let mut data = Cursor::new(Vec::new());
+        let mut event_writer = EventWriter::new();
+        let mut offsets = Vec::new();
+        for i in 0..4 {
+            offsets.push(data.stream_position().unwrap());
+            let event = EventWithHeader::new(&payload, ts(i * 100), true, true, i == 0);
+            event_writer.write(&event, &mut data).unwrap();
+        }
+        let end_offset = data.stream_position().unwrap();
+        let write_index = |records: &[IndexRecord]| {
+            let mut index = Cursor::new(Vec::new());
+            let mut index_record_writer = IndexRecordWriter::new();
+            for record in records {
+                index_record_writer.write(record, &mut index).unwrap();
+            }
+            index
+        };
+
+        // A consistent stream.
+        let good_records = vec![
+            IndexRecord::new(ts(0), offsets[0], true, true),
+            IndexRecord::new(ts(200), offsets[2], true, false),
+            IndexRecord::new(ts(301), end_offset, false, false),
+        ];
+        let report = verify_streams(Cursor::new(data.get_ref().clone()), write_index(&good_records)).unwrap();
+        assert_eq!(report.violations, vec![]);
+        assert!(report.is_ok());
+        assert_eq!(report.event_count, 4);
+        assert_eq!(report.index_record_count, 3);
+        assert_eq!(report.first_timestamp, ts(0));
+        assert_eq!(report.last_timestamp, ts(300));
+
+        // Index problems.
+        let bad_records = vec![
+            IndexRecord::new(ts(0), offsets[0], true, true),
+            IndexRecord::new(ts(200), offsets[2] + 1, true, false),
+            IndexRecord::new(ts(100), offsets[1], true, false),
+            IndexRecord::new(ts(150), offsets[3], true, false),
+        ];
+        let report = verify_streams(Cursor::new(data.get_ref().clone()), write_index(&bad_records)).unwrap();
+        assert_eq!(kinds(&report.violations), vec![
+            ViolationKind::NonMonotonicIndexOffset,
+            ViolationKind::NonMonotonicIndexTimestamp,
+            ViolationKind::EventOutsideIndexBounds,
+            ViolationKind::IndexOffsetNotOnEventBoundary,
+            ViolationKind::MissingFinalRecord,
+        ]);
+        assert_eq!(report.violations[3].data_offset, Some(offsets[2] + 1));
+        assert_eq!(report.violations[3].index_offset, Some(IndexRecord::RECORD_SIZE as u64));
+
+        // Corrupt data and a partial index record.
+        let mut corrupt_data = data.clone();
+        corrupt_data.set_position(offsets[1] + 8);
+        corrupt_data.write_all(&[0xff; 4]).unwrap();
+        let mut index = write_index(&good_records);
+        index.write_all(&[1; 3]).unwrap();
+        let report = verify_streams(corrupt_data, index).unwrap();
+        assert_eq!(kinds(&report.violations), vec![
+            ViolationKind::UndecodableIndexRecord,
+            ViolationKind::UndecodableEvent,
+        ]);
+        assert_eq!(report.violations[1].data_offset, Some(offsets[1]));
+        assert_eq!(report.event_count, 3);
+    }
+
+    #[test]
+    fn test_verify_index_bounds_per_span() {
+        let ts = |t: u64| PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000 + t));
+        let payload = vec![0; 100];
+        let mut data = Cursor::new(Vec::new());
+        let mut event_writer = EventWriter::new();
+        let mut offsets = Vec::new();
+        // The timestamps go back in time at the discontinuity. Event 1 is outside of the bounds of its span.
+        for (i, t) in [1000, 1300, 0, 100].iter().enumerate() {
+            offsets.push(data.stream_position().unwrap());
+            let event = EventWithHeader::new(&payload, ts(*t), true, true, i == 0 || i == 2);
+            event_writer.write(&event, &mut data).unwrap();
+        }
+        let end_offset = data.stream_position().unwrap();
+        let records = vec![
+            IndexRecord::new(ts(1000), offsets[0], true, true),
+            IndexRecord::new(ts(1200), offsets[2], false, false),
+            IndexRecord::new(ts(0), offsets[2], true, true),
+            IndexRecord::new(ts(101), end_offset, false, false),
+        ];
+        let mut index = Cursor::new(Vec::new());
+        let mut index_record_writer = IndexRecordWriter::new();
+        for record in records.iter() {
+            index_record_writer.write(record, &mut index).unwrap();
+        }
+        let report = verify_streams(data, index).unwrap();
+        assert_eq!(kinds(&report.violations), vec![ViolationKind::EventOutsideIndexBounds]);
+        assert_eq!(report.violations[0].data_offset, Some(offsets[1]));
+    }
+}